@@ -0,0 +1,193 @@
+//! Builds random [ToPixmapTaskSpec] trees two ways: once directly from the enum variants, and once
+//! through [stack], [paint_task] and [stack_alpha], which rewrite the tree as they go. Both trees
+//! are rendered at [GRID_SIZE], and any pixel that differs by more than rounding error means a
+//! rewrite was unsound.
+//!
+//! Run with `cargo +nightly fuzz run simplifier` from this directory.
+
+#![no_main]
+
+use arbitrary::{Result, Unstructured};
+use libfuzzer_sys::fuzz_target;
+use ochd::image_tasks::color::{c, premultiplied_diff, ComparableColor};
+use ochd::image_tasks::task_spec::{
+    from_svg_task, paint_task, stack, stack_alpha, TaskGraphBuildingContext, TaskSpecTraits,
+    ToAlphaChannelTaskSpec, ToPixmapTaskSpec,
+};
+use ochd::GRID_SIZE;
+use once_cell::sync::Lazy;
+use tokio::runtime::{Builder, Runtime};
+
+const MAX_DEPTH: u32 = 4;
+
+/// Reordering or merging layers changes how often the result is rounded, so allow a couple of
+/// units per level of the tree.
+const MAX_ERROR: u8 = 2 * MAX_DEPTH as u8;
+
+/// A few SVGs of each kind: binary alpha, anti-aliased, and full-color.
+const SVGS: &[&str] = &[
+    "borderSolid",
+    "bricks",
+    "checksSmall",
+    "circle24",
+    "bonemealSmall",
+    "torchFlameSmall",
+];
+
+/// Few enough colors that layers often share one, so that the simplifier has pairs to merge.
+static COLORS: Lazy<[ComparableColor; 5]> = Lazy::new(|| {
+    [
+        ComparableColor::BLACK,
+        ComparableColor::WHITE,
+        c(0x8a3a00),
+        ComparableColor::BLACK * 0.5,
+        c(0x8a3a00) * 0.25,
+    ]
+});
+
+static RUNTIME: Lazy<Runtime> = Lazy::new(|| Builder::new_multi_thread().build().unwrap());
+
+fn arbitrary_color(u: &mut Unstructured) -> Result<ComparableColor> {
+    u.choose(&*COLORS).copied()
+}
+
+/// Returns the same tree unsimplified and simplified.
+fn arbitrary_pixmap(
+    u: &mut Unstructured,
+    depth: u32,
+) -> Result<(ToPixmapTaskSpec, ToPixmapTaskSpec)> {
+    let kind = if depth == 0 {
+        0
+    } else {
+        u.int_in_range(0..=3)?
+    };
+    Ok(match kind {
+        0 => {
+            let svg = from_svg_task(*u.choose(SVGS)?);
+            (svg.clone(), svg)
+        }
+        1 => {
+            let (raw_base, simplified_base) = arbitrary_alpha(u, depth - 1)?;
+            let color = arbitrary_color(u)?;
+            (
+                ToPixmapTaskSpec::PaintAlphaChannel {
+                    base: Box::new(raw_base),
+                    color,
+                },
+                paint_task(simplified_base, color),
+            )
+        }
+        2 => {
+            let (raw_background, simplified_background) = arbitrary_pixmap(u, depth - 1)?;
+            let (raw_foreground, simplified_foreground) = arbitrary_pixmap(u, depth - 1)?;
+            (
+                ToPixmapTaskSpec::StackLayerOnLayer {
+                    background: Box::new(raw_background),
+                    foreground: Box::new(raw_foreground),
+                },
+                stack(simplified_background, simplified_foreground),
+            )
+        }
+        _ => {
+            let background = arbitrary_color(u)?;
+            let (raw_foreground, simplified_foreground) = arbitrary_pixmap(u, depth - 1)?;
+            (
+                ToPixmapTaskSpec::StackLayerOnColor {
+                    background,
+                    foreground: Box::new(raw_foreground),
+                },
+                ToPixmapTaskSpec::StackLayerOnColor {
+                    background,
+                    foreground: Box::new(simplified_foreground),
+                },
+            )
+        }
+    })
+}
+
+/// Returns the same tree unsimplified and simplified.
+fn arbitrary_alpha(
+    u: &mut Unstructured,
+    depth: u32,
+) -> Result<(ToAlphaChannelTaskSpec, ToAlphaChannelTaskSpec)> {
+    let kind = if depth == 0 {
+        0
+    } else {
+        u.int_in_range(0..=2)?
+    };
+    Ok(match kind {
+        0 => {
+            let (raw_base, simplified_base) = arbitrary_pixmap(u, depth)?;
+            (
+                ToAlphaChannelTaskSpec::FromPixmap { base: raw_base },
+                ToAlphaChannelTaskSpec::FromPixmap {
+                    base: simplified_base,
+                },
+            )
+        }
+        1 => {
+            let (raw_background, simplified_background) = arbitrary_alpha(u, depth - 1)?;
+            let (raw_foreground, simplified_foreground) = arbitrary_alpha(u, depth - 1)?;
+            (
+                ToAlphaChannelTaskSpec::StackAlphaOnAlpha {
+                    background: Box::new(raw_background),
+                    foreground: Box::new(raw_foreground),
+                },
+                stack_alpha(vec![simplified_background, simplified_foreground]),
+            )
+        }
+        _ => {
+            let (raw_base, simplified_base) = arbitrary_alpha(u, depth - 1)?;
+            let alpha = u.arbitrary()?;
+            (
+                ToAlphaChannelTaskSpec::MakeSemitransparent {
+                    base: Box::new(raw_base),
+                    alpha,
+                },
+                ToAlphaChannelTaskSpec::MakeSemitransparent {
+                    base: Box::new(simplified_base),
+                    alpha,
+                },
+            )
+        }
+    })
+}
+
+fn render(task: &ToPixmapTaskSpec) -> Vec<ComparableColor> {
+    RUNTIME.block_on(async {
+        let mut ctx = TaskGraphBuildingContext::new();
+        let image = task.add_to(&mut ctx, *GRID_SIZE).await;
+        image
+            .pixels()
+            .iter()
+            .copied()
+            .map(ComparableColor::from)
+            .collect()
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    let Ok((raw, simplified)) = arbitrary_pixmap(&mut u, MAX_DEPTH) else {
+        return;
+    };
+    if raw == simplified {
+        return;
+    }
+    let raw_pixels = render(&raw);
+    let simplified_pixels = render(&simplified);
+    assert_eq!(raw_pixels.len(), simplified_pixels.len());
+    for (index, (raw_pixel, simplified_pixel)) in
+        raw_pixels.iter().zip(&simplified_pixels).enumerate()
+    {
+        assert!(
+            premultiplied_diff(*raw_pixel, *simplified_pixel) <= MAX_ERROR,
+            "{} simplified to {}, but pixel {} changed from {} to {}",
+            raw,
+            simplified,
+            index,
+            raw_pixel,
+            simplified_pixel
+        );
+    }
+});
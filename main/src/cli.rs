@@ -0,0 +1,608 @@
+use std::fmt::Display;
+use std::path::PathBuf;
+
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use once_cell::sync::Lazy;
+use tracing::level_filters::LevelFilter;
+
+use crate::image_tasks::pack_meta::PackFormats;
+
+/// The smallest tile size that's allowed, since the SVGs are drawn on a 32-texel grid.
+pub const MIN_TILE_SIZE: u32 = 32;
+
+/// The command line. Options that only one part of the build cares about, such as `--only` or
+/// `--theme`, are in [BuildOptions] after the subcommand.
+#[derive(Clone, Debug, Parser)]
+#[command(
+    name = "OcHd-RustBuild",
+    version,
+    about = "Builds the OcHD resource pack",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// What to build when no subcommand is given, so that `OcHd-RustBuild 32` still works.
+    #[command(flatten)]
+    pub build: Target,
+    /// Write loose files to this directory instead of a ZIP file per tile size.
+    #[arg(
+        long,
+        global = true,
+        visible_alias = "output-dir",
+        env = "OCHD_OUTPUT_DIR",
+        value_name = "DIR"
+    )]
+    pub out: Option<PathBuf>,
+    /// How many worker threads to use; defaults to the number of CPUs.
+    #[arg(
+        long,
+        global = true,
+        visible_alias = "workers",
+        env = "OCHD_WORKERS",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub jobs: Option<u32>,
+    /// The most verbose level to write to log.txt: off, error, warn, info, debug or trace.
+    #[arg(long, global = true, default_value = "info", value_name = "LEVEL")]
+    pub log_level: LevelFilter,
+    /// The pack format to declare in pack.mcmeta, or a range of them such as `15-34` so that one
+    /// pack works in several Minecraft versions.
+    #[arg(
+        long,
+        global = true,
+        env = "OCHD_PACK_FORMAT",
+        value_name = "FORMAT[-MAX]"
+    )]
+    pub pack_format: Option<PackFormats>,
+}
+
+#[derive(Clone, Debug, Subcommand)]
+pub enum Command {
+    /// Builds the pack at each tile size (the default).
+    Build(Target),
+    /// Prints the path of every texture that would be built, without building any.
+    List(Target),
+    /// Renders one expression, such as `#7f7f7f+bricks@#000000`, to a PNG.
+    #[command(alias = "render-expr")]
+    Preview {
+        expr: String,
+        file: PathBuf,
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Writes every color the pack is predicted to use, in the format the file's extension names
+    /// (.json, .gpl or .aco).
+    Palette {
+        file: PathBuf,
+        #[command(flatten)]
+        target: Target,
+    },
+    /// Lists the textures whose path contains a term, that use a layer named by it, or that paint
+    /// with it if it's a color.
+    Search {
+        term: String,
+        #[command(flatten)]
+        target: Target,
+    },
+}
+
+#[derive(Clone, Debug, Default, Args)]
+pub struct Target {
+    /// A tile size, or several separated by commas such as `32,64,128`, each a power of two of at
+    /// least 32; defaults to the --profile's.
+    #[arg(value_name = "TILE_SIZE[,...]")]
+    pub tile_sizes: Option<String>,
+    #[command(flatten)]
+    pub options: BuildOptions,
+}
+
+/// Options that only one part of the build cares about. Each is read by name with
+/// [crate::option_value], [crate::parsed_option] or [crate::flag_present], which parse it and fall
+/// back to the config file and the `--profile`, so they're kept as strings here. Each can also be
+/// set with the environment variable `OCHD_<NAME>`, so that a machine can be configured once
+/// instead of on every invocation. A flag on its own means `=true`, and `=false` turns it off.
+#[derive(Clone, Debug, Default, Args)]
+#[command(next_help_heading = "Build options")]
+pub struct BuildOptions {
+    /// Whether to use one more worker thread when the CPU count is one short of a power of two;
+    /// defaults to true.
+    #[arg(long, env = "OCHD_ADJUST_PARALLELISM", value_name = "BOOL")]
+    pub adjust_parallelism: Option<String>,
+    /// Also write every alpha channel in the graph to this folder as a grayscale PNG.
+    #[arg(long, env = "OCHD_ALPHA_DEBUG_DIR", value_name = "DIR")]
+    pub alpha_debug_dir: Option<String>,
+    /// Also write the textures to this folder as loose files, when building one tile size.
+    #[arg(long, env = "OCHD_ALSO_WRITE_DIR", value_name = "DIR")]
+    pub also_write_dir: Option<String>,
+    /// How many frames of an animation can be rendered at once.
+    #[arg(long, env = "OCHD_ANIMATION_BATCH_SIZE", value_name = "FRAMES")]
+    pub animation_batch_size: Option<String>,
+    /// Only build the block textures of blocks that this CSV file counts, most common first.
+    #[arg(long, env = "OCHD_BLOCK_USAGE", value_name = "CSV")]
+    pub block_usage: Option<String>,
+    /// The most threads to use for blocking work such as file I/O.
+    #[arg(long, env = "OCHD_BLOCKING_THREADS", value_name = "N")]
+    pub blocking_threads: Option<String>,
+    /// Save every encoded PNG in this folder, and reuse those saved by earlier builds.
+    #[arg(long, env = "OCHD_CACHE_DIR", value_name = "DIR")]
+    pub cache_dir: Option<String>,
+    /// Folder names to use for the texture categories in --path-template.
+    #[arg(long, env = "OCHD_CATEGORY_NAMES", value_name = "CATEGORY=NAME[,...]")]
+    pub category_names: Option<String>,
+    /// A file of the most colors that individual textures may use, overriding --max-colors.
+    #[arg(long, env = "OCHD_COLOR_BUDGETS", value_name = "FILE")]
+    pub color_budgets: Option<String>,
+    /// Write the colors that were corrected to fit a palette or budget to this CSV file.
+    #[arg(long, env = "OCHD_COLOR_ERROR_REPORT", value_name = "CSV")]
+    pub color_error_report: Option<String>,
+    /// List the files that grew by more than --max-size-growth since this manifest was written.
+    #[arg(long, env = "OCHD_COMPARE_BASELINE", value_name = "FILE")]
+    pub compare_baseline: Option<String>,
+    /// The config file; defaults to ./ochd.toml if it exists.
+    #[arg(long, env = "OCHD_CONFIG", value_name = "FILE")]
+    pub config: Option<String>,
+    /// Write every step of making the texture at this output path, such as block/stone.
+    #[arg(long, env = "OCHD_DEBUG_BUNDLE", value_name = "PATH")]
+    pub debug_bundle: Option<String>,
+    /// Draw shadows and highlights as an ordered dither, like a painting.
+    #[arg(
+        long,
+        env = "OCHD_DITHERED_SHADING",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub dithered_shading: Option<String>,
+    /// The design to paint on the elytra.
+    #[arg(long, env = "OCHD_ELYTRA_DESIGN", value_name = "DESIGN")]
+    pub elytra_design: Option<String>,
+    /// Also write the emissive textures that OptiFine draws at full brightness.
+    #[arg(
+        long,
+        env = "OCHD_EMISSIVE_TEXTURES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub emissive_textures: Option<String>,
+    /// Write the graph of image tasks to this Graphviz file.
+    #[arg(long, env = "OCHD_EMIT_GRAPH", value_name = "FILE")]
+    pub emit_graph: Option<String>,
+    /// Leave out the textures whose paths match any of these patterns.
+    #[arg(long, env = "OCHD_EXCLUDE", value_name = "GLOB[,...]")]
+    pub exclude: Option<String>,
+    /// Shrink textures until the pack fits the Realms size limit.
+    #[arg(
+        long,
+        env = "OCHD_FIT_REALMS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub fit_realms: Option<String>,
+    /// The texture categories that --fit-realms may shrink; defaults to all of them.
+    #[arg(
+        long,
+        env = "OCHD_FIT_REALMS_CATEGORIES",
+        value_name = "CATEGORY[,...]"
+    )]
+    pub fit_realms_categories: Option<String>,
+    /// How many SVGs --verify-grid-perfect checks; defaults to every one that's used.
+    #[arg(long, env = "OCHD_GRID_CHECK_SAMPLE", value_name = "N")]
+    pub grid_check_sample: Option<String>,
+    /// How many texels per side most SVGs are drawn on; defaults to 32.
+    #[arg(long, env = "OCHD_GRID_SIZE", value_name = "TEXELS")]
+    pub grid_size: Option<String>,
+    /// Also build a high-contrast pack beside each one.
+    #[arg(
+        long,
+        env = "OCHD_HIGH_CONTRAST",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub high_contrast: Option<String>,
+    /// Copy the finished pack to --install-dir.
+    #[arg(
+        long,
+        env = "OCHD_INSTALL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub install: Option<String>,
+    /// Where --install copies the pack; defaults to the launcher's resourcepacks folder.
+    #[arg(long, env = "OCHD_INSTALL_DIR", value_name = "DIR")]
+    pub install_dir: Option<String>,
+    /// Snap every texture to the colors in this palette file.
+    #[arg(long, env = "OCHD_MASTER_PALETTE", value_name = "FILE")]
+    pub master_palette: Option<String>,
+    /// Fail if any color had to be moved farther than this to fit a palette or budget.
+    #[arg(long, env = "OCHD_MAX_COLOR_ERROR", value_name = "ERROR")]
+    pub max_color_error: Option<String>,
+    /// The most colors any texture may use.
+    #[arg(long, env = "OCHD_MAX_COLORS", value_name = "N")]
+    pub max_colors: Option<String>,
+    /// The most loose files to write at once.
+    #[arg(long, env = "OCHD_MAX_CONCURRENT_WRITES", value_name = "N")]
+    pub max_concurrent_writes: Option<String>,
+    /// How much a file may grow since --compare-baseline before it's listed.
+    #[arg(long, env = "OCHD_MAX_SIZE_GROWTH", value_name = "FRACTION")]
+    pub max_size_growth: Option<String>,
+    /// Chart the memory held by images over the build in PATH.csv and PATH.svg.
+    #[arg(long, env = "OCHD_MEMORY_TIMELINE", value_name = "PATH")]
+    pub memory_timeline: Option<String>,
+    /// How many times --block-usage must count a block for it to be built; defaults to 1.
+    #[arg(long, env = "OCHD_MIN_BLOCK_COUNT", value_name = "N")]
+    pub min_block_count: Option<String>,
+    /// Don't merge layers painted the same color into one stack of alpha channels.
+    #[arg(
+        long,
+        env = "OCHD_NO_ALPHA_RESTACKING",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub no_alpha_restacking: Option<String>,
+    /// Don't collapse repainting a layer that's already painted one color.
+    #[arg(
+        long,
+        env = "OCHD_NO_PAINT_COLLAPSE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub no_paint_collapse: Option<String>,
+    /// Don't merge stacks of layers that are upscaled from the grid size.
+    #[arg(
+        long,
+        env = "OCHD_NO_UPSCALE_MERGING",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub no_upscale_merging: Option<String>,
+    /// Only build the textures whose paths match any of these patterns.
+    #[arg(long, env = "OCHD_ONLY", value_name = "GLOB[,...]")]
+    pub only: Option<String>,
+    /// Hand-made textures that replace generated ones; defaults to ./overrides if it exists.
+    #[arg(long, env = "OCHD_OVERRIDES_DIR", value_name = "DIR")]
+    pub overrides_dir: Option<String>,
+    /// The oxipng optimization level for every PNG.
+    #[arg(long, env = "OCHD_OXIPNG_PRESET", value_name = "LEVEL")]
+    pub oxipng_preset: Option<String>,
+    /// The description to write in pack.mcmeta.
+    #[arg(long, env = "OCHD_PACK_DESCRIPTION", value_name = "TEXT")]
+    pub pack_description: Option<String>,
+    /// The expression to render as pack.png, in the syntax `preview` reads.
+    #[arg(long, env = "OCHD_PACK_ICON", value_name = "EXPR")]
+    pub pack_icon: Option<String>,
+    /// Where to write each texture, such as textures/{category}/{name}.png.
+    #[arg(long, env = "OCHD_PATH_TEMPLATE", value_name = "TEMPLATE")]
+    pub path_template: Option<String>,
+    /// How long optimizing PNGs may take, lowering the effort to finish in time.
+    #[arg(long, env = "OCHD_PNG_TIME_BUDGET", value_name = "SECONDS")]
+    pub png_time_budget: Option<String>,
+    /// A named set of defaults for the tile size and other options.
+    #[arg(long, env = "OCHD_PROFILE", value_name = "NAME")]
+    pub profile: Option<String>,
+    /// With --install, remove earlier builds of the pack from --install-dir.
+    #[arg(
+        long,
+        env = "OCHD_REMOVE_OLD_BUILDS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub remove_old_builds: Option<String>,
+    /// Render every SVG at this size and scale it to the tile size.
+    #[arg(long, env = "OCHD_RENDER_SVGS_AT", value_name = "SIZE")]
+    pub render_svgs_at: Option<String>,
+    /// Write the block textures with the most visible seams to this CSV file.
+    #[arg(long, env = "OCHD_SEAM_REPORT", value_name = "CSV")]
+    pub seam_report: Option<String>,
+    /// How visible a seam must be for --seam-report to list it.
+    #[arg(long, env = "OCHD_SEAM_THRESHOLD", value_name = "SCORE")]
+    pub seam_threshold: Option<String>,
+    /// How far every derived shadow and highlight is from its base color.
+    #[arg(long, env = "OCHD_SHADE_OFFSETS", value_name = "OFFSETS")]
+    pub shade_offsets: Option<String>,
+    /// The size to render `preview`'s expression at; defaults to the tile size.
+    #[arg(long, env = "OCHD_SIZE", value_name = "SIZE")]
+    pub size: Option<String>,
+    /// Write a summary of the output's size to this JSON file.
+    #[arg(long, env = "OCHD_STATS_JSON", value_name = "FILE")]
+    pub stats_json: Option<String>,
+    /// SVGs to use in place of the built-in ones with the same names.
+    #[arg(long, env = "OCHD_SVG_DIR", value_name = "DIR")]
+    pub svg_dir: Option<String>,
+    /// Write how many textures use each SVG to this CSV file.
+    #[arg(long, env = "OCHD_SVG_USAGE_REPORT", value_name = "CSV")]
+    pub svg_usage_report: Option<String>,
+    /// Only build the textures that this Minecraft version has; defaults to latest.
+    #[arg(long, env = "OCHD_TARGET_VERSION", value_name = "VERSION")]
+    pub target_version: Option<String>,
+    /// A theme file that recolors the pack.
+    #[arg(long, env = "OCHD_THEME", value_name = "FILE")]
+    pub theme: Option<String>,
+    /// Write a preview of the tinted textures to this PNG file.
+    #[arg(long, env = "OCHD_TINT_PREVIEW", value_name = "PNG")]
+    pub tint_preview: Option<String>,
+    /// The Minecraft client jar, for vanilla textures and --vanilla-parity-report.
+    #[arg(long, env = "OCHD_VANILLA_JAR", value_name = "JAR")]
+    pub vanilla_jar: Option<String>,
+    /// Write the textures whose silhouettes differ most from vanilla to this CSV file.
+    #[arg(long, env = "OCHD_VANILLA_PARITY_REPORT", value_name = "CSV")]
+    pub vanilla_parity_report: Option<String>,
+    /// How much a silhouette must differ for --vanilla-parity-report to list it.
+    #[arg(long, env = "OCHD_VANILLA_PARITY_THRESHOLD", value_name = "SCORE")]
+    pub vanilla_parity_threshold: Option<String>,
+    /// Check the finished ZIP file by reading every entry back.
+    #[arg(
+        long,
+        env = "OCHD_VERIFY",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub verify: Option<String>,
+    /// Check that SVGs treated as grid-perfect are, by rendering them first.
+    #[arg(
+        long,
+        env = "OCHD_VERIFY_GRID_PERFECT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub verify_grid_perfect: Option<String>,
+    /// Write the size of every file in the output to this manifest.
+    #[arg(long, env = "OCHD_WRITE_MANIFEST", value_name = "FILE")]
+    pub write_manifest: Option<String>,
+    /// Align the data of every ZIP entry to a multiple of this power of two.
+    #[arg(long, env = "OCHD_ZIP_ALIGN", value_name = "BYTES")]
+    pub zip_align: Option<String>,
+    /// Check that the ZIP file has no extra fields other than alignment padding.
+    #[arg(
+        long,
+        env = "OCHD_ZIP_STRIP_EXTRA",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        value_name = "BOOL"
+    )]
+    pub zip_strip_extra: Option<String>,
+}
+
+impl BuildOptions {
+    /// The value of `--<name>` from the command line or the environment, if any. Panics if there's
+    /// no such option, since that's a typo in the code that reads it.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        match name {
+            "adjust-parallelism" => &self.adjust_parallelism,
+            "alpha-debug-dir" => &self.alpha_debug_dir,
+            "also-write-dir" => &self.also_write_dir,
+            "animation-batch-size" => &self.animation_batch_size,
+            "block-usage" => &self.block_usage,
+            "blocking-threads" => &self.blocking_threads,
+            "cache-dir" => &self.cache_dir,
+            "category-names" => &self.category_names,
+            "color-budgets" => &self.color_budgets,
+            "color-error-report" => &self.color_error_report,
+            "compare-baseline" => &self.compare_baseline,
+            "config" => &self.config,
+            "debug-bundle" => &self.debug_bundle,
+            "dithered-shading" => &self.dithered_shading,
+            "elytra-design" => &self.elytra_design,
+            "emissive-textures" => &self.emissive_textures,
+            "emit-graph" => &self.emit_graph,
+            "exclude" => &self.exclude,
+            "fit-realms" => &self.fit_realms,
+            "fit-realms-categories" => &self.fit_realms_categories,
+            "grid-check-sample" => &self.grid_check_sample,
+            "grid-size" => &self.grid_size,
+            "high-contrast" => &self.high_contrast,
+            "install" => &self.install,
+            "install-dir" => &self.install_dir,
+            "master-palette" => &self.master_palette,
+            "max-color-error" => &self.max_color_error,
+            "max-colors" => &self.max_colors,
+            "max-concurrent-writes" => &self.max_concurrent_writes,
+            "max-size-growth" => &self.max_size_growth,
+            "memory-timeline" => &self.memory_timeline,
+            "min-block-count" => &self.min_block_count,
+            "no-alpha-restacking" => &self.no_alpha_restacking,
+            "no-paint-collapse" => &self.no_paint_collapse,
+            "no-upscale-merging" => &self.no_upscale_merging,
+            "only" => &self.only,
+            "overrides-dir" => &self.overrides_dir,
+            "oxipng-preset" => &self.oxipng_preset,
+            "pack-description" => &self.pack_description,
+            "pack-icon" => &self.pack_icon,
+            "path-template" => &self.path_template,
+            "png-time-budget" => &self.png_time_budget,
+            "profile" => &self.profile,
+            "remove-old-builds" => &self.remove_old_builds,
+            "render-svgs-at" => &self.render_svgs_at,
+            "seam-report" => &self.seam_report,
+            "seam-threshold" => &self.seam_threshold,
+            "shade-offsets" => &self.shade_offsets,
+            "size" => &self.size,
+            "stats-json" => &self.stats_json,
+            "svg-dir" => &self.svg_dir,
+            "svg-usage-report" => &self.svg_usage_report,
+            "target-version" => &self.target_version,
+            "theme" => &self.theme,
+            "tint-preview" => &self.tint_preview,
+            "vanilla-jar" => &self.vanilla_jar,
+            "vanilla-parity-report" => &self.vanilla_parity_report,
+            "vanilla-parity-threshold" => &self.vanilla_parity_threshold,
+            "verify" => &self.verify,
+            "verify-grid-perfect" => &self.verify_grid_perfect,
+            "write-manifest" => &self.write_manifest,
+            "zip-align" => &self.zip_align,
+            "zip-strip-extra" => &self.zip_strip_extra,
+            _ => panic!("--{} isn't declared in BuildOptions", name),
+        }
+        .as_deref()
+    }
+}
+
+/// Parses a comma-separated list of tile sizes, sorted from smallest to largest.
+pub fn parse_tile_sizes(arg: &str) -> Result<Vec<u32>, String> {
+    let mut sizes = arg
+        .split(',')
+        .map(|size| {
+            let size = size.trim();
+            match size.parse::<u32>() {
+                Ok(size) if size >= MIN_TILE_SIZE && size.is_power_of_two() => Ok(size),
+                _ => Err(format!(
+                    "tile size must be a power of two of at least {}, not {:?}",
+                    MIN_TILE_SIZE, size
+                )),
+            }
+        })
+        .collect::<Result<Vec<u32>, String>>()?;
+    sizes.sort_unstable();
+    sizes.dedup();
+    Ok(sizes)
+}
+
+impl Target {
+    /// The tile sizes, if they're given.
+    pub fn tile_sizes(&self) -> Option<Result<Vec<u32>, String>> {
+        self.tile_sizes.as_deref().map(parse_tile_sizes)
+    }
+}
+
+impl Cli {
+    pub fn target(&self) -> &Target {
+        match &self.command {
+            None => &self.build,
+            Some(Command::Build(target) | Command::List(target)) => target,
+            Some(
+                Command::Preview { target, .. }
+                | Command::Palette { target, .. }
+                | Command::Search { target, .. },
+            ) => target,
+        }
+    }
+
+    /// The tile sizes given on the command line, if any. Exits with a usage message if they're
+    /// malformed.
+    pub fn tile_sizes(&self) -> Option<Vec<u32>> {
+        self.target()
+            .tile_sizes()
+            .map(|sizes| sizes.unwrap_or_else(|e| usage_error(ErrorKind::ValueValidation, e)))
+    }
+
+    /// Whether the command builds the pack, and so needs to be told what tile size to build it
+    /// at. The others fall back to [MIN_TILE_SIZE] when it's left out.
+    pub fn needs_tile_size(&self) -> bool {
+        matches!(self.command, None | Some(Command::Build(_)))
+    }
+
+    /// The [BuildOptions] given after the subcommand, or without one.
+    pub fn options(&self) -> &BuildOptions {
+        &self.target().options
+    }
+}
+
+/// Prints the usage along with `message`, and exits with the status clap uses for usage errors.
+pub fn usage_error(kind: ErrorKind, message: impl Display) -> ! {
+    Cli::command().error(kind, message).exit()
+}
+
+/// The command line, parsed. Exits with a usage message if it's malformed, or after printing the
+/// help if that's what was asked for.
+#[cfg(not(any(test, fuzzing)))]
+pub static CLI: Lazy<Cli> = Lazy::new(Cli::parse);
+
+/// Tests and fuzzers aren't given the build's arguments, so they see only the environment's.
+#[cfg(any(test, fuzzing))]
+pub static CLI: Lazy<Cli> = Lazy::new(|| Cli::parse_from(["OcHd-RustBuild"]));
+
+#[test]
+fn test_cli() {
+    Cli::command().debug_assert();
+
+    assert_eq!(parse_tile_sizes("128, 32,128"), Ok(vec![32, 128]));
+    assert!(parse_tile_sizes("48").is_err());
+    assert!(parse_tile_sizes("16").is_err());
+    assert!(parse_tile_sizes("big").is_err());
+
+    // Without a subcommand, the arguments are for `build`
+    let cli = Cli::try_parse_from([
+        "OcHd-RustBuild",
+        "32,64",
+        "--only",
+        "block/*",
+        "--out",
+        "dir",
+        "--install",
+    ])
+    .unwrap();
+    assert!(cli.command.is_none());
+    assert!(cli.needs_tile_size());
+    assert_eq!(cli.tile_sizes(), Some(vec![32, 64]));
+    assert_eq!(cli.out, Some(PathBuf::from("dir")));
+    assert_eq!(cli.options().value("only"), Some("block/*"));
+    assert_eq!(cli.options().value("install"), Some("true"));
+    assert_eq!(cli.log_level, LevelFilter::INFO);
+
+    let cli = Cli::try_parse_from([
+        "OcHd-RustBuild",
+        "preview",
+        "borderSolid",
+        "out.png",
+        "--jobs=4",
+        "--log-level",
+        "debug",
+    ])
+    .unwrap();
+    assert!(
+        matches!(cli.command, Some(Command::Preview { ref expr, .. }) if expr == "borderSolid")
+    );
+    assert_eq!(cli.tile_sizes(), None);
+    assert_eq!(cli.jobs, Some(4));
+    assert_eq!(cli.log_level, LevelFilter::DEBUG);
+    assert_eq!(cli.pack_format, None);
+
+    let cli = Cli::try_parse_from([
+        "OcHd-RustBuild",
+        "list",
+        "--profile",
+        "hd",
+        "--pack-format=15-34",
+    ])
+    .unwrap();
+    assert_eq!(cli.pack_format, Some(PackFormats { min: 15, max: 34 }));
+    assert!(matches!(cli.command, Some(Command::List(_))));
+    assert!(!cli.needs_tile_size());
+    assert_eq!(cli.tile_sizes(), None);
+    assert_eq!(cli.options().value("profile"), Some("hd"));
+    let cli = Cli::try_parse_from(["OcHd-RustBuild", "build", "--verify=false", "--zip-align=4"])
+        .unwrap();
+    assert_eq!(cli.options().value("verify"), Some("false"));
+    assert_eq!(cli.options().value("zip-align"), Some("4"));
+    // A misspelled option isn't silently ignored
+    assert!(Cli::try_parse_from(["OcHd-RustBuild", "32", "--onyl=block/*"]).is_err());
+    // Every declared option can be looked up by its name
+    let options = BuildOptions::augment_args(clap::Command::new("options"));
+    for name in options.get_arguments().filter_map(|arg| arg.get_long()) {
+        cli.options().value(name);
+    }
+    assert!(Cli::try_parse_from(["OcHd-RustBuild", "build", "--jobs", "0"]).is_err());
+}
@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::read_to_string;
+use std::path::Path;
+use std::str::FromStr;
+
+use log::warn;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::anyhoo;
+use crate::cli::CLI;
+use crate::image_tasks::cloneable::CloneableError;
+
+/// Used when `--config` isn't given, if it exists.
+const DEFAULT_CONFIG_FILE: &str = "./ochd.toml";
+
+/// Settings that are kept in a file, so that a pack's build can be customized without code edits.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Config {
+    /// Values of options, by name without the leading `--`.
+    options: HashMap<String, String>,
+    /// Names of the material groups to leave out, as [group_name] gives them.
+    disabled_groups: HashSet<String>,
+    /// Glob patterns of texture paths, and the transforms to apply to the textures they match, in
+    /// the order they're listed; see [crate::image_tasks::post_process].
+    post_processing: Vec<(String, String)>,
+    /// Output categories, and how to compress the files in them; see
+    /// [crate::image_tasks::compression].
+    compression: Vec<(String, String)>,
+}
+
+/// Loaded from the file named by `--config`, or else from `./ochd.toml` if it exists.
+pub static CONFIG: Lazy<Config> = Lazy::new(|| {
+    let path = CLI
+        .options()
+        .value("config")
+        .or_else(|| Some(DEFAULT_CONFIG_FILE).filter(|path| Path::new(path).is_file()));
+    match path {
+        Some(path) => read_to_string(&path)
+            .map_err(CloneableError::from)
+            .and_then(|text| text.parse())
+            .unwrap_or_else(|e| panic!("Invalid value for --config: {:?}", e)),
+        None => Config::default(),
+    }
+});
+
+/// Names of the groups that [Config::group_enabled] has been asked about, and of the members of
+/// disabled groups, which are never asked about since they aren't built.
+static SEEN_GROUPS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// The name a config file uses for a [crate::group]: the identifier of its static, without the
+/// module path or an `ALL_` prefix, so that `particle::ALL_PARTICLES` is `PARTICLES`.
+pub fn group_name(member: &str) -> &str {
+    let name = member.rsplit("::").next().unwrap_or(member).trim();
+    name.strip_prefix("ALL_").unwrap_or(name)
+}
+
+/// One `key = value` line of a file in the subset of TOML that [parse_toml_subset] reads.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) struct TomlEntry<'a> {
+    /// The header of the table the line is under, or empty if it's before any.
+    pub(crate) table: &'a str,
+    pub(crate) key: &'a str,
+    pub(crate) value: &'a str,
+    /// The whole line, for error messages.
+    pub(crate) line: &'a str,
+}
+
+/// Parses the subset of TOML that config and theme files need: `key = value` lines, each under
+/// the `[table]` header before it if any, which must be one of `tables`. Quotes around keys and
+/// values are removed. Blank lines and comments starting with `#` are ignored.
+pub(crate) fn parse_toml_subset<'a>(
+    s: &'a str,
+    tables: &[&str],
+) -> Result<Vec<TomlEntry<'a>>, CloneableError> {
+    let mut entries = Vec::new();
+    let mut table = "";
+    for line in s.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(header) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            table = header.trim();
+            if !tables.contains(&table) {
+                return Err(anyhoo!("Unknown table: [{}]", table));
+            }
+            continue;
+        }
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhoo!("Expected `name = value`: {}", line))?;
+        entries.push(TomlEntry {
+            table,
+            key: key.trim().trim_matches('"'),
+            value: value.trim().trim_matches('"'),
+            line,
+        });
+    }
+    Ok(entries)
+}
+
+impl FromStr for Config {
+    type Err = CloneableError;
+
+    /// Parses the subset of TOML that a config needs; see [parse_toml_subset]. Lines before any
+    /// table header are options, such as `max-colors = 64`; lines like `MUSIC_DISCS = false` under
+    /// a `[groups]` header turn material groups on or off, and lines like
+    /// `"block/*" = "sharpen:50, vignette:20"` under a `[post_process]` header transform textures,
+    /// and lines like `item = "zopfli, oxipng:6"` under a `[compression]` header choose how a
+    /// category of files is compressed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut config = Config::default();
+        for entry in parse_toml_subset(s, &["groups", "post_process", "compression"])? {
+            let (key, value) = (entry.key.to_owned(), entry.value.to_owned());
+            match entry.table {
+                "" => {
+                    config.options.insert(key, value);
+                }
+                "post_process" => config.post_processing.push((key, value)),
+                "compression" => config.compression.push((key, value)),
+                _ => {
+                    let enabled: bool = value
+                        .parse()
+                        .map_err(|_| anyhoo!("Expected true or false: {}", entry.line))?;
+                    let key = key.to_ascii_uppercase();
+                    if enabled {
+                        config.disabled_groups.remove(&key);
+                    } else {
+                        config.disabled_groups.insert(key);
+                    }
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+impl Config {
+    pub fn option_value(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    /// The `[post_process]` table, as pairs of a glob pattern and a list of transforms.
+    pub fn post_processing(&self) -> &[(String, String)] {
+        &self.post_processing
+    }
+
+    /// The `[compression]` table, as pairs of an output category and a compression policy.
+    pub fn compression_policies(&self) -> &[(String, String)] {
+        &self.compression
+    }
+
+    /// Whether a [crate::group], given as the identifier it's declared with, should be built.
+    pub fn group_enabled(&self, group: &str) -> bool {
+        let name = group_name(group);
+        SEEN_GROUPS.lock().insert(name.to_owned());
+        !self.disabled_groups.contains(name)
+    }
+
+    /// Called with the members of a disabled group, as the expressions that name them, so that
+    /// [Self::warn_unknown_groups] doesn't take those that are also disabled for misspellings.
+    pub fn skip_members(&self, members: &[&str]) {
+        SEEN_GROUPS
+            .lock()
+            .extend(members.iter().map(|member| group_name(member).to_owned()));
+    }
+
+    /// Warns about disabled groups that no material group has matched, since they're probably
+    /// misspelled. Called once all the material groups have been built.
+    pub fn warn_unknown_groups(&self) {
+        let seen = SEEN_GROUPS.lock();
+        let mut unknown: Vec<&str> = self
+            .disabled_groups
+            .iter()
+            .filter(|name| !seen.contains(*name))
+            .map(String::as_str)
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            warn!(
+                "Config disables material groups that don't exist: {}",
+                unknown.join(", ")
+            );
+        }
+    }
+}
+
+#[test]
+fn test_config() {
+    assert_eq!(group_name("particle::ALL_PARTICLES"), "PARTICLES");
+    assert_eq!(group_name("MUSIC_DISCS"), "MUSIC_DISCS");
+    let config: Config = "# Lightweight\nmax-colors = 64\n\n[groups]\nmusic_discs = false\n\
+        PARTICLES = false\nCLOCK = true\n"
+        .parse()
+        .unwrap();
+    assert_eq!(config.option_value("max-colors"), Some("64"));
+    assert_eq!(config.option_value("theme"), None);
+    assert!(!config.group_enabled("MUSIC_DISCS"));
+    assert!(!config.group_enabled("particle::ALL_PARTICLES"));
+    assert!(config.group_enabled("CLOCK"));
+    assert!(config.group_enabled("block::ALL_BLOCKS"));
+    assert!("[groups]\nPARTICLES = no".parse::<Config>().is_err());
+    assert!("[colors]\nred = \"#ff0000\"".parse::<Config>().is_err());
+    let config: Config = "[post_process]\n\"block/*\" = \"sharpen:50\"\nitem/* = scanlines:20\n"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        config.post_processing(),
+        [
+            ("block/*".to_owned(), "sharpen:50".to_owned()),
+            ("item/*".to_owned(), "scanlines:20".to_owned())
+        ]
+    );
+    let config: Config = "[compression]\nitem = \"zopfli, oxipng:6\"\n"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        config.compression_policies(),
+        [("item".to_owned(), "zopfli, oxipng:6".to_owned())]
+    );
+}
@@ -0,0 +1,87 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use resvg::tiny_skia::Mask;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::task_spec::NodeId;
+use crate::option_value;
+
+/// The folder named by `--alpha-debug-dir`, if any. When it's set, every alpha channel in the
+/// graph is also written there as a grayscale PNG, so that a mistake in stacking alpha channels
+/// can be seen before it's painted.
+pub static ALPHA_DEBUG_DIR: Lazy<Option<PathBuf>> =
+    Lazy::new(|| option_value("alpha-debug-dir").map(PathBuf::from));
+
+/// The spec of each alpha channel written, by tile size and [NodeId], for the index.
+static WRITTEN: Lazy<Mutex<BTreeMap<(u32, NodeId), String>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// Encodes an alpha channel as an 8-bit grayscale PNG, where white is opaque.
+fn encode_mask(mask: &Mask) -> Result<Vec<u8>, CloneableError> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, mask.width(), mask.height());
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(mask.data())?;
+    Ok(png)
+}
+
+/// Writes `<tile size>/<id>.png` under [ALPHA_DEBUG_DIR]. Failures are only logged, since this is
+/// a debugging aid and shouldn't stop the build.
+pub fn write_alpha_debug(id: NodeId, spec: &str, tile_size: u32, mask: &Mask) {
+    let Some(dir) = &*ALPHA_DEBUG_DIR else {
+        return;
+    };
+    let dir = dir.join(tile_size.to_string());
+    let path = dir.join(format!("{}.png", id));
+    let result = encode_mask(mask).and_then(|png| {
+        fs::create_dir_all(&dir)?;
+        fs::write(&path, png)?;
+        Ok(())
+    });
+    match result {
+        Ok(()) => {
+            WRITTEN.lock().insert((tile_size, id), spec.to_owned());
+        }
+        Err(error) => warn!("Failed to write {}: {}", path.display(), error),
+    }
+}
+
+/// Writes `index.tsv` under [ALPHA_DEBUG_DIR], which gives the spec of each PNG written there.
+pub fn finish_alpha_debug() -> Result<(), CloneableError> {
+    let Some(dir) = &*ALPHA_DEBUG_DIR else {
+        return Ok(());
+    };
+    let written = WRITTEN.lock();
+    let mut index = String::from("file\tspec\n");
+    for ((tile_size, id), spec) in written.iter() {
+        writeln!(index, "{}/{}.png\t{}", tile_size, id, spec)?;
+    }
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("index.tsv"), index)?;
+    info!(
+        "Wrote {} alpha channels to {}",
+        written.len(),
+        dir.display()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_encode_mask() {
+    let mut mask = Mask::new(4, 2).unwrap();
+    mask.data_mut()[1] = 0x80;
+    mask.data_mut()[7] = u8::MAX;
+    let png = encode_mask(&mask).unwrap();
+    let mut reader = png::Decoder::new(&*png).read_info().unwrap();
+    let mut decoded = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut decoded).unwrap();
+    assert_eq!(reader.info().color_type, png::ColorType::Grayscale);
+    assert_eq!(decoded, mask.data());
+}
@@ -1,59 +1,327 @@
-use core::mem::size_of;
-use futures_util::FutureExt;
-use resvg::tiny_skia::{Pixmap, PixmapMut, PixmapPaint, PremultipliedColorU8, Transform};
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+use resvg::tiny_skia::{Pixmap, PixmapPaint, Transform};
 use tokio::task::JoinSet;
 use tracing::instrument;
 
-use crate::image_tasks::cloneable::{Arcow, SimpleArcow};
+use crate::image_tasks::cloneable::{Arcow, CloneableError, SimpleArcow};
 use crate::image_tasks::task_spec::BasicTask;
 use crate::image_tasks::{allocate_pixmap_empty, allocate_pixmap_for_overwrite, MaybeFromPool};
-use crate::join_all;
+use crate::{anyhoo, parsed_option, GRID_SIZE};
+
+const DEFAULT_ANIMATION_BATCH_SIZE: usize = 32;
+
+/// How many frames of an animation can be rendered at once. [animate] drops its handle to each
+/// frame as soon as it's been composited, and frames are composited in order, so this bounds how
+/// many it holds at a time.
+static ANIMATION_BATCH_SIZE: Lazy<usize> = Lazy::new(|| {
+    parsed_option("animation-batch-size")
+        .unwrap_or(DEFAULT_ANIMATION_BATCH_SIZE)
+        .max(1)
+});
+
+/// How Minecraft plays an animated texture: the frames of a [SheetLayout::Vertical] strip in order,
+/// each for `frame_time` game ticks (20 per second), and blended into the next if `interpolate`.
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct FrameTiming {
+    pub frame_time: u32,
+    pub interpolate: bool,
+}
+
+impl FrameTiming {
+    pub const fn new(frame_time: u32) -> FrameTiming {
+        FrameTiming {
+            frame_time,
+            interpolate: false,
+        }
+    }
+
+    /// The contents of the texture's `.png.mcmeta` file.
+    pub fn mcmeta(&self) -> String {
+        format!(
+            "{{\"animation\":{{\"frametime\":{},\"interpolate\":{}}}}}\n",
+            self.frame_time, self.interpolate
+        )
+    }
+}
+
+/// How the frames of an [animate] output are arranged.
+#[derive(Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum SheetLayout {
+    /// Each frame below the previous one, as Minecraft expects for animated textures.
+    #[default]
+    Vertical,
+    /// Each frame to the right of the previous one.
+    Horizontal,
+    /// Frames fill each row from left to right, then the next row down, with `padding` transparent
+    /// pixels between cells. Padding is measured at [GRID_SIZE] and scales with the tile size.
+    Grid {
+        columns: u32,
+        rows: u32,
+        padding: u32,
+    },
+}
+
+impl Display for SheetLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SheetLayout::Vertical => f.write_str("vertical"),
+            SheetLayout::Horizontal => f.write_str("horizontal"),
+            SheetLayout::Grid {
+                columns,
+                rows,
+                padding,
+            } => write!(f, "{}x{}+{}", columns, rows, padding),
+        }
+    }
+}
+
+impl FromStr for SheetLayout {
+    type Err = CloneableError;
+
+    /// Parses `vertical`, `horizontal`, or `<columns>x<rows>` with an optional `+<padding>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "vertical" => return Ok(SheetLayout::Vertical),
+            "horizontal" => return Ok(SheetLayout::Horizontal),
+            _ => {}
+        }
+        let (size, padding) = s.split_once('+').unwrap_or((s, "0"));
+        let (columns, rows) = size
+            .split_once('x')
+            .ok_or_else(|| anyhoo!("Invalid sheet layout: {}", s))?;
+        let parse = |value: &str| {
+            value
+                .parse::<u32>()
+                .map_err(|_| anyhoo!("Invalid sheet layout: {}", s))
+        };
+        let (columns, rows, padding) = (parse(columns)?, parse(rows)?, parse(padding)?);
+        if columns == 0 || rows == 0 {
+            return Err(anyhoo!("Sheet layout has no cells: {}", s));
+        }
+        Ok(SheetLayout::Grid {
+            columns,
+            rows,
+            padding,
+        })
+    }
+}
+
+impl SheetLayout {
+    fn columns_and_rows(&self, frame_count: u32) -> (u32, u32) {
+        match *self {
+            SheetLayout::Vertical => (1, frame_count),
+            SheetLayout::Horizontal => (frame_count, 1),
+            SheetLayout::Grid { columns, rows, .. } => (columns, rows),
+        }
+    }
+
+    fn padding(&self, frame_width: u32) -> u32 {
+        match *self {
+            SheetLayout::Grid { padding, .. } => padding * frame_width / *GRID_SIZE,
+            _ => 0,
+        }
+    }
+
+    /// Whether some of the sheet isn't covered by any frame, so it has to start out transparent.
+    pub fn has_gaps(&self, frame_count: u32) -> bool {
+        let (columns, rows) = self.columns_and_rows(frame_count);
+        columns * rows > frame_count || self.padding(*GRID_SIZE) > 0
+    }
+
+    /// Width and height of the whole sheet.
+    pub fn size(&self, frame_count: u32, frame_width: u32, frame_height: u32) -> (u32, u32) {
+        let (columns, rows) = self.columns_and_rows(frame_count);
+        let padding = self.padding(frame_width);
+        (
+            columns * (frame_width + padding) - padding,
+            rows * (frame_height + padding) - padding,
+        )
+    }
+
+    /// Position of the top-left corner of the given frame.
+    pub fn frame_origin(
+        &self,
+        index: u32,
+        frame_count: u32,
+        frame_width: u32,
+        frame_height: u32,
+    ) -> (u32, u32) {
+        let (columns, _) = self.columns_and_rows(frame_count);
+        let padding = self.padding(frame_width);
+        (
+            (index % columns) * (frame_width + padding),
+            (index / columns) * (frame_height + padding),
+        )
+    }
+}
 
 #[instrument(skip(background))]
 pub async fn animate(
     background: &Pixmap,
     frames: Box<[BasicTask<MaybeFromPool<Pixmap>>]>,
+    layout: SheetLayout,
     clear_output: bool,
 ) -> SimpleArcow<MaybeFromPool<Pixmap>> {
+    let frame_count = frames.len() as u32;
+    let (columns, rows) = layout.columns_and_rows(frame_count);
+    assert!(
+        frame_count <= columns * rows,
+        "{} frames don't fit in a {} sheet",
+        frame_count,
+        layout
+    );
+    let frame_width = background.width();
     let frame_height = background.height();
-    let total_height = frame_height * (frames.len() as u32);
-    let width = background.width();
-    let out = Box::leak(Box::new(if clear_output {
-        allocate_pixmap_empty(width, total_height)
+    let (width, height) = layout.size(frame_count, frame_width, frame_height);
+    let mut out = if clear_output || layout.has_gaps(frame_count) {
+        allocate_pixmap_empty(width, height)
     } else {
-        allocate_pixmap_for_overwrite(background.width(), total_height)
-    })) as *mut MaybeFromPool<Pixmap>;
-    let background = (*background).as_ref();
-    let out_data = unsafe { out.as_mut() }.unwrap().data_mut();
-    let mut frame_chunks = out_data
-        .chunks_mut(frame_height as usize * width as usize * size_of::<PremultipliedColorU8>());
+        allocate_pixmap_for_overwrite(width, height)
+    };
+    let background = background.as_ref();
+    let batch_size = *ANIMATION_BATCH_SIZE as u32;
+    // Frames are rendered in parallel, but composited in order so that the result doesn't depend
+    // on which finishes first. Frames that finish early wait in `finished` for the ones before
+    // them, and no frame is started until it's within `batch_size` of the next one to composite.
+    // Dropping the JoinSet aborts the frames still rendering if this task is cancelled.
+    let mut frames = frames.into_vec().into_iter();
     let mut join_set = JoinSet::new();
-    for frame in frames.into_vec().into_iter() {
-        let frame_pixels = frame_chunks.next().unwrap();
-        let mut frame_buffer = PixmapMut::from_bytes(frame_pixels, width, frame_height).unwrap();
-        frame_buffer.draw_pixmap(
-            0,
-            0,
-            background,
-            &PixmapPaint::default(),
-            Transform::default(),
-            None,
-        );
-        let frame_buffer = Box::new(frame_buffer);
-        join_set.spawn(frame.map(
-            async move |frame_pixmap: SimpleArcow<MaybeFromPool<Pixmap>>| {
-                Box::leak(frame_buffer).draw_pixmap(
-                    0,
-                    0,
-                    frame_pixmap.as_ref(),
-                    &PixmapPaint::default(),
-                    Transform::default(),
-                    None,
-                );
-            },
-        ));
-    }
-    let out = unsafe { Box::from_raw(out) };
-    join_all(join_set).await;
-    Arcow::SharingRef(out.into())
+    let mut finished = BTreeMap::new();
+    let mut next_to_start = 0;
+    let mut next_to_draw = 0;
+    while next_to_draw < frame_count {
+        while next_to_start < frame_count && next_to_start - next_to_draw < batch_size {
+            let index = next_to_start;
+            let frame = frames.next().unwrap();
+            join_set.spawn(async move { (index, frame.await) });
+            next_to_start += 1;
+        }
+        let Some(frame_pixmap) = finished.remove(&next_to_draw) else {
+            let (index, frame_pixmap) = join_set
+                .join_next()
+                .await
+                .expect("Animation frame wasn't started")
+                .unwrap();
+            finished.insert(index, frame_pixmap);
+            continue;
+        };
+        let (x, y) = layout.frame_origin(next_to_draw, frame_count, frame_width, frame_height);
+        for layer in [background, frame_pixmap.as_ref()] {
+            out.draw_pixmap(
+                x as i32,
+                y as i32,
+                layer,
+                &PixmapPaint::default(),
+                Transform::default(),
+                None,
+            );
+        }
+        drop(frame_pixmap);
+        next_to_draw += 1;
+    }
+    Arcow::from_owned(out)
+}
+
+#[test]
+fn test_animate() {
+    use crate::image_tasks::color::{c, ComparableColor};
+    use futures_util::FutureExt;
+    use tokio::sync::oneshot;
+
+    let mut background = Pixmap::new(2, 2).unwrap();
+    background.fill(ComparableColor::BLACK.into());
+    let frame_count = 2 * DEFAULT_ANIMATION_BATCH_SIZE + 1;
+    // Within each batch, every frame waits for the one after it to finish, so that frames finish in
+    // reverse order and can't be composited as they finish. Batches don't wait for each other,
+    // since a frame isn't started until the frames a batch before it have been composited.
+    let (mut senders, mut receivers): (Vec<_>, Vec<_>) = (0..frame_count)
+        .map(|_| {
+            let (sender, receiver) = oneshot::channel::<()>();
+            (Some(sender), Some(receiver))
+        })
+        .unzip();
+    let frames: Box<[BasicTask<MaybeFromPool<Pixmap>>]> = (0..frame_count)
+        .map(|index| {
+            let mut frame = Pixmap::new(2, 2).unwrap();
+            frame.pixels_mut()[index % 4] = c(0x8a3a00).into();
+            let next_finished =
+                if (index + 1) % DEFAULT_ANIMATION_BATCH_SIZE != 0 && index + 1 < frame_count {
+                    receivers[index].take()
+                } else {
+                    None
+                };
+            let finished = if index % DEFAULT_ANIMATION_BATCH_SIZE != 0 {
+                senders[index - 1].take()
+            } else {
+                None
+            };
+            async move {
+                if let Some(next_finished) = next_finished {
+                    let _ = next_finished.await;
+                }
+                if let Some(finished) = finished {
+                    let _ = finished.send(());
+                }
+                Arcow::from_owned(MaybeFromPool::not_from_pool(frame))
+            }
+            .boxed()
+            .shared()
+        })
+        .collect();
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let out = runtime.block_on(animate(&background, frames, SheetLayout::Vertical, false));
+    assert_eq!(out.height(), 2 * frame_count as u32);
+    for (index, pixel) in out.pixels().iter().enumerate() {
+        let expected = if index % 4 == (index / 4) % 4 {
+            c(0x8a3a00)
+        } else {
+            ComparableColor::BLACK
+        };
+        assert_eq!(ComparableColor::from(*pixel), expected);
+    }
+}
+
+#[test]
+fn test_frame_timing() {
+    let timing = FrameTiming {
+        frame_time: 4,
+        interpolate: true,
+    };
+    assert_eq!(
+        timing.mcmeta(),
+        "{\"animation\":{\"frametime\":4,\"interpolate\":true}}\n"
+    );
+    assert!(!FrameTiming::new(2).interpolate);
+}
+
+#[test]
+fn test_sheet_layout() {
+    let grid: SheetLayout = "3x2+1".parse().unwrap();
+    assert_eq!(
+        grid,
+        SheetLayout::Grid {
+            columns: 3,
+            rows: 2,
+            padding: 1
+        }
+    );
+    assert_eq!(grid.to_string(), "3x2+1");
+    assert_eq!(
+        "horizontal".parse::<SheetLayout>().unwrap(),
+        SheetLayout::Horizontal
+    );
+    assert!("0x2".parse::<SheetLayout>().is_err());
+    assert!("3by2".parse::<SheetLayout>().is_err());
+
+    // Padding scales with the tile size
+    assert_eq!(grid.size(5, 64, 64), (3 * 64 + 2 * 2, 2 * 64 + 2));
+    assert_eq!(grid.frame_origin(4, 5, 64, 64), (66, 66));
+    assert!(grid.has_gaps(6));
+    assert!(!SheetLayout::Horizontal.has_gaps(4));
+    assert_eq!(SheetLayout::Horizontal.size(4, 32, 32), (128, 32));
+    assert_eq!(SheetLayout::Vertical.frame_origin(2, 4, 32, 32), (0, 64));
 }
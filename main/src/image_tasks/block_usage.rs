@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::str::FromStr;
+
+use log::info;
+use once_cell::sync::Lazy;
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::task_spec::FileOutputTaskSpec;
+use crate::{option_value, parsed_option};
+
+/// How many times each block appears in a world, such as a count of each block ID exported from a
+/// server's map by a world scanner.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BlockUsage {
+    counts: HashMap<Box<str>, u64>,
+}
+
+/// Read from the CSV file named by `--block-usage`, if any. When it's set, only the block
+/// textures of blocks that appear at least `--min-block-count` times (default 1) are built, and
+/// the most common blocks are built first, so that a server with a custom map gets a smaller pack.
+pub static BLOCK_USAGE: Lazy<Option<BlockUsage>> = Lazy::new(|| {
+    option_value("block-usage").map(|path| {
+        read_to_string(&path)
+            .map_err(CloneableError::from)
+            .and_then(|text| text.parse())
+            .unwrap_or_else(|e| panic!("Invalid value for --block-usage: {:?}", e))
+    })
+});
+
+static MIN_BLOCK_COUNT: Lazy<u64> = Lazy::new(|| parsed_option("min-block-count").unwrap_or(1));
+
+impl FromStr for BlockUsage {
+    type Err = CloneableError;
+
+    /// Each line is a block ID and a count, such as `minecraft:stone,1532`. The namespace is
+    /// optional, and a block listed more than once has its counts added. A header line, blank lines
+    /// and lines starting with `#` are ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut usage = BlockUsage::default();
+        for (index, line) in s.lines().map(str::trim).enumerate() {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (block, count) = line
+                .split_once(',')
+                .ok_or_else(|| anyhoo!("Missing block count: {}", line))?;
+            let count: u64 = match count.trim().parse() {
+                Ok(count) => count,
+                Err(_) if index == 0 => continue,
+                Err(_) => return Err(anyhoo!("Invalid block count: {}", line)),
+            };
+            let block = block.trim();
+            let block = block.strip_prefix("minecraft:").unwrap_or(block);
+            *usage.counts.entry(block.into()).or_default() += count;
+        }
+        Ok(usage)
+    }
+}
+
+/// Textures that blocks use under names that neither extends the other, so that
+/// [BlockUsage::texture_count] can't match them by name. A texture here also stands for those
+/// whose names extend it, such as `redstone_dust` for `redstone_dust_dot`.
+const BLOCK_TEXTURES: &[(&str, &[&str])] = &[
+    ("grass_block", &["dirt"]),
+    ("podzol", &["dirt"]),
+    ("mycelium", &["dirt"]),
+    ("dirt_path", &["dirt"]),
+    ("farmland", &["dirt"]),
+    ("redstone_wire", &["redstone_dust"]),
+    (
+        "sticky_piston",
+        &[
+            "piston_top_sticky",
+            "piston_side",
+            "piston_bottom",
+            "piston_inner",
+        ],
+    ),
+    (
+        "piston_head",
+        &["piston_top", "piston_top_sticky", "piston_side"],
+    ),
+    (
+        "moving_piston",
+        &["piston_top", "piston_top_sticky", "piston_side"],
+    ),
+];
+
+/// Shapes of a full block that use its textures, such as `oak_stairs` using `block/oak_planks`
+/// and `stone_brick_wall` using `block/stone_bricks`.
+const SHAPE_SUFFIXES: &[&str] = &[
+    "_stairs",
+    "_slab",
+    "_wall",
+    "_fence",
+    "_fence_gate",
+    "_pressure_plate",
+    "_button",
+];
+
+/// Whether `longer` is `shorter` or starts with `shorter` followed by `_`.
+fn extends(longer: &str, shorter: &str) -> bool {
+    longer
+        .strip_prefix(shorter)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('_'))
+}
+
+/// Whether `block` may use the block texture `texture` (with no `block/` prefix).
+fn block_uses(block: &str, texture: &str) -> bool {
+    if extends(texture, block) || extends(block, texture) {
+        return true;
+    }
+    let mut listed = BLOCK_TEXTURES
+        .iter()
+        .filter(|(listed_block, _)| *listed_block == block)
+        .flat_map(|(_, textures)| textures.iter());
+    if listed.any(|used| extends(texture, used)) {
+        return true;
+    }
+    SHAPE_SUFFIXES
+        .iter()
+        .filter_map(|suffix| block.strip_suffix(suffix))
+        .any(|full_block| {
+            texture
+                .strip_prefix(full_block)
+                .is_some_and(|rest| rest == "_planks" || rest == "s")
+        })
+}
+
+impl BlockUsage {
+    /// How often the texture `name` is seen, as the count of the most common block that may use
+    /// it, or `None` if it's not a block texture and so can't be judged from a world. A block is
+    /// taken to use the textures named after it, such as `oak_log` using `block/oak_log_top`, the
+    /// ones its own name is an extension of, such as `stone_stairs` using `block/stone`, and those
+    /// listed in [BLOCK_TEXTURES] or implied by [SHAPE_SUFFIXES].
+    pub fn texture_count(&self, name: &str) -> Option<u64> {
+        let texture = name.strip_prefix("minecraft:").unwrap_or(name);
+        let texture = texture.strip_prefix("block/")?;
+        Some(
+            self.counts
+                .iter()
+                .filter(|(block, _)| block_uses(block, texture))
+                .map(|(_, count)| *count)
+                .max()
+                .unwrap_or(0),
+        )
+    }
+
+    /// How often the textures `task` writes are seen; `None` if none of them is a block texture.
+    fn task_count(&self, task: &FileOutputTaskSpec) -> Option<u64> {
+        let names = match task {
+            FileOutputTaskSpec::PngOutput {
+                destination_name, ..
+            } => std::slice::from_ref(destination_name),
+            FileOutputTaskSpec::Copy { link_names, .. } => &**link_names,
+        };
+        names
+            .iter()
+            .filter_map(|name| self.texture_count(name))
+            .max()
+    }
+
+    /// Removes the tasks that only write textures of blocks seen fewer than `min_count` times, and
+    /// puts the rest in order from most to least often seen. Textures that aren't of blocks, such
+    /// as items and GUI, are kept and go first.
+    pub fn trim(&self, tasks: Vec<FileOutputTaskSpec>, min_count: u64) -> Vec<FileOutputTaskSpec> {
+        let total = tasks.len();
+        let mut kept: Vec<(Option<u64>, FileOutputTaskSpec)> = tasks
+            .into_iter()
+            .map(|task| (self.task_count(&task), task))
+            .filter(|(count, _)| count.is_none_or(|count| count >= min_count))
+            .collect();
+        kept.sort_by_key(|(count, _)| std::cmp::Reverse(count.unwrap_or(u64::MAX)));
+        info!(
+            "Building {} of {} textures for the blocks in --block-usage",
+            kept.len(),
+            total
+        );
+        kept.into_iter().map(|(_, task)| task).collect()
+    }
+}
+
+/// Applies [BLOCK_USAGE], if it's set, to the output tasks.
+pub fn trim_to_block_usage(tasks: Vec<FileOutputTaskSpec>) -> Vec<FileOutputTaskSpec> {
+    match &*BLOCK_USAGE {
+        Some(usage) => usage.trim(tasks, *MIN_BLOCK_COUNT),
+        None => tasks,
+    }
+}
+
+#[test]
+fn test_block_usage() {
+    use crate::image_tasks::color::ComparableColor;
+    use crate::image_tasks::task_spec::{out_task, paint_svg_task};
+
+    let usage: BlockUsage = "block,count\nminecraft:stone_stairs,5\noak_log,20\n# x\nstone,1\n"
+        .parse()
+        .unwrap();
+    assert_eq!(usage.texture_count("block/stone"), Some(5));
+    assert_eq!(usage.texture_count("block/oak_log_top"), Some(20));
+    assert_eq!(usage.texture_count("block/stone_bricks"), Some(1));
+    assert_eq!(usage.texture_count("block/dirt"), Some(0));
+    assert_eq!(usage.texture_count("block/oak_logs"), Some(0));
+    assert_eq!(usage.texture_count("item/stick"), None);
+
+    // Blocks that use textures named after something else
+    let usage: BlockUsage =
+        "oak_stairs,3\ngrass_block,4\nredstone_wire,5\nsticky_piston,6\nstone_brick_wall,7\n"
+            .parse()
+            .unwrap();
+    assert_eq!(usage.texture_count("block/oak_planks"), Some(3));
+    assert_eq!(usage.texture_count("block/dirt"), Some(4));
+    assert_eq!(usage.texture_count("block/redstone_dust_line0"), Some(5));
+    assert_eq!(usage.texture_count("block/piston_top_sticky"), Some(6));
+    assert_eq!(usage.texture_count("block/piston_top"), Some(0));
+    assert_eq!(usage.texture_count("block/stone_bricks"), Some(7));
+    assert_eq!(usage.texture_count("block/spruce_planks"), Some(0));
+    assert!("stone,1\nstone,many".parse::<BlockUsage>().is_err());
+
+    let task =
+        |name: &'static str| out_task(name, paint_svg_task("borderSolid", ComparableColor::BLACK));
+    let trimmed = usage.trim(
+        vec![
+            task("block/stone"),
+            task("block/dirt"),
+            task("item/stick"),
+            task("block/oak_log"),
+        ],
+        1,
+    );
+    let names: Vec<Box<str>> = trimmed.iter().map(|task| task.get_path()).collect();
+    assert_eq!(
+        names,
+        vec![
+            "assets/minecraft/textures/item/stick.png".into(),
+            "assets/minecraft/textures/block/oak_log.png".into(),
+            "assets/minecraft/textures/block/stone.png".into(),
+        ] as Vec<Box<str>>
+    );
+}
@@ -0,0 +1,391 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde_json::{json, Map, Value};
+use zip::ZipArchive;
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::task_spec::legacy_name_alias;
+use crate::texture_base::material::MaterialGroup;
+
+/// Number of textures listed by name in the summary.
+const LARGEST_ENTRY_COUNT: usize = 10;
+
+/// Size of one file in the output.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EntrySize {
+    pub path: Box<str>,
+    /// Bytes the file takes up in the ZIP file, or on disk for loose files.
+    pub stored: u64,
+    pub uncompressed: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+struct Totals {
+    entries: usize,
+    stored: u64,
+    uncompressed: u64,
+}
+
+impl Totals {
+    fn add(&mut self, entry: &EntrySize) {
+        self.entries += 1;
+        self.stored += entry.stored;
+        self.uncompressed += entry.uncompressed;
+    }
+
+    fn compression_ratio(&self) -> f64 {
+        if self.uncompressed == 0 {
+            1.0
+        } else {
+            self.stored as f64 / self.uncompressed as f64
+        }
+    }
+}
+
+/// Sizes of everything that a build wrote, overall and by material group.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BuildStats {
+    totals: Totals,
+    groups: BTreeMap<&'static str, Totals>,
+    largest: Vec<EntrySize>,
+}
+
+impl BuildStats {
+    /// Files that aren't in any of the given groups are counted as `metadata` if they're at the top
+    /// level, and as `other` otherwise.
+    pub fn new(
+        mut entries: Vec<EntrySize>,
+        groups: &[(&'static str, &MaterialGroup)],
+    ) -> BuildStats {
+        let mut group_for_path: HashMap<Box<str>, &'static str> = HashMap::new();
+        for (group_name, group) in groups {
+            for task in group.tasks.iter() {
+                for path in task.get_paths().into_iter().chain(
+                    legacy_name_alias(task)
+                        .iter()
+                        .flat_map(|alias| alias.get_paths()),
+                ) {
+                    group_for_path.insert(path, group_name);
+                }
+            }
+        }
+        let mut stats = BuildStats::default();
+        for entry in entries.iter() {
+            let group_name =
+                group_for_path
+                    .get(&entry.path)
+                    .copied()
+                    .unwrap_or(if entry.path.contains('/') {
+                        "other"
+                    } else {
+                        "metadata"
+                    });
+            stats.totals.add(entry);
+            stats.groups.entry(group_name).or_default().add(entry);
+        }
+        entries.sort_by(|first, second| {
+            second
+                .stored
+                .cmp(&first.stored)
+                .then(first.path.cmp(&second.path))
+        });
+        entries.truncate(LARGEST_ENTRY_COUNT);
+        stats.largest = entries;
+        stats
+    }
+
+    pub fn entry_sizes_in_zip(zip_contents: &[u8]) -> Result<Vec<EntrySize>, CloneableError> {
+        let mut archive = ZipArchive::new(Cursor::new(zip_contents))?;
+        let mut entries = Vec::with_capacity(archive.len());
+        for index in 0..archive.len() {
+            let entry = archive.by_index_raw(index)?;
+            entries.push(EntrySize {
+                path: entry.name().into(),
+                stored: entry.compressed_size(),
+                uncompressed: entry.size(),
+            });
+        }
+        Ok(entries)
+    }
+
+    pub fn entry_sizes_in_dir(root: &Path) -> Result<Vec<EntrySize>, CloneableError> {
+        fn visit(
+            root: &Path,
+            dir: &Path,
+            entries: &mut Vec<EntrySize>,
+        ) -> Result<(), CloneableError> {
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let metadata = entry.metadata()?;
+                if metadata.is_dir() {
+                    visit(root, &entry.path(), entries)?;
+                } else {
+                    entries.push(EntrySize {
+                        path: entry
+                            .path()
+                            .strip_prefix(root)?
+                            .to_string_lossy()
+                            .replace('\\', "/")
+                            .into(),
+                        stored: metadata.len(),
+                        uncompressed: metadata.len(),
+                    });
+                }
+            }
+            Ok(())
+        }
+        let mut entries = Vec::new();
+        visit(root, root, &mut entries)?;
+        Ok(entries)
+    }
+
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "{} entries, {} bytes ({} uncompressed, ratio {:.3})",
+            self.totals.entries,
+            self.totals.stored,
+            self.totals.uncompressed,
+            self.totals.compression_ratio()
+        )
+        .unwrap();
+        out.push_str("By group:\n");
+        for (group_name, totals) in self.groups.iter() {
+            writeln!(
+                out,
+                "  {:24} {:6} entries {:12} bytes (ratio {:.3})",
+                group_name,
+                totals.entries,
+                totals.stored,
+                totals.compression_ratio()
+            )
+            .unwrap();
+        }
+        out.push_str("Largest:\n");
+        for entry in self.largest.iter() {
+            writeln!(out, "  {:12} bytes  {}", entry.stored, entry.path).unwrap();
+        }
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        fn totals_json(totals: &Totals) -> Value {
+            json!({
+                "entries": totals.entries,
+                "bytes": totals.stored,
+                "uncompressed_bytes": totals.uncompressed,
+                "compression_ratio": (totals.compression_ratio() * 1e4).round() / 1e4,
+            })
+        }
+        let mut stats = totals_json(&self.totals);
+        stats["groups"] = self
+            .groups
+            .iter()
+            .map(|(group_name, totals)| (group_name.to_string(), totals_json(totals)))
+            .collect::<Map<String, Value>>()
+            .into();
+        stats["largest"] = self
+            .largest
+            .iter()
+            .map(|entry| {
+                json!({
+                    "path": entry.path,
+                    "bytes": entry.stored,
+                    "uncompressed_bytes": entry.uncompressed,
+                })
+            })
+            .collect();
+        serde_json::to_string_pretty(&stats).unwrap() + "\n"
+    }
+}
+
+/// The stored size of every file in a build, so that a later build can be compared with it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SizeManifest {
+    sizes: BTreeMap<Box<str>, u64>,
+}
+
+/// A file that's more than the allowed fraction larger than in the baseline.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SizeRegression {
+    pub path: Box<str>,
+    pub baseline: u64,
+    pub current: u64,
+}
+
+impl SizeManifest {
+    pub fn new(entries: &[EntrySize]) -> SizeManifest {
+        SizeManifest {
+            sizes: entries
+                .iter()
+                .map(|entry| (entry.path.clone(), entry.stored))
+                .collect(),
+        }
+    }
+
+    /// One entry per line, so that manifests checked into a repository diff cleanly.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(&json!({ "entries": self.sizes })).unwrap() + "\n"
+    }
+
+    /// Returns the files that grew by more than `max_growth` (a fraction of their baseline size),
+    /// largest growth first. Files that are new or were removed aren't regressions.
+    pub fn regressions_from(
+        &self,
+        baseline: &SizeManifest,
+        max_growth: f64,
+    ) -> Vec<SizeRegression> {
+        let mut regressions: Vec<SizeRegression> = self
+            .sizes
+            .iter()
+            .filter_map(|(path, current)| {
+                let baseline = *baseline.sizes.get(path)?;
+                (*current as f64 > baseline as f64 * (1.0 + max_growth)).then(|| SizeRegression {
+                    path: path.clone(),
+                    baseline,
+                    current: *current,
+                })
+            })
+            .collect();
+        regressions
+            .sort_by_key(|regression| regression.baseline as i64 - regression.current as i64);
+        regressions
+    }
+}
+
+impl FromStr for SizeManifest {
+    type Err = CloneableError;
+
+    /// Reads any JSON object whose `entries` maps each path to its size, such as one written by
+    /// [SizeManifest::to_json], however it's been reformatted since.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut manifest: Value = serde_json::from_str(s)?;
+        let entries = manifest
+            .get_mut("entries")
+            .ok_or_else(|| anyhoo!("Manifest has no \"entries\""))?
+            .take();
+        Ok(SizeManifest {
+            sizes: serde_json::from_value(entries)?,
+        })
+    }
+}
+
+#[test]
+fn test_build_stats() {
+    let entry = |path: &str, stored, uncompressed| EntrySize {
+        path: path.into(),
+        stored,
+        uncompressed,
+    };
+    let stats = BuildStats::new(
+        vec![
+            entry("pack.mcmeta", 50, 100),
+            entry("assets/minecraft/textures/block/stone.png", 300, 300),
+            entry("assets/minecraft/textures/block/dirt.png", 300, 600),
+        ],
+        &[],
+    );
+    assert_eq!(
+        serde_json::from_str::<Value>(&stats.to_json()).unwrap(),
+        json!({
+            "entries": 3,
+            "bytes": 650,
+            "uncompressed_bytes": 1000,
+            "compression_ratio": 0.65,
+            "groups": {
+                "metadata": {
+                    "entries": 1,
+                    "bytes": 50,
+                    "uncompressed_bytes": 100,
+                    "compression_ratio": 0.5,
+                },
+                "other": {
+                    "entries": 2,
+                    "bytes": 600,
+                    "uncompressed_bytes": 900,
+                    "compression_ratio": 0.6667,
+                },
+            },
+            "largest": [
+                {
+                    "path": "assets/minecraft/textures/block/dirt.png",
+                    "bytes": 300,
+                    "uncompressed_bytes": 600,
+                },
+                {
+                    "path": "assets/minecraft/textures/block/stone.png",
+                    "bytes": 300,
+                    "uncompressed_bytes": 300,
+                },
+                {"path": "pack.mcmeta", "bytes": 50, "uncompressed_bytes": 100},
+            ],
+        })
+    );
+    assert!(stats
+        .summary()
+        .starts_with("3 entries, 650 bytes (1000 uncompressed, ratio 0.650)\n"));
+}
+
+#[test]
+fn test_size_manifest() {
+    let entry = |path: &str, stored| EntrySize {
+        path: path.into(),
+        stored,
+        uncompressed: stored,
+    };
+    let baseline = SizeManifest::new(&[
+        entry("a.png", 100),
+        entry("b.png", 100),
+        entry("c.png", 100),
+        entry("removed.png", 100),
+    ]);
+    let parsed: SizeManifest = baseline.to_json().parse().unwrap();
+    assert_eq!(parsed, baseline);
+    assert_eq!(
+        baseline.to_json().lines().count(),
+        4 + baseline.sizes.len(),
+        "Expected one entry per line"
+    );
+    let quoted = SizeManifest::new(&[entry("odd \"name\"\\.png", 12)]);
+    assert_eq!(quoted.to_json().parse::<SizeManifest>().unwrap(), quoted);
+    // Reformatted, such as by `jq -c`
+    assert_eq!(
+        "{\"entries\":{\"odd \\\"name\\\"\\\\.png\":12}}"
+            .parse::<SizeManifest>()
+            .unwrap(),
+        quoted
+    );
+    assert!("{\"entries\": {\"a.png\": \"big\"}}"
+        .parse::<SizeManifest>()
+        .is_err());
+    assert!("{\"entries\": []}".parse::<SizeManifest>().is_err());
+    assert!("{\"sizes\": {}}".parse::<SizeManifest>().is_err());
+    let current = SizeManifest::new(&[
+        entry("a.png", 105),
+        entry("b.png", 150),
+        entry("c.png", 120),
+        entry("new.png", 1000),
+    ]);
+    assert_eq!(
+        current.regressions_from(&baseline, 0.1),
+        [
+            SizeRegression {
+                path: "b.png".into(),
+                baseline: 100,
+                current: 150
+            },
+            SizeRegression {
+                path: "c.png".into(),
+                baseline: 100,
+                current: 120
+            },
+        ]
+    );
+}
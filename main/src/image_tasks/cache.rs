@@ -0,0 +1,230 @@
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use siphasher::sip::SipHasher13;
+
+use crate::image_tasks::compression::{png_dimensions, COMPRESSION_POLICIES};
+use crate::image_tasks::from_svg::svg_source;
+use crate::image_tasks::png_budget::skip_png;
+use crate::image_tasks::post_process::POST_PROCESSING;
+use crate::image_tasks::search::Ingredients;
+use crate::image_tasks::task_spec::{
+    PackId, TaskGraphBuildingContext, ToPixmapTaskSpec, RASTER_DIR,
+};
+use crate::image_tasks::verify::expect_png;
+use crate::{option_value, GRID_SIZE};
+
+/// The folder named by `--cache-dir`, if any. When it's set, every PNG the build encodes is also
+/// saved there under a hash of everything it was made from, and a later build that would make the
+/// same image reads it from there instead. Delete the folder after changing how images are
+/// rendered or encoded, since only the task graph, its SVG and raster inputs, the options in
+/// [KEYED_OPTIONS], the [POST_PROCESSING] rules and the [COMPRESSION_POLICIES] are hashed.
+pub static CACHE_DIR: Lazy<Option<PathBuf>> =
+    Lazy::new(|| option_value("cache-dir").map(PathBuf::from));
+
+/// Reports that are made while an image is encoded, so they'd be missing the PNGs that came from
+/// the cache.
+const PER_IMAGE_REPORTS: &[&str] = &[
+    "seam-report",
+    "vanilla-parity-report",
+    "color-error-report",
+    "debug-bundle",
+    "alpha-debug-dir",
+];
+
+/// The first of the [PER_IMAGE_REPORTS] that was requested, if any. While one is, cached PNGs are
+/// still written but not read, so that every image is encoded and appears in the report.
+static REPORT_NEEDING_ENCODING: Lazy<Option<&'static str>> = Lazy::new(|| {
+    let report = PER_IMAGE_REPORTS
+        .iter()
+        .copied()
+        .find(|report| option_value(report).is_some());
+    if let Some(report) = report
+        && CACHE_DIR.is_some()
+    {
+        warn!(
+            "Not reading PNGs from the cache, because --{} needs every image to be encoded",
+            report
+        );
+    }
+    report
+});
+
+/// Options that change how an image is encoded without changing its task graph.
+const KEYED_OPTIONS: &[&str] = &[
+    "master-palette",
+    "oxipng-preset",
+    "png-time-budget",
+    "vanilla-jar",
+];
+
+/// Hash of everything that affects an encoded PNG. Two hashes are taken for the same reason as in
+/// [crate::image_tasks::png_output]'s in-memory cache. They use SipHash-1-3 with fixed keys rather
+/// than [std::collections::hash_map::DefaultHasher], whose algorithm may change between Rust
+/// releases and would then make every cached PNG unreachable.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct CacheKey([u64; 2]);
+
+impl CacheKey {
+    fn file_name(&self) -> String {
+        format!("{:016x}{:016x}.png", self.0[0], self.0[1])
+    }
+}
+
+static HITS: AtomicUsize = AtomicUsize::new(0);
+static MISSES: AtomicUsize = AtomicUsize::new(0);
+
+/// Hashes `spec` and, since its name alone doesn't say what's in it, the contents of every SVG and
+/// raster it's made from; textures it refers to with `texture_of` are hashed the same way.
+fn hash_image_inputs(
+    spec: &ToPixmapTaskSpec,
+    ctx: &TaskGraphBuildingContext,
+    hasher: &mut SipHasher13,
+) {
+    format!("{:?}", spec).hash(hasher);
+    for layer in Ingredients::of_pixmap(spec).layers {
+        if let Some(svg) = svg_source(&format!("{}.svg", layer)) {
+            svg.hash(hasher);
+        } else if let Some(raster) = RASTER_DIR.get_file(format!("{}.png", layer)) {
+            raster.contents().hash(hasher);
+        } else if ctx.has_texture(&layer) {
+            hash_image_inputs(&ctx.resolve_texture(&layer), ctx, hasher);
+        } else if let Ok(raster) = fs::read(&layer) {
+            raster.hash(hasher);
+        }
+    }
+}
+
+/// The key that the PNG of `base`, rendered for `tile_size`, is cached under.
+pub fn cache_key(
+    base: &ToPixmapTaskSpec,
+    require_gray: bool,
+    tile_size: u32,
+    ctx: &TaskGraphBuildingContext,
+) -> CacheKey {
+    CacheKey([0u64, 1u64].map(|salt| {
+        let mut hasher = SipHasher13::new_with_keys(0, salt);
+        (
+            env!("CARGO_PKG_VERSION"),
+            tile_size,
+            *GRID_SIZE,
+            require_gray,
+        )
+            .hash(&mut hasher);
+        for option in KEYED_OPTIONS {
+            option_value(option).hash(&mut hasher);
+        }
+        POST_PROCESSING.hash(&mut hasher);
+        COMPRESSION_POLICIES.hash(&mut hasher);
+        hash_image_inputs(base, ctx, &mut hasher);
+        hasher.finish()
+    }))
+}
+
+fn read_cached_png(dir: &Path, key: CacheKey) -> Option<Vec<u8>> {
+    fs::read(dir.join(key.file_name())).ok()
+}
+
+/// Returns the cached PNG for `key` if there is one, it's readable and, unless `expected_width` is
+/// None, it's that wide, and none of the [PER_IMAGE_REPORTS] was requested. Otherwise, the caller
+/// should encode the PNG and pass it to [record_cached_png].
+pub fn cached_png(
+    pack: PackId,
+    file_path: &str,
+    key: CacheKey,
+    expected_width: Option<u32>,
+) -> Option<Vec<u8>> {
+    let dir = CACHE_DIR.as_ref()?;
+    if REPORT_NEEDING_ENCODING.is_none()
+        && let Some(png) = read_cached_png(dir, key)
+        && let Some((width, height)) = png_dimensions(&png)
+    {
+        if expected_width.is_none_or(|expected_width| width == expected_width) {
+            info!("Using the cached PNG for {}", file_path);
+            HITS.fetch_add(1, Ordering::Relaxed);
+            skip_png();
+            expect_png(pack, file_path, &png, width, height);
+            return Some(png);
+        }
+        warn!(
+            "Cached PNG for {} is {}x{}, but should be {} pixels wide; encoding it again",
+            file_path,
+            width,
+            height,
+            expected_width.unwrap()
+        );
+    }
+    MISSES.fetch_add(1, Ordering::Relaxed);
+    None
+}
+
+fn write_cached_png(dir: &Path, key: CacheKey, png: &[u8]) -> Result<(), std::io::Error> {
+    fs::create_dir_all(dir)?;
+    // Written under another name first, so that a build that's interrupted doesn't leave a
+    // truncated PNG that a later build would use
+    let temp_path = dir.join(format!("{}.tmp", key.file_name()));
+    fs::write(&temp_path, png)?;
+    fs::rename(temp_path, dir.join(key.file_name()))
+}
+
+/// Saves the PNG encoded for `file_path` to the cache under `key`, after [cached_png] didn't find
+/// it there. Failures are only logged, since the PNG will just be encoded again next time.
+pub fn record_cached_png(file_path: &str, key: CacheKey, png: &[u8]) {
+    let Some(dir) = CACHE_DIR.as_ref() else {
+        return;
+    };
+    if let Err(error) = write_cached_png(dir, key, png) {
+        warn!("Failed to cache {}: {}", file_path, error);
+    }
+}
+
+/// Logs how many PNGs came from the cache.
+pub fn finish_cache_report() {
+    if CACHE_DIR.is_some() {
+        info!(
+            "{} PNGs came from the cache, and {} were encoded",
+            HITS.load(Ordering::Relaxed),
+            MISSES.load(Ordering::Relaxed)
+        );
+    }
+}
+
+#[test]
+fn test_cache_key() {
+    use crate::image_tasks::color::ComparableColor;
+    use crate::image_tasks::task_spec::{from_svg_task, out_task, paint_svg_task, texture_of};
+
+    let ctx = TaskGraphBuildingContext::new();
+    let border = from_svg_task("borderSolid");
+    let key = cache_key(&border, false, 32, &ctx);
+    assert_eq!(key, cache_key(&border, false, 32, &ctx));
+    assert_ne!(key, cache_key(&border, true, 32, &ctx));
+    assert_ne!(key, cache_key(&border, false, 64, &ctx));
+    assert_ne!(
+        key,
+        cache_key(&from_svg_task("borderDotted"), false, 32, &ctx)
+    );
+
+    let mut ctx = TaskGraphBuildingContext::new();
+    ctx.add_texture_names(&[out_task("block/smooth_stone", border.clone())]);
+    let texture_key = cache_key(&texture_of("block/smooth_stone"), false, 32, &ctx);
+    let mut repainted = TaskGraphBuildingContext::new();
+    repainted.add_texture_names(&[out_task(
+        "block/smooth_stone",
+        paint_svg_task("borderSolid", ComparableColor::RED),
+    )]);
+    assert_ne!(
+        texture_key,
+        cache_key(&texture_of("block/smooth_stone"), false, 32, &repainted)
+    );
+
+    let dir = std::env::temp_dir().join(format!("ochd-test-cache-{}", std::process::id()));
+    assert_eq!(read_cached_png(&dir, key), None);
+    write_cached_png(&dir, key, b"png").unwrap();
+    assert_eq!(read_cached_png(&dir, key).as_deref(), Some(&b"png"[..]));
+    fs::remove_dir_all(&dir).unwrap();
+}
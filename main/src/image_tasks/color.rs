@@ -1,11 +1,12 @@
-use core::mem::transmute;
-use oxipng::{BitDepth, RGBA8};
+use oxipng::{BitDepth, RGB16, RGBA8};
 use palette::blend::Compose;
-use palette::Srgba;
+use palette::{FromColor, Mix, Oklaba, Srgba};
 use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::fmt::{Debug, Display, Formatter};
 use std::hash::{Hash, Hasher};
 use std::ops::Mul;
+use std::str::FromStr;
 
 use bytemuck::{cast, Pod, Zeroable};
 use resvg::tiny_skia::Color;
@@ -13,6 +14,9 @@ use resvg::tiny_skia::ColorU8;
 use resvg::tiny_skia::PremultipliedColor;
 use resvg::tiny_skia::PremultipliedColorU8;
 
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+
 /// Wrapper around [ColorU8] that implements important missing traits such as [Eq], [Hash], [Copy],
 /// [Clone] and [Ord]. Represents a 24-bit sRGB color + 8-bit alpha value (not premultiplied).
 #[derive(Eq, Copy, Clone, Pod)]
@@ -140,8 +144,19 @@ impl ComparableColor {
     /// [ComparableColor::WHITE] instead.
     pub const LIGHTEST_GRAY: ComparableColor = gray(0xdd);
 
+    /// Preferred color for transparent pixels in RGB mode; see [transparency_sentinel].
     pub const RESERVED_FOR_TRANSPARENCY: ComparableColor = c(0xc0ff3e);
 
+    /// Converts to the 16-bit form that [oxipng::ColorType::RGB] uses for its transparent color,
+    /// ignoring alpha.
+    pub const fn to_rgb16(&self) -> RGB16 {
+        RGB16 {
+            r: self.red as u16 * 0x101,
+            g: self.green as u16 * 0x101,
+            b: self.blue as u16 * 0x101,
+        }
+    }
+
     pub const fn is_gray(&self) -> bool {
         self.alpha == 0 || (self.green == self.red && self.blue == self.red)
     }
@@ -171,6 +186,301 @@ impl ComparableColor {
                 .max(BIT_DEPTH_FOR_CHANNEL[self.alpha as usize])
         }
     }
+
+    /// Creates an opaque color from a hue in degrees and a saturation and lightness from 0.0 to
+    /// 1.0.
+    pub const fn from_hsl(hue: f32, saturation: f32, lightness: f32) -> ComparableColor {
+        let saturation = clamp_unit(saturation);
+        let lightness = clamp_unit(lightness);
+        let chroma = (1.0 - abs(2.0 * lightness - 1.0)) * saturation;
+        let sector = ((hue % 360.0 + 360.0) % 360.0) / 60.0;
+        let second = chroma * (1.0 - abs(sector % 2.0 - 1.0));
+        let (red, green, blue) = match sector as u8 {
+            0 => (chroma, second, 0.0),
+            1 => (second, chroma, 0.0),
+            2 => (0.0, chroma, second),
+            3 => (0.0, second, chroma),
+            4 => (second, 0.0, chroma),
+            _ => (chroma, 0.0, second),
+        };
+        let offset = lightness - chroma / 2.0;
+        rgb(
+            unit_to_channel(red + offset),
+            unit_to_channel(green + offset),
+            unit_to_channel(blue + offset),
+        )
+    }
+
+    /// Creates an opaque color from a hue in degrees and an HSV saturation and value from 0.0 to
+    /// 1.0.
+    pub const fn from_hsv(hue: f32, saturation: f32, value: f32) -> ComparableColor {
+        let saturation = clamp_unit(saturation);
+        let value = clamp_unit(value);
+        let lightness = value * (1.0 - saturation / 2.0);
+        let hsl_saturation = if lightness == 0.0 || lightness == 1.0 {
+            0.0
+        } else {
+            (value - lightness) / min(lightness, 1.0 - lightness)
+        };
+        ComparableColor::from_hsl(hue, hsl_saturation, lightness)
+    }
+
+    /// Returns the hue in degrees and the saturation and lightness from 0.0 to 1.0. Grays have a
+    /// hue of 0.
+    pub const fn to_hsl(&self) -> (f32, f32, f32) {
+        let red = channel_to_unit(self.red);
+        let green = channel_to_unit(self.green);
+        let blue = channel_to_unit(self.blue);
+        let max = max(red, max(green, blue));
+        let min = min(red, min(green, blue));
+        let chroma = max - min;
+        let lightness = (max + min) / 2.0;
+        if chroma == 0.0 {
+            return (0.0, 0.0, lightness);
+        }
+        let saturation = chroma / (1.0 - abs(2.0 * lightness - 1.0));
+        let sector = if max == red {
+            ((green - blue) / chroma + 6.0) % 6.0
+        } else if max == green {
+            (blue - red) / chroma + 2.0
+        } else {
+            (red - green) / chroma + 4.0
+        };
+        (sector * 60.0, clamp_unit(saturation), lightness)
+    }
+
+    /// Moves the lightness the given fraction of the way towards white, keeping hue, saturation
+    /// and alpha.
+    pub const fn lighten(&self, amount: f32) -> ComparableColor {
+        let (hue, saturation, lightness) = self.to_hsl();
+        self.with_hsl(hue, saturation, lightness + (1.0 - lightness) * amount)
+    }
+
+    /// Moves the lightness the given fraction of the way towards black, keeping hue, saturation
+    /// and alpha.
+    pub const fn darken(&self, amount: f32) -> ComparableColor {
+        let (hue, saturation, lightness) = self.to_hsl();
+        self.with_hsl(hue, saturation, lightness * (1.0 - amount))
+    }
+
+    /// Moves the saturation the given fraction of the way towards fully saturated; a negative
+    /// amount desaturates towards gray instead.
+    pub const fn saturate(&self, amount: f32) -> ComparableColor {
+        let (hue, saturation, lightness) = self.to_hsl();
+        let saturation = if amount >= 0.0 {
+            saturation + (1.0 - saturation) * amount
+        } else {
+            saturation * (1.0 + amount)
+        };
+        self.with_hsl(hue, saturation, lightness)
+    }
+
+    /// Rotates the hue by the given number of degrees, keeping saturation, lightness and alpha.
+    pub const fn shift_hue(&self, degrees: f32) -> ComparableColor {
+        let (hue, saturation, lightness) = self.to_hsl();
+        self.with_hsl(hue + degrees, saturation, lightness)
+    }
+
+    /// Interpolates every channel, including alpha, linearly in sRGB: a `t` of 0.0 gives `self` and
+    /// 1.0 gives `other`. See [oklab_ramp] for perceptually even steps.
+    pub const fn lerp(&self, other: &ComparableColor, t: f32) -> ComparableColor {
+        const fn lerp_channel(from: u8, to: u8, t: f32) -> u8 {
+            unit_to_channel(
+                channel_to_unit(from) + (channel_to_unit(to) - channel_to_unit(from)) * t,
+            )
+        }
+        let t = clamp_unit(t);
+        rgba(
+            lerp_channel(self.red, other.red, t),
+            lerp_channel(self.green, other.green, t),
+            lerp_channel(self.blue, other.blue, t),
+            lerp_channel(self.alpha, other.alpha, t),
+        )
+    }
+
+    /// Adds `amount` to the OKLab lightness, which runs from 0.0 for black to 1.0 for white, keeping
+    /// alpha. Unlike [ComparableColor::lighten], equal amounts look like equal steps whatever the
+    /// hue.
+    pub fn shift_oklab_lightness(&self, amount: f32) -> ComparableColor {
+        let mut oklab = Oklaba::from_color(self.as_f32_srgba());
+        oklab.l = (oklab.l + amount).clamp(0.0, 1.0);
+        from_oklab(oklab)
+    }
+
+    /// Moves the OKLab lightness away from (or, if `factor` is less than 1.0, toward) the middle
+    /// gray, keeping hue and alpha.
+    pub fn stretch_oklab_lightness(&self, factor: f32) -> ComparableColor {
+        let mut oklab = Oklaba::from_color(self.as_f32_srgba());
+        oklab.l = (0.5 + (oklab.l - 0.5) * factor).clamp(0.0, 1.0);
+        from_oklab(oklab)
+    }
+
+    const fn with_hsl(&self, hue: f32, saturation: f32, lightness: f32) -> ComparableColor {
+        let mut color = ComparableColor::from_hsl(hue, saturation, lightness);
+        color.alpha = self.alpha;
+        color
+    }
+}
+
+/// Returns an opaque color whose RGB value no visible color in `colors` has, so that RGB mode can
+/// use it to mark transparent pixels: [ComparableColor::RESERVED_FOR_TRANSPARENCY] if possible,
+/// and otherwise the next RGB value after it that's free.
+pub fn transparency_sentinel<T: IntoIterator<Item = ComparableColor>>(
+    colors: T,
+) -> ComparableColor {
+    let reserved = ComparableColor::RESERVED_FOR_TRANSPARENCY;
+    let reserved = u32::from_be_bytes([0, reserved.red, reserved.green, reserved.blue]);
+    let used: HashSet<u32> = colors
+        .into_iter()
+        .filter(|color| color.alpha != 0)
+        .map(|color| u32::from_be_bytes([0, color.red, color.green, color.blue]))
+        .collect();
+    let free = (reserved..=0xffffff)
+        .chain(0..reserved)
+        .find(|candidate| !used.contains(candidate))
+        .expect("Every RGB value is in use");
+    c(free)
+}
+
+/// Returns `steps` colors evenly spaced in OKLab from `start` to `end`, including both ends, for
+/// gradients and palettes that should look evenly spaced.
+pub fn oklab_ramp(
+    start: ComparableColor,
+    end: ComparableColor,
+    steps: usize,
+) -> Vec<ComparableColor> {
+    let start_oklab = Oklaba::from_color(start.as_f32_srgba());
+    let end_oklab = Oklaba::from_color(end.as_f32_srgba());
+    (0..steps)
+        .map(|step| match step {
+            0 => start,
+            _ if step == steps - 1 => end,
+            _ => from_oklab(start_oklab.mix(end_oklab, step as f32 / (steps - 1) as f32)),
+        })
+        .collect()
+}
+
+/// OKLab coordinates scaled by alpha, like premultiplied channels, so that faint colors are all close
+/// to transparency and to each other.
+fn perceptual_coordinates(color: ComparableColor) -> [f32; 4] {
+    let oklab = Oklaba::from_color(color.as_f32_srgba());
+    let alpha = oklab.alpha;
+    [oklab.l * alpha, oklab.a * alpha, oklab.b * alpha, alpha]
+}
+
+fn squared_distance(first: &[f32; 4], second: &[f32; 4]) -> f32 {
+    first
+        .iter()
+        .zip(second)
+        .map(|(first, second)| (first - second) * (first - second))
+        .sum()
+}
+
+/// Distance between colors in OKLab space, with alpha as a fourth axis. A difference of 1.0 is
+/// about the difference between black and white.
+pub fn perceptual_distance(first: ComparableColor, second: ComparableColor) -> f32 {
+    squared_distance(
+        &perceptual_coordinates(first),
+        &perceptual_coordinates(second),
+    )
+    .sqrt()
+}
+
+/// A palette with each color's coordinates for [perceptual_distance] computed in advance, for
+/// matching many pixels against it. The colors are stored as a k-d tree, so that a lookup in a
+/// 256-color palette only has to measure the distance to a few of them.
+#[derive(Clone, Debug)]
+pub struct PerceptualPalette {
+    /// Each subslice is a subtree whose root is its middle element, and the elements before and
+    /// after the root are its children on either side of the root's split.
+    nodes: Vec<KdNode>,
+}
+
+#[derive(Clone, Debug)]
+struct KdNode {
+    coordinates: [f32; 4],
+    index: usize,
+    /// The coordinate that this node splits its subtree by.
+    axis: usize,
+}
+
+/// Sorts a subtree into the layout that [PerceptualPalette::nodes] describes, splitting each level
+/// along the axis with the widest spread.
+fn build_kd_tree(nodes: &mut [KdNode]) {
+    if nodes.len() <= 1 {
+        return;
+    }
+    let axis = (0..4)
+        .max_by(|first, second| {
+            let spread = |axis: &usize| {
+                let values = nodes.iter().map(|node| node.coordinates[*axis]);
+                values.clone().fold(f32::MIN, f32::max) - values.fold(f32::MAX, f32::min)
+            };
+            spread(first).total_cmp(&spread(second))
+        })
+        .unwrap();
+    let middle = nodes.len() / 2;
+    nodes.select_nth_unstable_by(middle, |first, second| {
+        first.coordinates[axis].total_cmp(&second.coordinates[axis])
+    });
+    nodes[middle].axis = axis;
+    let (before, after) = nodes.split_at_mut(middle);
+    build_kd_tree(before);
+    build_kd_tree(&mut after[1..]);
+}
+
+/// Updates `best`, which holds an index and a squared distance, if any node in the subtree is
+/// nearer to `target`. Ties go to the lower index, as they would in a linear search.
+fn search_kd_tree(nodes: &[KdNode], target: &[f32; 4], best: &mut (usize, f32)) {
+    if nodes.is_empty() {
+        return;
+    }
+    let middle = nodes.len() / 2;
+    let node = &nodes[middle];
+    let distance = squared_distance(&node.coordinates, target);
+    if distance < best.1 || (distance == best.1 && node.index < best.0) {
+        *best = (node.index, distance);
+    }
+    let offset = target[node.axis] - node.coordinates[node.axis];
+    let (near, far) = if offset < 0.0 {
+        (&nodes[..middle], &nodes[middle + 1..])
+    } else {
+        (&nodes[middle + 1..], &nodes[..middle])
+    };
+    search_kd_tree(near, target, best);
+    if offset * offset <= best.1 {
+        search_kd_tree(far, target, best);
+    }
+}
+
+impl PerceptualPalette {
+    pub fn new(colors: &[ComparableColor]) -> PerceptualPalette {
+        let mut nodes: Vec<KdNode> = colors
+            .iter()
+            .enumerate()
+            .map(|(index, color)| KdNode {
+                coordinates: perceptual_coordinates(*color),
+                index,
+                axis: 0,
+            })
+            .collect();
+        build_kd_tree(&mut nodes);
+        PerceptualPalette { nodes }
+    }
+
+    /// Returns the index of the palette color nearest to the given one, and its distance. Panics if
+    /// the palette is empty.
+    pub fn nearest(&self, color: ComparableColor) -> (usize, f32) {
+        assert!(!self.nodes.is_empty(), "Empty palette");
+        let mut best = (usize::MAX, f32::INFINITY);
+        search_kd_tree(&self.nodes, &perceptual_coordinates(color), &mut best);
+        (best.0, best.1.sqrt())
+    }
+}
+
+fn from_oklab(oklab: Oklaba) -> ComparableColor {
+    let srgb: Srgba<u8> = Srgba::from_color(oklab).into_format();
+    rgba(srgb.red, srgb.green, srgb.blue, srgb.alpha)
 }
 
 impl Mul<f32> for ComparableColor {
@@ -201,6 +511,28 @@ impl Display for ComparableColor {
     }
 }
 
+impl FromStr for ComparableColor {
+    type Err = CloneableError;
+
+    /// Parses the output of [Display]: `#rrggbb`, `#rrggbbaa` or `transparent`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "transparent" {
+            return Ok(ComparableColor::TRANSPARENT);
+        }
+        let hex = s
+            .strip_prefix('#')
+            .filter(|hex| hex.len() == 6 || hex.len() == 8)
+            .ok_or_else(|| anyhoo!("Not a color: {}", s))?;
+        let value = u32::from_str_radix(hex, 16).map_err(|_| anyhoo!("Not a color: {}", s))?;
+        Ok(if hex.len() == 6 {
+            c(value)
+        } else {
+            let [red, green, blue, alpha] = value.to_be_bytes();
+            rgba(red, green, blue, alpha)
+        })
+    }
+}
+
 impl Debug for ComparableColor {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         Display::fmt(self, f)
@@ -232,8 +564,12 @@ impl From<PremultipliedColor> for ComparableColor {
 
 impl From<ColorU8> for ComparableColor {
     fn from(value: ColorU8) -> Self {
-        unsafe {
-            transmute(value)
+        // Can't transmute, because the fields are in a different order so that colors sort by alpha
+        ComparableColor {
+            red: value.red(),
+            green: value.green(),
+            blue: value.blue(),
+            alpha: value.alpha(),
         }
     }
 }
@@ -259,9 +595,7 @@ impl From<ComparableColor> for PremultipliedColor {
 
 impl From<ComparableColor> for ColorU8 {
     fn from(val: ComparableColor) -> Self {
-        unsafe {
-            transmute(val)
-        }
+        ColorU8::from_rgba(val.red, val.green, val.blue, val.alpha)
     }
 }
 
@@ -366,7 +700,7 @@ const fn rgb(r: u8, g: u8, b: u8) -> ComparableColor {
     }
 }
 
-const fn rgba(r: u8, g: u8, b: u8, a: u8) -> ComparableColor {
+pub const fn rgba(r: u8, g: u8, b: u8, a: u8) -> ComparableColor {
     ComparableColor {
         red: r,
         green: g,
@@ -375,6 +709,42 @@ const fn rgba(r: u8, g: u8, b: u8, a: u8) -> ComparableColor {
     }
 }
 
+const fn channel_to_unit(channel: u8) -> f32 {
+    channel as f32 / u8::MAX as f32
+}
+
+const fn unit_to_channel(value: f32) -> u8 {
+    (clamp_unit(value) * u8::MAX as f32 + 0.5) as u8
+}
+
+const fn clamp_unit(value: f32) -> f32 {
+    max(0.0, min(value, 1.0))
+}
+
+const fn abs(value: f32) -> f32 {
+    if value < 0.0 {
+        -value
+    } else {
+        value
+    }
+}
+
+const fn max(first: f32, second: f32) -> f32 {
+    if first > second {
+        first
+    } else {
+        second
+    }
+}
+
+const fn min(first: f32, second: f32) -> f32 {
+    if first < second {
+        first
+    } else {
+        second
+    }
+}
+
 pub const fn gray(lightness: u8) -> ComparableColor {
     rgb(lightness, lightness, lightness)
 }
@@ -411,6 +781,136 @@ fn test_c() {
     )
 }
 
+#[test]
+fn test_hsl() {
+    assert_eq!(
+        ComparableColor::from_hsl(0.0, 1.0, 0.5),
+        ComparableColor::RED
+    );
+    assert_eq!(
+        ComparableColor::from_hsl(120.0, 1.0, 0.5),
+        ComparableColor::GREEN
+    );
+    assert_eq!(
+        ComparableColor::from_hsl(-120.0, 1.0, 0.5),
+        ComparableColor::BLUE
+    );
+    assert_eq!(
+        ComparableColor::from_hsl(300.0, 1.0, 0.5),
+        ComparableColor::MAGENTA
+    );
+    assert_eq!(ComparableColor::from_hsl(42.0, 0.0, 0.5), gray(0x80));
+    assert_eq!(ComparableColor::from_hsv(60.0, 1.0, 1.0), ComparableColor::YELLOW);
+    assert_eq!(ComparableColor::from_hsv(0.0, 0.5, 1.0), c(0xff8080));
+    assert_eq!(ComparableColor::from_hsv(0.0, 0.0, 0.0), ComparableColor::BLACK);
+    assert_eq!(ComparableColor::RED.to_hsl(), (0.0, 1.0, 0.5));
+    assert_eq!(ComparableColor::STONE.lighten(1.0), ComparableColor::WHITE);
+    assert_eq!(ComparableColor::STONE.darken(1.0), ComparableColor::BLACK);
+    assert_eq!(gray(0x80).darken(0.5), gray(0x40));
+    assert_eq!(c(0xbf4040).saturate(1.0), c(0xff0000));
+    assert_eq!(c(0xbf4040).saturate(-1.0), gray(0x80));
+    assert_eq!(ComparableColor::RED.shift_hue(120.0), ComparableColor::GREEN);
+    assert_eq!(ComparableColor::BLUE.shift_hue(-480.0), ComparableColor::GREEN);
+    assert_eq!((ComparableColor::RED * 0.5).darken(0.5).alpha, 0x80);
+}
+
+#[test]
+fn test_lerp() {
+    assert_eq!(
+        ComparableColor::RED.lerp(&ComparableColor::BLUE, 0.0),
+        ComparableColor::RED
+    );
+    assert_eq!(
+        ComparableColor::RED.lerp(&ComparableColor::BLUE, 1.0),
+        ComparableColor::BLUE
+    );
+    assert_eq!(
+        ComparableColor::BLACK.lerp(&ComparableColor::WHITE, 0.5),
+        gray(0x80)
+    );
+    assert_eq!(
+        ComparableColor::BLACK.lerp(&ComparableColor::TRANSPARENT, 0.5),
+        rgba(0, 0, 0, 0x80)
+    );
+}
+
+#[test]
+fn test_oklab_ramp() {
+    assert!(oklab_ramp(ComparableColor::BLACK, ComparableColor::WHITE, 0).is_empty());
+    assert_eq!(
+        oklab_ramp(ComparableColor::BLACK, ComparableColor::WHITE, 1),
+        [ComparableColor::BLACK]
+    );
+    let ramp = oklab_ramp(ComparableColor::BLACK, ComparableColor::WHITE, 5);
+    assert_eq!(ramp.len(), 5);
+    assert_eq!(ramp[0], ComparableColor::BLACK);
+    assert_eq!(ramp[4], ComparableColor::WHITE);
+    assert!(ramp.iter().all(ComparableColor::is_gray));
+    assert!(ramp.windows(2).all(|pair| pair[0].red < pair[1].red));
+}
+
+#[test]
+fn test_shift_oklab_lightness() {
+    assert_eq!(
+        ComparableColor::BLACK.shift_oklab_lightness(1.0),
+        ComparableColor::WHITE
+    );
+    assert_eq!(
+        ComparableColor::WHITE.shift_oklab_lightness(-2.0),
+        ComparableColor::BLACK
+    );
+    let color = c(0xc77e4f) * 0.5;
+    let lighter = color.shift_oklab_lightness(0.1);
+    let darker = color.shift_oklab_lightness(-0.1);
+    assert_eq!(lighter.alpha(), color.alpha());
+    assert!(lighter.red() > color.red() && lighter.blue() > color.blue());
+    assert!(darker.red() < color.red() && darker.blue() < color.blue());
+}
+
+#[test]
+fn test_from_str() {
+    assert_eq!("#c77e4f".parse::<ComparableColor>().unwrap(), c(0xc77e4f));
+    assert_eq!(
+        "#c77e4f80".parse::<ComparableColor>().unwrap(),
+        rgba(0xc7, 0x7e, 0x4f, 0x80)
+    );
+    assert_eq!(
+        "transparent".parse::<ComparableColor>().unwrap(),
+        ComparableColor::TRANSPARENT
+    );
+    assert!("c77e4f".parse::<ComparableColor>().is_err());
+    assert!("#c77e4".parse::<ComparableColor>().is_err());
+    assert!("#c77e4g".parse::<ComparableColor>().is_err());
+}
+
+#[test]
+fn test_perceptual_palette() {
+    let palette = PerceptualPalette::new(&[
+        ComparableColor::TRANSPARENT,
+        c(0x0000ff),
+        gray(0x80),
+        c(0xffff00),
+    ]);
+    // Manhattan distance on the raw channels would choose the gray.
+    assert_eq!(palette.nearest(c(0x6060ff)).0, 1);
+    assert_eq!(palette.nearest(c(0xa0a0a0)).0, 2);
+    assert_eq!(palette.nearest(c(0xffff00) * 0.05).0, 0);
+    assert_eq!(palette.nearest(c(0xffff00)), (3, 0.0));
+    assert!(perceptual_distance(ComparableColor::BLACK, ComparableColor::WHITE) > 0.99);
+}
+
+#[test]
+fn test_transparency_sentinel() {
+    assert_eq!(
+        transparency_sentinel([ComparableColor::BLACK, ComparableColor::TRANSPARENT]),
+        ComparableColor::RESERVED_FOR_TRANSPARENCY
+    );
+    assert_eq!(
+        transparency_sentinel([c(0xc0ff3e), c(0xc0ff3f), c(0xc0ff40) * 0.0]),
+        c(0xc0ff40)
+    );
+}
+
 #[test]
 fn test_ord() {
     assert_eq!(
@@ -445,3 +945,120 @@ fn test_ord() {
         }
     );
 }
+
+#[test]
+fn test_color_u8_conversion() {
+    let color = ColorU8::from_rgba(0x12, 0x34, 0x56, 0x78);
+    let comparable: ComparableColor = color.into();
+    assert_eq!(comparable.red, 0x12);
+    assert_eq!(comparable.green, 0x34);
+    assert_eq!(comparable.blue, 0x56);
+    assert_eq!(comparable.alpha, 0x78);
+    assert_eq!(ColorU8::from(comparable), color);
+}
+
+/// Largest difference between two colors in any channel once they're premultiplied, which is how
+/// tiny-skia stores and blends them; rounding errors are only small in that space.
+pub fn premultiplied_diff(first: ComparableColor, second: ComparableColor) -> u8 {
+    let first = PremultipliedColorU8::from(first);
+    let second = PremultipliedColorU8::from(second);
+    first
+        .red()
+        .abs_diff(second.red())
+        .max(first.green().abs_diff(second.green()))
+        .max(first.blue().abs_diff(second.blue()))
+        .max(first.alpha().abs_diff(second.alpha()))
+}
+
+#[cfg(test)]
+pub(crate) fn arb_color() -> impl proptest::strategy::Strategy<Value = ComparableColor> {
+    use proptest::prelude::*;
+
+    any::<[u8; 4]>().prop_map(|[red, green, blue, alpha]| rgba(red, green, blue, alpha))
+}
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_perceptual_palette_matches_linear_search(
+        colors in proptest::collection::vec(arb_color(), 1..300),
+        target in arb_color(),
+    ) {
+        let (index, distance) = PerceptualPalette::new(&colors).nearest(target);
+        let distances: Vec<f32> = colors
+            .iter()
+            .map(|color| perceptual_distance(*color, target))
+            .collect();
+        let expected = distances
+            .iter()
+            .enumerate()
+            .min_by(|(_, first), (_, second)| first.total_cmp(second))
+            .unwrap()
+            .0;
+        proptest::prop_assert_eq!(index, expected);
+        proptest::prop_assert_eq!(distance, distances[expected]);
+    }
+
+    #[test]
+    fn test_hsl_round_trip(red: u8, green: u8, blue: u8) {
+        let color = rgb(red, green, blue);
+        let (hue, saturation, lightness) = color.to_hsl();
+        let round_trip = ComparableColor::from_hsl(hue, saturation, lightness);
+        proptest::prop_assert!(
+            color.abs_diff(&round_trip) <= 3,
+            "{} became {}", color, round_trip
+        );
+    }
+
+    #[test]
+    fn test_under_matches_compositing(background in arb_color(), foreground in arb_color()) {
+        use crate::image_tasks::stack::stack_layer_on_layer;
+        use futures_util::FutureExt;
+        use resvg::tiny_skia::Pixmap;
+        use std::iter::once;
+
+        let mut background_pixmap = Pixmap::new(1, 1).unwrap();
+        background_pixmap.fill(background.into());
+        let mut foreground_pixmap = Pixmap::new(1, 1).unwrap();
+        foreground_pixmap.fill(foreground.into());
+        stack_layer_on_layer(&mut background_pixmap, &foreground_pixmap)
+            .now_or_never()
+            .unwrap();
+        let composited = ComparableColor::from(background_pixmap.pixels()[0]);
+        let predicted = background.under(once(foreground));
+        proptest::prop_assert_eq!(predicted.len(), 1);
+        // tiny-skia rounds both inputs when premultiplying them, then rounds the result
+        proptest::prop_assert!(
+            premultiplied_diff(predicted[0], composited) <= 2,
+            "Predicted {} but got {}", predicted[0], composited
+        );
+    }
+
+    #[test]
+    fn test_under_is_associative(
+        bottom in arb_color(),
+        middle in arb_color(),
+        top in arb_color()
+    ) {
+        use std::iter::once;
+
+        let bottom_first = bottom.under(once(middle))[0].under(once(top))[0];
+        let top_first = bottom.under(middle.under(once(top)).into_iter())[0];
+        // Each grouping rounds twice
+        proptest::prop_assert!(
+            premultiplied_diff(bottom_first, top_first) <= 2,
+            "{} != {}", bottom_first, top_first
+        );
+    }
+
+    #[test]
+    fn test_under_alpha(background in arb_color(), foreground in arb_color()) {
+        use crate::image_tasks::make_semitransparent::ALPHA_STACKING_TABLE;
+        use std::iter::once;
+
+        let stacked = background.under(once(foreground))[0];
+        let expected_alpha =
+            ALPHA_STACKING_TABLE[background.alpha as usize][foreground.alpha as usize];
+        proptest::prop_assert!(stacked.alpha.abs_diff(expected_alpha) <= 1);
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::task_spec::{
+    ColorDescription, FileOutputTaskSpec, TaskGraphBuildingContext,
+};
+use crate::{option_value, parsed_option};
+
+/// The most colors each output may have, for packs that should look like they were drawn with few
+/// colors per texture.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ColorBudget {
+    default: Option<usize>,
+    per_texture: HashMap<Box<str>, usize>,
+}
+
+/// Set by `--max-colors` for every texture, and overridden for individual textures by the file
+/// named by `--color-budgets`. `None` if neither is given.
+pub static COLOR_BUDGET: Lazy<Option<ColorBudget>> = Lazy::new(|| {
+    let mut budget: ColorBudget = match option_value("color-budgets") {
+        Some(path) => read_to_string(&path)
+            .map_err(CloneableError::from)
+            .and_then(|text| text.parse())
+            .unwrap_or_else(|e| panic!("Invalid value for --color-budgets: {:?}", e)),
+        None => ColorBudget::default(),
+    };
+    if let Some(max_colors) = parsed_option("max-colors") {
+        budget.default = Some(max_colors);
+    }
+    (budget != ColorBudget::default()).then_some(budget)
+});
+
+impl FromStr for ColorBudget {
+    type Err = CloneableError;
+
+    /// Each line is a texture name and the most colors it may have, such as `block/stone 16`. A
+    /// name of `*` sets the budget for textures that aren't listed. Blank lines and lines starting
+    /// with `#` are ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut budget = ColorBudget::default();
+        for line in s.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, max_colors) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| anyhoo!("Missing color count: {}", line))?;
+            let max_colors: usize = max_colors
+                .trim()
+                .parse()
+                .map_err(|_| anyhoo!("Invalid color count: {}", line))?;
+            if name == "*" {
+                budget.default = Some(max_colors);
+            } else {
+                budget.per_texture.insert(name.into(), max_colors);
+            }
+        }
+        Ok(budget)
+    }
+}
+
+impl ColorBudget {
+    pub fn for_texture(&self, name: &str) -> Option<usize> {
+        self.per_texture.get(name).copied().or(self.default)
+    }
+
+    /// A message if the description has more colors than `max_colors`, or more than [ColorBudget]
+    /// can count without rendering the image.
+    fn check(name: &str, description: &ColorDescription, max_colors: usize) -> Option<String> {
+        match description {
+            ColorDescription::SpecifiedColors(colors) if colors.len() > max_colors => {
+                Some(format!(
+                    "{} has {} colors; its budget is {}",
+                    name,
+                    colors.len(),
+                    max_colors
+                ))
+            }
+            ColorDescription::SpecifiedColors(_) => None,
+            ColorDescription::Rgb(_) => Some(format!(
+                "{} has too many colors to predict; its budget is {}",
+                name, max_colors
+            )),
+        }
+    }
+
+    /// Checks the predicted colors at `tile_size` of every output that has a budget, and fails with
+    /// a list of every one that's over it. A copy is checked against the budget for each of its
+    /// names, using the colors of its original.
+    pub async fn audit(
+        &self,
+        tasks: &[FileOutputTaskSpec],
+        ctx: &mut TaskGraphBuildingContext,
+        tile_size: u32,
+    ) -> Result<(), CloneableError> {
+        let mut violations = Vec::new();
+        for task in tasks {
+            let mut names: Vec<&str> = vec![];
+            let mut original = task;
+            while let FileOutputTaskSpec::Copy {
+                original: copied,
+                link_names,
+            } = original
+            {
+                // Budgets don't name the namespace, which only legacy aliases give
+                names.extend(
+                    link_names
+                        .iter()
+                        .map(|name| name.strip_prefix("minecraft:").unwrap_or(&**name)),
+                );
+                original = copied.as_ref();
+            }
+            let FileOutputTaskSpec::PngOutput {
+                base,
+                destination_name,
+                ..
+            } = original
+            else {
+                continue;
+            };
+            if names.is_empty() {
+                names.push(&**destination_name);
+            }
+            for name in names {
+                let Some(max_colors) = self.for_texture(name) else {
+                    continue;
+                };
+                let analysis = base.get_analysis_task(ctx, tile_size).await;
+                violations.extend(Self::check(name, &analysis.colors, max_colors));
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhoo!(
+                "{} textures are over their color budgets:\n{}",
+                violations.len(),
+                violations.join("\n")
+            ))
+        }
+    }
+}
+
+#[test]
+fn test_color_budget() {
+    use crate::image_tasks::color::ComparableColor;
+    use crate::image_tasks::task_spec::{alias_task, from_svg_task, out_task, paint_svg_task};
+
+    let budget: ColorBudget = "# Low-color profile\n* 2\n\nblock/stone   4\n"
+        .parse()
+        .unwrap();
+    assert_eq!(budget.for_texture("block/stone"), Some(4));
+    assert_eq!(budget.for_texture("block/dirt"), Some(2));
+    assert!("block/stone".parse::<ColorBudget>().is_err());
+    assert!("block/stone many".parse::<ColorBudget>().is_err());
+
+    let two_colors = from_svg_task("borderSolid");
+    let three_colors = crate::stack!(
+        two_colors.clone(),
+        paint_svg_task("bricks", ComparableColor::WHITE)
+    );
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let mut ctx = TaskGraphBuildingContext::new();
+    let mut audit =
+        |tasks: Vec<FileOutputTaskSpec>| runtime.block_on(budget.audit(&tasks, &mut ctx, 32));
+    assert!(audit(vec![
+        out_task("block/dirt", two_colors),
+        out_task("block/stone", three_colors.clone()),
+    ])
+    .is_ok());
+    assert!(audit(vec![out_task("block/dirt", three_colors.clone())]).is_err());
+    // An alias is held to the budget for its own name
+    let stone = out_task("block/stone", three_colors);
+    assert!(audit(vec![alias_task(stone, ["block/polished_stone"])]).is_err());
+}
@@ -0,0 +1,107 @@
+use std::fmt::{Display, Formatter};
+
+use resvg::tiny_skia::Pixmap;
+use tracing::instrument;
+
+use crate::image_tasks::color::ComparableColor;
+use crate::image_tasks::{allocate_pixmap_for_overwrite, MaybeFromPool};
+
+/// A change to every color of an image, such as an animal's coat color made from a template: the
+/// hue rotates by `hue` degrees, then the saturation and lightness move by the given percentages
+/// as [ComparableColor::saturate] and [ComparableColor::lighten] move them, with a negative
+/// `lightness` darkening instead. Whole numbers, so that task specs can be compared and hashed.
+#[derive(Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct ColorShift {
+    pub hue: i16,
+    pub saturation: i8,
+    pub lightness: i8,
+}
+
+impl ColorShift {
+    pub const NONE: ColorShift = ColorShift::new(0, 0, 0);
+
+    pub const fn new(hue: i16, saturation: i8, lightness: i8) -> ColorShift {
+        ColorShift {
+            hue,
+            saturation,
+            lightness,
+        }
+    }
+
+    pub fn is_none(&self) -> bool {
+        *self == ColorShift::NONE
+    }
+
+    pub fn apply(&self, color: ComparableColor) -> ComparableColor {
+        if self.is_none() || color.alpha() == 0 {
+            return color;
+        }
+        let color = color
+            .shift_hue(self.hue as f32)
+            .saturate(self.saturation as f32 / 100.0);
+        let lightness = self.lightness as f32 / 100.0;
+        if lightness >= 0.0 {
+            color.lighten(lightness)
+        } else {
+            color.darken(-lightness)
+        }
+    }
+}
+
+impl Display for ColorShift {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "h{}s{}l{}", self.hue, self.saturation, self.lightness)
+    }
+}
+
+/// Applies `shift` to every pixel. Neighboring pixels usually share a color, so the last one's
+/// result is reused.
+#[instrument(skip(source))]
+pub fn shift_colors(source: &Pixmap, shift: ColorShift) -> MaybeFromPool<Pixmap> {
+    let mut out = allocate_pixmap_for_overwrite(source.width(), source.height());
+    let mut last = None;
+    for (out_pixel, pixel) in out.pixels_mut().iter_mut().zip(source.pixels()) {
+        *out_pixel = match last {
+            Some((last_pixel, shifted)) if last_pixel == *pixel => shifted,
+            _ => {
+                let shifted = shift.apply(ComparableColor::from(*pixel)).into();
+                last = Some((*pixel, shifted));
+                shifted
+            }
+        };
+    }
+    out
+}
+
+#[test]
+fn test_shift_colors() {
+    use crate::image_tasks::color::c;
+
+    let shift = ColorShift::new(120, 0, 0);
+    assert_eq!(shift.to_string(), "h120s0l0");
+    assert_eq!(shift.apply(ComparableColor::RED), ComparableColor::GREEN);
+    assert_eq!(
+        shift.apply(ComparableColor::TRANSPARENT),
+        ComparableColor::TRANSPARENT
+    );
+    assert_eq!(ColorShift::NONE.apply(c(0x8a5a2b)), c(0x8a5a2b));
+    assert_eq!(
+        ColorShift::new(0, 0, -100).apply(c(0x8a5a2b)),
+        ComparableColor::BLACK
+    );
+    assert!(ColorShift::new(0, -100, 0).apply(c(0x8a5a2b)).is_gray());
+
+    let mut source = Pixmap::new(3, 1).unwrap();
+    source.pixels_mut()[0] = ComparableColor::RED.into();
+    source.pixels_mut()[1] = ComparableColor::RED.into();
+    let out = shift_colors(&source, shift);
+    let colors: Vec<ComparableColor> = out.pixels().iter().map(|pixel| (*pixel).into()).collect();
+    assert_eq!(
+        colors,
+        [
+            ComparableColor::GREEN,
+            ComparableColor::GREEN,
+            ComparableColor::TRANSPARENT
+        ]
+    );
+}
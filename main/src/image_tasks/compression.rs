@@ -0,0 +1,236 @@
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+use oxipng::Options;
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+
+use crate::anyhoo;
+use crate::config::CONFIG;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::png_output::OXIPNG_OPTIONS;
+use crate::image_tasks::realms::category_of;
+
+/// The category that animation strips fall into, ahead of the one their path gives, if the
+/// `[compression]` table has it.
+pub const ANIMATED_CATEGORY: &str = "animated";
+
+/// How files in the ZIP file are compressed.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum ZipCompression {
+    Stored,
+    /// Deflate at a level from 0 to 9.
+    Deflate(u8),
+    /// Zopfli with a number of iterations from 1 to 255, which is much slower than any level of
+    /// deflate but makes smaller files.
+    Zopfli(u8),
+}
+
+impl ZipCompression {
+    pub fn apply(self, options: SimpleFileOptions) -> SimpleFileOptions {
+        match self {
+            ZipCompression::Stored => options
+                .compression_method(CompressionMethod::Stored)
+                .compression_level(None),
+            ZipCompression::Deflate(level) => options
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(level as i64)),
+            // The zip crate uses zopfli for levels above 9
+            ZipCompression::Zopfli(iterations) => options
+                .compression_method(CompressionMethod::Deflated)
+                .compression_level(Some(iterations as i64 + 9)),
+        }
+    }
+}
+
+/// How one category of output is compressed, from the `[compression]` table of the config file,
+/// such as `item = "zopfli, oxipng:6"` for small icons that are worth the extra time, or
+/// `animated = "deflate:6, oxipng:1"` for large animation strips that aren't. Anything that isn't
+/// set is left as it would otherwise be.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct CompressionPolicy {
+    pub zip: Option<ZipCompression>,
+    /// Replaces the oxipng preset that `--oxipng-preset`, the tile size and `--png-time-budget`
+    /// would choose.
+    pub oxipng_preset: Option<u8>,
+}
+
+impl FromStr for CompressionPolicy {
+    type Err = CloneableError;
+
+    /// Parses a comma-separated list of `stored`, `deflate[:level]`, `zopfli[:iterations]` and
+    /// `oxipng:preset`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = CompressionPolicy::default();
+        for term in s.split(',').map(str::trim).filter(|term| !term.is_empty()) {
+            let (name, value) = match term.split_once(':') {
+                Some((name, value)) => (name.trim(), Some(value.trim())),
+                None => (term, None),
+            };
+            let number = |default: Option<u8>, range: RangeInclusive<u8>| {
+                let number = match value {
+                    Some(value) => value.parse::<u8>().ok(),
+                    None => default,
+                };
+                number
+                    .filter(|number| range.contains(number))
+                    .ok_or_else(|| {
+                        anyhoo!(
+                            "Expected {} from {} to {}: {}",
+                            name,
+                            range.start(),
+                            range.end(),
+                            term
+                        )
+                    })
+            };
+            match name {
+                "stored" => policy.zip = Some(ZipCompression::Stored),
+                "deflate" => policy.zip = Some(ZipCompression::Deflate(number(Some(9), 0..=9)?)),
+                "zopfli" => {
+                    policy.zip = Some(ZipCompression::Zopfli(number(Some(u8::MAX), 1..=u8::MAX)?))
+                }
+                "oxipng" => policy.oxipng_preset = Some(number(None, 0..=6)?),
+                _ => return Err(anyhoo!("Unknown compression setting: {}", term)),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+/// The `[compression]` table of the config file, parsed, by category.
+pub static COMPRESSION_POLICIES: Lazy<Vec<(String, CompressionPolicy)>> = Lazy::new(|| {
+    CONFIG
+        .compression_policies()
+        .iter()
+        .map(|(category, policy)| {
+            let policy = policy.parse().unwrap_or_else(|e| {
+                panic!("Invalid value for [compression] {}: {:?}", category, e)
+            });
+            (category.to_owned(), policy)
+        })
+        .collect()
+});
+
+/// The width and height in a PNG's header, or `None` if it isn't a PNG.
+pub fn png_dimensions(png: &[u8]) -> Option<(u32, u32)> {
+    if !png.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return None;
+    }
+    let field = |offset: usize| {
+        Some(u32::from_be_bytes(
+            png.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    Some((field(16)?, field(20)?))
+}
+
+fn find_policy(
+    policies: &[(String, CompressionPolicy)],
+    path: &str,
+    dimensions: Option<(u32, u32)>,
+) -> CompressionPolicy {
+    let animated = dimensions
+        .is_some_and(|(width, height)| width > 0 && height > width && height.is_multiple_of(width));
+    let lookup = |category: &str| {
+        policies
+            .iter()
+            .find(|(name, _)| name == category)
+            .map(|(_, policy)| *policy)
+    };
+    animated
+        .then(|| lookup(ANIMATED_CATEGORY))
+        .flatten()
+        .or_else(|| lookup(&category_of(path)))
+        .unwrap_or_default()
+}
+
+/// The policy for a file at `path`, of the given size if it's an image.
+pub fn policy_for(path: &str, dimensions: Option<(u32, u32)>) -> CompressionPolicy {
+    find_policy(&COMPRESSION_POLICIES, path, dimensions)
+}
+
+/// The options to add `contents` to the ZIP file at `path` with, given the usual ones.
+pub fn zip_options_for(
+    path: &str,
+    contents: &[u8],
+    default: SimpleFileOptions,
+) -> SimpleFileOptions {
+    match policy_for(path, png_dimensions(contents)).zip {
+        Some(zip) => zip.apply(default),
+        None => default,
+    }
+}
+
+/// The oxipng options for a preset: the usual [OXIPNG_OPTIONS], with the row filters and
+/// evaluation that the preset chooses. The deflater isn't taken from the preset, since the usual
+/// one depends on the tile size.
+pub fn oxipng_options(preset: u8) -> Options {
+    let preset = Options::from_preset(preset);
+    let mut options = OXIPNG_OPTIONS.clone();
+    options.filter = preset.filter;
+    options.fast_evaluation = preset.fast_evaluation;
+    options
+}
+
+#[test]
+fn test_compression_policy() {
+    use crate::image_tasks::output_path::texture_path;
+
+    assert_eq!(
+        "zopfli, oxipng:6".parse(),
+        Ok(CompressionPolicy {
+            zip: Some(ZipCompression::Zopfli(255)),
+            oxipng_preset: Some(6),
+        })
+    );
+    assert_eq!(
+        "deflate:3".parse(),
+        Ok(CompressionPolicy {
+            zip: Some(ZipCompression::Deflate(3)),
+            oxipng_preset: None,
+        })
+    );
+    assert!("deflate:10".parse::<CompressionPolicy>().is_err());
+    assert!("oxipng".parse::<CompressionPolicy>().is_err());
+    assert!("brotli".parse::<CompressionPolicy>().is_err());
+
+    let policies: [(String, CompressionPolicy); 3] = [
+        ("item".to_owned(), "zopfli".parse().unwrap()),
+        ("animated".to_owned(), "stored, oxipng:1".parse().unwrap()),
+        ("metadata".to_owned(), "deflate:1".parse().unwrap()),
+    ];
+    let item = &*texture_path("item/stick");
+    assert_eq!(
+        find_policy(&policies, item, Some((32, 32))).zip,
+        Some(ZipCompression::Zopfli(255))
+    );
+    // An animation strip is `animated` whatever its folder
+    assert_eq!(
+        find_policy(&policies, item, Some((32, 128))).oxipng_preset,
+        Some(1)
+    );
+    assert_eq!(
+        find_policy(&policies, "pack.mcmeta", None).zip,
+        Some(ZipCompression::Deflate(1))
+    );
+    assert_eq!(
+        find_policy(&policies, &texture_path("block/stone"), Some((32, 32))),
+        CompressionPolicy::default()
+    );
+
+    let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+    png.extend_from_slice(&[0, 0, 0, 32, 0, 0, 0, 64]);
+    assert_eq!(png_dimensions(&png), Some((32, 64)));
+    assert_eq!(png_dimensions(b"{}"), None);
+
+    // A preset keeps the usual deflater, which depends on the tile size
+    let options = oxipng_options(1);
+    assert_eq!(
+        format!("{:?}", options.deflate),
+        format!("{:?}", OXIPNG_OPTIONS.deflate)
+    );
+    assert_eq!(options.optimize_alpha, OXIPNG_OPTIONS.optimize_alpha);
+    assert_eq!(options.filter, Options::from_preset(1).filter);
+}
@@ -0,0 +1,132 @@
+use std::fmt::Write;
+use std::fs;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::color::ComparableColor;
+use crate::image_tasks::task_spec::PackId;
+use crate::{option_value, parsed_option};
+
+/// A color that was in an image but not in its predicted palette, so the indexed encoder replaced
+/// it with the nearest color that was.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorCorrection {
+    pub found: ComparableColor,
+    pub chosen: ComparableColor,
+    pub pixels: usize,
+    /// [crate::image_tasks::color::perceptual_distance] from `found` to `chosen`.
+    pub error: f32,
+}
+
+/// Corrections from every image encoded so far, with the pack and path of the image.
+type PathsAndCorrections = Vec<(PackId, Box<str>, ColorCorrection)>;
+
+static CORRECTIONS: Lazy<Mutex<PathsAndCorrections>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+pub fn record_corrections(pack: PackId, file_path: &str, corrections: Vec<ColorCorrection>) {
+    let mut all_corrections = CORRECTIONS.lock();
+    all_corrections.extend(
+        corrections
+            .into_iter()
+            .map(|correction| (pack, file_path.into(), correction)),
+    );
+}
+
+fn to_csv(corrections: &mut [(PackId, Box<str>, ColorCorrection)]) -> String {
+    corrections.sort_by(
+        |(first_pack, first_path, first), (second_pack, second_path, second)| {
+            first_pack
+                .cmp(second_pack)
+                .then(first_path.cmp(second_path))
+                .then(first.found.cmp(&second.found))
+        },
+    );
+    let mut csv = String::from("pack,texture,found,chosen,pixels,error\n");
+    for (pack, file_path, correction) in corrections.iter() {
+        writeln!(
+            csv,
+            "{},{},{},{},{},{:.4}",
+            pack,
+            file_path,
+            correction.found,
+            correction.chosen,
+            correction.pixels,
+            correction.error
+        )
+        .unwrap();
+    }
+    csv
+}
+
+/// Called once every image has been encoded. Writes the corrections to the CSV file named by
+/// `--color-error-report`, if any, and then fails if any was worse than `--max-color-error`.
+pub fn finish_correction_report() -> Result<(), CloneableError> {
+    let mut corrections = CORRECTIONS.lock();
+    if let Some(report_path) = option_value("color-error-report") {
+        fs::write(report_path, to_csv(&mut corrections))?;
+    }
+    if let Some(max_error) = parsed_option::<f32>("max-color-error")
+        && let Some((pack, file_path, worst)) = corrections
+            .iter()
+            .max_by(|(_, _, first), (_, _, second)| first.error.total_cmp(&second.error))
+        && worst.error > max_error
+    {
+        return Err(anyhoo!(
+            "{} of {} corrected colors exceeded --max-color-error {}; the worst was {} -> {} in {} \
+            of the {} pack with error {:.4}",
+            corrections
+                .iter()
+                .filter(|(_, _, correction)| correction.error > max_error)
+                .count(),
+            corrections.len(),
+            max_error,
+            worst.found,
+            worst.chosen,
+            file_path,
+            pack,
+            worst.error
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_to_csv() {
+    use crate::image_tasks::color::c;
+
+    let pack = PackId {
+        tile_size: 32,
+        high_contrast: false,
+    };
+    let mut corrections = vec![
+        (
+            pack,
+            "b.png".into(),
+            ColorCorrection {
+                found: c(0x010203),
+                chosen: ComparableColor::BLACK,
+                pixels: 3,
+                error: 0.01,
+            },
+        ),
+        (
+            pack,
+            "a.png".into(),
+            ColorCorrection {
+                found: c(0xfefefe),
+                chosen: ComparableColor::WHITE,
+                pixels: 1,
+                error: 0.005,
+            },
+        ),
+    ];
+    assert_eq!(
+        to_csv(&mut corrections),
+        "pack,texture,found,chosen,pixels,error\n\
+        32x32,a.png,#fefefeff,#ffffffff,1,0.0050\n\
+        32x32,b.png,#010203ff,#000000ff,3,0.0100\n"
+    );
+}
@@ -0,0 +1,136 @@
+use std::fmt::{Display, Formatter};
+
+use resvg::tiny_skia::Pixmap;
+use tracing::instrument;
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::{allocate_pixmap_empty, MaybeFromPool};
+
+/// A rectangle within a tile, measured in sixteenths of its width and height so that it lines up
+/// with the pixels of a vanilla texture.
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct TileRect {
+    pub x: u8,
+    pub y: u8,
+    pub width: u8,
+    pub height: u8,
+}
+
+impl TileRect {
+    /// Units per side of a tile.
+    pub const UNITS: u32 = 16;
+
+    pub const FULL: TileRect = TileRect::new(0, 0, 16, 16);
+
+    pub const fn new(x: u8, y: u8, width: u8, height: u8) -> TileRect {
+        assert!(width > 0 && height > 0);
+        assert!(x as u32 + width as u32 <= TileRect::UNITS);
+        assert!(y as u32 + height as u32 <= TileRect::UNITS);
+        TileRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// A square `size` units on a side in the middle of the tile, or half a unit up and left of
+    /// the middle if `size` is odd.
+    ///
+    /// # Panics
+    /// If `size` is more than [TileRect::UNITS].
+    pub const fn centered(size: u8) -> TileRect {
+        assert!(
+            size as u32 <= TileRect::UNITS,
+            "A centered TileRect can't be bigger than the tile"
+        );
+        let offset = (TileRect::UNITS as u8 - size) / 2;
+        TileRect::new(offset, offset, size, size)
+    }
+
+    /// Left, top, width and height in pixels, for a tile with the given side length.
+    fn to_pixels(self, side_length: u32) -> (u32, u32, u32, u32) {
+        let unit = side_length / TileRect::UNITS;
+        (
+            self.x as u32 * unit,
+            self.y as u32 * unit,
+            self.width as u32 * unit,
+            self.height as u32 * unit,
+        )
+    }
+}
+
+impl Display for TileRect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{}x{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// Scales the `from` region of a square image into the `to` region of a transparent one of the
+/// same size. Uses nearest-neighbor sampling, so that no colors are added but those of the source
+/// and transparency.
+#[instrument(skip(source))]
+pub fn crop_and_scale(
+    source: &Pixmap,
+    from: TileRect,
+    to: TileRect,
+) -> Result<MaybeFromPool<Pixmap>, CloneableError> {
+    let side_length = source.width();
+    if side_length != source.height() || !side_length.is_multiple_of(TileRect::UNITS) {
+        return Err(anyhoo!(
+            "Can't crop a {}x{} image in sixteenths",
+            source.width(),
+            source.height()
+        ));
+    }
+    let (from_x, from_y, from_width, from_height) = from.to_pixels(side_length);
+    let (to_x, to_y, to_width, to_height) = to.to_pixels(side_length);
+    let mut out = allocate_pixmap_empty(side_length, side_length);
+    let source_pixels = source.pixels();
+    let out_pixels = out.pixels_mut();
+    for y in 0..to_height {
+        let source_y = from_y + y * from_height / to_height;
+        for x in 0..to_width {
+            let source_x = from_x + x * from_width / to_width;
+            out_pixels[((to_y + y) * side_length + to_x + x) as usize] =
+                source_pixels[(source_y * side_length + source_x) as usize];
+        }
+    }
+    Ok(out)
+}
+
+#[test]
+fn test_crop_and_scale() {
+    use crate::image_tasks::color::{c, ComparableColor};
+
+    let mut source = Pixmap::new(32, 32).unwrap();
+    source.fill(c(0x8a3a00).into());
+    for x in 0..32 {
+        // Top 2 rows white
+        source.pixels_mut()[x] = ComparableColor::WHITE.into();
+        source.pixels_mut()[32 + x] = ComparableColor::WHITE.into();
+    }
+    let out = crop_and_scale(&source, TileRect::FULL, TileRect::new(4, 8, 8, 8)).unwrap();
+    let pixel = |x: usize, y: usize| ComparableColor::from(out.pixels()[y * 32 + x]);
+    assert_eq!(pixel(0, 0), ComparableColor::TRANSPARENT);
+    assert_eq!(pixel(7, 16), ComparableColor::TRANSPARENT);
+    // The 2 white rows become 1
+    assert_eq!(pixel(8, 16), ComparableColor::WHITE);
+    assert_eq!(pixel(23, 16), ComparableColor::WHITE);
+    assert_eq!(pixel(8, 17), c(0x8a3a00));
+    assert_eq!(pixel(23, 31), c(0x8a3a00));
+    assert_eq!(pixel(24, 31), ComparableColor::TRANSPARENT);
+
+    let out = crop_and_scale(&source, TileRect::new(0, 0, 16, 1), TileRect::FULL).unwrap();
+    assert!(out
+        .pixels()
+        .iter()
+        .all(|pixel| ComparableColor::from(*pixel) == ComparableColor::WHITE));
+    assert!(crop_and_scale(&Pixmap::new(8, 8).unwrap(), TileRect::FULL, TileRect::FULL).is_err());
+    assert_eq!(TileRect::new(4, 8, 8, 8).to_string(), "4,8,8x8");
+    assert_eq!(TileRect::centered(8), TileRect::new(4, 4, 8, 8));
+    assert_eq!(TileRect::centered(5), TileRect::new(5, 5, 5, 5));
+    assert_eq!(TileRect::centered(16), TileRect::FULL);
+    assert!(std::panic::catch_unwind(|| TileRect::centered(17)).is_err());
+}
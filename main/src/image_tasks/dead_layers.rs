@@ -0,0 +1,344 @@
+use log::{info, warn};
+
+use crate::image_tasks::task_spec::{
+    FileOutputTaskSpec, TaskGraphBuildingContext, ToAlphaChannelTaskSpec, ToPixmapTaskSpec,
+    Transparency,
+};
+
+/// Removes every layer that's predicted to be completely hidden, at every one of `tile_sizes`, by
+/// an opaque layer above it, and warns about each texture that had any, since they usually mean a
+/// material definition stacks something it doesn't need. Stacking on an opaque layer gives exactly
+/// that layer, so the output doesn't change.
+pub async fn eliminate_dead_layers(
+    tasks: Vec<FileOutputTaskSpec>,
+    ctx: &mut TaskGraphBuildingContext,
+    tile_sizes: &[u32],
+) -> Vec<FileOutputTaskSpec> {
+    let mut out = Vec::with_capacity(tasks.len());
+    let mut textures_with_dead_layers = 0;
+    let mut dead_layer_count = 0;
+    for task in tasks {
+        let mut dead_layers = Vec::new();
+        let task = prune_output(task, ctx, tile_sizes, &mut dead_layers).await;
+        if !dead_layers.is_empty() {
+            warn!(
+                "{} has {} layers hidden under opaque layers: {}",
+                task.get_path(),
+                dead_layers.len(),
+                dead_layers.join(", ")
+            );
+            textures_with_dead_layers += 1;
+            dead_layer_count += dead_layers.len();
+        }
+        out.push(task);
+    }
+    info!(
+        "Removed {} hidden layers from {} textures",
+        dead_layer_count, textures_with_dead_layers
+    );
+    out
+}
+
+async fn prune_output(
+    task: FileOutputTaskSpec,
+    ctx: &mut TaskGraphBuildingContext,
+    tile_sizes: &[u32],
+    dead_layers: &mut Vec<String>,
+) -> FileOutputTaskSpec {
+    match task {
+        FileOutputTaskSpec::PngOutput {
+            base,
+            destination_name,
+            require_gray,
+            render_layer,
+        } => FileOutputTaskSpec::PngOutput {
+            base: prune_pixmap(&base, ctx, tile_sizes, dead_layers).await,
+            destination_name,
+            require_gray,
+            render_layer,
+        },
+        FileOutputTaskSpec::Copy {
+            original,
+            link_names,
+        } => FileOutputTaskSpec::Copy {
+            original: Box::new(
+                Box::pin(prune_output(*original, ctx, tile_sizes, dead_layers)).await,
+            ),
+            link_names,
+        },
+    }
+}
+
+/// Whether `spec` is predicted to be opaque at every one of `tile_sizes`, since the pruned graph is
+/// shared by all of them.
+async fn is_opaque(
+    spec: &ToPixmapTaskSpec,
+    ctx: &mut TaskGraphBuildingContext,
+    tile_sizes: &[u32],
+) -> bool {
+    for &tile_size in tile_sizes {
+        let analysis = spec.get_analysis_task(ctx, tile_size).await;
+        if analysis.colors.transparency() != Transparency::Opaque {
+            return false;
+        }
+    }
+    true
+}
+
+async fn is_fully_opaque_alpha(
+    spec: &ToAlphaChannelTaskSpec,
+    ctx: &mut TaskGraphBuildingContext,
+    tile_sizes: &[u32],
+) -> bool {
+    for &tile_size in tile_sizes {
+        let alphas = spec.get_possible_alpha_values(ctx, tile_size).await;
+        if alphas.len() != 1 || !alphas.contains(u8::MAX) {
+            return false;
+        }
+    }
+    true
+}
+
+/// Checks the foreground first, since the background doesn't need pruning if it's hidden.
+async fn prune_pixmap(
+    spec: &ToPixmapTaskSpec,
+    ctx: &mut TaskGraphBuildingContext,
+    tile_sizes: &[u32],
+    dead_layers: &mut Vec<String>,
+) -> ToPixmapTaskSpec {
+    match spec {
+        ToPixmapTaskSpec::StackLayerOnLayer {
+            background,
+            foreground,
+        } => {
+            let foreground = Box::pin(prune_pixmap(foreground, ctx, tile_sizes, dead_layers)).await;
+            if is_opaque(&foreground, ctx, tile_sizes).await {
+                dead_layers.push(background.to_string());
+                return foreground;
+            }
+            ToPixmapTaskSpec::StackLayerOnLayer {
+                background: Box::pin(prune_pixmap(background, ctx, tile_sizes, dead_layers))
+                    .await
+                    .into(),
+                foreground: foreground.into(),
+            }
+        }
+        ToPixmapTaskSpec::StackLayerOnColor {
+            background,
+            foreground,
+        } => {
+            let foreground = Box::pin(prune_pixmap(foreground, ctx, tile_sizes, dead_layers)).await;
+            if is_opaque(&foreground, ctx, tile_sizes).await {
+                dead_layers.push(background.to_string());
+                return foreground;
+            }
+            ToPixmapTaskSpec::StackLayerOnColor {
+                background: *background,
+                foreground: foreground.into(),
+            }
+        }
+        ToPixmapTaskSpec::Animate {
+            background,
+            frames,
+            layout,
+            timing,
+        } => {
+            let mut pruned_frames = Vec::with_capacity(frames.len());
+            for frame in frames.iter() {
+                pruned_frames
+                    .push(Box::pin(prune_pixmap(frame, ctx, tile_sizes, dead_layers)).await);
+            }
+            ToPixmapTaskSpec::Animate {
+                background: Box::pin(prune_pixmap(background, ctx, tile_sizes, dead_layers))
+                    .await
+                    .into(),
+                frames: pruned_frames.into(),
+                layout: *layout,
+                timing: *timing,
+            }
+        }
+        ToPixmapTaskSpec::PaintAlphaChannel { base, color } => {
+            ToPixmapTaskSpec::PaintAlphaChannel {
+                base: Box::pin(prune_alpha(base, ctx, tile_sizes, dead_layers))
+                    .await
+                    .into(),
+                color: *color,
+            }
+        }
+        ToPixmapTaskSpec::UpscaleFromGridSize { base } => ToPixmapTaskSpec::UpscaleFromGridSize {
+            base: Box::pin(prune_pixmap(base, ctx, tile_sizes, dead_layers))
+                .await
+                .into(),
+        },
+        ToPixmapTaskSpec::DetailAtLeast {
+            base,
+            min_tile_size,
+        } => ToPixmapTaskSpec::DetailAtLeast {
+            base: Box::pin(prune_pixmap(base, ctx, tile_sizes, dead_layers))
+                .await
+                .into(),
+            min_tile_size: *min_tile_size,
+        },
+        ToPixmapTaskSpec::OnGrid { base, grid_size } => ToPixmapTaskSpec::OnGrid {
+            base: Box::pin(prune_pixmap(base, ctx, tile_sizes, dead_layers))
+                .await
+                .into(),
+            grid_size: *grid_size,
+        },
+        ToPixmapTaskSpec::CropAndScale { base, from, to } => ToPixmapTaskSpec::CropAndScale {
+            base: Box::pin(prune_pixmap(base, ctx, tile_sizes, dead_layers))
+                .await
+                .into(),
+            from: *from,
+            to: *to,
+        },
+        ToPixmapTaskSpec::Rotate {
+            base,
+            quarter_turns,
+        } => ToPixmapTaskSpec::Rotate {
+            base: Box::pin(prune_pixmap(base, ctx, tile_sizes, dead_layers))
+                .await
+                .into(),
+            quarter_turns: *quarter_turns,
+        },
+        ToPixmapTaskSpec::Flip { base, axis } => ToPixmapTaskSpec::Flip {
+            base: Box::pin(prune_pixmap(base, ctx, tile_sizes, dead_layers))
+                .await
+                .into(),
+            axis: *axis,
+        },
+        ToPixmapTaskSpec::Remap { base, mapping } => ToPixmapTaskSpec::Remap {
+            base: Box::pin(prune_pixmap(base, ctx, tile_sizes, dead_layers))
+                .await
+                .into(),
+            mapping: mapping.to_owned(),
+        },
+        ToPixmapTaskSpec::ShiftColors { base, shift } => ToPixmapTaskSpec::ShiftColors {
+            base: Box::pin(prune_pixmap(base, ctx, tile_sizes, dead_layers))
+                .await
+                .into(),
+            shift: *shift,
+        },
+        ToPixmapTaskSpec::PlaceOnSheet {
+            width,
+            height,
+            placements,
+        } => {
+            let mut pruned_placements = Vec::with_capacity(placements.len());
+            for (layer, rect) in placements.iter() {
+                pruned_placements.push((
+                    Box::pin(prune_pixmap(layer, ctx, tile_sizes, dead_layers)).await,
+                    *rect,
+                ));
+            }
+            ToPixmapTaskSpec::PlaceOnSheet {
+                width: *width,
+                height: *height,
+                placements: pruned_placements.into(),
+            }
+        }
+        ToPixmapTaskSpec::FromSvg { .. }
+        | ToPixmapTaskSpec::FromRaster { .. }
+        | ToPixmapTaskSpec::TextureOf { .. } => spec.to_owned(),
+    }
+}
+
+async fn prune_alpha(
+    spec: &ToAlphaChannelTaskSpec,
+    ctx: &mut TaskGraphBuildingContext,
+    tile_sizes: &[u32],
+    dead_layers: &mut Vec<String>,
+) -> ToAlphaChannelTaskSpec {
+    match spec {
+        ToAlphaChannelTaskSpec::StackAlphaOnAlpha {
+            background,
+            foreground,
+        } => {
+            let foreground = Box::pin(prune_alpha(foreground, ctx, tile_sizes, dead_layers)).await;
+            if is_fully_opaque_alpha(&foreground, ctx, tile_sizes).await {
+                dead_layers.push(background.to_string());
+                return foreground;
+            }
+            ToAlphaChannelTaskSpec::StackAlphaOnAlpha {
+                background: Box::pin(prune_alpha(background, ctx, tile_sizes, dead_layers))
+                    .await
+                    .into(),
+                foreground: foreground.into(),
+            }
+        }
+        ToAlphaChannelTaskSpec::StackAlphaOnBackground {
+            background,
+            foreground,
+        } => {
+            let foreground = Box::pin(prune_alpha(foreground, ctx, tile_sizes, dead_layers)).await;
+            if is_fully_opaque_alpha(&foreground, ctx, tile_sizes).await {
+                dead_layers.push(background.to_string());
+                return foreground;
+            }
+            ToAlphaChannelTaskSpec::StackAlphaOnBackground {
+                background: *background,
+                foreground: foreground.into(),
+            }
+        }
+        ToAlphaChannelTaskSpec::MakeSemitransparent { base, alpha } => {
+            ToAlphaChannelTaskSpec::MakeSemitransparent {
+                base: Box::pin(prune_alpha(base, ctx, tile_sizes, dead_layers))
+                    .await
+                    .into(),
+                alpha: *alpha,
+            }
+        }
+        ToAlphaChannelTaskSpec::FromPixmap { base } => ToAlphaChannelTaskSpec::FromPixmap {
+            base: Box::pin(prune_pixmap(base, ctx, tile_sizes, dead_layers)).await,
+        },
+        ToAlphaChannelTaskSpec::UpscaleFromGridSize { base } => {
+            ToAlphaChannelTaskSpec::UpscaleFromGridSize {
+                base: Box::pin(prune_alpha(base, ctx, tile_sizes, dead_layers))
+                    .await
+                    .into(),
+            }
+        }
+        ToAlphaChannelTaskSpec::Dither { base, coverage } => ToAlphaChannelTaskSpec::Dither {
+            base: Box::pin(prune_alpha(base, ctx, tile_sizes, dead_layers))
+                .await
+                .into(),
+            coverage: *coverage,
+        },
+    }
+}
+
+#[test]
+fn test_eliminate_dead_layers() {
+    use crate::image_tasks::color::{c, ComparableColor};
+    use crate::image_tasks::task_spec::{from_svg_task, out_task, paint_svg_task};
+
+    let hidden = paint_svg_task("bricks", c(0x8a3a00));
+    let visible = paint_svg_task("borderSolid", ComparableColor::WHITE);
+    let opaque = ToPixmapTaskSpec::StackLayerOnColor {
+        background: ComparableColor::BLACK,
+        foreground: visible.clone().into(),
+    };
+    let tasks = vec![
+        out_task(
+            "block/hidden",
+            ToPixmapTaskSpec::StackLayerOnLayer {
+                background: hidden.clone().into(),
+                foreground: opaque.clone().into(),
+            },
+        ),
+        out_task(
+            "block/visible",
+            ToPixmapTaskSpec::StackLayerOnLayer {
+                background: hidden.clone().into(),
+                foreground: from_svg_task("borderSolid").into(),
+            },
+        ),
+    ];
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let pruned = runtime.block_on(async {
+        let mut ctx = TaskGraphBuildingContext::new();
+        eliminate_dead_layers(tasks.clone(), &mut ctx, &[32]).await
+    });
+    assert_eq!(pruned[0], out_task("block/hidden", opaque));
+    assert_eq!(pruned[1], tasks[1]);
+}
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+
+use itertools::Itertools;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use oxipng::{BitDepth, ColorType};
+use parking_lot::Mutex;
+use resvg::tiny_skia::Pixmap;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::from_svg::svg_source;
+use crate::image_tasks::output_path::texture_name;
+use crate::image_tasks::search::Ingredients;
+use crate::image_tasks::task_spec::{FileOutputTaskSpec, PackId, RASTER_DIR};
+use crate::{anyhoo, option_value, TILE_SIZES};
+
+/// The output path named by `--debug-bundle`, such as `block/stone`, if any.
+static DEBUG_BUNDLE: Lazy<Option<String>> = Lazy::new(|| option_value("debug-bundle"));
+
+/// The files collected so far for the bundle, by their path inside it, once [start_debug_bundle]
+/// has found the texture.
+static BUNDLE: Lazy<Mutex<Option<DebugBundle>>> = Lazy::new(|| Mutex::new(None));
+
+struct DebugBundle {
+    /// The path that the image is encoded under, which for a copy is its original's.
+    encoded_path: Box<str>,
+    files: BTreeMap<Box<str>, Vec<u8>>,
+}
+
+/// Whether `wanted` names the texture at `path`, either as its path inside the pack or as its
+/// name such as `block/stone`, with or without `.png`.
+fn names_texture(wanted: &str, path: &str) -> bool {
+    let wanted = wanted.trim_start_matches('/');
+    *path == *wanted
+        || texture_name(path)
+            .is_some_and(|name| *name == *wanted.strip_suffix(".png").unwrap_or(wanted))
+}
+
+/// The task that actually encodes the image `task` writes.
+fn source_output(task: &FileOutputTaskSpec) -> &FileOutputTaskSpec {
+    match task {
+        FileOutputTaskSpec::PngOutput { .. } => task,
+        FileOutputTaskSpec::Copy { original, .. } => source_output(original),
+    }
+}
+
+/// Finds the texture named by `--debug-bundle` among the output tasks, and adds its task subtree
+/// and the SVGs and rasters it's built from to the bundle. Does nothing if the option isn't set.
+pub fn start_debug_bundle(tasks: &[FileOutputTaskSpec]) -> Result<(), CloneableError> {
+    let Some(wanted) = &*DEBUG_BUNDLE else {
+        return Ok(());
+    };
+    let task = tasks
+        .iter()
+        .find(|task| {
+            task.get_paths()
+                .iter()
+                .any(|path| names_texture(wanted, path))
+        })
+        .ok_or_else(|| anyhoo!("--debug-bundle: no texture is written to {}", wanted))?;
+    let FileOutputTaskSpec::PngOutput { base, .. } = source_output(task) else {
+        unreachable!("source_output always returns a PngOutput");
+    };
+    let mut files = BTreeMap::new();
+    let mut description = String::new();
+    writeln!(description, "Tile size: {}", TILE_SIZES.iter().join(","))?;
+    writeln!(description, "Paths: {}", task.get_paths().join(", "))?;
+    writeln!(description, "\n{}\n\n{:#?}", base, base)?;
+    files.insert("task.txt".into(), description.into_bytes());
+    for layer in Ingredients::of(task).layers {
+        if let Some(svg) = svg_source(&format!("{}.svg", layer)) {
+            files.insert(format!("svg/{}.svg", layer).into(), svg.into_owned());
+        } else if let Some(raster) = RASTER_DIR.get_file(PathBuf::from(format!("{}.png", layer))) {
+            files.insert(
+                format!("raster/{}.png", layer).into(),
+                raster.contents().to_vec(),
+            );
+        }
+    }
+    *BUNDLE.lock() = Some(DebugBundle {
+        encoded_path: source_output(task).get_path(),
+        files,
+    });
+    Ok(())
+}
+
+/// Adds the image as rendered, before it's converted to `color_type` and optimized, if it's the
+/// one being bundled. Each pack's images go in a folder named after it, such as `32x32/`.
+pub fn record_raw_image(
+    pack: PackId,
+    file_path: &str,
+    image: &Pixmap,
+    color_type: &ColorType,
+    bit_depth: BitDepth,
+) {
+    let mut bundle = BUNDLE.lock();
+    let Some(bundle) = bundle
+        .as_mut()
+        .filter(|bundle| *bundle.encoded_path == *file_path)
+    else {
+        return;
+    };
+    match image.encode_png() {
+        Ok(png) => {
+            bundle.files.insert(format!("{}/raw.png", pack).into(), png);
+        }
+        Err(error) => warn!("Failed to encode the raw image of {}: {}", file_path, error),
+    }
+    bundle.files.insert(
+        format!("{}/color_mode.txt", pack).into(),
+        format!("{:?}, {} bits per channel\n", color_type, bit_depth).into_bytes(),
+    );
+}
+
+/// Adds the PNG as written to the pack, if it's the one being bundled.
+pub fn record_final_png(pack: PackId, file_path: &str, png: &[u8]) {
+    let mut bundle = BUNDLE.lock();
+    if let Some(bundle) = bundle
+        .as_mut()
+        .filter(|bundle| *bundle.encoded_path == *file_path)
+    {
+        bundle
+            .files
+            .insert(format!("{}/final.png", pack).into(), png.to_vec());
+    }
+}
+
+/// The ZIP file that the bundle for `path` is written to, in the working directory.
+fn bundle_file_name(path: &str) -> String {
+    let name = texture_name(path).unwrap_or_else(|| path.trim_end_matches(".png").into());
+    format!("debug-bundle-{}.zip", name.replace(['/', ':'], "_"))
+}
+
+/// Writes the bundle started by [start_debug_bundle], if any.
+pub fn finish_debug_bundle() -> Result<(), CloneableError> {
+    let Some(bundle) = BUNDLE.lock().take() else {
+        return Ok(());
+    };
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    for (path, contents) in bundle.files.iter() {
+        zip.start_file(&**path, SimpleFileOptions::default())?;
+        zip.write_all(contents)?;
+    }
+    let file_name = bundle_file_name(&bundle.encoded_path);
+    info!(
+        "Writing debug bundle of {} with {} files to {}",
+        bundle.encoded_path,
+        bundle.files.len(),
+        file_name
+    );
+    fs::write(&file_name, zip.finish()?.into_inner())?;
+    Ok(())
+}
+
+#[test]
+fn test_bundle_paths() {
+    use crate::image_tasks::output_path::texture_path;
+
+    let stone = texture_path("block/stone");
+    assert!(names_texture("block/stone", &stone));
+    assert!(names_texture("block/stone.png", &stone));
+    assert!(names_texture(
+        "/assets/minecraft/textures/block/stone.png",
+        &stone
+    ));
+    assert!(!names_texture("block/stone_bricks", &stone));
+    assert_eq!(
+        bundle_file_name(&texture_path("entity/villager/villager")),
+        "debug-bundle-entity_villager_villager.zip"
+    );
+    assert_eq!(bundle_file_name("pack.png"), "debug-bundle-pack.zip");
+}
@@ -0,0 +1,227 @@
+use resvg::tiny_skia::{Pixmap, PremultipliedColorU8};
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+
+/// Side length of the windows that [ssim] compares.
+const SSIM_WINDOW: u32 = 8;
+
+/// Constants that keep [ssim] stable where a window is nearly flat, for 8-bit channels.
+const SSIM_C1: f64 = (0.01 * u8::MAX as f64) * (0.01 * u8::MAX as f64);
+const SSIM_C2: f64 = (0.03 * u8::MAX as f64) * (0.03 * u8::MAX as f64);
+
+/// How two images of the same size differ. Channels are compared premultiplied, so that the color
+/// of a fully transparent pixel doesn't count.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImageDiff {
+    /// Pixels where any channel differs by more than the tolerance given to [diff_images].
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    /// The largest difference in any one channel of any one pixel.
+    pub max_channel_difference: u8,
+    /// Peak signal-to-noise ratio in decibels; infinite if the images are identical.
+    pub psnr: f64,
+    /// Mean structural similarity, from -1 to 1, where 1 means identical.
+    pub ssim: f64,
+}
+
+impl ImageDiff {
+    pub fn is_identical(&self) -> bool {
+        self.max_channel_difference == 0
+    }
+}
+
+fn channels(pixel: &PremultipliedColorU8) -> [u8; 4] {
+    [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]
+}
+
+fn max_channel_difference(first: &PremultipliedColorU8, second: &PremultipliedColorU8) -> u8 {
+    channels(first)
+        .into_iter()
+        .zip(channels(second))
+        .map(|(first, second)| first.abs_diff(second))
+        .max()
+        .unwrap()
+}
+
+fn check_same_size(first: &Pixmap, second: &Pixmap) -> Result<(), CloneableError> {
+    if first.width() != second.width() || first.height() != second.height() {
+        return Err(anyhoo!(
+            "Can't compare a {}x{} image with a {}x{} one",
+            first.width(),
+            first.height(),
+            second.width(),
+            second.height()
+        ));
+    }
+    Ok(())
+}
+
+/// How many pixels have a channel that differs by more than `tolerance`.
+pub fn count_differing_pixels(
+    first: &Pixmap,
+    second: &Pixmap,
+    tolerance: u8,
+) -> Result<usize, CloneableError> {
+    check_same_size(first, second)?;
+    Ok(first
+        .pixels()
+        .iter()
+        .zip(second.pixels())
+        .filter(|(first, second)| max_channel_difference(first, second) > tolerance)
+        .count())
+}
+
+/// Peak signal-to-noise ratio over every channel, in decibels.
+pub fn psnr(first: &Pixmap, second: &Pixmap) -> Result<f64, CloneableError> {
+    check_same_size(first, second)?;
+    let squared_error: u64 = first
+        .pixels()
+        .iter()
+        .zip(second.pixels())
+        .flat_map(|(first, second)| channels(first).into_iter().zip(channels(second)))
+        .map(|(first, second)| (first.abs_diff(second) as u64).pow(2))
+        .sum();
+    if squared_error == 0 {
+        return Ok(f64::INFINITY);
+    }
+    let mean_squared_error = squared_error as f64 / (first.pixels().len() * 4) as f64;
+    Ok(10.0 * ((u8::MAX as f64).powi(2) / mean_squared_error).log10())
+}
+
+/// Mean structural similarity over every channel, taken in [SSIM_WINDOW]-pixel square windows that
+/// don't overlap. Windows at the right and bottom edges may be smaller.
+pub fn ssim(first: &Pixmap, second: &Pixmap) -> Result<f64, CloneableError> {
+    check_same_size(first, second)?;
+    let (width, height) = (first.width(), first.height());
+    let mut total = 0.0;
+    let mut windows = 0usize;
+    for window_y in (0..height).step_by(SSIM_WINDOW as usize) {
+        for window_x in (0..width).step_by(SSIM_WINDOW as usize) {
+            let indices: Vec<usize> = (window_y..(window_y + SSIM_WINDOW).min(height))
+                .flat_map(|y| {
+                    (window_x..(window_x + SSIM_WINDOW).min(width))
+                        .map(move |x| (y * width + x) as usize)
+                })
+                .collect();
+            let count = indices.len() as f64;
+            for channel in 0..4 {
+                let values = |image: &Pixmap| -> Vec<f64> {
+                    indices
+                        .iter()
+                        .map(|index| channels(&image.pixels()[*index])[channel] as f64)
+                        .collect()
+                };
+                let (first_values, second_values) = (values(first), values(second));
+                let first_mean = first_values.iter().sum::<f64>() / count;
+                let second_mean = second_values.iter().sum::<f64>() / count;
+                let mut first_variance = 0.0;
+                let mut second_variance = 0.0;
+                let mut covariance = 0.0;
+                for (first, second) in first_values.iter().zip(second_values.iter()) {
+                    first_variance += (first - first_mean).powi(2);
+                    second_variance += (second - second_mean).powi(2);
+                    covariance += (first - first_mean) * (second - second_mean);
+                }
+                first_variance /= count;
+                second_variance /= count;
+                covariance /= count;
+                total += ((2.0 * first_mean * second_mean + SSIM_C1)
+                    * (2.0 * covariance + SSIM_C2))
+                    / ((first_mean.powi(2) + second_mean.powi(2) + SSIM_C1)
+                        * (first_variance + second_variance + SSIM_C2));
+                windows += 1;
+            }
+        }
+    }
+    Ok(if windows == 0 {
+        1.0
+    } else {
+        total / windows as f64
+    })
+}
+
+/// Compares two images of the same size; see [ImageDiff].
+pub fn diff_images(
+    first: &Pixmap,
+    second: &Pixmap,
+    tolerance: u8,
+) -> Result<ImageDiff, CloneableError> {
+    Ok(ImageDiff {
+        differing_pixels: count_differing_pixels(first, second, tolerance)?,
+        total_pixels: first.pixels().len(),
+        max_channel_difference: first
+            .pixels()
+            .iter()
+            .zip(second.pixels())
+            .map(|(first, second)| max_channel_difference(first, second))
+            .max()
+            .unwrap_or(0),
+        psnr: psnr(first, second)?,
+        ssim: ssim(first, second)?,
+    })
+}
+
+/// An opaque image of where two images differ. Pixels that match are a dimmed copy of `first`;
+/// pixels that differ go from yellow for the smallest difference to red for the largest.
+pub fn heatmap(first: &Pixmap, second: &Pixmap) -> Result<Pixmap, CloneableError> {
+    check_same_size(first, second)?;
+    let mut out = Pixmap::new(first.width(), first.height())
+        .ok_or_else(|| anyhoo!("Can't make a heatmap of an empty image"))?;
+    for ((out, first), second) in out
+        .pixels_mut()
+        .iter_mut()
+        .zip(first.pixels())
+        .zip(second.pixels())
+    {
+        let difference = max_channel_difference(first, second);
+        *out = if difference == 0 {
+            let dimmed = |channel: u8| channel / 4;
+            PremultipliedColorU8::from_rgba(
+                dimmed(first.red()),
+                dimmed(first.green()),
+                dimmed(first.blue()),
+                u8::MAX,
+            )
+        } else {
+            PremultipliedColorU8::from_rgba(u8::MAX, u8::MAX - difference, 0, u8::MAX)
+        }
+        .unwrap();
+    }
+    Ok(out)
+}
+
+/// [heatmap], encoded as a PNG.
+pub fn heatmap_png(first: &Pixmap, second: &Pixmap) -> Result<Vec<u8>, CloneableError> {
+    Ok(heatmap(first, second)?.encode_png()?)
+}
+
+#[test]
+fn test_diff_images() {
+    use crate::image_tasks::color::{gray, ComparableColor};
+
+    let mut first = Pixmap::new(16, 16).unwrap();
+    first.pixels_mut().fill(ComparableColor::STONE.into());
+    let same = diff_images(&first, &first.clone(), 0).unwrap();
+    assert!(same.is_identical());
+    assert_eq!(same.differing_pixels, 0);
+    assert_eq!(same.psnr, f64::INFINITY);
+    assert!((same.ssim - 1.0).abs() < 1e-9);
+
+    let mut second = first.clone();
+    second.pixels_mut()[17] = ComparableColor::BLACK.into();
+    second.pixels_mut()[18] = gray(0x8a).into();
+    let diff = diff_images(&first, &second, 8).unwrap();
+    assert_eq!(diff.differing_pixels, 1);
+    assert_eq!(diff.total_pixels, 256);
+    assert_eq!(diff.max_channel_difference, 0x88);
+    assert!(diff.psnr.is_finite());
+    assert!(diff.ssim < 1.0);
+    assert_eq!(count_differing_pixels(&first, &second, 0).unwrap(), 2);
+
+    let heatmap = heatmap(&first, &second).unwrap();
+    assert_eq!(heatmap.pixels()[17].red(), u8::MAX);
+    assert_eq!(heatmap.pixels()[17].green(), u8::MAX - 0x88);
+    assert_eq!(heatmap.pixels()[0].red(), 0x88 / 4);
+    assert!(diff_images(&first, &Pixmap::new(8, 8).unwrap(), 0).is_err());
+}
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use log::info;
+use tokio::fs::{copy, create_dir_all, write};
+use tokio::sync::Semaphore;
+use tracing::instrument;
+
+use crate::image_tasks::cloneable::CloneableError;
+
+/// Maximum number of files that can be in the process of being written at once, unless overridden
+/// with `--max-concurrent-writes`.
+pub const DEFAULT_MAX_CONCURRENT_WRITES: usize = 64;
+
+/// Writes output files as loose files in a directory tree instead of a ZIP file. Writes use tokio's
+/// async file IO, and at most a fixed number of them are in flight at once so that thousands of
+/// small PNGs don't tie up every blocking thread or exhaust file descriptors.
+#[derive(Debug)]
+pub struct DirectoryOutput {
+    root: PathBuf,
+    permits: Semaphore,
+}
+
+impl DirectoryOutput {
+    pub fn new(root: PathBuf, max_concurrent_writes: usize) -> Self {
+        info!(
+            "Writing loose files to {} with up to {} concurrent writes",
+            root.to_string_lossy(),
+            max_concurrent_writes
+        );
+        DirectoryOutput {
+            root,
+            permits: Semaphore::new(max_concurrent_writes),
+        }
+    }
+
+    async fn create_parent(&self, path: &str) -> Result<PathBuf, CloneableError> {
+        let full_path = self.root.join(path);
+        if let Some(parent) = full_path.parent() {
+            create_dir_all(parent).await?;
+        }
+        Ok(full_path)
+    }
+
+    #[instrument(skip(self, contents))]
+    pub async fn write(&self, path: &str, contents: Vec<u8>) -> Result<(), CloneableError> {
+        let _permit = self.permits.acquire().await?;
+        let full_path = self.create_parent(path).await?;
+        write(full_path, contents).await?;
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    pub async fn copy(&self, source_path: &str, dest_path: &str) -> Result<(), CloneableError> {
+        let _permit = self.permits.acquire().await?;
+        let full_dest_path = self.create_parent(dest_path).await?;
+        copy(self.root.join(source_path), full_dest_path).await?;
+        Ok(())
+    }
+}
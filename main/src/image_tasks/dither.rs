@@ -0,0 +1,158 @@
+use resvg::tiny_skia::Mask;
+use tracing::instrument;
+
+use crate::image_tasks::color::ComparableColor;
+use crate::image_tasks::crop::TileRect;
+use crate::image_tasks::task_spec::{FileOutputTaskSpec, ToAlphaChannelTaskSpec, ToPixmapTaskSpec};
+
+/// Thresholds of a 4x4 ordered (Bayer) dither, in sixteenths.
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// How much of a shadow or highlight layer survives [dither_shading], in sixteenths.
+pub const SHADE_COVERAGE: u8 = 8;
+
+/// Largest difference in hue, in degrees, between a base color and its shadow or highlight.
+const MAX_SHADE_HUE_DIFFERENCE: f32 = 30.0;
+
+/// Largest difference in HSL lightness between a base color and its shadow or highlight.
+const MAX_SHADE_LIGHTNESS_DIFFERENCE: f32 = 0.4;
+
+/// Colors less saturated than this count as gray, so their hue doesn't matter.
+const MAX_GRAY_SATURATION: f32 = 0.1;
+
+/// Makes each pixel fully opaque or fully transparent according to an ordered dither of its
+/// opacity times `coverage` sixteenths. The dither pattern repeats every 4 texels (sixteenths of
+/// the tile), so it lines up with the pixels of a vanilla texture at any tile size.
+#[instrument(skip(input))]
+pub fn dither_alpha(input: &mut Mask, coverage: u8) {
+    let width = input.width();
+    let texel = (width / TileRect::UNITS).max(1);
+    for (index, pixel) in input.data_mut().iter_mut().enumerate() {
+        let x = index as u32 % width / texel;
+        let y = index as u32 / width / texel;
+        let threshold = BAYER_4X4[(y % 4) as usize][(x % 4) as usize] as u32;
+        *pixel = if *pixel as u32 * coverage as u32 > threshold * u8::MAX as u32 {
+            u8::MAX
+        } else {
+            0
+        };
+    }
+}
+
+/// Whether `color` looks like a shadow or highlight of `base`: both are opaque, they differ in
+/// lightness but not by much, and their hues are close unless both are nearly gray.
+fn is_shade_of(color: ComparableColor, base: ComparableColor) -> bool {
+    if color == base || color.alpha() != u8::MAX || base.alpha() != u8::MAX {
+        return false;
+    }
+    let (hue, saturation, lightness) = color.to_hsl();
+    let (base_hue, base_saturation, base_lightness) = base.to_hsl();
+    let hue_difference = (hue - base_hue).abs() % 360.0;
+    let hue_difference = hue_difference.min(360.0 - hue_difference);
+    let both_gray = saturation < MAX_GRAY_SATURATION && base_saturation < MAX_GRAY_SATURATION;
+    (both_gray || hue_difference <= MAX_SHADE_HUE_DIFFERENCE)
+        && (lightness - base_lightness).abs() <= MAX_SHADE_LIGHTNESS_DIFFERENCE
+}
+
+/// Returns the painting-style version of an output, where every layer that shades the color it's
+/// stacked on is drawn as an ordered dither instead of solid. Shading is recognized by its color,
+/// since a material's shadow and highlight are only colors in its spec.
+pub fn dither_shading(task: &FileOutputTaskSpec) -> FileOutputTaskSpec {
+    task.map_base(&|base| dither_pixmap(base, None))
+}
+
+/// `base_color` is the color of the nearest [ToPixmapTaskSpec::StackLayerOnColor] that `spec` is
+/// drawn over, if any.
+fn dither_pixmap(spec: &ToPixmapTaskSpec, base_color: Option<ComparableColor>) -> ToPixmapTaskSpec {
+    match spec {
+        ToPixmapTaskSpec::PaintAlphaChannel { base, color } => match base_color {
+            Some(base_color) if is_shade_of(*color, base_color) => {
+                ToPixmapTaskSpec::PaintAlphaChannel {
+                    base: ToAlphaChannelTaskSpec::Dither {
+                        base: base.to_owned(),
+                        coverage: SHADE_COVERAGE,
+                    }
+                    .into(),
+                    color: *color,
+                }
+            }
+            _ => spec.to_owned(),
+        },
+        ToPixmapTaskSpec::StackLayerOnColor {
+            background,
+            foreground,
+        } => ToPixmapTaskSpec::StackLayerOnColor {
+            background: *background,
+            foreground: dither_pixmap(foreground, Some(*background)).into(),
+        },
+        _ => spec.map_children(
+            &|child| dither_pixmap(child, base_color),
+            &ToAlphaChannelTaskSpec::to_owned,
+        ),
+    }
+}
+
+#[test]
+fn test_dither_alpha() {
+    let mut mask = Mask::new(32, 32).unwrap();
+    mask.data_mut().fill(u8::MAX);
+    dither_alpha(&mut mask, 8);
+    // Each texel is 2x2 pixels
+    assert_eq!(mask.data()[0], u8::MAX);
+    assert_eq!(mask.data()[33], u8::MAX);
+    assert_eq!(mask.data()[2], 0);
+    assert_eq!(
+        mask.data()
+            .iter()
+            .filter(|alpha| **alpha == u8::MAX)
+            .count(),
+        32 * 32 / 2
+    );
+
+    let mut mask = Mask::new(16, 16).unwrap();
+    mask.data_mut().fill(u8::MAX / 2);
+    dither_alpha(&mut mask, 16);
+    assert_eq!(
+        mask.data()
+            .iter()
+            .filter(|alpha| **alpha == u8::MAX)
+            .count(),
+        16 * 16 / 2
+    );
+}
+
+#[test]
+fn test_dither_shading() {
+    use crate::image_tasks::color::c;
+    use crate::image_tasks::task_spec::{out_task, paint_svg_task};
+    use crate::stack_on;
+
+    let task = out_task(
+        "block/stone",
+        stack_on!(
+            ComparableColor::STONE,
+            paint_svg_task("borderSolid", ComparableColor::STONE_SHADOW),
+            paint_svg_task("bigDotsTop", c(0xba0000))
+        ),
+    );
+    let FileOutputTaskSpec::PngOutput { base, .. } = dither_shading(&task) else {
+        panic!("Not a PngOutput");
+    };
+    let dithered = base.to_string();
+    assert!(
+        dithered.contains("dither(alpha(borderSolid),8/16)"),
+        "{}",
+        dithered
+    );
+    assert!(
+        !dithered.contains("dither(alpha(bigDotsTop)"),
+        "{}",
+        dithered
+    );
+    assert!(is_shade_of(c(0x4a3324), c(0x6a4a33)));
+    assert!(!is_shade_of(ComparableColor::BLACK, ComparableColor::WHITE));
+    assert!(!is_shade_of(
+        ComparableColor::STONE * 0.5,
+        ComparableColor::STONE
+    ));
+}
@@ -0,0 +1,264 @@
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::color::ComparableColor;
+use crate::image_tasks::from_svg::svg_source;
+use crate::image_tasks::remap::PaletteMap;
+use crate::image_tasks::task_spec::{
+    detail_at_least, flip_task, from_raster_task, from_svg_task, on_grid, paint_svg_task,
+    paint_task, remap_task, rotate_task, stack, texture_of, ToAlphaChannelTaskSpec,
+    ToPixmapTaskSpec,
+};
+use crate::image_tasks::transform::Axis;
+
+/// Parses an expression in the notation that [ToPixmapTaskSpec]'s `Display` uses, apart from
+/// animations, crops and sheets, so that a stack of layers can be tried out without defining a
+/// material:
+///
+/// - `bricks` is the SVG `bricks.svg`, and `bricks@#7f7f7f` is it painted that color; `@` also
+///   paints the alpha channel of any other layer, such as `(bricks+borderSolid)@#000000`.
+/// - `a+b+c` stacks `b` on `a` and `c` on both. The bottom layer may be a color, such as
+///   `#7f7f7f+bricks@#000000`, and parentheses group layers.
+/// - `raster(name)` and `texture_of(block/stone)` are a raster image and another texture;
+///   `upscale(a)` renders `a` at [crate::GRID_SIZE] and upscales it, and `grid64(a)` declares that
+///   `a` is drawn on a 64x64 grid. `detail64(a)` only draws `a` at tile sizes of 64 and up.
+/// - `rotate90(a)` turns `a` clockwise by 90, 180 or 270 degrees, and `fliph(a)` and `flipv(a)`
+///   mirror it left to right and top to bottom.
+/// - `remap[#ffffff>#8a3a00,#000000>transparent](a)` replaces each listed color of `a` with the
+///   one after `>`.
+pub fn parse_expr(expr: &str) -> Result<ToPixmapTaskSpec, CloneableError> {
+    let mut parser = Parser {
+        input: expr,
+        pos: 0,
+    };
+    let parsed = parser.stack()?;
+    parser.skip_whitespace();
+    if parser.pos < expr.len() {
+        return Err(parser.error("Expected + or the end"));
+    }
+    Ok(parsed)
+}
+
+/// Parses the `Display` of a [PaletteMap], such as `#ffffff>#8a3a00,#000000>transparent`.
+fn parse_palette_map(pairs: &str) -> Result<PaletteMap, CloneableError> {
+    let pairs = pairs
+        .split(',')
+        .map(|pair| {
+            let (from, to) = pair
+                .split_once('>')
+                .ok_or_else(|| anyhoo!("Expected a color>color pair: {}", pair))?;
+            Ok((
+                from.trim().parse::<ComparableColor>()?,
+                to.trim().parse::<ComparableColor>()?,
+            ))
+        })
+        .collect::<Result<Vec<_>, CloneableError>>()?;
+    Ok(PaletteMap::new(pairs))
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+/// One operand of `+`, which can only be a color if it's the first.
+enum Term {
+    Color(ComparableColor),
+    Layer(ToPixmapTaskSpec),
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> CloneableError {
+        anyhoo!("{} at column {} of {}", message, self.pos + 1, self.input)
+    }
+
+    fn rest(&self) -> &str {
+        &self.input[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.input.len() - self.rest().trim_start().len();
+    }
+
+    /// Consumes `token` if it's next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), CloneableError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected {}", token)))
+        }
+    }
+
+    /// A name, color or function name: everything up to the next operator or parenthesis.
+    fn word(&mut self) -> Result<&str, CloneableError> {
+        self.skip_whitespace();
+        let len = self
+            .rest()
+            .find(|c: char| c.is_whitespace() || "()+@".contains(c))
+            .unwrap_or(self.rest().len());
+        if len == 0 {
+            return Err(self.error("Expected a layer"));
+        }
+        let word = &self.input[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(word)
+    }
+
+    fn stack(&mut self) -> Result<ToPixmapTaskSpec, CloneableError> {
+        let (background, mut layers) = match self.term()? {
+            Term::Color(color) => {
+                self.expect("+")?;
+                (color, self.layer()?)
+            }
+            Term::Layer(layer) => (ComparableColor::TRANSPARENT, layer),
+        };
+        while self.eat("+") {
+            layers = stack(layers, self.layer()?);
+        }
+        Ok(if background == ComparableColor::TRANSPARENT {
+            layers
+        } else {
+            ToPixmapTaskSpec::StackLayerOnColor {
+                background,
+                foreground: layers.into(),
+            }
+        })
+    }
+
+    fn layer(&mut self) -> Result<ToPixmapTaskSpec, CloneableError> {
+        match self.term()? {
+            Term::Layer(layer) => Ok(layer),
+            Term::Color(_) => Err(self.error("Only the bottom layer can be a color")),
+        }
+    }
+
+    fn term(&mut self) -> Result<Term, CloneableError> {
+        let term = self.atom()?;
+        if !self.eat("@") {
+            return Ok(term);
+        }
+        let Term::Layer(layer) = term else {
+            return Err(self.error("Can't paint a color"));
+        };
+        let color: ComparableColor = self.word()?.parse()?;
+        Ok(Term::Layer(match layer {
+            ToPixmapTaskSpec::FromSvg { source } => paint_svg_task(source, color),
+            layer => paint_task(ToAlphaChannelTaskSpec::FromPixmap { base: layer }, color),
+        }))
+    }
+
+    fn atom(&mut self) -> Result<Term, CloneableError> {
+        if self.eat("(") {
+            let inner = self.stack()?;
+            self.expect(")")?;
+            return Ok(Term::Layer(inner));
+        }
+        let word = self.word()?.to_owned();
+        if word.starts_with('#') || word == "transparent" {
+            return Ok(Term::Color(word.parse()?));
+        }
+        if !self.eat("(") {
+            if svg_source(&format!("{}.svg", word)).is_none() {
+                return Err(anyhoo!("No SVG named {} in {}", word, self.input));
+            }
+            return Ok(Term::Layer(from_svg_task(word)));
+        }
+        let layer = match &*word {
+            "raster" => from_raster_task(self.word()?.to_owned()),
+            "texture_of" => texture_of(self.word()?.to_owned()),
+            "upscale" => ToPixmapTaskSpec::UpscaleFromGridSize {
+                base: self.stack()?.into(),
+            },
+            "fliph" => flip_task(self.stack()?, Axis::Horizontal),
+            "flipv" => flip_task(self.stack()?, Axis::Vertical),
+            _ => {
+                if let Some(Ok(grid_size)) = word.strip_prefix("grid").map(str::parse::<u32>) {
+                    on_grid(grid_size, self.stack()?)
+                } else if let Some(Ok(min_tile_size)) =
+                    word.strip_prefix("detail").map(str::parse::<u32>)
+                {
+                    detail_at_least(min_tile_size, self.stack()?)
+                } else if let Some(Ok(degrees @ (90 | 180 | 270))) =
+                    word.strip_prefix("rotate").map(str::parse::<u32>)
+                {
+                    rotate_task(self.stack()?, (degrees / 90) as u8)
+                } else if let Some(pairs) = word
+                    .strip_prefix("remap[")
+                    .and_then(|pairs| pairs.strip_suffix(']'))
+                {
+                    remap_task(self.stack()?, parse_palette_map(pairs)?)
+                } else {
+                    return Err(anyhoo!("Unknown function {} in {}", word, self.input));
+                }
+            }
+        };
+        self.expect(")")?;
+        Ok(Term::Layer(layer))
+    }
+}
+
+#[test]
+fn test_parse_expr() {
+    use crate::image_tasks::color::c;
+    use crate::{stack, stack_on};
+
+    assert_eq!(parse_expr("bricks").unwrap(), from_svg_task("bricks"));
+    let painted = parse_expr("#7f7f7f + bricks@#000000 + borderSolid@#ff0000").unwrap();
+    assert_eq!(
+        painted,
+        stack_on!(
+            c(0x7f7f7f),
+            paint_svg_task("bricks", ComparableColor::BLACK),
+            paint_svg_task("borderSolid", ComparableColor::RED)
+        )
+    );
+    assert_eq!(parse_expr(&painted.to_string()).unwrap(), painted);
+    assert_eq!(
+        parse_expr("upscale(bricks+borderSolid)").unwrap(),
+        ToPixmapTaskSpec::UpscaleFromGridSize {
+            base: stack!(from_svg_task("bricks"), from_svg_task("borderSolid")).into()
+        }
+    );
+    assert_eq!(
+        parse_expr("grid64(texture_of(block/stone))").unwrap(),
+        on_grid(64, texture_of("block/stone"))
+    );
+    assert_eq!(
+        parse_expr("detail256(bricks)").unwrap(),
+        detail_at_least(256, from_svg_task("bricks"))
+    );
+    assert!(parse_expr("bricks+#000000").is_err());
+    assert!(parse_expr("doesNotExist").is_err());
+    assert!(parse_expr("(bricks").is_err());
+    assert!(parse_expr("bricks)").is_err());
+    assert!(parse_expr("blur(bricks)").is_err());
+    let turned = parse_expr("rotate270(fliph(bricks))").unwrap();
+    assert_eq!(
+        turned,
+        rotate_task(flip_task(from_svg_task("bricks"), Axis::Horizontal), 3)
+    );
+    assert_eq!(parse_expr(&turned.to_string()).unwrap(), turned);
+    assert!(parse_expr("rotate45(bricks)").is_err());
+    let recolored = parse_expr("remap[#ffffff>#8a3a00,#000000>transparent](bricks)").unwrap();
+    assert_eq!(
+        recolored,
+        remap_task(
+            from_svg_task("bricks"),
+            PaletteMap::new([
+                (ComparableColor::WHITE, c(0x8a3a00)),
+                (ComparableColor::BLACK, ComparableColor::TRANSPARENT)
+            ])
+        )
+    );
+    assert_eq!(parse_expr(&recolored.to_string()).unwrap(), recolored);
+    assert!(parse_expr("remap[#ffffff](bricks)").is_err());
+}
@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use log::info;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use resvg::tiny_skia::{FilterQuality, Pixmap, PixmapPaint, Transform};
+use tracing::instrument;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::task_spec::RASTER_DIR;
+use crate::image_tasks::upscale::{downscale_image, upscale_image};
+use crate::image_tasks::vanilla::{vanilla_png, VANILLA_PREFIX};
+use crate::image_tasks::{allocate_pixmap_empty, MaybeFromPool};
+
+type RasterCache = HashMap<String, Arc<OnceCell<Arc<Pixmap>>>>;
+
+/// Decoded PNG layers, keyed by source, so that each is only read and decoded once.
+static RASTERS: Lazy<Mutex<RasterCache>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Decodes `<source>.png` from [RASTER_DIR] if it's there, a texture from the vanilla jar if
+/// `source` starts with [VANILLA_PREFIX], or else the file at the path `source`.
+fn decoded_raster(source: &str) -> Result<Arc<Pixmap>, CloneableError> {
+    let cached = RASTERS.lock().entry(source.to_owned()).or_default().clone();
+    let pixmap = cached.get_or_try_init(|| {
+        let png = if let Some(texture) = source.strip_prefix(VANILLA_PREFIX) {
+            vanilla_png(texture)?
+        } else if let Some(file) = RASTER_DIR.get_file(PathBuf::from(format!("{}.png", source))) {
+            file.contents().to_vec()
+        } else {
+            fs::read(source)?
+        };
+        info!("Decoding {}", source);
+        Ok::<_, CloneableError>(Arc::new(Pixmap::decode_png(&png)?))
+    })?;
+    Ok(pixmap.clone())
+}
+
+/// Loads a PNG layer and scales it to the given width, keeping its aspect ratio. Whole-number
+/// scale factors use [upscale_image] or [downscale_image]; anything else is resampled bicubically.
+#[instrument]
+pub fn from_raster(source: &str, width: u32) -> Result<MaybeFromPool<Pixmap>, CloneableError> {
+    let raster = decoded_raster(source)?;
+    if width >= raster.width() && width.is_multiple_of(raster.width()) {
+        return upscale_image(&raster, width / raster.width());
+    }
+    if raster.width().is_multiple_of(width)
+        && raster.height().is_multiple_of(raster.width() / width)
+    {
+        return downscale_image(&raster, raster.width() / width);
+    }
+    let scale = width as f32 / raster.width() as f32;
+    let height = (raster.height() as f32 * scale).round() as u32;
+    let mut out = allocate_pixmap_empty(width, height);
+    out.draw_pixmap(
+        0,
+        0,
+        raster.as_ref().as_ref(),
+        &PixmapPaint {
+            quality: FilterQuality::Bicubic,
+            ..PixmapPaint::default()
+        },
+        Transform::from_scale(scale, scale),
+        None,
+    );
+    Ok(out)
+}
+
+#[test]
+fn test_from_raster() {
+    use crate::image_tasks::color::{c, ComparableColor};
+
+    let mut source = Pixmap::new(2, 4).unwrap();
+    source.pixels_mut()[0] = c(0x8a3a00).into();
+    source.pixels_mut()[7] = ComparableColor::WHITE.into();
+    let path = std::env::temp_dir().join(format!("ochd-test-raster-{}.png", std::process::id()));
+    source.save_png(&path).unwrap();
+    let path = path.to_str().unwrap();
+
+    let upscaled = from_raster(path, 4).unwrap();
+    assert_eq!((upscaled.width(), upscaled.height()), (4, 8));
+    assert_eq!(ComparableColor::from(upscaled.pixels()[5]), c(0x8a3a00));
+    assert_eq!(
+        ComparableColor::from(upscaled.pixels()[31]),
+        ComparableColor::WHITE
+    );
+    assert_eq!(
+        ComparableColor::from(upscaled.pixels()[2]),
+        ComparableColor::TRANSPARENT
+    );
+    let downscaled = from_raster(path, 1).unwrap();
+    assert_eq!((downscaled.width(), downscaled.height()), (1, 2));
+    assert_eq!(from_raster(path, 3).unwrap().height(), 6);
+    fs::remove_file(path).unwrap();
+    assert!(from_raster("doesNotExist", 4).is_err());
+}
@@ -1,15 +1,22 @@
+use log::{info, warn};
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
 use resvg::render;
-use std::path::PathBuf;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use resvg::tiny_skia::{Pixmap, Transform};
 use resvg::usvg::fontdb::Database;
 use resvg::usvg::{Options, Tree};
 use tracing::instrument;
 
-use crate::anyhoo;
 use crate::image_tasks::cloneable::CloneableError;
 use crate::image_tasks::task_spec::SVG_DIR;
 use crate::image_tasks::{allocate_pixmap_empty, MaybeFromPool};
+use crate::{anyhoo, option_value};
 
 pub const COLOR_SVGS: &[&str] = &[
     "barrelSlats",
@@ -95,8 +102,7 @@ pub const COLOR_SVGS: &[&str] = &[
     "doorKnob",
     "furnaceFrontLit",
     "loopArrow4x",
-    "soulFlameTorch",
-    "soulFlameTorchSmall",
+    "soulTorchFlameSmall",
     "torchFlame",
     "torchFlameSmall",
     "torchRedstoneHead",
@@ -180,13 +186,57 @@ pub const SEMITRANSPARENCY_FREE_SVGS: &[&str] = &[
     "trapdoor1",
 ];
 
+/// The folder named by `--svg-dir`, if any. An SVG found there is used instead of the built-in one
+/// with the same name, so that a layer can be edited without recompiling.
+pub static SVG_OVERRIDE_DIR: Lazy<Option<PathBuf>> =
+    Lazy::new(|| option_value("svg-dir").map(PathBuf::from));
+
+fn svg_source_in(override_dir: Option<&Path>, path: &str) -> Option<Cow<'static, [u8]>> {
+    if let Some(file) = override_dir
+        .map(|dir| dir.join(path))
+        .filter(|file| file.is_file())
+    {
+        info!("Using {} from {}", path, file.to_string_lossy());
+        match fs::read(&file) {
+            Ok(contents) => return Some(Cow::Owned(contents)),
+            Err(error) => warn!("Failed to read {}: {}", file.to_string_lossy(), error),
+        }
+    }
+    SVG_DIR
+        .get_file(PathBuf::from(path))
+        .map(|svg| Cow::Borrowed(svg.contents()))
+}
+
+/// The contents of the SVG file `path`, such as `borderSolid.svg`, from [SVG_OVERRIDE_DIR] if it's
+/// there, or else from [SVG_DIR].
+pub fn svg_source(path: &str) -> Option<Cow<'static, [u8]>> {
+    svg_source_in(SVG_OVERRIDE_DIR.as_deref(), path)
+}
+
+type SvgTreeCache = HashMap<String, Arc<OnceCell<Arc<Tree>>>>;
+
+/// Parsed SVGs, keyed by file name, so that an SVG used at more than one size or in more than one
+/// texture is only parsed once.
+static SVG_TREES: Lazy<Mutex<SvgTreeCache>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn svg_tree(path: &str) -> Result<Arc<Tree>, CloneableError> {
+    let cached = SVG_TREES.lock().entry(path.to_owned()).or_default().clone();
+    let tree = cached.get_or_try_init(|| {
+        let svg = svg_source(path).ok_or(anyhoo!(format!("File not found: {}", path)))?;
+        info!("Parsing {}", path);
+        Ok::<_, CloneableError>(Arc::new(Tree::from_data(
+            &svg,
+            &Options::default(),
+            &Database::new(),
+        )?))
+    })?;
+    Ok(tree.clone())
+}
+
 #[instrument]
 pub fn from_svg(mut path: String, width: u32) -> Result<MaybeFromPool<Pixmap>, CloneableError> {
     path.push_str(".svg");
-    let svg = SVG_DIR
-        .get_file(PathBuf::from(&path))
-        .ok_or(anyhoo!(format!("File not found: {}", path)))?;
-    let svg_tree = Tree::from_data(svg.contents(), &Options::default(), &Database::new())?;
+    let svg_tree = svg_tree(&path)?;
     let view_box = svg_tree.view_box();
     let height = f64::from(width) * view_box.rect.height() as f64 / view_box.rect.width() as f64;
     let scale = (width as f64 / svg_tree.size().width() as f64) as f32;
@@ -198,3 +248,29 @@ pub fn from_svg(mut path: String, width: u32) -> Result<MaybeFromPool<Pixmap>, C
     );
     Ok(out)
 }
+
+#[test]
+fn test_svg_tree_cache() {
+    let first = svg_tree("borderSolid.svg").unwrap();
+    let second = svg_tree("borderSolid.svg").unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert!(svg_tree("doesNotExist.svg").is_err());
+    assert_eq!(from_svg("borderSolid".into(), 32).unwrap().width(), 32);
+}
+
+#[test]
+fn test_svg_override_dir() {
+    let dir = std::env::temp_dir().join(format!("ochd-test-svg-dir-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("borderSolid.svg"), "<svg/>").unwrap();
+    assert_eq!(
+        &*svg_source_in(Some(&dir), "borderSolid.svg").unwrap(),
+        b"<svg/>"
+    );
+    assert_eq!(
+        &*svg_source_in(Some(&dir), "borderDotted.svg").unwrap(),
+        SVG_DIR.get_file("borderDotted.svg").unwrap().contents()
+    );
+    assert!(svg_source_in(Some(&dir), "doesNotExist.svg").is_none());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,294 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+
+use itertools::Itertools;
+use log::info;
+use once_cell::sync::Lazy;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::task_spec::{
+    FileOutputTaskSpec, TaskGraphBuildingContext, ToAlphaChannelTaskSpec, ToPixmapTaskSpec,
+};
+use crate::option_value;
+
+/// The Graphviz file named by `--emit-graph`, if any. When it's set, the graph of image tasks is
+/// written there once every output task has been added, with an arrow from each image to the ones
+/// made from it. Red arrows lead from an image that's shared, so that it's only made once, to
+/// each image after the first that uses it.
+pub static EMIT_GRAPH: Lazy<Option<String>> = Lazy::new(|| option_value("emit-graph"));
+
+/// Builds the DOT source, visiting each distinct task once.
+struct GraphWriter<'a> {
+    ctx: &'a TaskGraphBuildingContext,
+    dot: String,
+    visited: HashSet<String>,
+}
+
+/// Quotes an ID or label, with any line breaks in it kept.
+fn quote(text: &str) -> String {
+    format!(
+        "\"{}\"",
+        text.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n")
+    )
+}
+
+fn sizes_label(sizes: &[u32]) -> String {
+    if sizes.is_empty() {
+        String::new()
+    } else {
+        format!("\nat {}", sizes.iter().join(", "))
+    }
+}
+
+impl GraphWriter<'_> {
+    /// Adds a node that hasn't been visited.
+    fn node(&mut self, id: &str, label: &str, shape: &str) {
+        self.visited.insert(id.to_owned());
+        writeln!(
+            self.dot,
+            "  {} [label={}, shape={}];",
+            quote(id),
+            quote(label),
+            shape
+        )
+        .unwrap();
+    }
+
+    /// An arrow from an input to what's made from it, highlighted if the input was already used.
+    fn edge(&mut self, (input, new): (String, bool), output: &str) {
+        let style = if new { "" } else { " [color=red, penwidth=2]" };
+        writeln!(
+            self.dot,
+            "  {} -> {}{};",
+            quote(&input),
+            quote(output),
+            style
+        )
+        .unwrap();
+    }
+
+    fn pixmap(&mut self, spec: &ToPixmapTaskSpec) -> (String, bool) {
+        let id = format!("p{}", spec.node_id());
+        if self.visited.contains(&id) {
+            return (id, false);
+        }
+        let kind = match spec {
+            ToPixmapTaskSpec::Animate { layout, frames, .. } => {
+                format!("animate[{}] {} frames", layout, frames.len())
+            }
+            ToPixmapTaskSpec::FromSvg { source } => format!("svg {}", source),
+            ToPixmapTaskSpec::FromRaster { source } => format!("raster {}", source),
+            ToPixmapTaskSpec::TextureOf { name } => format!("texture_of {}", name),
+            ToPixmapTaskSpec::PaintAlphaChannel { color, .. } => format!("paint {}", color),
+            ToPixmapTaskSpec::StackLayerOnColor { background, .. } => {
+                format!("stack on {}", background)
+            }
+            ToPixmapTaskSpec::StackLayerOnLayer { .. } => "stack".to_owned(),
+            ToPixmapTaskSpec::UpscaleFromGridSize { .. } => "upscale".to_owned(),
+            ToPixmapTaskSpec::OnGrid { grid_size, .. } => format!("grid{}", grid_size),
+            ToPixmapTaskSpec::DetailAtLeast { min_tile_size, .. } => {
+                format!("detail at least {}", min_tile_size)
+            }
+            ToPixmapTaskSpec::CropAndScale { from, to, .. } => {
+                format!("crop {} to {}", from, to)
+            }
+            ToPixmapTaskSpec::Rotate { quarter_turns, .. } => {
+                format!("rotate {}", *quarter_turns as u32 * 90)
+            }
+            ToPixmapTaskSpec::Flip { axis, .. } => format!("flip {:?}", axis),
+            ToPixmapTaskSpec::Remap { mapping, .. } => {
+                format!("remap {} colors", mapping.pairs().len())
+            }
+            ToPixmapTaskSpec::ShiftColors { shift, .. } => format!("shift colors {}", shift),
+            ToPixmapTaskSpec::PlaceOnSheet { width, height, .. } => {
+                format!("sheet {}x{}", width, height)
+            }
+        };
+        let label = kind + &sizes_label(&self.ctx.pixmap_tile_sizes(spec));
+        self.node(&id, &label, "box");
+        match spec {
+            ToPixmapTaskSpec::Animate {
+                background, frames, ..
+            } => {
+                let input = self.pixmap(background);
+                self.edge(input, &id);
+                for frame in frames.iter() {
+                    let input = self.pixmap(frame);
+                    self.edge(input, &id);
+                }
+            }
+            ToPixmapTaskSpec::FromSvg { .. } | ToPixmapTaskSpec::FromRaster { .. } => {}
+            ToPixmapTaskSpec::TextureOf { name } => {
+                let input = self.pixmap(&self.ctx.resolve_texture(name));
+                self.edge(input, &id);
+            }
+            ToPixmapTaskSpec::PaintAlphaChannel { base, .. } => {
+                let input = self.alpha(base);
+                self.edge(input, &id);
+            }
+            ToPixmapTaskSpec::StackLayerOnColor { foreground, .. } => {
+                let input = self.pixmap(foreground);
+                self.edge(input, &id);
+            }
+            ToPixmapTaskSpec::StackLayerOnLayer {
+                background,
+                foreground,
+            } => {
+                let input = self.pixmap(background);
+                self.edge(input, &id);
+                let input = self.pixmap(foreground);
+                self.edge(input, &id);
+            }
+            ToPixmapTaskSpec::UpscaleFromGridSize { base }
+            | ToPixmapTaskSpec::OnGrid { base, .. }
+            | ToPixmapTaskSpec::DetailAtLeast { base, .. }
+            | ToPixmapTaskSpec::CropAndScale { base, .. }
+            | ToPixmapTaskSpec::Rotate { base, .. }
+            | ToPixmapTaskSpec::Flip { base, .. }
+            | ToPixmapTaskSpec::Remap { base, .. }
+            | ToPixmapTaskSpec::ShiftColors { base, .. } => {
+                let input = self.pixmap(base);
+                self.edge(input, &id);
+            }
+            ToPixmapTaskSpec::PlaceOnSheet { placements, .. } => {
+                for (layer, _) in placements.iter() {
+                    let input = self.pixmap(layer);
+                    self.edge(input, &id);
+                }
+            }
+        }
+        (id, true)
+    }
+
+    fn alpha(&mut self, spec: &ToAlphaChannelTaskSpec) -> (String, bool) {
+        let id = format!("a{}", spec.node_id());
+        if self.visited.contains(&id) {
+            return (id, false);
+        }
+        let kind = match spec {
+            ToAlphaChannelTaskSpec::MakeSemitransparent { alpha, .. } => {
+                format!("alpha * {}", alpha)
+            }
+            ToAlphaChannelTaskSpec::FromPixmap { .. } => "alpha of".to_owned(),
+            ToAlphaChannelTaskSpec::StackAlphaOnAlpha { .. } => "stack alpha".to_owned(),
+            ToAlphaChannelTaskSpec::StackAlphaOnBackground { background, .. } => {
+                format!("stack alpha on {}", background)
+            }
+            ToAlphaChannelTaskSpec::UpscaleFromGridSize { .. } => "upscale alpha".to_owned(),
+            ToAlphaChannelTaskSpec::Dither { coverage, .. } => {
+                format!("dither {}/16", coverage)
+            }
+        };
+        let label = kind + &sizes_label(&self.ctx.alpha_tile_sizes(spec));
+        self.node(&id, &label, "ellipse");
+        match spec {
+            ToAlphaChannelTaskSpec::MakeSemitransparent { base, .. }
+            | ToAlphaChannelTaskSpec::UpscaleFromGridSize { base }
+            | ToAlphaChannelTaskSpec::Dither { base, .. } => {
+                let input = self.alpha(base);
+                self.edge(input, &id);
+            }
+            ToAlphaChannelTaskSpec::FromPixmap { base } => {
+                let input = self.pixmap(base);
+                self.edge(input, &id);
+            }
+            ToAlphaChannelTaskSpec::StackAlphaOnAlpha {
+                background,
+                foreground,
+            } => {
+                let input = self.alpha(background);
+                self.edge(input, &id);
+                let input = self.alpha(foreground);
+                self.edge(input, &id);
+            }
+            ToAlphaChannelTaskSpec::StackAlphaOnBackground { foreground, .. } => {
+                let input = self.alpha(foreground);
+                self.edge(input, &id);
+            }
+        }
+        (id, true)
+    }
+
+    fn output(&mut self, task: &FileOutputTaskSpec) -> (String, bool) {
+        let paths = task.get_paths();
+        let id = format!("o{}", paths[0]);
+        if self.visited.contains(&id) {
+            return (id, false);
+        }
+        self.node(&id, &paths.join("\n"), "note");
+        let input = match task {
+            FileOutputTaskSpec::PngOutput { base, .. } => self.pixmap(base),
+            FileOutputTaskSpec::Copy { original, .. } => self.output(original),
+        };
+        self.edge(input, &id);
+        (id, true)
+    }
+}
+
+/// The DOT source for the graph of everything the given output tasks are made from. Tile sizes
+/// come from `ctx`, which must already have the textures they refer to.
+pub fn task_graph_dot<'a, T: IntoIterator<Item = &'a FileOutputTaskSpec>>(
+    tasks: T,
+    ctx: &TaskGraphBuildingContext,
+) -> String {
+    let mut writer = GraphWriter {
+        ctx,
+        dot: String::from("digraph tasks {\n  rankdir=LR;\n"),
+        visited: HashSet::new(),
+    };
+    for task in tasks.into_iter().sorted_by_key(|task| task.get_path()) {
+        writer.output(task);
+    }
+    writer.dot.push_str("}\n");
+    writer.dot
+}
+
+/// Writes the graph of the output tasks in `ctx` to [EMIT_GRAPH], if it's set.
+pub fn write_task_graph(ctx: &TaskGraphBuildingContext) -> Result<(), CloneableError> {
+    let Some(path) = EMIT_GRAPH.as_ref() else {
+        return Ok(());
+    };
+    let dot = task_graph_dot(ctx.output_task_to_future_map.keys(), ctx);
+    fs::write(path, &dot)?;
+    info!(
+        "Wrote the graph of {} output tasks to {}",
+        ctx.output_task_to_future_map.len(),
+        path
+    );
+    Ok(())
+}
+
+#[test]
+fn test_task_graph_dot() {
+    use crate::image_tasks::color::ComparableColor;
+    use crate::image_tasks::task_spec::{alias_task, out_task, paint_svg_task, stack, texture_of};
+
+    let border = paint_svg_task("borderSolid", ComparableColor::RED);
+    let stone = out_task("block/stone", border.to_owned());
+    let bricks = out_task(
+        "block/stone_bricks",
+        stack(
+            border.to_owned(),
+            paint_svg_task("bricks", ComparableColor::STONE),
+        ),
+    );
+    let smooth = out_task("block/smooth_stone", texture_of("block/stone"));
+    let aliased = alias_task(stone.to_owned(), ["block/stone_slab"]);
+    let mut ctx = TaskGraphBuildingContext::new();
+    ctx.add_texture_names(&[stone.to_owned(), bricks.to_owned()]);
+    let dot = task_graph_dot([&bricks, &smooth, &aliased, &stone], &ctx);
+    assert!(dot.starts_with("digraph tasks {\n"));
+    assert!(dot.ends_with("}\n"));
+    assert!(dot.contains("label=\"paint #ff0000ff\""));
+    assert!(dot.contains("label=\"texture_of block/stone\""));
+    // The border is used three times and the stone texture twice, but each is only listed once
+    assert_eq!(dot.matches("color=red").count(), 3);
+    assert_eq!(
+        dot.matches(&format!("\"p{}\" [", border.node_id())).count(),
+        1
+    );
+    assert!(dot.contains("\"oassets/minecraft/textures/block/stone.png\" -> "));
+}
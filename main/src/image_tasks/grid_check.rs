@@ -0,0 +1,136 @@
+use std::collections::BTreeSet;
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use resvg::tiny_skia::Pixmap;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::diffing::{diff_images, ImageDiff};
+use crate::image_tasks::from_svg::{from_svg, svg_source};
+use crate::image_tasks::search::Ingredients;
+use crate::image_tasks::task_spec::{from_svg_task, FileOutputTaskSpec, TaskGraphBuildingContext};
+use crate::image_tasks::upscale::upscale_image;
+use crate::{anyhoo, flag_present, parsed_option, GRID_SIZE, TILE_SIZE};
+
+/// Whether `--verify-grid-perfect` was given, so that SVGs treated as grid-perfect are checked by
+/// rendering them before the build starts.
+pub static VERIFY_GRID_PERFECT: Lazy<bool> = Lazy::new(|| flag_present("verify-grid-perfect"));
+
+/// How many SVGs `--verify-grid-perfect` checks, set with `--grid-check-sample`. Every one that's
+/// used is checked if this isn't set.
+static GRID_CHECK_SAMPLE: Lazy<Option<usize>> = Lazy::new(|| parsed_option("grid-check-sample"));
+
+/// How far apart a channel of the upscaled and direct renders can be before the pixel counts as
+/// different, to allow for rounding in the rasterizer.
+const TOLERANCE: u8 = 2;
+
+/// Picks `count` items spread evenly through `items`, so that the same ones are picked every time.
+fn evenly_spaced<T: Clone>(items: &[T], count: usize) -> Vec<T> {
+    if count >= items.len() {
+        return items.to_vec();
+    }
+    (0..count)
+        .map(|index| items[index * items.len() / count].to_owned())
+        .collect()
+}
+
+/// How `direct` differs from `small` upscaled to the same size by nearest neighbour, or `None` if
+/// the sizes aren't a whole multiple of each other.
+fn diff_upscale(small: &Pixmap, direct: &Pixmap) -> Result<Option<ImageDiff>, CloneableError> {
+    if !direct.width().is_multiple_of(small.width())
+        || direct.width() / small.width() * small.height() != direct.height()
+    {
+        return Ok(None);
+    }
+    let upscaled = upscale_image(small, direct.width() / small.width())?;
+    Ok(Some(diff_images(&upscaled, direct, TOLERANCE)?))
+}
+
+/// Renders `svg` at [GRID_SIZE] and at `size`, and returns a description of how they differ if the
+/// nearest-neighbour upscale of the first isn't the second.
+fn check_svg(svg: &str, size: u32) -> Result<Option<String>, CloneableError> {
+    let small = from_svg(svg.to_owned(), *GRID_SIZE)?;
+    let direct = from_svg(svg.to_owned(), size)?;
+    Ok(match diff_upscale(&small, &direct)? {
+        Some(diff) if diff.differing_pixels == 0 => None,
+        Some(diff) => Some(format!(
+            "{}: {} of {} pixels differ when upscaled from {} to {} (PSNR {:.1} dB, SSIM {:.4})",
+            svg, diff.differing_pixels, diff.total_pixels, *GRID_SIZE, size, diff.psnr, diff.ssim
+        )),
+        None => Some(format!(
+            "{}: renders at {}x{} and {}x{}, which aren't in proportion",
+            svg,
+            small.width(),
+            small.height(),
+            direct.width(),
+            direct.height()
+        )),
+    })
+}
+
+/// With `--verify-grid-perfect`, renders a sample of the SVGs that `tasks` use and that
+/// [crate::image_tasks::task_spec::ToPixmapTaskSpec::is_grid_perfect] trusts to be drawn on the
+/// grid, and fails with a list of the ones whose upscaled render doesn't match a direct render at
+/// the tile size. Such SVGs come out blocky, and should be taken off
+/// [crate::image_tasks::from_svg::SEMITRANSPARENCY_FREE_SVGS].
+pub fn verify_grid_perfect_svgs(
+    tasks: &[FileOutputTaskSpec],
+    ctx: &mut TaskGraphBuildingContext,
+) -> Result<(), CloneableError> {
+    if !*VERIFY_GRID_PERFECT {
+        return Ok(());
+    }
+    let grid_perfect: Vec<String> = tasks
+        .iter()
+        .flat_map(|task| Ingredients::of(task).layers)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .filter(|layer| svg_source(&format!("{}.svg", layer)).is_some())
+        .filter(|svg| from_svg_task(svg.to_owned()).is_grid_perfect(ctx))
+        .collect();
+    let sample = evenly_spaced(
+        &grid_perfect,
+        GRID_CHECK_SAMPLE.unwrap_or(grid_perfect.len()),
+    );
+    // Comparing at GRID_SIZE would be comparing a render with itself
+    let size = if *TILE_SIZE > *GRID_SIZE {
+        *TILE_SIZE
+    } else {
+        *GRID_SIZE * 4
+    };
+    let mut misclassified = Vec::new();
+    for svg in sample.iter() {
+        if let Some(mismatch) = check_svg(svg, size)? {
+            warn!("Not grid-perfect: {}", mismatch);
+            misclassified.push(mismatch);
+        }
+    }
+    info!(
+        "Checked {} of {} grid-perfect SVGs at {}",
+        sample.len(),
+        grid_perfect.len(),
+        size
+    );
+    if misclassified.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhoo!(
+            "{} SVGs are treated as grid-perfect but aren't:\n{}",
+            misclassified.len(),
+            misclassified.join("\n")
+        ))
+    }
+}
+
+#[test]
+fn test_evenly_spaced() {
+    let items: Vec<u32> = (0..10).collect();
+    assert_eq!(evenly_spaced(&items, 3), vec![0, 3, 6]);
+    assert_eq!(evenly_spaced(&items, 20), items);
+}
+
+#[test]
+fn test_check_svg() {
+    assert_eq!(check_svg("borderSolid", *GRID_SIZE * 4).unwrap(), None);
+    assert!(check_svg("circle24", *GRID_SIZE * 4).unwrap().is_some());
+}
@@ -0,0 +1,78 @@
+use crate::image_tasks::color::ComparableColor;
+use crate::image_tasks::task_spec::{FileOutputTaskSpec, ToPixmapTaskSpec};
+
+/// How much farther from middle gray each color's lightness is in the high-contrast pack.
+pub const CONTRAST_STRETCH: f32 = 1.5;
+
+/// Outlines that are replaced with the next thicker one in the high-contrast pack, so that block
+/// edges are easier to tell apart.
+const THICKER_OUTLINES: &[(&str, &str)] = &[
+    ("borderSolid", "borderSolidThick"),
+    ("borderSolidThick", "borderSolidExtraThick"),
+    ("strokeBottomLeftTopRight", "strokeBottomLeftTopRightThick"),
+    ("strokeTopLeftBottomRight", "strokeTopLeftBottomRightThick"),
+];
+
+fn stretch_contrast(color: ComparableColor) -> ComparableColor {
+    if color.alpha() == 0 {
+        color
+    } else {
+        color.stretch_oklab_lightness(CONTRAST_STRETCH)
+    }
+}
+
+/// Returns the high-contrast version of an output: every color is stretched away from middle gray
+/// and outlines are thickened, so that the same material definitions also build a pack that's
+/// easier to see. Color SVGs and rasters are left as they are, since their colors aren't part of
+/// the spec.
+pub fn high_contrast_output(task: &FileOutputTaskSpec) -> FileOutputTaskSpec {
+    // TextureOf resolves to the other texture's high-contrast version, as long as the
+    // high-contrast outputs are the ones passed to add_texture_names
+    task.map_colors(&stretch_contrast)
+        .map_base(&|base| base.rewrite(&thicker_outline))
+}
+
+fn thicker_outline(spec: &ToPixmapTaskSpec) -> Option<ToPixmapTaskSpec> {
+    let ToPixmapTaskSpec::FromSvg { source } = spec else {
+        return None;
+    };
+    THICKER_OUTLINES
+        .iter()
+        .find(|(thin, _)| **source == **thin)
+        .map(|(_, thick)| ToPixmapTaskSpec::FromSvg {
+            source: (*thick).into(),
+        })
+}
+
+#[test]
+fn test_high_contrast_output() {
+    use crate::image_tasks::color::gray;
+    use crate::image_tasks::task_spec::{out_task, paint_svg_task};
+
+    let task = out_task(
+        "block/stone",
+        ToPixmapTaskSpec::StackLayerOnColor {
+            background: ComparableColor::STONE,
+            foreground: paint_svg_task("borderSolid", gray(0x44)).into(),
+        },
+    );
+    let FileOutputTaskSpec::PngOutput { base, .. } = high_contrast_output(&task) else {
+        panic!("Not a PngOutput");
+    };
+    let ToPixmapTaskSpec::StackLayerOnColor {
+        background,
+        foreground,
+    } = base
+    else {
+        panic!("Not a StackLayerOnColor: {}", base);
+    };
+    assert_eq!(
+        background,
+        ComparableColor::STONE.stretch_oklab_lightness(1.5)
+    );
+    let ToPixmapTaskSpec::PaintAlphaChannel { color, base } = (*foreground).to_owned() else {
+        panic!("Not a PaintAlphaChannel: {}", foreground);
+    };
+    assert!(color.red() < 0x44);
+    assert_eq!(base.to_string(), "alpha(borderSolidThick)");
+}
@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+/// Assigns each distinct value of `T` a small integer ID, and keeps one shared copy of it.
+/// Values are never removed, since every task spec lives until the graph is built anyway.
+pub struct Arena<T> {
+    ids: Mutex<HashMap<Arc<T>, u32>>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Arena {
+            ids: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl<T: Eq + Hash> Arena<T> {
+    /// Returns the shared copy of `value`, adding it if it's new. Only clones `value` if it's new.
+    pub fn intern_ref(&self, value: &T) -> Interned<T>
+    where
+        T: Clone,
+    {
+        let mut ids = self.ids.lock();
+        if let Some((value, id)) = ids.get_key_value(value) {
+            return Interned {
+                id: *id,
+                value: value.to_owned(),
+            };
+        }
+        Self::insert(&mut ids, Arc::new(value.to_owned()))
+    }
+
+    /// Returns the shared copy of `value`, adding it if it's new.
+    pub fn intern(&self, value: T) -> Interned<T> {
+        let mut ids = self.ids.lock();
+        if let Some((value, id)) = ids.get_key_value(&value) {
+            return Interned {
+                id: *id,
+                value: value.to_owned(),
+            };
+        }
+        Self::insert(&mut ids, Arc::new(value))
+    }
+
+    fn insert(ids: &mut HashMap<Arc<T>, u32>, value: Arc<T>) -> Interned<T> {
+        let id = u32::try_from(ids.len()).expect("Too many distinct task specs to intern");
+        ids.insert(value.to_owned(), id);
+        Interned { id, value }
+    }
+
+    /// How many distinct values have been interned.
+    pub fn len(&self) -> usize {
+        self.ids.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A type with one global [Arena], such as a kind of task spec.
+pub trait Internable: Eq + Hash + Sized + 'static {
+    fn arena() -> &'static Arena<Self>;
+}
+
+/// A node in a task-spec tree, shared with every equal node. Cloning one is a reference-count
+/// increment, and comparing or hashing one only looks at its ID, since equal values are always
+/// interned to the same ID. Ordering still compares the values, so that sorted output stays
+/// deterministic regardless of the order nodes were interned in.
+pub struct Interned<T> {
+    id: u32,
+    value: Arc<T>,
+}
+
+impl<T: Internable> Interned<T> {
+    pub fn new(value: T) -> Self {
+        T::arena().intern(value)
+    }
+
+    /// The ID of this value in its type's [Arena].
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+}
+
+impl<T: Internable + Clone> Interned<T> {
+    pub fn from_ref(value: &T) -> Self {
+        T::arena().intern_ref(value)
+    }
+}
+
+impl<T: Internable> From<T> for Interned<T> {
+    fn from(value: T) -> Self {
+        Interned::new(value)
+    }
+}
+
+impl<T> Clone for Interned<T> {
+    fn clone(&self) -> Self {
+        Interned {
+            id: self.id,
+            value: self.value.to_owned(),
+        }
+    }
+}
+
+impl<T> Deref for Interned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T> AsRef<T> for Interned<T> {
+    fn as_ref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T> PartialEq for Interned<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Interned<T> {}
+
+impl<T> Hash for Interned<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T: Ord> PartialOrd for Interned<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Ord> Ord for Interned<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        if self.id == other.id {
+            Ordering::Equal
+        } else {
+            self.value.cmp(&other.value)
+        }
+    }
+}
+
+impl<T: Debug> Debug for Interned<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&*self.value, f)
+    }
+}
+
+impl<T: Display> Display for Interned<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&*self.value, f)
+    }
+}
+
+#[test]
+fn test_interned() {
+    use once_cell::sync::Lazy;
+
+    #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+    struct Node(&'static str, Option<Interned<Node>>);
+
+    static NODES: Lazy<Arena<Node>> = Lazy::new(Arena::default);
+
+    impl Internable for Node {
+        fn arena() -> &'static Arena<Self> {
+            &NODES
+        }
+    }
+
+    let leaf = Interned::new(Node("leaf", None));
+    let parent = Interned::new(Node("parent", Some(leaf.to_owned())));
+    let same_parent = Interned::new(Node("parent", Some(Node("leaf", None).into())));
+    assert_eq!(parent, same_parent);
+    assert_eq!(parent.id(), same_parent.id());
+    assert_ne!(parent.id(), leaf.id());
+    assert!(leaf < parent);
+    assert_eq!(Interned::from_ref(&*leaf), leaf);
+    assert_eq!(NODES.len(), 2);
+}
@@ -7,7 +7,7 @@ const fn create_alpha_multiplication_table() -> [[u8; u8::MAX as usize + 1]; u8:
     loop {
         let mut y = 1;
         loop {
-            table[x as usize][y as usize] = (((x as u16) * (y as u16) + 128) / 255) as u8;
+            table[x as usize][y as usize] = (((x as u16) * (y as u16) + 127) / 255) as u8;
             if y == u8::MAX {
                 break;
             } else {
@@ -84,6 +84,37 @@ fn test_alpha_stacking_table() {
     }
 }
 
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_alpha_multiplication_rounds_correctly(first: u8, second: u8) {
+        let exact = first as f64 * second as f64 / u8::MAX as f64;
+        let product = ALPHA_MULTIPLICATION_TABLE[first as usize][second as usize];
+        proptest::prop_assert!((product as f64 - exact).abs() <= 0.5);
+        proptest::prop_assert!(product <= first.min(second));
+    }
+
+    #[test]
+    fn test_alpha_stacking_is_associative(bottom: u8, middle: u8, top: u8) {
+        let bottom_first = ALPHA_STACKING_TABLE
+            [ALPHA_STACKING_TABLE[bottom as usize][middle as usize] as usize][top as usize];
+        let top_first = ALPHA_STACKING_TABLE[bottom as usize]
+            [ALPHA_STACKING_TABLE[middle as usize][top as usize] as usize];
+        proptest::prop_assert!(bottom_first.abs_diff(top_first) <= 1);
+    }
+
+    #[test]
+    fn test_alpha_stacking_is_monotonic(background: u8, foreground: u8) {
+        let stacked = ALPHA_STACKING_TABLE[background as usize][foreground as usize];
+        proptest::prop_assert!(stacked >= background.max(foreground));
+        if foreground < u8::MAX {
+            proptest::prop_assert!(
+                ALPHA_STACKING_TABLE[background as usize][foreground as usize + 1] >= stacked
+            );
+        }
+    }
+}
+
 /// Multiplies the opacity of all pixels in the [input](given pixmap) by a given [alpha].
 #[instrument(skip(input))]
 pub fn make_semitransparent(input: &mut Mask, alpha: u8) {
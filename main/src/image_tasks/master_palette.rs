@@ -0,0 +1,188 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::read_to_string;
+use std::str::FromStr;
+
+use bytemuck::cast;
+use itertools::Itertools;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use oxipng::{BitDepth, ColorType};
+use resvg::tiny_skia::{Pixmap, PremultipliedColorU8};
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::{Arcow, CloneableError};
+use crate::image_tasks::color::{rgba, ComparableColor, PerceptualPalette};
+use crate::image_tasks::task_spec::color_description_to_mode;
+use crate::image_tasks::task_spec::ColorDescription::SpecifiedColors;
+use crate::option_value;
+
+/// A fixed set of colors that every output image is snapped to, for packs that should look like they
+/// were drawn with a limited palette. Transparency is always allowed.
+#[derive(Clone, Debug)]
+pub struct MasterPalette {
+    colors: Vec<ComparableColor>,
+    perceptual: PerceptualPalette,
+}
+
+/// The palette loaded from the file named by `--master-palette`, if any.
+pub static MASTER_PALETTE: Lazy<Option<MasterPalette>> = Lazy::new(|| {
+    option_value("master-palette").map(|path| {
+        let palette: MasterPalette = read_to_string(&path)
+            .map_err(CloneableError::from)
+            .and_then(|text| text.parse())
+            .unwrap_or_else(|e| panic!("Invalid value for --master-palette: {:?}", e));
+        info!(
+            "Using {} colors from master palette {}",
+            palette.len(),
+            path
+        );
+        palette
+    })
+});
+
+impl FromStr for MasterPalette {
+    type Err = CloneableError;
+
+    /// Reads a GIMP palette, or a list of `#rrggbb` or `#rrggbbaa` colors with one per line. A GIMP
+    /// palette entry whose name is also a color, as in one written by the `palette` subcommand, uses
+    /// that color so that it keeps its alpha. Other lines are ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut colors: Vec<ComparableColor> = s.lines().filter_map(parse_line).collect();
+        colors.push(ComparableColor::TRANSPARENT);
+        colors.sort();
+        colors.dedup();
+        if colors.len() == 1 {
+            return Err(anyhoo!("No colors found in master palette"));
+        }
+        Ok(MasterPalette {
+            perceptual: PerceptualPalette::new(&colors),
+            colors,
+        })
+    }
+}
+
+fn parse_line(line: &str) -> Option<ComparableColor> {
+    let mut tokens = line.split_whitespace();
+    let first = tokens.next()?;
+    if let Ok(color) = first.parse() {
+        return Some(color);
+    }
+    let red = first.parse().ok()?;
+    let green = tokens.next()?.parse().ok()?;
+    let blue = tokens.next()?.parse().ok()?;
+    Some(
+        tokens
+            .next()
+            .and_then(|name| name.parse().ok())
+            .unwrap_or(rgba(red, green, blue, u8::MAX)),
+    )
+}
+
+impl MasterPalette {
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Faint pixels snap to transparency unless the palette has a similarly faint color; see
+    /// [PerceptualPalette].
+    pub fn nearest(&self, color: ComparableColor) -> ComparableColor {
+        self.colors[self.perceptual.nearest(color).0]
+    }
+
+    /// Replaces every pixel with the nearest palette color, logs the colors that had to change, and
+    /// returns the colors left in the image.
+    pub fn snap(&self, image: &mut Pixmap, file_path: &str) -> Vec<ComparableColor> {
+        let mut snapped: HashMap<[u8; 4], PremultipliedColorU8> = HashMap::new();
+        let mut pixel_counts: BTreeMap<(ComparableColor, ComparableColor), usize> = BTreeMap::new();
+        for pixel in image.pixels_mut() {
+            let replacement = *snapped
+                .entry(cast(*pixel))
+                .or_insert_with(|| self.nearest((*pixel).into()).into());
+            if replacement != *pixel {
+                *pixel_counts
+                    .entry(((*pixel).into(), replacement.into()))
+                    .or_default() += 1;
+                *pixel = replacement;
+            }
+        }
+        if !pixel_counts.is_empty() {
+            warn!(
+                "Snapped {} colors in {} to the master palette: {}",
+                pixel_counts.len(),
+                file_path,
+                pixel_counts
+                    .iter()
+                    .map(|((found, replacement), count)| format!(
+                        "{} -> {} ({} pixels)",
+                        found, replacement, count
+                    ))
+                    .join(", ")
+            );
+        }
+        let mut colors: Vec<ComparableColor> =
+            snapped.into_values().map(ComparableColor::from).collect();
+        colors.sort();
+        colors.dedup();
+        colors
+    }
+
+    /// Snaps the image, then chooses the color type again since the colors predicted for it may no
+    /// longer be accurate.
+    pub fn constrain(&self, image: &mut Pixmap, file_path: &str) -> (ColorType, BitDepth) {
+        let colors = self.snap(image, file_path);
+        color_description_to_mode(&SpecifiedColors(Arcow::from_owned(colors)), file_path)
+    }
+}
+
+#[test]
+fn test_parse() {
+    use crate::image_tasks::color::c;
+
+    let palette: MasterPalette = "GIMP Palette\nName: Test\n#\n  0   0   0\tBlack\n \
+        18  52  86\t#12345680\n#ffffff\n#ff000080 with a comment\n"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        palette.colors,
+        [
+            ComparableColor::TRANSPARENT,
+            rgba(0x12, 0x34, 0x56, 0x80),
+            rgba(0xff, 0x00, 0x00, 0x80),
+            ComparableColor::BLACK,
+            ComparableColor::WHITE,
+        ]
+    );
+    assert_eq!(palette.nearest(c(0x101010)), ComparableColor::BLACK);
+    assert_eq!(
+        palette.nearest(ComparableColor::WHITE * 0.05),
+        ComparableColor::TRANSPARENT
+    );
+    assert!("GIMP Palette\n".parse::<MasterPalette>().is_err());
+}
+
+#[test]
+fn test_snap() {
+    use crate::image_tasks::color::c;
+
+    let palette: MasterPalette = "#000000\n#ffffff\n".parse().unwrap();
+    let mut image = Pixmap::new(2, 2).unwrap();
+    image.pixels_mut()[1] = c(0xeeeeee).into();
+    image.pixels_mut()[2] = ComparableColor::BLACK.into();
+    let colors = palette.snap(&mut image, "test");
+    assert_eq!(
+        colors,
+        [
+            ComparableColor::TRANSPARENT,
+            ComparableColor::BLACK,
+            ComparableColor::WHITE
+        ]
+    );
+    assert_eq!(
+        ComparableColor::from(image.pixels()[1]),
+        ComparableColor::WHITE
+    );
+}
@@ -0,0 +1,256 @@
+use std::fmt::Write;
+use std::fs;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use log::info;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use resvg::tiny_skia::{Mask, Pixmap};
+use tokio::time::sleep;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::option_value;
+
+/// The path, without an extension, that `--memory-timeline` names, if any. When it's set, the
+/// memory held by images is sampled every [SAMPLE_INTERVAL], and at the end of the build the
+/// samples are written to `<path>.csv` and charted in `<path>.svg`.
+pub static MEMORY_TIMELINE: Lazy<Option<String>> = Lazy::new(|| option_value("memory-timeline"));
+
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Where the memory an image uses comes from.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MemoryKind {
+    GridSizePool,
+    TileSizePool,
+    NotFromPool,
+}
+
+const KINDS: [MemoryKind; 3] = [
+    MemoryKind::GridSizePool,
+    MemoryKind::TileSizePool,
+    MemoryKind::NotFromPool,
+];
+
+impl MemoryKind {
+    fn name(self) -> &'static str {
+        match self {
+            MemoryKind::GridSizePool => "grid_size_pool",
+            MemoryKind::TileSizePool => "tile_size_pool",
+            MemoryKind::NotFromPool => "not_from_pool",
+        }
+    }
+
+    fn chart_color(self) -> &'static str {
+        match self {
+            MemoryKind::GridSizePool => "#4e79a7",
+            MemoryKind::TileSizePool => "#f28e2b",
+            MemoryKind::NotFromPool => "#e15759",
+        }
+    }
+}
+
+/// Bytes of images that are in use, by [MemoryKind]. Images that are back in a pool don't count.
+static LIVE_BYTES: [AtomicUsize; 3] = [const { AtomicUsize::new(0) }; 3];
+
+/// Counts an image's bytes as in use until it's dropped, which for an image from a pool is when
+/// the image goes back to the pool.
+pub struct MemoryUse {
+    kind: MemoryKind,
+    bytes: usize,
+}
+
+impl MemoryUse {
+    pub fn new(kind: MemoryKind, bytes: usize) -> MemoryUse {
+        LIVE_BYTES[kind as usize].fetch_add(bytes, Ordering::Relaxed);
+        MemoryUse { kind, bytes }
+    }
+}
+
+impl Drop for MemoryUse {
+    fn drop(&mut self) {
+        LIVE_BYTES[self.kind as usize].fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+/// Images whose memory can be counted by a [MemoryUse].
+pub trait MemorySize {
+    fn memory_size(&self) -> usize;
+}
+
+impl MemorySize for Pixmap {
+    fn memory_size(&self) -> usize {
+        self.data().len()
+    }
+}
+
+impl MemorySize for Mask {
+    fn memory_size(&self) -> usize {
+        self.data().len()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Sample {
+    elapsed: Duration,
+    live_bytes: [usize; 3],
+}
+
+static START: Lazy<Instant> = Lazy::new(Instant::now);
+static SAMPLES: Lazy<Mutex<Vec<Sample>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+fn take_sample() {
+    let live_bytes = LIVE_BYTES
+        .each_ref()
+        .map(|bytes| bytes.load(Ordering::Relaxed));
+    SAMPLES.lock().push(Sample {
+        elapsed: START.elapsed(),
+        live_bytes,
+    });
+}
+
+/// Samples memory use until the runtime shuts down, if [MEMORY_TIMELINE] is set.
+pub async fn sample_memory_timeline() {
+    if MEMORY_TIMELINE.is_none() {
+        return;
+    }
+    loop {
+        take_sample();
+        sleep(SAMPLE_INTERVAL).await;
+    }
+}
+
+fn timeline_csv(samples: &[Sample]) -> String {
+    let mut csv = String::from("seconds");
+    for kind in KINDS {
+        write!(csv, ",{}_bytes", kind.name()).unwrap();
+    }
+    csv.push('\n');
+    for sample in samples {
+        write!(csv, "{:.3}", sample.elapsed.as_secs_f64()).unwrap();
+        for bytes in sample.live_bytes {
+            write!(csv, ",{}", bytes).unwrap();
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+const CHART_WIDTH: usize = 1000;
+const CHART_HEIGHT: usize = 400;
+const LEGEND_HEIGHT: usize = 20;
+
+/// A stacked bar chart with one bar per sample, scaled so that the highest total reaches the top.
+fn timeline_svg(samples: &[Sample]) -> String {
+    let peak = samples
+        .iter()
+        .map(|sample| sample.live_bytes.iter().sum::<usize>())
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let bar_width = CHART_WIDTH as f64 / samples.len().max(1) as f64;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n",
+        CHART_WIDTH,
+        CHART_HEIGHT + LEGEND_HEIGHT
+    );
+    for (index, sample) in samples.iter().enumerate() {
+        let mut top = CHART_HEIGHT as f64;
+        for (kind, bytes) in KINDS.into_iter().zip(sample.live_bytes) {
+            if bytes == 0 {
+                continue;
+            }
+            let height = bytes as f64 / peak as f64 * CHART_HEIGHT as f64;
+            top -= height;
+            writeln!(
+                svg,
+                "<rect x=\"{:.2}\" y=\"{:.2}\" width=\"{:.2}\" height=\"{:.2}\" fill=\"{}\"/>",
+                index as f64 * bar_width,
+                top,
+                bar_width,
+                height,
+                kind.chart_color()
+            )
+            .unwrap();
+        }
+    }
+    let legend_y = CHART_HEIGHT + LEGEND_HEIGHT - 5;
+    for (index, kind) in KINDS.into_iter().enumerate() {
+        writeln!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" fill=\"{}\">{}</text>",
+            index * 150,
+            legend_y,
+            kind.chart_color(),
+            kind.name()
+        )
+        .unwrap();
+    }
+    let duration = samples
+        .last()
+        .map_or(0.0, |sample| sample.elapsed.as_secs_f64());
+    writeln!(
+        svg,
+        "<text x=\"{}\" y=\"{}\" text-anchor=\"end\">peak {:.1} MiB, {:.1} s</text>",
+        CHART_WIDTH,
+        legend_y,
+        peak as f64 / (1024.0 * 1024.0),
+        duration
+    )
+    .unwrap();
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Writes the samples taken by [sample_memory_timeline], if [MEMORY_TIMELINE] is set.
+pub fn finish_memory_timeline() -> Result<(), CloneableError> {
+    let Some(path) = MEMORY_TIMELINE.as_ref() else {
+        return Ok(());
+    };
+    take_sample();
+    let samples = SAMPLES.lock();
+    fs::write(format!("{}.csv", path), timeline_csv(&samples))?;
+    fs::write(format!("{}.svg", path), timeline_svg(&samples))?;
+    info!(
+        "Wrote a memory timeline of {} samples to {}.csv and {}.svg",
+        samples.len(),
+        path,
+        path
+    );
+    Ok(())
+}
+
+#[test]
+fn test_memory_timeline() {
+    use resvg::usvg::fontdb::Database;
+    use resvg::usvg::{Options, Tree};
+
+    let live = || LIVE_BYTES[MemoryKind::NotFromPool as usize].load(Ordering::Relaxed);
+    let before = live();
+    let usage = MemoryUse::new(MemoryKind::NotFromPool, 1 << 40);
+    assert!(live() >= 1 << 40);
+    drop(usage);
+    assert!(live() < before + (1 << 40));
+
+    let samples = [
+        Sample {
+            elapsed: Duration::from_millis(0),
+            live_bytes: [100, 0, 0],
+        },
+        Sample {
+            elapsed: Duration::from_millis(1500),
+            live_bytes: [100, 50, 50],
+        },
+    ];
+    assert_eq!(
+        timeline_csv(&samples),
+        "seconds,grid_size_pool_bytes,tile_size_pool_bytes,not_from_pool_bytes\n\
+         0.000,100,0,0\n\
+         1.500,100,50,50\n"
+    );
+    let svg = timeline_svg(&samples);
+    assert_eq!(svg.matches("<rect").count(), 4);
+    assert!(svg.contains("height=\"200.00\""));
+    assert!(Tree::from_data(svg.as_bytes(), &Options::default(), &Database::new()).is_ok());
+}
@@ -0,0 +1,167 @@
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::Arc;
+
+use include_dir::{Dir, DirEntry, File};
+use log::{error, info};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::task::JoinSet;
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::output_sink::{write_to_sinks, OutputSink};
+use crate::texture_base::material::{EMISSIVE_PROPERTIES_PATH, EMISSIVE_TEXTURES};
+
+/// Checks that `contents` is valid UTF-8 holding exactly one JSON value, with an error that says
+/// where it first goes wrong if it isn't.
+pub fn validate_json(contents: &[u8]) -> Result<(), String> {
+    std::str::from_utf8(contents).map_err(|e| e.to_string())?;
+    serde_json::from_slice::<serde_json::Value>(contents)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Checks that a legacy `.lang` file has a `key=value` pair on every line that isn't blank or a
+/// `#` comment.
+fn validate_lang(contents: &[u8]) -> Result<(), String> {
+    let text = std::str::from_utf8(contents).map_err(|e| e.to_string())?;
+    match text.lines().enumerate().find(|(_, line)| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with('#') && !line.contains('=')
+    }) {
+        Some((index, line)) => Err(format!(
+            "expected key=value at line {}: {:?}",
+            index + 1,
+            line
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Checks a metadata file whose format is known from its extension: `.mcmeta` and `.json` files
+/// must be JSON, and `.lang` files `key=value` pairs. Other files are taken as they are.
+pub fn validate_metadata_file(path: &str, contents: &[u8]) -> Result<(), String> {
+    let result = if path.ends_with(".mcmeta") || path.ends_with(".json") {
+        validate_json(contents)
+    } else if path.ends_with(".lang") {
+        validate_lang(contents)
+    } else {
+        Ok(())
+    };
+    result.map_err(|e| format!("{}: {}", path, e))
+}
+
+fn collect_metadata_files(
+    source_dir: &'static Dir<'static>,
+    files: &mut Vec<&'static File<'static>>,
+) {
+    source_dir.entries().iter().for_each(|entry| match entry {
+        DirEntry::Dir(dir) => collect_metadata_files(dir, files),
+        DirEntry::File(file) => files.push(file),
+    });
+}
+
+/// Validates every file in `source_dir` before anything is built, so that a mistake in one fails
+/// the build at once, and every such mistake is reported together.
+pub fn validate_metadata(source_dir: &'static Dir<'static>) -> Result<(), CloneableError> {
+    let mut files = Vec::new();
+    collect_metadata_files(source_dir, &mut files);
+    let errors: Vec<String> = files
+        .iter()
+        .filter_map(|file| {
+            validate_metadata_file(&file.path().to_string_lossy(), file.contents()).err()
+        })
+        .collect();
+    if errors.is_empty() {
+        info!("{} metadata files are well-formed", files.len());
+        Ok(())
+    } else {
+        Err(anyhoo!(
+            "{} of {} metadata files are malformed:\n{}",
+            errors.len(),
+            files.len(),
+            errors.join("\n")
+        ))
+    }
+}
+
+/// Metadata files that couldn't be written, with why.
+static COPY_ERRORS: Lazy<Mutex<Vec<String>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Writes one metadata file to the sinks. A failure is logged and recorded rather than returned, so
+/// that it doesn't stop the others; every one is reported by [finish_metadata_copies].
+pub async fn write_metadata_file(sinks: &[Arc<dyn OutputSink>], path: &str, contents: Vec<u8>) {
+    if let Err(e) = write_to_sinks(sinks, path, contents).await {
+        record_metadata_error(path, e);
+    }
+}
+
+/// Records that a metadata file couldn't be made or written, to be reported by
+/// [finish_metadata_copies].
+pub(crate) fn record_metadata_error(path: &str, error: impl Debug) {
+    error!("Failed to write metadata file {}: {:?}", path, error);
+    COPY_ERRORS.lock().push(format!("{}: {:?}", path, error));
+}
+
+/// Spawns a task to write each file in `source_dir` to the sinks with [write_metadata_file]. The
+/// file that sets [EMISSIVE_PROPERTIES_PATH] is skipped unless `--emissive-textures` is set.
+pub fn spawn_metadata_copies(
+    source_dir: &'static Dir<'static>,
+    sinks: &[Arc<dyn OutputSink>],
+    task_futures: &mut JoinSet<()>,
+) {
+    let mut files = Vec::new();
+    collect_metadata_files(source_dir, &mut files);
+    for file in files {
+        if !*EMISSIVE_TEXTURES && file.path() == Path::new(EMISSIVE_PROPERTIES_PATH) {
+            continue;
+        }
+        let sinks = sinks.to_vec();
+        task_futures.spawn(async move {
+            let path = file.path().to_string_lossy();
+            write_metadata_file(&sinks, &path, file.contents().to_vec()).await;
+        });
+    }
+}
+
+/// Called once every task has finished. Fails with every metadata file that couldn't be written.
+pub fn finish_metadata_copies() -> Result<(), CloneableError> {
+    let errors = COPY_ERRORS.lock();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhoo!(
+            "Failed to write {} metadata files:\n{}",
+            errors.len(),
+            errors.join("\n")
+        ))
+    }
+}
+
+#[test]
+fn test_validate_metadata() {
+    use crate::image_tasks::task_spec::METADATA_DIR;
+
+    assert!(validate_metadata(&METADATA_DIR).is_ok());
+    assert_eq!(
+        validate_json(b"{\"pack\": {\"pack_format\": 15, \"description\": \"\\u00e9\"}}\n"),
+        Ok(())
+    );
+    assert_eq!(validate_json(b"[1, -2.5e3, true, null, []]"), Ok(()));
+    assert!(validate_json(b"{\n  \"pack\": {\"pack_format\": 15,}\n}")
+        .unwrap_err()
+        .contains("line 2"));
+    assert!(validate_json(b"{\"a\": 1} {}").is_err());
+    assert!(validate_json(b"[01]").is_err());
+    assert!(validate_json(b"\"unterminated").is_err());
+    assert!(validate_json(b"").is_err());
+
+    assert!(validate_metadata_file("pack.mcmeta", b"{}").is_ok());
+    assert!(validate_metadata_file("pack.mcmeta", b"{").is_err());
+    assert!(
+        validate_metadata_file("assets/minecraft/lang/en_us.lang", b"# Comment\n\na.b=c\n").is_ok()
+    );
+    assert!(validate_metadata_file("assets/minecraft/lang/en_us.lang", b"a.b c\n").is_err());
+    assert!(validate_metadata_file("a.properties", b"{").is_ok());
+}
@@ -1,3 +1,4 @@
+use crate::image_tasks::memory_timeline::{MemoryKind, MemorySize, MemoryUse};
 use crate::{GRID_SIZE, TILE_SIZE};
 use lockfree_object_pool::{LinearObjectPool, LinearReusable};
 use log::info;
@@ -8,16 +9,60 @@ use std::fmt::{Debug, Formatter};
 use std::hash::{Hash, Hasher};
 use std::ops::{Deref, DerefMut};
 
+pub mod alpha_debug;
 pub mod animate;
-pub(crate) mod cloneable;
+pub mod block_usage;
+pub mod build_stats;
+pub mod cache;
+pub mod cloneable;
 pub mod color;
+pub mod color_shift;
+pub mod color_budget;
+pub mod compression;
+pub mod correction_report;
+pub mod crop;
+pub mod dead_layers;
+pub mod debug_bundle;
+pub mod diffing;
+pub mod dir_output;
+pub mod dither;
+pub mod expr;
+pub mod from_raster;
 pub mod from_svg;
+pub mod graph_export;
+pub mod grid_check;
+pub mod high_contrast;
+pub mod intern;
 pub mod make_semitransparent;
+pub mod master_palette;
+pub mod memory_timeline;
+pub mod metadata;
+pub mod output_path;
+pub mod output_sink;
+pub mod pack_meta;
+pub mod overrides;
+pub mod palette_export;
+pub mod png_budget;
 pub mod png_output;
+pub mod post_process;
+pub mod realms;
+pub mod remap;
 pub mod repaint;
+pub mod seam_report;
+pub mod search;
+pub mod sheet;
 pub mod stack;
+pub mod subtask;
+pub mod svg_usage;
 pub mod task_spec;
+pub mod texture_filter;
+pub mod tint_preview;
+pub mod transform;
 pub mod upscale;
+pub mod vanilla;
+pub mod vanilla_parity;
+pub mod verify;
+pub mod zip_layout;
 
 #[allow(clippy::uninit_vec)]
 fn new_uninit_pixmap(width: u32, height: u32) -> Pixmap {
@@ -43,7 +88,7 @@ static GRID_SIZE_PIXMAP_POOL: Lazy<LinearObjectPool<Pixmap>> = Lazy::new(|| {
     LinearObjectPool::new(
         || {
             info!("Allocating a grid-size Pixmap for pool");
-            new_uninit_pixmap(GRID_SIZE, GRID_SIZE)
+            new_uninit_pixmap(*GRID_SIZE, *GRID_SIZE)
         },
         |_| {}, // no reset needed if using allocate_pixmap_for_overwrite
     )
@@ -51,7 +96,7 @@ static GRID_SIZE_PIXMAP_POOL: Lazy<LinearObjectPool<Pixmap>> = Lazy::new(|| {
 
 pub fn prewarm_pixmap_pool() {
     GRID_SIZE_PIXMAP_POOL.pull();
-    if GRID_SIZE != *TILE_SIZE {
+    if *GRID_SIZE != *TILE_SIZE {
         TILE_SIZE_PIXMAP_POOL.pull();
     }
 }
@@ -59,8 +104,24 @@ pub fn prewarm_pixmap_pool() {
 pub enum MaybeFromPool<T: 'static> {
     FromPool {
         reusable: LinearReusable<'static, T>,
+        usage: MemoryUse,
     },
-    NotFromPool(T),
+    NotFromPool(T, MemoryUse),
+}
+
+impl<T> MaybeFromPool<T>
+where
+    T: MemorySize,
+{
+    pub fn from_pool(reusable: LinearReusable<'static, T>, kind: MemoryKind) -> Self {
+        let usage = MemoryUse::new(kind, reusable.memory_size());
+        MaybeFromPool::FromPool { reusable, usage }
+    }
+
+    pub fn not_from_pool(value: T) -> Self {
+        let usage = MemoryUse::new(MemoryKind::NotFromPool, value.memory_size());
+        MaybeFromPool::NotFromPool(value, usage)
+    }
 }
 
 impl<T> MaybeFromPool<T>
@@ -69,8 +130,8 @@ where
 {
     pub fn unwrap_or_clone(self) -> T {
         match self {
-            MaybeFromPool::FromPool { reusable } => reusable.deref().to_owned(),
-            MaybeFromPool::NotFromPool(inner) => inner,
+            MaybeFromPool::FromPool { reusable, .. } => reusable.deref().to_owned(),
+            MaybeFromPool::NotFromPool(inner, _) => inner,
         }
     }
 }
@@ -81,7 +142,7 @@ impl<T> Deref for MaybeFromPool<T> {
     fn deref(&self) -> &Self::Target {
         match self {
             MaybeFromPool::FromPool { reusable, .. } => reusable.deref(),
-            MaybeFromPool::NotFromPool(value) => value,
+            MaybeFromPool::NotFromPool(value, _) => value,
         }
     }
 }
@@ -93,7 +154,7 @@ where
     fn deref_mut(&mut self) -> &mut Self::Target {
         match self {
             MaybeFromPool::FromPool { reusable, .. } => reusable.deref_mut(),
-            MaybeFromPool::NotFromPool(value) => value,
+            MaybeFromPool::NotFromPool(value, _) => value,
         }
     }
 }
@@ -157,42 +218,38 @@ impl<T> Debug for MaybeFromPool<T> {
 }
 
 pub fn allocate_pixmap_for_overwrite(width: u32, height: u32) -> MaybeFromPool<Pixmap> {
-    if width == GRID_SIZE && height == GRID_SIZE {
+    if width == *GRID_SIZE && height == *GRID_SIZE {
         info!("Borrowing a grid-size Pixmap from pool");
-        MaybeFromPool::FromPool {
-            reusable: GRID_SIZE_PIXMAP_POOL.pull(),
-        }
+        MaybeFromPool::from_pool(GRID_SIZE_PIXMAP_POOL.pull(), MemoryKind::GridSizePool)
     } else if width == *TILE_SIZE && height == *TILE_SIZE {
         info!("Borrowing a tile-size Pixmap from pool");
-        MaybeFromPool::FromPool {
-            reusable: TILE_SIZE_PIXMAP_POOL.pull(),
-        }
+        MaybeFromPool::from_pool(TILE_SIZE_PIXMAP_POOL.pull(), MemoryKind::TileSizePool)
     } else {
         info!(
             "Allocating a Pixmap outside pool (not required empty) for unusual size {}x{}",
             width, height
         );
-        MaybeFromPool::NotFromPool(new_uninit_pixmap(width, height))
+        MaybeFromPool::not_from_pool(new_uninit_pixmap(width, height))
     }
 }
 
 pub fn allocate_pixmap_empty(width: u32, height: u32) -> MaybeFromPool<Pixmap> {
-    if width == GRID_SIZE && height == GRID_SIZE {
+    if width == *GRID_SIZE && height == *GRID_SIZE {
         info!("Borrowing and clearing a grid-size Pixmap from pool");
         let mut reusable = GRID_SIZE_PIXMAP_POOL.pull();
         reusable.fill(Color::TRANSPARENT);
-        MaybeFromPool::FromPool { reusable }
+        MaybeFromPool::from_pool(reusable, MemoryKind::GridSizePool)
     } else if width == *TILE_SIZE && height == *TILE_SIZE {
         info!("Borrowing and clearing a tile-size Pixmap from pool");
         let mut reusable = TILE_SIZE_PIXMAP_POOL.pull();
         reusable.fill(Color::TRANSPARENT);
-        MaybeFromPool::FromPool { reusable }
+        MaybeFromPool::from_pool(reusable, MemoryKind::TileSizePool)
     } else {
         info!(
             "Allocating a Pixmap outside pool (required empty) for unusual size {}x{}",
             width, height
         );
-        MaybeFromPool::NotFromPool(
+        MaybeFromPool::not_from_pool(
             Pixmap::new(width, height).expect("Failed to allocate a Pixmap outside pool"),
         )
     }
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::option_value;
+use crate::texture_base::version::name_for_target_version;
+
+/// Where Java Edition looks for each texture. `{namespace}` is `minecraft` unless the texture's
+/// name has a namespace prefix, `{category}` is the first folder of the name, such as `block`, and
+/// `{name}` is the rest of it.
+pub const DEFAULT_PATH_TEMPLATE: &str = "assets/{namespace}/textures/{category}/{name}.png";
+
+/// Decides where in the pack each texture is written, so that the same materials can target a
+/// layout other than Java Edition's.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PathTemplate {
+    template: Box<str>,
+    /// What each `{category}` is called in the output, if not the same as in the texture's name.
+    category_names: HashMap<Box<str>, Box<str>>,
+}
+
+impl Default for PathTemplate {
+    fn default() -> Self {
+        PathTemplate::new(DEFAULT_PATH_TEMPLATE, "").unwrap()
+    }
+}
+
+impl PathTemplate {
+    /// `category_names` is a comma-separated list such as `block=blocks,item=items`.
+    pub fn new(template: &str, category_names: &str) -> Result<PathTemplate, CloneableError> {
+        if !template.contains("{name}") {
+            return Err(anyhoo!(
+                "--path-template must contain {{name}}: {}",
+                template
+            ));
+        }
+        Ok(PathTemplate {
+            template: template.into(),
+            category_names: category_names
+                .split(',')
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| {
+                    let (from, to) = entry.split_once('=').ok_or_else(|| {
+                        anyhoo!("--category-names entries must be <from>=<to>: {}", entry)
+                    })?;
+                    Ok((from.trim().into(), to.trim().into()))
+                })
+                .collect::<Result<_, CloneableError>>()?,
+        })
+    }
+
+    /// The path in the pack of the texture `name`, such as `block/stone`, in `namespace`.
+    pub fn path_of(&self, namespace: &str, name: &str) -> Box<str> {
+        let (category, name) = name.split_once('/').unwrap_or(("", name));
+        let category = self
+            .category_names
+            .get(category)
+            .map_or(category, |category| &**category);
+        let template = if category.is_empty() {
+            // So that a name without a category doesn't leave an empty folder in the path
+            self.template.replace("{category}/", "")
+        } else {
+            self.template.to_string()
+        };
+        template
+            .replace("{namespace}", namespace)
+            .replace("{category}", category)
+            .replace("{name}", name)
+            .into_boxed_str()
+    }
+}
+
+/// Set with `--path-template` and `--category-names`; for example, Bedrock Edition's layout is
+/// `--path-template textures/{category}/{name}.png --category-names block=blocks,item=items`.
+pub static PATH_TEMPLATE: Lazy<PathTemplate> = Lazy::new(|| {
+    PathTemplate::new(
+        &option_value("path-template").unwrap_or_else(|| DEFAULT_PATH_TEMPLATE.to_owned()),
+        &option_value("category-names").unwrap_or_default(),
+    )
+    .unwrap_or_else(|e| panic!("{:?}", e))
+});
+
+/// The name of the texture that [texture_path] put at each path, so that code that's only given a
+/// path, such as a report on encoded images, can tell which texture it is without assuming the
+/// layout that [PATH_TEMPLATE] gives the pack.
+static TEXTURE_NAMES: Lazy<Mutex<HashMap<Box<str>, Box<str>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Converts a texture name to its path in the resource pack, according to [PATH_TEMPLATE]. The
+/// name may be prefixed with a namespace and a colon; otherwise it's in the `minecraft` namespace.
+pub fn texture_path(name: &str) -> Box<str> {
+    let (path, name) = match name.split_once(':') {
+        Some(("minecraft", name)) => (PATH_TEMPLATE.path_of("minecraft", name), name),
+        Some((namespace, texture)) => (PATH_TEMPLATE.path_of(namespace, texture), name),
+        None => {
+            let name = name_for_target_version(name);
+            (PATH_TEMPLATE.path_of("minecraft", name), name)
+        }
+    };
+    let mut names = TEXTURE_NAMES.lock();
+    if !names.contains_key(&path) {
+        names.insert(path.clone(), name.into());
+    }
+    path
+}
+
+/// The name of the texture at `path` in the pack, such as `block/stone` or `mymod:block/ore`, if
+/// it's one that [texture_path] made. Textures in the `minecraft` namespace have no prefix.
+pub fn texture_name(path: &str) -> Option<Box<str>> {
+    TEXTURE_NAMES.lock().get(path).cloned()
+}
+
+#[test]
+fn test_path_template() {
+    let java = PathTemplate::default();
+    assert_eq!(
+        &*java.path_of("minecraft", "block/stone"),
+        "assets/minecraft/textures/block/stone.png"
+    );
+    assert_eq!(
+        &*java.path_of("minecraft", "entity/villager/villager"),
+        "assets/minecraft/textures/entity/villager/villager.png"
+    );
+    let bedrock =
+        PathTemplate::new("textures/{category}/{name}.png", "block=blocks, item=items").unwrap();
+    assert_eq!(
+        &*bedrock.path_of("minecraft", "block/stone"),
+        "textures/blocks/stone.png"
+    );
+    assert_eq!(
+        &*bedrock.path_of("minecraft", "entity/villager/villager"),
+        "textures/entity/villager/villager.png"
+    );
+    assert_eq!(&*bedrock.path_of("minecraft", "pack"), "textures/pack.png");
+    let flat = PathTemplate::new("{category}_{name}.png", "").unwrap();
+    assert_eq!(
+        &*flat.path_of("minecraft", "block/stone"),
+        "block_stone.png"
+    );
+    assert!(PathTemplate::new("textures/{category}.png", "").is_err());
+    assert!(PathTemplate::new(DEFAULT_PATH_TEMPLATE, "block").is_err());
+
+    let path = texture_path("block/stone");
+    assert_eq!(&*path, "assets/minecraft/textures/block/stone.png");
+    assert_eq!(texture_name(&path).as_deref(), Some("block/stone"));
+    let path = texture_path("mymod:block/ore");
+    assert_eq!(&*path, "assets/mymod/textures/block/ore.png");
+    assert_eq!(texture_name(&path).as_deref(), Some("mymod:block/ore"));
+    assert_eq!(texture_name("pack.mcmeta"), None);
+}
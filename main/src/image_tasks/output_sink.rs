@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use futures_util::future::{try_join_all, BoxFuture};
+use futures_util::FutureExt;
+use parking_lot::Mutex;
+use zip::ZipWriter;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::dir_output::DirectoryOutput;
+use crate::image_tasks::png_output::{
+    copy_out_to_out, write_metadata_to_zip, write_png_to_zip, ZipBufferRaw,
+};
+
+/// Somewhere the files of the pack are written, such as a ZIP file or a directory tree. Paths are
+/// relative to the root of the pack, such as `assets/minecraft/textures/block/stone.png`.
+pub trait OutputSink: Send + Sync {
+    fn write<'a>(
+        &'a self,
+        path: &'a str,
+        contents: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), CloneableError>>;
+
+    /// Copies a file that has already been written to this sink to another path in it.
+    fn copy<'a>(
+        &'a self,
+        source_path: &'a str,
+        dest_path: &'a str,
+    ) -> BoxFuture<'a, Result<(), CloneableError>>;
+}
+
+/// Writes to a ZIP file in memory. PNGs are compressed with the settings for PNGs, and other files
+/// with the settings for metadata.
+pub struct ZipOutput {
+    zip: Arc<Mutex<ZipWriter<ZipBufferRaw>>>,
+}
+
+impl ZipOutput {
+    pub fn new(zip: Arc<Mutex<ZipWriter<ZipBufferRaw>>>) -> ZipOutput {
+        ZipOutput { zip }
+    }
+}
+
+impl OutputSink for ZipOutput {
+    fn write<'a>(
+        &'a self,
+        path: &'a str,
+        contents: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), CloneableError>> {
+        async move {
+            if path.ends_with(".png") {
+                write_png_to_zip(&contents, path.into(), &self.zip)
+            } else {
+                write_metadata_to_zip(&contents, path.into(), &self.zip)
+            }
+        }
+        .boxed()
+    }
+
+    fn copy<'a>(
+        &'a self,
+        source_path: &'a str,
+        dest_path: &'a str,
+    ) -> BoxFuture<'a, Result<(), CloneableError>> {
+        async move { copy_out_to_out(source_path.into(), dest_path.into(), &self.zip) }.boxed()
+    }
+}
+
+impl OutputSink for DirectoryOutput {
+    fn write<'a>(
+        &'a self,
+        path: &'a str,
+        contents: Vec<u8>,
+    ) -> BoxFuture<'a, Result<(), CloneableError>> {
+        DirectoryOutput::write(self, path, contents).boxed()
+    }
+
+    fn copy<'a>(
+        &'a self,
+        source_path: &'a str,
+        dest_path: &'a str,
+    ) -> BoxFuture<'a, Result<(), CloneableError>> {
+        DirectoryOutput::copy(self, source_path, dest_path).boxed()
+    }
+}
+
+/// Writes the same file to every sink at once.
+pub async fn write_to_sinks(
+    sinks: &[Arc<dyn OutputSink>],
+    path: &str,
+    contents: Vec<u8>,
+) -> Result<(), CloneableError> {
+    match sinks {
+        [sink] => sink.write(path, contents).await,
+        sinks => {
+            try_join_all(sinks.iter().map(|sink| sink.write(path, contents.clone()))).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Makes the same copy in every sink at once.
+pub async fn copy_in_sinks(
+    sinks: &[Arc<dyn OutputSink>],
+    source_path: &str,
+    dest_path: &str,
+) -> Result<(), CloneableError> {
+    try_join_all(sinks.iter().map(|sink| sink.copy(source_path, dest_path))).await?;
+    Ok(())
+}
+
+#[test]
+fn test_zip_output() {
+    use std::io::Read;
+    use zip::ZipArchive;
+
+    let zip = Arc::new(Mutex::new(ZipWriter::new(ZipBufferRaw::new(vec![]))));
+    let sinks: [Arc<dyn OutputSink>; 1] = [Arc::new(ZipOutput::new(zip.clone()))];
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        write_to_sinks(&sinks, "pack.mcmeta", b"{}".to_vec())
+            .await
+            .unwrap();
+        write_to_sinks(&sinks, "a.png", b"png".to_vec())
+            .await
+            .unwrap();
+        copy_in_sinks(&sinks, "a.png", "b.png").await.unwrap();
+    });
+    drop(sinks);
+    let zip = Arc::into_inner(zip).unwrap().into_inner();
+    let mut archive = ZipArchive::new(zip.finish().unwrap()).unwrap();
+    let mut contents = String::new();
+    archive
+        .by_name("b.png")
+        .unwrap()
+        .read_to_string(&mut contents)
+        .unwrap();
+    assert_eq!(contents, "png");
+    assert!(archive.by_name("pack.mcmeta").is_ok());
+}
@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use itertools::Itertools;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::fs::read;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::compression::png_dimensions;
+use crate::image_tasks::output_sink::{write_to_sinks, OutputSink};
+use crate::image_tasks::png_output::optimize_png;
+use crate::image_tasks::task_spec::PackId;
+use crate::option_value;
+
+/// Directory of hand-made textures that replace generated ones: `--overrides-dir` if given, or else
+/// `./overrides` if it exists.
+static OVERRIDES_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| match option_value("overrides-dir") {
+    Some(dir) => Some(PathBuf::from(dir)),
+    None => Some(PathBuf::from("./overrides")).filter(|dir| dir.is_dir()),
+});
+
+/// Paths that were written from an override, with the pack they were written to.
+static ACTIVE_OVERRIDES: Lazy<Mutex<Vec<(PackId, Box<str>)>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Where the override for a texture would be, relative to the overrides directory: for example,
+/// `block/stone.png` for `block/stone`, or `mymod/block/ore.png` for `mymod:block/ore`.
+fn relative_override_path(destination_name: &str) -> PathBuf {
+    PathBuf::from(format!("{}.png", destination_name.replace(':', "/")))
+}
+
+/// The hand-made PNG that replaces the texture with this name, if there is one.
+pub fn override_path(destination_name: &str) -> Option<PathBuf> {
+    OVERRIDES_DIR
+        .as_ref()
+        .map(|dir| dir.join(relative_override_path(destination_name)))
+        .filter(|path| path.is_file())
+}
+
+/// Optimizes the override and writes it to `destination_path` in every sink a generated texture
+/// would go to. Warns if it isn't `expected_width` pixels wide, since it was probably drawn for
+/// another tile size.
+pub async fn write_override(
+    override_file: &Path,
+    pack: PackId,
+    destination_path: Box<str>,
+    expected_width: Option<u32>,
+    sinks: &[Arc<dyn OutputSink>],
+) -> Result<(), CloneableError> {
+    info!(
+        "Using {} for {}",
+        override_file.to_string_lossy(),
+        destination_path
+    );
+    let original = read(override_file).await?;
+    if let (Some(expected_width), Some((width, height))) =
+        (expected_width, png_dimensions(&original))
+        && width != expected_width
+    {
+        warn!(
+            "Override {} is {}x{}, but {} should be {} pixels wide at this tile size",
+            override_file.to_string_lossy(),
+            width,
+            height,
+            destination_path,
+            expected_width
+        );
+    }
+    let png = optimize_png(&original, pack, &destination_path)?;
+    write_to_sinks(sinks, &destination_path, png).await?;
+    ACTIVE_OVERRIDES.lock().push((pack, destination_path));
+    Ok(())
+}
+
+/// Called once every output has been written. Lists the textures that came from overrides, so
+/// that they aren't forgotten when the material that would generate them changes.
+pub fn finish_override_report() {
+    let mut overrides = ACTIVE_OVERRIDES.lock();
+    if overrides.is_empty() {
+        return;
+    }
+    overrides.sort();
+    info!(
+        "{} textures came from overrides:\n  {}",
+        overrides.len(),
+        overrides
+            .iter()
+            .map(|(pack, path)| format!("{}: {}", pack, path))
+            .join("\n  ")
+    );
+}
+
+#[test]
+fn test_relative_override_path() {
+    assert_eq!(
+        relative_override_path("block/stone"),
+        PathBuf::from("block/stone.png")
+    );
+    assert_eq!(
+        relative_override_path("mymod:block/ore"),
+        PathBuf::from("mymod/block/ore.png")
+    );
+}
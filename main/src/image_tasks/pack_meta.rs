@@ -0,0 +1,180 @@
+use std::str::FromStr;
+
+use log::warn;
+use once_cell::sync::Lazy;
+use serde_json::json;
+use tokio::task::JoinSet;
+
+use crate::anyhoo;
+use crate::cli::CLI;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::expr::parse_expr;
+use crate::image_tasks::metadata::{record_metadata_error, write_metadata_file};
+use crate::image_tasks::search::Ingredients;
+use crate::image_tasks::task_spec::{TaskGraphBuildingContext, TaskSpecTraits, ToPixmapTaskSpec};
+use crate::{option_value, parsed_configured_option};
+
+pub const PACK_MCMETA_PATH: &str = "pack.mcmeta";
+pub const PACK_ICON_PATH: &str = "pack.png";
+
+/// The pack format for Minecraft 1.20 to 1.20.1.
+pub const DEFAULT_PACK_FORMAT: u32 = 15;
+
+/// Used unless `--pack-description` is set. `{size}` is replaced with the tile size.
+pub const DEFAULT_DESCRIPTION: &str = "HD geometric texture pack ({size}x{size})";
+
+/// Rendered as `pack.png` unless `--pack-icon` names another expression; see [parse_expr].
+pub const DEFAULT_ICON: &str = "texture_of(block/diamond_ore)";
+
+/// The width and height of `pack.png`, whatever the tile size.
+pub const PACK_ICON_SIZE: u32 = 128;
+
+/// The pack formats a pack declares that it supports: one format such as `15`, or a range such as
+/// `15-34` so that one pack works in every Minecraft version in between.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PackFormats {
+    pub min: u32,
+    pub max: u32,
+}
+
+impl Default for PackFormats {
+    fn default() -> Self {
+        PackFormats {
+            min: DEFAULT_PACK_FORMAT,
+            max: DEFAULT_PACK_FORMAT,
+        }
+    }
+}
+
+impl FromStr for PackFormats {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s.split_once('-').unwrap_or((s, s));
+        let parse = |format: &str| {
+            format
+                .trim()
+                .parse::<u32>()
+                .map_err(|_| format!("expected a pack format or a range such as 15-34: {}", s))
+        };
+        let (min, max) = (parse(min)?, parse(max)?);
+        if min > max {
+            return Err(format!("pack format range is backwards: {}", s));
+        }
+        Ok(PackFormats { min, max })
+    }
+}
+
+/// From `--pack-format`.
+pub static PACK_FORMATS: Lazy<PackFormats> = Lazy::new(|| {
+    CLI.pack_format
+        .or_else(|| parsed_configured_option("pack-format"))
+        .unwrap_or_default()
+});
+
+static PACK_DESCRIPTION: Lazy<String> = Lazy::new(|| {
+    option_value("pack-description").unwrap_or_else(|| DEFAULT_DESCRIPTION.to_owned())
+});
+
+static PACK_ICON: Lazy<Result<ToPixmapTaskSpec, CloneableError>> = Lazy::new(|| {
+    let expr = option_value("pack-icon").unwrap_or_else(|| DEFAULT_ICON.to_owned());
+    parse_expr(&expr).map_err(|e| anyhoo!("Invalid value for --pack-icon: {:?}", e))
+});
+
+/// Parses `--pack-icon`, so that a bad value is reported before the build starts rather than
+/// when the first pack's metadata is written.
+pub fn check_pack_icon() -> Result<(), CloneableError> {
+    PACK_ICON.as_ref().map(drop).map_err(CloneableError::clone)
+}
+
+/// The contents of `pack.mcmeta` for a pack at `tile_size`. A range of formats is declared with
+/// `supported_formats`, which Minecraft versions before it was added ignore in favor of
+/// `pack_format`.
+pub fn pack_mcmeta(formats: PackFormats, description: &str, tile_size: u32) -> String {
+    let mut pack = json!({
+        "pack_format": formats.min,
+        "description": description.replace("{size}", &tile_size.to_string()),
+    });
+    if formats.max > formats.min {
+        pack["supported_formats"] = json!({
+            "min_inclusive": formats.min,
+            "max_inclusive": formats.max,
+        });
+    }
+    serde_json::to_string_pretty(&json!({ "pack": pack })).unwrap() + "\n"
+}
+
+/// Spawns a task that writes `pack.mcmeta` and `pack.png` to the pack `ctx` is currently building.
+/// Failures are reported along with those of the other metadata files. `pack.png` is left out,
+/// with a warning, if the icon uses a texture that this pack doesn't build.
+pub fn spawn_pack_metadata(
+    ctx: &mut TaskGraphBuildingContext,
+    tile_size: u32,
+    task_futures: &mut JoinSet<()>,
+) {
+    let sinks = ctx.output_sinks();
+    let mcmeta = pack_mcmeta(*PACK_FORMATS, &PACK_DESCRIPTION, tile_size);
+    let icon = PACK_ICON
+        .as_ref()
+        .expect("--pack-icon should have been checked");
+    // Such as the default icon when --only leaves out diamond ore and nothing else uses it
+    let icon = match ctx.missing_texture(Ingredients::of_pixmap(icon)) {
+        Some(name) => {
+            warn!(
+                "Not writing {} for {}, since it uses texture_of({}), which isn't being built",
+                PACK_ICON_PATH, ctx.pack, name
+            );
+            None
+        }
+        None => Some(icon.add_to(ctx, PACK_ICON_SIZE)),
+    };
+    task_futures.spawn(async move {
+        write_metadata_file(&sinks, PACK_MCMETA_PATH, mcmeta.into_bytes()).await;
+        if let Some(icon) = icon {
+            match icon.await.encode_png() {
+                Ok(png) => write_metadata_file(&sinks, PACK_ICON_PATH, png).await,
+                Err(e) => record_metadata_error(PACK_ICON_PATH, e),
+            }
+        }
+    });
+}
+
+#[test]
+fn test_pack_mcmeta() {
+    use crate::image_tasks::metadata::validate_json;
+    use serde_json::Value;
+
+    assert_eq!("15".parse(), Ok(PackFormats { min: 15, max: 15 }));
+    assert_eq!("15-34".parse(), Ok(PackFormats { min: 15, max: 34 }));
+    assert!("34-15".parse::<PackFormats>().is_err());
+    assert!("1.20".parse::<PackFormats>().is_err());
+
+    let mcmeta = pack_mcmeta(PackFormats::default(), DEFAULT_DESCRIPTION, 32);
+    assert_eq!(
+        serde_json::from_str::<Value>(&mcmeta).unwrap(),
+        json!({
+            "pack": {
+                "pack_format": 15,
+                "description": "HD geometric texture pack (32x32)",
+            }
+        })
+    );
+    let mcmeta = pack_mcmeta(
+        PackFormats { min: 15, max: 34 },
+        "\"OcHD\" at {size}\\{size}",
+        64,
+    );
+    assert_eq!(validate_json(mcmeta.as_bytes()), Ok(()));
+    assert_eq!(
+        serde_json::from_str::<Value>(&mcmeta).unwrap(),
+        json!({
+            "pack": {
+                "pack_format": 15,
+                "supported_formats": {"min_inclusive": 15, "max_inclusive": 34},
+                "description": "\"OcHD\" at 64\\64",
+            }
+        })
+    );
+    assert!(parse_expr(DEFAULT_ICON).is_ok());
+    assert_eq!(check_pack_icon(), Ok(()));
+}
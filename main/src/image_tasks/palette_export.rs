@@ -0,0 +1,222 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::color::ComparableColor;
+use crate::image_tasks::task_spec::{ColorDescription, TaskGraphBuildingContext};
+use crate::texture_base::material::MaterialGroup;
+
+/// File formats that [PackPalette] can be exported as.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PaletteFormat {
+    /// Every color with its usage counts.
+    Json,
+    /// GIMP palette; also read by Inkscape and Krita. Drops alpha, so each color is named with its
+    /// full `#rrggbbaa` value.
+    Gpl,
+    /// Adobe Photoshop swatches (version 1 followed by version 2 with names). Also drops alpha.
+    Aco,
+}
+
+impl FromStr for PaletteFormat {
+    type Err = CloneableError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &*s.to_ascii_lowercase() {
+            "json" => Ok(PaletteFormat::Json),
+            "gpl" => Ok(PaletteFormat::Gpl),
+            "aco" => Ok(PaletteFormat::Aco),
+            _ => Err(anyhoo!("Unknown palette format: {}", s)),
+        }
+    }
+}
+
+impl PaletteFormat {
+    /// Chooses the format from a file's extension.
+    pub fn for_path(path: &Path) -> Result<PaletteFormat, CloneableError> {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .ok_or_else(|| anyhoo!("No extension on {}", path.to_string_lossy()))?
+            .parse()
+    }
+}
+
+/// Every visible color that the pack's textures are predicted to contain, and how many textures in
+/// each material group use it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PackPalette {
+    usage: BTreeMap<ComparableColor, BTreeMap<&'static str, usize>>,
+    /// Textures whose colors couldn't be predicted, so they're missing from the palette.
+    unpredicted: BTreeMap<&'static str, usize>,
+}
+
+impl PackPalette {
+    /// Collects the final color descriptions at `tile_size` of every texture in the given groups.
+    /// Copies are skipped, since they'd only count their originals again.
+    pub async fn collect(
+        groups: &[(&'static str, &MaterialGroup)],
+        ctx: &mut TaskGraphBuildingContext,
+        tile_size: u32,
+    ) -> PackPalette {
+        let mut palette = PackPalette::default();
+        for (_, group) in groups {
+            ctx.add_texture_names(&group.tasks);
+        }
+        for (group_name, group) in groups {
+            let tasks: Vec<_> = group
+                .tasks
+                .iter()
+                .filter_map(|task| task.analysis(ctx, tile_size))
+                .collect();
+            for task in tasks {
+                palette.add(group_name, &task.await.colors);
+            }
+        }
+        palette
+    }
+
+    pub fn add(&mut self, group_name: &'static str, description: &ColorDescription) {
+        match description {
+            ColorDescription::SpecifiedColors(colors) => {
+                for color in colors.iter().filter(|color| color.alpha() != 0) {
+                    *self
+                        .usage
+                        .entry(*color)
+                        .or_default()
+                        .entry(group_name)
+                        .or_default() += 1;
+                }
+            }
+            ColorDescription::Rgb(_) => *self.unpredicted.entry(group_name).or_default() += 1,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.usage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.usage.is_empty()
+    }
+
+    pub fn export(&self, format: PaletteFormat) -> Result<Vec<u8>, CloneableError> {
+        match format {
+            PaletteFormat::Json => Ok(self.to_json().into_bytes()),
+            PaletteFormat::Gpl => Ok(self.to_gpl().into_bytes()),
+            PaletteFormat::Aco => self.to_aco(),
+        }
+    }
+
+    fn to_json(&self) -> String {
+        let colors: Vec<Value> = self
+            .usage
+            .iter()
+            .map(|(color, counts)| {
+                json!({
+                    "color": color.to_string(),
+                    "total": counts.values().sum::<usize>(),
+                    "groups": counts,
+                })
+            })
+            .collect();
+        let palette = json!({
+            "colors": colors,
+            "unpredicted": self.unpredicted,
+        });
+        serde_json::to_string_pretty(&palette).unwrap() + "\n"
+    }
+
+    fn to_gpl(&self) -> String {
+        let mut out = String::from("GIMP Palette\nName: OcHD\nColumns: 16\n#\n");
+        for color in self.usage.keys() {
+            writeln!(
+                out,
+                "{:3} {:3} {:3}\t{}",
+                color.red(),
+                color.green(),
+                color.blue(),
+                color
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    fn to_aco(&self) -> Result<Vec<u8>, CloneableError> {
+        fn push_u16(out: &mut Vec<u8>, value: u16) {
+            out.extend_from_slice(&value.to_be_bytes());
+        }
+        fn push_color(out: &mut Vec<u8>, color: &ComparableColor) {
+            push_u16(out, 0); // RGB color space
+            for channel in [color.red(), color.green(), color.blue()] {
+                push_u16(out, u16::from(channel) * 0x101);
+            }
+            push_u16(out, 0);
+        }
+        let count = u16::try_from(self.usage.len())
+            .map_err(|_| anyhoo!("{} colors is too many for an ACO file", self.usage.len()))?;
+        let mut out = Vec::new();
+        for version in [1, 2] {
+            push_u16(&mut out, version);
+            push_u16(&mut out, count);
+            for color in self.usage.keys() {
+                push_color(&mut out, color);
+                if version == 2 {
+                    let name: Vec<u16> = color.to_string().encode_utf16().chain([0]).collect();
+                    out.extend_from_slice(&(name.len() as u32).to_be_bytes());
+                    name.into_iter().for_each(|unit| push_u16(&mut out, unit));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[test]
+fn test_export() {
+    use crate::image_tasks::cloneable::Arcow;
+    use crate::image_tasks::color::c;
+    use crate::image_tasks::task_spec::ColorDescription::{Rgb, SpecifiedColors};
+    use crate::image_tasks::task_spec::Transparency::Opaque;
+
+    let mut palette = PackPalette::default();
+    palette.add(
+        "item",
+        &SpecifiedColors(Arcow::from_owned(vec![
+            ComparableColor::TRANSPARENT,
+            c(0x123456),
+        ])),
+    );
+    palette.add(
+        "block/axe",
+        &SpecifiedColors(Arcow::from_owned(vec![c(0x123456), ComparableColor::WHITE])),
+    );
+    palette.add("block/axe", &Rgb(Opaque));
+    assert_eq!(palette.len(), 2);
+    assert_eq!(
+        serde_json::from_str::<Value>(&palette.to_json()).unwrap(),
+        json!({
+            "colors": [
+                {"color": "#123456ff", "total": 2, "groups": {"block/axe": 1, "item": 1}},
+                {"color": "#ffffffff", "total": 1, "groups": {"block/axe": 1}},
+            ],
+            "unpredicted": {"block/axe": 1},
+        })
+    );
+    assert!(palette
+        .to_gpl()
+        .ends_with(" 18  52  86\t#123456ff\n255 255 255\t#ffffffff\n"));
+    let aco = palette.to_aco().unwrap();
+    assert_eq!(&aco[..4], &[0, 1, 0, 2]);
+    assert_eq!(
+        &aco[4..14],
+        &[0, 0, 0x12, 0x12, 0x34, 0x34, 0x56, 0x56, 0, 0]
+    );
+    // Version 1 has 10 bytes per color; version 2 adds a 4-byte length and 10 UTF-16 units.
+    assert_eq!(aco.len(), 4 + 2 * 10 + 4 + 2 * (10 + 4 + 20));
+}
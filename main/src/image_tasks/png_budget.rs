@@ -0,0 +1,186 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use oxipng::{Deflaters, Options};
+use parking_lot::Mutex;
+
+use crate::image_tasks::png_output::OXIPNG_OPTIONS;
+use crate::parsed_option;
+
+/// The wall-clock time, in seconds, that `--png-time-budget` allows for optimizing PNGs, counted
+/// from when the first one starts, if any. When the PNGs still to be optimized would take longer
+/// than what's left at the rate they're finishing at the current effort, the effort is stepped
+/// down, so that a build's duration is predictable however many threads share the work.
+static PNG_TIME_BUDGET: Lazy<Option<Duration>> =
+    Lazy::new(|| parsed_option::<f64>("png-time-budget").map(Duration::from_secs_f64));
+
+/// How many PNGs must be optimized at an effort level before its average time is trusted.
+const MIN_SAMPLES: usize = 8;
+
+/// Oxipng presets and libdeflate levels to fall back to, from most to least effort, after the
+/// usual [OXIPNG_OPTIONS].
+const FALLBACK_EFFORTS: [(u8, u8); 3] = [(3, 10), (2, 8), (0, 6)];
+
+/// Options for each effort level, from most to least effort.
+static EFFORTS: Lazy<Vec<Options>> = Lazy::new(|| {
+    let mut efforts = vec![OXIPNG_OPTIONS.clone()];
+    efforts.extend(FALLBACK_EFFORTS.iter().map(|&(preset, compression)| {
+        let mut options = Options::from_preset(preset);
+        options.deflate = Deflaters::Libdeflater { compression };
+        options.optimize_alpha = true;
+        options
+    }));
+    efforts
+});
+
+/// How many PNGs the build is expected to optimize; see [expect_pngs].
+static EXPECTED_PNGS: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct BudgetState {
+    /// Index into [EFFORTS].
+    level: usize,
+    /// When the first PNG started being optimized.
+    started: Option<Instant>,
+    /// Wall-clock time from [Self::started] to when the last PNG was done.
+    spent: Duration,
+    pngs_done: usize,
+    /// Wall-clock time from [Self::started] to when the current level began.
+    level_started: Duration,
+    pngs_at_level: usize,
+}
+
+static STATE: Lazy<Mutex<BudgetState>> = Lazy::new(|| Mutex::new(BudgetState::default()));
+
+impl BudgetState {
+    /// Whether the remaining PNGs, if they keep finishing at the rate they have at the current
+    /// level, would take the total past `budget`.
+    fn over_budget(&self, budget: Duration, expected_pngs: usize) -> bool {
+        if self.spent >= budget {
+            return true;
+        }
+        if self.pngs_at_level < MIN_SAMPLES {
+            return false;
+        }
+        let remaining = expected_pngs.saturating_sub(self.pngs_done) as u32;
+        let spent_at_level = self.spent - self.level_started;
+        let projected = self.spent + spent_at_level / self.pngs_at_level as u32 * remaining;
+        projected > budget
+    }
+
+    /// Counts one PNG, done `spent` after the first one started, and steps down the effort level if
+    /// the budget would be exceeded.
+    fn record(&mut self, spent: Duration, budget: Duration, expected_pngs: usize, levels: usize) {
+        self.spent = self.spent.max(spent);
+        self.pngs_done += 1;
+        self.pngs_at_level += 1;
+        if self.level + 1 < levels && self.over_budget(budget, expected_pngs) {
+            self.level += 1;
+            self.level_started = self.spent;
+            self.pngs_at_level = 0;
+            warn!(
+                "Spent {:.1} s optimizing {} of about {} PNGs; stepping down to effort level {} of \
+                {} to stay within --png-time-budget",
+                self.spent.as_secs_f64(),
+                self.pngs_done,
+                expected_pngs,
+                self.level,
+                levels - 1
+            );
+        }
+    }
+}
+
+/// Tells the budget how many PNGs the build will output, so that it can tell whether the rest
+/// will fit. Each one that turns out not to need optimizing is taken off with [skip_png].
+pub fn expect_pngs(count: usize) {
+    EXPECTED_PNGS.store(count, Ordering::Relaxed);
+}
+
+/// Called for each expected PNG that won't be optimized, such as one found in the cache or
+/// identical to another.
+pub fn skip_png() {
+    let _ = EXPECTED_PNGS.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |count| {
+        count.checked_sub(1)
+    });
+}
+
+/// The options to optimize the next PNG with. Starts the clock if it's the first one.
+pub fn budgeted_oxipng_options() -> Options {
+    if PNG_TIME_BUDGET.is_none() {
+        return OXIPNG_OPTIONS.clone();
+    }
+    let mut state = STATE.lock();
+    state.started.get_or_insert_with(Instant::now);
+    EFFORTS[state.level].clone()
+}
+
+/// Called after each PNG is optimized.
+pub fn record_png_optimized() {
+    if let Some(budget) = *PNG_TIME_BUDGET {
+        let mut state = STATE.lock();
+        let spent = state.started.get_or_insert_with(Instant::now).elapsed();
+        state.record(
+            spent,
+            budget,
+            EXPECTED_PNGS.load(Ordering::Relaxed),
+            EFFORTS.len(),
+        );
+    }
+}
+
+/// Logs how much of the budget was spent, if there is one.
+pub fn finish_png_budget_report() {
+    if let Some(budget) = *PNG_TIME_BUDGET {
+        let state = STATE.lock();
+        info!(
+            "Spent {:.1} s of the {:.1} s --png-time-budget optimizing {} PNGs, ending at effort \
+            level {}",
+            state.spent.as_secs_f64(),
+            budget.as_secs_f64(),
+            state.pngs_done,
+            state.level
+        );
+    }
+}
+
+#[test]
+fn test_png_budget() {
+    let budget = Duration::from_secs(10);
+    let mut state = BudgetState::default();
+    // 100 PNGs finishing every 50 ms fit in 10 s
+    for index in 1..=MIN_SAMPLES as u32 {
+        state.record(Duration::from_millis(50) * index, budget, 100, 4);
+    }
+    assert_eq!(state.level, 0);
+    // At one every 200 ms, the rest would take too long
+    let level_started = state.spent;
+    for index in 1..=MIN_SAMPLES as u32 {
+        state.record(
+            level_started + Duration::from_millis(200) * index,
+            budget,
+            100,
+            4,
+        );
+    }
+    assert_eq!(state.level, 1);
+    assert!(state.pngs_at_level < MIN_SAMPLES);
+    // Threads finishing out of order don't turn the clock back
+    let spent = state.spent;
+    state.record(spent - Duration::from_millis(1), budget, 100, 4);
+    assert_eq!(state.spent, spent);
+    // Once the budget is spent, each PNG steps down until the lowest level
+    state.record(budget, budget, 100, 4);
+    state.record(budget, budget, 100, 4);
+    state.record(budget, budget, 100, 4);
+    assert_eq!(state.level, 3);
+    assert_eq!(state.pngs_done, 2 * MIN_SAMPLES + 4);
+
+    expect_pngs(2);
+    skip_png();
+    skip_png();
+    skip_png();
+    assert_eq!(EXPECTED_PNGS.load(Ordering::Relaxed), 0);
+}
@@ -1,16 +1,18 @@
 use bitstream_io::{BigEndian, BitWrite, BitWriter};
-use bytemuck::{cast, cast_slice_box};
-use core::alloc::Layout;
-use core::mem::transmute;
+use bytemuck::cast;
 use include_dir::File;
 use itertools::Itertools;
 use log::{info, warn};
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 #[cfg(not(debug_assertions))]
 use oxipng::Deflaters;
-use oxipng::{BitDepth, ColorType, IndexSet, Options, RawImage, RowFilter};
+use oxipng::{BitDepth, ColorType, IndexSet, Options, RawImage, RowFilter, RGB16};
 use parking_lot::Mutex;
+use std::cell::RefCell;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::io::{Cursor, Write};
 use std::ops::DerefMut;
 use std::sync::Arc;
@@ -22,19 +24,38 @@ use zip::ZipWriter;
 use zip::{CompressionMethod, ZipArchive};
 
 use crate::image_tasks::cloneable::CloneableError;
-use crate::image_tasks::color::ComparableColor;
-use crate::image_tasks::task_spec::channel_to_bit_depth;
+use crate::image_tasks::color::{transparency_sentinel, ComparableColor, PerceptualPalette};
+use crate::image_tasks::compression::{
+    oxipng_options, png_dimensions, policy_for, zip_options_for,
+};
+use crate::image_tasks::correction_report::{record_corrections, ColorCorrection};
+use crate::image_tasks::debug_bundle::{record_final_png, record_raw_image};
+use crate::image_tasks::master_palette::MASTER_PALETTE;
+use crate::image_tasks::png_budget::{budgeted_oxipng_options, record_png_optimized, skip_png};
+use crate::image_tasks::post_process::post_process;
+use crate::image_tasks::seam_report::record_seams;
+use crate::image_tasks::task_spec::{channel_to_bit_depth, PackId};
+use crate::image_tasks::vanilla_parity::record_vanilla_parity;
+use crate::image_tasks::verify::expect_png;
+use crate::image_tasks::zip_layout::ZIP_LAYOUT;
 use crate::image_tasks::MaybeFromPool;
-use crate::TILE_SIZE;
+#[cfg(not(debug_assertions))]
+use crate::parsed_option;
+use crate::{anyhoo, TILE_SIZE};
 
 pub type ZipBufferRaw = Cursor<Vec<u8>>;
 
 #[cfg(not(debug_assertions))]
 const PNG_BUFFER_SIZE: usize = 1024 * 1024;
 
-static ZIP_BUFFER_SIZE: Lazy<usize> = Lazy::new(|| (*TILE_SIZE as usize) * 32 * 1024);
+thread_local! {
+    /// Holds the single-file ZIP that [write_png_to_zip] compresses into when another thread is
+    /// writing to the main one. Kept between files so that a worker writing a batch of small
+    /// outputs doesn't allocate a new buffer for each of them.
+    static SINGLE_FILE_ZIP_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
 #[cfg(not(debug_assertions))]
-static PNG_ZIP_OPTIONS: Lazy<SimpleFileOptions> = Lazy::new(|| {
+pub(crate) static PNG_ZIP_OPTIONS: Lazy<SimpleFileOptions> = Lazy::new(|| {
     SimpleFileOptions::default()
         .compression_method(CompressionMethod::Deflated)
         .with_zopfli_buffer(Some(PNG_BUFFER_SIZE))
@@ -47,24 +68,26 @@ static PNG_ZIP_OPTIONS: Lazy<SimpleFileOptions> = Lazy::new(|| {
         }))
 });
 #[cfg(debug_assertions)]
-static PNG_ZIP_OPTIONS: Lazy<SimpleFileOptions> =
+pub(crate) static PNG_ZIP_OPTIONS: Lazy<SimpleFileOptions> =
     Lazy::new(|| SimpleFileOptions::default().compression_method(CompressionMethod::Stored));
 
-static METADATA_ZIP_OPTIONS: Lazy<SimpleFileOptions> = Lazy::new(|| {
+pub(crate) static METADATA_ZIP_OPTIONS: Lazy<SimpleFileOptions> = Lazy::new(|| {
     SimpleFileOptions::default()
         .compression_method(CompressionMethod::Deflated)
         .compression_level(Some(264))
 });
 
 #[cfg(not(debug_assertions))]
-static OXIPNG_OPTIONS: Lazy<Options> = Lazy::new(|| {
-    let mut options = Options::from_preset(if *TILE_SIZE < 1024 {
-        6
-    } else if *TILE_SIZE < 2048 {
-        5
-    } else {
-        4
-    });
+pub(crate) static OXIPNG_OPTIONS: Lazy<Options> = Lazy::new(|| {
+    let mut options = Options::from_preset(parsed_option("oxipng-preset").unwrap_or(
+        if *TILE_SIZE < 1024 {
+            6
+        } else if *TILE_SIZE < 2048 {
+            5
+        } else {
+            4
+        },
+    ));
     options.deflate = if *TILE_SIZE < 64 {
         Deflaters::Zopfli {
             iterations: u8::MAX.try_into().unwrap(),
@@ -82,7 +105,7 @@ static OXIPNG_OPTIONS: Lazy<Options> = Lazy::new(|| {
     options
 });
 #[cfg(debug_assertions)]
-static OXIPNG_OPTIONS: Lazy<Options> = Lazy::new(|| Options::from_preset(0));
+pub(crate) static OXIPNG_OPTIONS: Lazy<Options> = Lazy::new(|| Options::from_preset(0));
 
 fn png_filters_to_try(file_path: &str) -> Option<IndexSet<RowFilter>> {
     let tile_size = *TILE_SIZE;
@@ -97,14 +120,80 @@ fn png_filters_to_try(file_path: &str) -> Option<IndexSet<RowFilter>> {
     }
 }
 
-#[instrument(skip(image, color_type))]
-pub fn png_output(
-    image: MaybeFromPool<Pixmap>,
-    color_type: ColorType,
-    bit_depth: BitDepth,
+/// Adds an already-encoded PNG to the ZIP file. If another thread holds the lock, the PNG is
+/// compressed into a single-file ZIP first, so that only the copy has to wait for it.
+pub fn write_png_to_zip(
+    png: &[u8],
     file_path: Box<str>,
-    zip: &Arc<Mutex<ZipWriter<ZipBufferRaw>>>
+    zip: &Arc<Mutex<ZipWriter<ZipBufferRaw>>>,
 ) -> Result<(), CloneableError> {
+    let options = zip_options_for(&file_path, png, PNG_ZIP_OPTIONS.to_owned());
+    let deflate_span = info_span!("Deflating file");
+    let deflate_span = deflate_span.enter();
+    match zip.try_lock() {
+        Some(mut writer_guard) => {
+            writer_guard.start_file(file_path, options)?;
+            writer_guard.write_all(png)?;
+        }
+        None => {
+            let mut buffer = SINGLE_FILE_ZIP_BUFFER.take();
+            buffer.clear();
+            let mut single_file_out = ZipWriter::new(Cursor::new(buffer));
+            single_file_out.start_file(file_path, options)?;
+            single_file_out.write_all(png)?;
+            let mut single_compressed_file = ZipArchive::new(single_file_out.finish()?)?;
+            drop(deflate_span);
+            let mut writer = match zip.try_lock() {
+                None => {
+                    let get_lock_span = info_span!("Waiting for lock on ZIP file");
+                    let get_lock_span = get_lock_span.enter();
+                    let writer = zip.lock();
+                    drop(get_lock_span);
+                    writer
+                }
+                Some(locked_writer) => locked_writer,
+            };
+            let write_file_span = info_span!("Adding file to ZIP file");
+            let write_file_span = write_file_span.enter();
+            writer.raw_copy_file(single_compressed_file.by_index_raw(0).unwrap())?;
+            drop(write_file_span);
+            drop(writer);
+            SINGLE_FILE_ZIP_BUFFER.set(single_compressed_file.into_inner().into_inner());
+        }
+    }
+    Ok(())
+}
+
+/// Converts the image to the given color type and bit depth, and returns it as an optimized PNG.
+/// Any [crate::image_tasks::post_process] transforms for its path are applied first. With those or
+/// with `--master-palette`, which snaps the image to that palette, the color type and bit depth are
+/// chosen again.
+#[instrument(skip(image, color_type))]
+pub fn encode_png(
+    mut image: MaybeFromPool<Pixmap>,
+    mut color_type: ColorType,
+    mut bit_depth: BitDepth,
+    pack: PackId,
+    file_path: &str,
+) -> Result<Vec<u8>, CloneableError> {
+    if let Some(mode) = post_process(&mut image, file_path) {
+        (color_type, bit_depth) = mode;
+    }
+    if let Some(master_palette) = &*MASTER_PALETTE {
+        (color_type, bit_depth) = master_palette.constrain(&mut image, file_path);
+    }
+    if let ColorType::RGB {
+        transparent_color: Some(transparent_color),
+    } = color_type
+        && let Some(replacement) = check_transparent_color(&image, transparent_color, file_path)
+    {
+        color_type = ColorType::RGB {
+            transparent_color: Some(replacement),
+        };
+    }
+    record_seams(pack, file_path, &image);
+    record_vanilla_parity(pack, file_path, &image);
+    record_raw_image(pack, file_path, &image, &color_type, bit_depth);
     let width = image.width();
     let height = image.height();
     info!("Dimensions of {} are {}x{}", file_path, width, height);
@@ -134,7 +223,7 @@ pub fn png_output(
         }
         ColorType::RGBA => {
             info!("Writing {} in RGBA mode", file_path);
-            take_demultiplied(image.unwrap_or_clone())
+            demultiplied_bytes(image)
         }
         ColorType::Grayscale { transparent_shade } => {
             info!("Writing {} in {}-bit grayscale mode", file_path, bit_depth);
@@ -179,144 +268,605 @@ pub fn png_output(
             let mut bit_writer: BitWriter<_, BigEndian> = BitWriter::new(Cursor::new(bytes));
             let mut palette_premul: Vec<[u8; 4]> = Vec::with_capacity(palette.len());
             let mut palette_demult: Vec<ComparableColor> = Vec::with_capacity(palette.len());
-            let mut palette_with_error_corrections: HashMap<[u8; 4], usize> = HashMap::new();
+            let mut palette_indices: HashMap<[u8; 4], u16> = HashMap::new();
             for (index, color) in palette.iter().enumerate() {
                 let premul_bytes =
                     cast(ColorU8::from_rgba(color.r, color.g, color.b, color.a).premultiply());
                 palette_premul.push(premul_bytes);
-                palette_with_error_corrections.insert(premul_bytes, index);
+                palette_indices.insert(premul_bytes, index as u16);
                 palette_demult.push(ComparableColor::from(*color));
             }
-            let mut worst_discrepancy: u16 = 0;
-            let mut prev_pixel: PremultipliedColorU8 = cast(palette_premul[0]);
+            let perceptual_palette = PerceptualPalette::new(&palette_demult);
+            let mut corrections: HashMap<[u8; 4], (u16, ColorCorrection)> = HashMap::new();
+            let mut prev_pixel: [u8; 4] = palette_premul[0];
             let mut prev_index: u16 = 0;
+            let mut prev_corrected = false;
             for pixel in image.pixels() {
-                let index = if prev_pixel == *pixel {
-                    prev_index
-                } else {
-                    let pixel_bytes: [u8; 4] = cast(*pixel);
-                    let index = match palette_with_error_corrections.get(&pixel_bytes) {
+                let pixel_bytes: [u8; 4] = cast(*pixel);
+                if prev_pixel != pixel_bytes {
+                    prev_pixel = pixel_bytes;
+                    prev_corrected = !palette_indices.contains_key(&pixel_bytes);
+                    prev_index = match palette_indices.get(&pixel_bytes) {
                         Some(index) => *index,
                         None => {
-                            let pixel_color = ComparableColor::from(*pixel);
-                            let (index, (_, discrepancy)) = palette_demult
-                                .iter()
-                                .map(|color| (color, color.abs_diff(&pixel_color)))
-                                .enumerate()
-                                .min_by_key(|(_, (_, discrepancy))| *discrepancy)
-                                .unwrap();
-                            palette_with_error_corrections.insert(pixel_bytes, index);
-                            worst_discrepancy = worst_discrepancy.max(discrepancy);
-                            index
+                            corrections
+                                .entry(pixel_bytes)
+                                .or_insert_with(|| {
+                                    let found = ComparableColor::from(*pixel);
+                                    let (index, error) = perceptual_palette.nearest(found);
+                                    let correction = ColorCorrection {
+                                        found,
+                                        chosen: palette_demult[index],
+                                        pixels: 0,
+                                        error,
+                                    };
+                                    (index as u16, correction)
+                                })
+                                .0
                         }
-                    } as u16;
-                    prev_pixel = *pixel;
-                    prev_index = index;
-                    index
-                };
-                bit_writer.write(bit_depth as u8 as u32, index)?;
+                    };
+                }
+                if prev_corrected {
+                    corrections.get_mut(&pixel_bytes).unwrap().1.pixels += 1;
+                }
+                bit_writer.write(bit_depth as u8 as u32, prev_index)?;
             }
             bit_writer.flush()?;
-            if let Some(corrected_color_count) = palette_with_error_corrections
-                .len()
-                .checked_sub(palette.len())
-                && corrected_color_count > 0
-            {
-                let corrections = palette_with_error_corrections
-                    .into_iter()
-                    .flat_map(|(raw, corrected_index)| {
-                        let found: PremultipliedColorU8 = cast(raw);
-                        let found: ComparableColor = found.into();
-                        let corrected = palette_demult[corrected_index];
-                        if found != corrected {
-                            Some(format!("{} -> {}", found, corrected))
-                        } else {
-                            None
-                        }
-                        .into_iter()
-                    })
-                    .join(", ");
+            let corrections: Vec<ColorCorrection> = corrections
+                .into_values()
+                .map(|(_, correction)| correction)
+                .filter(|correction| correction.found != correction.chosen)
+                .collect();
+            if !corrections.is_empty() {
+                let worst_discrepancy = corrections
+                    .iter()
+                    .map(|correction| correction.error)
+                    .fold(0.0, f32::max);
                 warn!(
-                    "Corrected {} color errors in {} (worst error amount was {}): {}",
-                    corrected_color_count, file_path, worst_discrepancy, corrections
+                    "Corrected {} color errors in {} (worst error amount was {:.4}): {}",
+                    corrections.len(),
+                    file_path,
+                    worst_discrepancy,
+                    corrections
+                        .iter()
+                        .map(|correction| format!("{} -> {}", correction.found, correction.chosen))
+                        .join(", ")
                 );
+                record_corrections(pack, file_path, corrections);
             }
             bit_writer.into_writer().into_inner()
         }
     };
-    let mut mut_png_options: Options;
-    let png_options = if let Some(png_filters) = png_filters_to_try(&file_path) {
-        mut_png_options = OXIPNG_OPTIONS.clone();
-        mut_png_options.filter = png_filters;
-        &mut_png_options
-    } else {
-        &*OXIPNG_OPTIONS
+    let png_filters = png_filters_to_try(file_path);
+    let oxipng_preset = policy_for(file_path, Some((width, height))).oxipng_preset;
+    let transparent_rgb = transparent_rgb(&color_type);
+    let key = png_cache_key(
+        &raw_bytes,
+        &color_type,
+        bit_depth,
+        width,
+        height,
+        png_filters.as_ref(),
+        oxipng_preset,
+    );
+    let cached = match PNG_CACHE.lock().entry(key) {
+        Entry::Vacant(entry) => {
+            entry.insert(None);
+            None
+        }
+        Entry::Occupied(mut entry) => {
+            Some(entry.get_mut().get_or_insert_with(Default::default).clone())
+        }
     };
+    let optimize = || -> Result<Vec<u8>, CloneableError> {
+        let mut png_options = oxipng_preset.map_or_else(budgeted_oxipng_options, oxipng_options);
+        if let Some(png_filters) = png_filters {
+            png_options.filter = png_filters;
+        }
+        let png_span = info_span!("PNG optimization");
+        let png_span = png_span.enter();
+        let png = RawImage::new(width, height, color_type, bit_depth, raw_bytes)?
+            .create_optimized_png(&png_options)?;
+        record_png_optimized();
+        drop(png_span);
+        match transparent_rgb {
+            Some(transparent_rgb) => restore_trns(png, transparent_rgb, file_path),
+            None => Ok(png),
+        }
+    };
+    let png = match cached {
+        None => optimize()?,
+        Some(cached) => {
+            let mut optimized_here = false;
+            let png = cached.get_or_try_init(|| {
+                optimized_here = true;
+                optimize()
+            })?;
+            if !optimized_here {
+                skip_png();
+                info!(
+                    "Reusing the optimized PNG of an identical image for {}",
+                    file_path
+                );
+            }
+            png.to_owned()
+        }
+    };
+    expect_png(pack, file_path, &png, width, height);
+    record_final_png(pack, file_path, &png);
+    Ok(png)
+}
+
+/// Optimizes a PNG that wasn't encoded here, such as a hand-made override, with the same settings
+/// as the generated ones.
+pub fn optimize_png(
+    original: &[u8],
+    pack: PackId,
+    file_path: &str,
+) -> Result<Vec<u8>, CloneableError> {
     let png_span = info_span!("PNG optimization");
     let png_span = png_span.enter();
-    let png = RawImage::new(width, height, color_type, bit_depth, raw_bytes)?
-        .create_optimized_png(png_options)?;
+    let png_options = policy_for(file_path, png_dimensions(original))
+        .oxipng_preset
+        .map_or_else(budgeted_oxipng_options, oxipng_options);
+    let png = oxipng::optimize_from_memory(original, &png_options)?;
+    record_png_optimized();
     drop(png_span);
-    let deflate_span = info_span!("Deflating file");
-    let deflate_span = deflate_span.enter();
-    match zip.try_lock() {
-        Some(mut writer_guard) => {
-            writer_guard.start_file(file_path, PNG_ZIP_OPTIONS.to_owned())?;
-            writer_guard.write_all(&png)?;
-        }
-        None => {
-            let mut single_file_out =
-                ZipWriter::new(Cursor::new(Vec::with_capacity(*ZIP_BUFFER_SIZE)));
-            single_file_out.start_file(file_path, PNG_ZIP_OPTIONS.to_owned())?;
-            single_file_out.write_all(&png)?;
-            let mut single_compressed_file = ZipArchive::new(single_file_out.finish()?)?;
-            drop(deflate_span);
-            let mut writer = match zip.try_lock() {
-                None => {
-                    let get_lock_span = info_span!("Waiting for lock on ZIP file");
-                    let get_lock_span = get_lock_span.enter();
-                    let writer = zip.lock();
-                    drop(get_lock_span);
-                    writer
+    let header = png::Decoder::new(&*png).read_info()?;
+    let (width, height) = (header.info().width, header.info().height);
+    drop(header);
+    expect_png(pack, file_path, &png, width, height);
+    record_final_png(pack, file_path, &png);
+    Ok(png)
+}
+
+/// Optimized PNGs by [png_cache_key], so that when different tasks produce the same image, oxipng
+/// doesn't have to optimize it every time. The first time an image is seen, only its key is
+/// recorded; its PNG is kept from the second time on, so that only images that actually repeat are
+/// held in memory. A thread encoding an image whose PNG is being kept waits for it.
+type PngCache = HashMap<[u64; 2], Option<Arc<OnceCell<Vec<u8>>>>>;
+
+static PNG_CACHE: Lazy<Mutex<PngCache>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Hashes everything that affects the optimized PNG. Two hashes are taken so that a collision,
+/// which would silently put the wrong image in the pack, is vanishingly unlikely.
+fn png_cache_key(
+    raw_bytes: &[u8],
+    color_type: &ColorType,
+    bit_depth: BitDepth,
+    width: u32,
+    height: u32,
+    png_filters: Option<&IndexSet<RowFilter>>,
+    oxipng_preset: Option<u8>,
+) -> [u64; 2] {
+    [0u8, 1u8].map(|salt| {
+        let mut hasher = DefaultHasher::new();
+        salt.hash(&mut hasher);
+        match color_type {
+            ColorType::Grayscale { transparent_shade } => {
+                (0u8, *transparent_shade).hash(&mut hasher)
+            }
+            ColorType::RGB { transparent_color } => (
+                1u8,
+                transparent_color.map(|color| [color.r, color.g, color.b]),
+            )
+                .hash(&mut hasher),
+            ColorType::Indexed { palette } => {
+                2u8.hash(&mut hasher);
+                for color in palette {
+                    [color.r, color.g, color.b, color.a].hash(&mut hasher);
                 }
-                Some(locked_writer) => locked_writer,
-            };
-            let write_file_span = info_span!("Adding file to ZIP file");
-            let write_file_span = write_file_span.enter();
-            writer.raw_copy_file(single_compressed_file.by_index_raw(0).unwrap())?;
-            drop(write_file_span);
+            }
+            ColorType::RGBA => 3u8.hash(&mut hasher),
+            ColorType::GrayscaleAlpha => 4u8.hash(&mut hasher),
         }
+        (bit_depth as u8, width, height, raw_bytes).hash(&mut hasher);
+        png_filters
+            .map(|filters| filters.iter().copied().collect::<Vec<_>>())
+            .hash(&mut hasher);
+        oxipng_preset.hash(&mut hasher);
+        hasher.finish()
+    })
+}
+
+/// If a visible pixel has the color reserved for transparent pixels in RGB mode, returns one that no
+/// visible pixel has.
+fn check_transparent_color(
+    image: &Pixmap,
+    transparent_color: RGB16,
+    file_path: &str,
+) -> Option<RGB16> {
+    let colors = || image.pixels().iter().copied().map(ComparableColor::from);
+    if !colors().any(|color| color.alpha() != 0 && color.to_rgb16() == transparent_color) {
+        return None;
     }
-    Ok(())
+    let sentinel = transparency_sentinel(colors());
+    warn!(
+        "{} contains its transparent color, so using {} instead",
+        file_path, sentinel
+    );
+    Some(sentinel.to_rgb16())
 }
 
-pub fn copy_out_to_out(source_path: Box<str>, dest_path: Box<str>, zip: &Arc<Mutex<ZipWriter<ZipBufferRaw>>>) -> Result<(), CloneableError> {
+/// The color that marks transparent pixels in an RGB or grayscale image, at 8 bits per channel.
+fn transparent_rgb(color_type: &ColorType) -> Option<[u8; 3]> {
+    match color_type {
+        ColorType::RGB {
+            transparent_color: Some(color),
+        } => Some([color.r, color.g, color.b].map(|channel| (channel >> 8) as u8)),
+        ColorType::Grayscale {
+            transparent_shade: Some(shade),
+        } => Some([(shade >> 8) as u8; 3]),
+        _ => None,
+    }
+}
+
+/// The contents of a tRNS chunk that makes `transparent_rgb` transparent in an RGB or grayscale PNG
+/// with the given header, or `None` for a color type that doesn't use one.
+fn trns_data(
+    transparent_rgb: [u8; 3],
+    color_type: png::ColorType,
+    bit_depth: png::BitDepth,
+) -> Option<Vec<u8>> {
+    let sample = |channel: u8| match bit_depth {
+        png::BitDepth::Sixteen => channel as u16 * 0x101,
+        depth => channel as u16 >> (8 - depth as u8),
+    };
+    match color_type {
+        png::ColorType::Rgb => Some(
+            transparent_rgb
+                .iter()
+                .flat_map(|channel| sample(*channel).to_be_bytes())
+                .collect(),
+        ),
+        png::ColorType::Grayscale => Some(sample(transparent_rgb[0]).to_be_bytes().to_vec()),
+        _ => None,
+    }
+}
+
+/// Inserts a chunk before the first IDAT chunk, which is where the PNG spec requires tRNS to be.
+fn insert_chunk_before_idat(
+    png: &[u8],
+    chunk_type: &[u8; 4],
+    data: &[u8],
+) -> Result<Vec<u8>, CloneableError> {
+    // Skip the signature
+    let mut offset = 8;
+    while offset + 8 <= png.len() {
+        if png[offset + 4..offset + 8] == *b"IDAT" {
+            let mut crc = crc32fast::Hasher::new();
+            crc.update(chunk_type);
+            crc.update(data);
+            let mut out = Vec::with_capacity(png.len() + data.len() + 12);
+            out.extend_from_slice(&png[..offset]);
+            out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+            out.extend_from_slice(chunk_type);
+            out.extend_from_slice(data);
+            out.extend_from_slice(&crc.finalize().to_be_bytes());
+            out.extend_from_slice(&png[offset..]);
+            return Ok(out);
+        }
+        let length = u32::from_be_bytes(png[offset..offset + 4].try_into()?) as usize;
+        // Length, type, data and CRC
+        offset += length + 12;
+    }
+    Err(anyhoo!("PNG has no IDAT chunk"))
+}
+
+/// Makes sure that an optimized PNG whose transparent pixels are marked by `transparent_rgb` still
+/// has the tRNS chunk that marks them, rather than relying on oxipng to keep it. If oxipng reduced
+/// the image to a palette or added an alpha channel, those carry the transparency instead; but if
+/// it wrote an RGB or grayscale image without tRNS, the chunk is added back, so that transparent
+/// pixels don't silently turn opaque.
+fn restore_trns(
+    png: Vec<u8>,
+    transparent_rgb: [u8; 3],
+    file_path: &str,
+) -> Result<Vec<u8>, CloneableError> {
+    let (has_trns, color_type, bit_depth) = {
+        let reader = png::Decoder::new(&*png).read_info()?;
+        let info = reader.info();
+        (info.trns.is_some(), info.color_type, info.bit_depth)
+    };
+    if has_trns {
+        return Ok(png);
+    }
+    let Some(trns) = trns_data(transparent_rgb, color_type, bit_depth) else {
+        return Ok(png);
+    };
+    warn!(
+        "The optimized PNG of {} had no tRNS chunk, so adding one",
+        file_path
+    );
+    insert_chunk_before_idat(&png, b"tRNS", &trns)
+}
+
+pub fn copy_out_to_out(
+    source_path: Box<str>,
+    dest_path: Box<str>,
+    zip: &Arc<Mutex<ZipWriter<ZipBufferRaw>>>,
+) -> Result<(), CloneableError> {
     zip.lock()
         .deref_mut()
         .deep_copy_file(&source_path, &dest_path)?;
     Ok(())
 }
 
-pub fn copy_in_to_out(source: &File, dest_path: Box<str>, zip: Arc<Mutex<ZipWriter<ZipBufferRaw>>>) -> Result<(), CloneableError> {
+pub fn copy_in_to_out(
+    source: &File,
+    dest_path: Box<str>,
+    zip: &Arc<Mutex<ZipWriter<ZipBufferRaw>>>,
+) -> Result<(), CloneableError> {
+    write_metadata_to_zip(source.contents(), dest_path, zip)
+}
+
+/// Adds a file other than a PNG to the ZIP file.
+pub fn write_metadata_to_zip(
+    contents: &[u8],
+    dest_path: Box<str>,
+    zip: &Arc<Mutex<ZipWriter<ZipBufferRaw>>>,
+) -> Result<(), CloneableError> {
+    let options = zip_options_for(&dest_path, contents, METADATA_ZIP_OPTIONS.to_owned());
     let mut writer = zip.lock();
-    writer
-        .deref_mut()
-        .start_file(dest_path, METADATA_ZIP_OPTIONS.to_owned())?;
-    writer.deref_mut().write_all(source.contents())?;
+    writer.deref_mut().start_file(dest_path, options)?;
+    writer.deref_mut().write_all(contents)?;
     Ok(())
 }
 
-fn take_demultiplied(image: Pixmap) -> Vec<u8> {
-    debug_assert!(
-        Layout::new::<PremultipliedColorU8>() ==
-        Layout::new::<ComparableColor>()
-    );
-    let mut pixels: Box<[ComparableColor]> = cast_slice_box(image.take().into());
-    for pixel in pixels.iter_mut() {
-        unsafe {
-            // Treat this as a PremultipliedColorU8 slice for input and a ColorU8 slice for output
-            *pixel = transmute::<_, PremultipliedColorU8>(*pixel).into();
+/// Finishes the ZIP file that every output was written to as it became ready, and rewrites it with
+/// `pack.mcmeta`, `pack.png` and any other top-level files first and the rest in path order, so
+/// that the output doesn't depend on which task finished first, and launchers that only read the
+/// start of the archive find the metadata. Entries are copied without being compressed again.
+pub fn finish_zip(zip: ZipWriter<ZipBufferRaw>) -> Result<Vec<u8>, CloneableError> {
+    let staged = zip.finish()?;
+    let mut out = ZipWriter::new(Cursor::new(Vec::with_capacity(staged.get_ref().len())));
+    let mut staged = ZipArchive::new(staged)?;
+    let mut indices: Vec<usize> = (0..staged.len()).collect();
+    indices.sort_by_cached_key(|index| {
+        let name = staged.name_for_index(*index).unwrap();
+        (zip_entry_order(name), Box::<str>::from(name))
+    });
+    for index in indices {
+        ZIP_LAYOUT.copy_entry(&mut out, staged.by_index_raw(index)?)?;
+    }
+    Ok(out.finish()?.into_inner())
+}
+
+fn zip_entry_order(name: &str) -> u8 {
+    match name {
+        "pack.mcmeta" => 0,
+        "pack.png" => 1,
+        _ if !name.contains('/') => 2,
+        _ => 3,
+    }
+}
+
+/// Returns the image's pixels as non-premultiplied RGBA bytes. An image from a pool is read in
+/// place, since it has to go back to the pool; any other image is demultiplied in place and its
+/// buffer is returned, so neither case copies the image more than once.
+fn demultiplied_bytes(image: MaybeFromPool<Pixmap>) -> Vec<u8> {
+    match image {
+        MaybeFromPool::FromPool { reusable, .. } => {
+            let mut bytes = Vec::with_capacity(reusable.data().len());
+            for pixel in reusable.pixels() {
+                let pixel = pixel.demultiply();
+                bytes.extend_from_slice(&[pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]);
+            }
+            bytes
         }
+        MaybeFromPool::NotFromPool(pixmap, _) => {
+            let mut bytes = pixmap.take();
+            for pixel in bytes.chunks_exact_mut(4) {
+                let demultiplied =
+                    PremultipliedColorU8::from_rgba(pixel[0], pixel[1], pixel[2], pixel[3])
+                        .expect("Invalid premultiplied color")
+                        .demultiply();
+                pixel.copy_from_slice(&[
+                    demultiplied.red(),
+                    demultiplied.green(),
+                    demultiplied.blue(),
+                    demultiplied.alpha(),
+                ]);
+            }
+            bytes
+        }
+    }
+}
+
+#[test]
+fn test_transparent_color_collision() {
+    let mut image = Pixmap::new(2, 1).unwrap();
+    image.pixels_mut()[1] = ComparableColor::RESERVED_FOR_TRANSPARENCY.into();
+    let png = encode_png(
+        MaybeFromPool::not_from_pool(image),
+        ColorType::RGB {
+            transparent_color: Some(ComparableColor::RESERVED_FOR_TRANSPARENCY.to_rgb16()),
+        },
+        BitDepth::Eight,
+        PackId::default(),
+        "test",
+    )
+    .unwrap();
+    let decoded = decode_rgba(&png);
+    assert_eq!(decoded[3], 0);
+    assert_eq!(decoded[4..], [0xc0, 0xff, 0x3e, 0xff]);
+}
+
+/// Decodes a PNG to 8-bit RGBA, applying any tRNS chunk.
+#[cfg(test)]
+fn decode_rgba(png: &[u8]) -> Vec<u8> {
+    let mut decoder = png::Decoder::new(png);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().unwrap();
+    let mut decoded = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut decoded).unwrap();
+    assert_eq!(
+        reader.output_color_type(),
+        (png::ColorType::Rgba, png::BitDepth::Eight)
+    );
+    decoded
+}
+
+#[test]
+fn test_restore_trns() {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, 2, 1);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(&[0xc0, 0xff, 0x3e, 0x12, 0x34, 0x56])
+        .unwrap();
+    assert_eq!(decode_rgba(&png)[3], 0xff);
+    let restored = restore_trns(png, [0xc0, 0xff, 0x3e], "test").unwrap();
+    assert_eq!(
+        decode_rgba(&restored),
+        [0xc0, 0xff, 0x3e, 0, 0x12, 0x34, 0x56, 0xff]
+    );
+    // Restoring again leaves the chunk alone
+    assert_eq!(
+        restore_trns(restored.clone(), [0xc0, 0xff, 0x3e], "test").unwrap(),
+        restored
+    );
+}
+
+#[test]
+fn test_binary_transparency_survives_optimization() {
+    let mut image = Pixmap::new(4, 1).unwrap();
+    image.pixels_mut()[1] = ComparableColor::WHITE.into();
+    image.pixels_mut()[2] = ComparableColor::BLACK.into();
+    image.pixels_mut()[3] = ComparableColor::STONE.into();
+    let gray = encode_png(
+        MaybeFromPool::not_from_pool(image.clone()),
+        ColorType::Grayscale {
+            transparent_shade: Some(0x2020),
+        },
+        BitDepth::Eight,
+        PackId::default(),
+        "gray",
+    )
+    .unwrap();
+    let decoded = decode_rgba(&gray);
+    assert_eq!(decoded[3], 0);
+    assert_eq!(decoded[7], 0xff);
+    image.pixels_mut()[3] = ComparableColor::RED.into();
+    let rgb = encode_png(
+        MaybeFromPool::not_from_pool(image),
+        ColorType::RGB {
+            transparent_color: Some(ComparableColor::RESERVED_FOR_TRANSPARENCY.to_rgb16()),
+        },
+        BitDepth::Eight,
+        PackId::default(),
+        "rgb",
+    )
+    .unwrap();
+    let decoded = decode_rgba(&rgb);
+    assert_eq!(decoded[3], 0);
+    assert_eq!(decoded[12..], [0xff, 0, 0, 0xff]);
+}
+
+#[test]
+fn test_demultiplied_bytes() {
+    use crate::image_tasks::allocate_pixmap_for_overwrite;
+    use crate::image_tasks::color::c;
+    use crate::GRID_SIZE;
+
+    let mut image = Pixmap::new(2, 1).unwrap();
+    image.pixels_mut()[1] = (c(0x806040) * 0.5).into();
+    let demultiplied = image.pixels()[1].demultiply();
+    let expected = [
+        0,
+        0,
+        0,
+        0,
+        demultiplied.red(),
+        demultiplied.green(),
+        demultiplied.blue(),
+        0x80,
+    ];
+    let mut pooled = allocate_pixmap_for_overwrite(*GRID_SIZE, *GRID_SIZE);
+    assert!(matches!(pooled, MaybeFromPool::FromPool { .. }));
+    pooled.pixels_mut()[..2].copy_from_slice(image.pixels());
+    assert_eq!(demultiplied_bytes(pooled)[..8], expected);
+    assert_eq!(
+        demultiplied_bytes(MaybeFromPool::not_from_pool(image)),
+        expected
+    );
+}
+
+#[test]
+fn test_png_cache() {
+    use crate::image_tasks::color::c;
+
+    let image = || {
+        let mut image = Pixmap::new(4, 4).unwrap();
+        image.pixels_mut()[5] = (c(0x8a3a00) * 0.5).into();
+        MaybeFromPool::not_from_pool(image)
+    };
+    let key_for = |image| {
+        png_cache_key(
+            &demultiplied_bytes(image),
+            &ColorType::RGBA,
+            BitDepth::Eight,
+            4,
+            4,
+            None,
+            None,
+        )
+    };
+    let first = encode_png(
+        image(),
+        ColorType::RGBA,
+        BitDepth::Eight,
+        PackId::default(),
+        "first",
+    )
+    .unwrap();
+    assert!(PNG_CACHE.lock()[&key_for(image())].is_none());
+    let second = encode_png(
+        image(),
+        ColorType::RGBA,
+        BitDepth::Eight,
+        PackId::default(),
+        "second",
+    )
+    .unwrap();
+    assert_eq!(first, second);
+    assert_eq!(
+        PNG_CACHE.lock()[&key_for(image())]
+            .as_ref()
+            .and_then(|cached| cached.get()),
+        Some(&first)
+    );
+    let mut different = image();
+    different.pixels_mut()[6] = ComparableColor::BLACK.into();
+    assert_ne!(key_for(different), key_for(image()));
+}
+
+#[test]
+fn test_finish_zip() {
+    let mut zip = ZipWriter::new(ZipBufferRaw::new(vec![]));
+    for name in [
+        "assets/minecraft/textures/block/stone.png",
+        "pack.png",
+        "assets/minecraft/textures/block/dirt.png",
+        "LICENSE",
+        "pack.mcmeta",
+    ] {
+        zip.start_file(name, METADATA_ZIP_OPTIONS.to_owned())
+            .unwrap();
+        zip.write_all(name.as_bytes()).unwrap();
     }
-    cast_slice_box(pixels).to_vec()
+    let out = ZipArchive::new(Cursor::new(finish_zip(zip).unwrap())).unwrap();
+    assert_eq!(
+        out.file_names().collect::<Vec<_>>(),
+        [
+            "pack.mcmeta",
+            "pack.png",
+            "LICENSE",
+            "assets/minecraft/textures/block/dirt.png",
+            "assets/minecraft/textures/block/stone.png",
+        ]
+    );
 }
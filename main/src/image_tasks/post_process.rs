@@ -0,0 +1,248 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use log::info;
+use once_cell::sync::Lazy;
+use oxipng::{BitDepth, ColorType};
+use resvg::tiny_skia::{Pixmap, PremultipliedColorU8};
+
+use crate::anyhoo;
+use crate::config::CONFIG;
+use crate::image_tasks::cloneable::{Arcow, CloneableError};
+use crate::image_tasks::color::{rgba, ComparableColor};
+use crate::image_tasks::task_spec::{color_description_to_mode, ColorDescription};
+use crate::image_tasks::texture_filter::path_matches;
+use crate::GRID_SIZE;
+
+/// A stylistic transform applied to a finished texture just before it's encoded, so that a variant
+/// of the pack can be built without changing any material. Each takes a strength in percent.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum PostProcess {
+    /// Pushes each pixel away from the mean of its neighbors (an unsharp mask).
+    Sharpen(u8),
+    /// Darkens each frame toward its corners, which are darkened by the given amount.
+    Vignette(u8),
+    /// Darkens every other row of texels, a texel being 1/[GRID_SIZE] of the frame's width.
+    Scanlines(u8),
+}
+
+impl FromStr for PostProcess {
+    type Err = CloneableError;
+
+    /// Parses a name and an optional strength, such as `sharpen:50` or `vignette`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, strength) = s.split_once(':').unwrap_or((s, "50"));
+        let strength: u8 = strength
+            .trim()
+            .parse()
+            .ok()
+            .filter(|strength| *strength <= 100)
+            .ok_or_else(|| anyhoo!("Expected a strength from 0 to 100: {}", s))?;
+        match name.trim() {
+            "sharpen" => Ok(PostProcess::Sharpen(strength)),
+            "vignette" => Ok(PostProcess::Vignette(strength)),
+            "scanlines" => Ok(PostProcess::Scanlines(strength)),
+            _ => Err(anyhoo!("Unknown post-processing transform: {}", s)),
+        }
+    }
+}
+
+/// The `[post_process]` table of the config file, parsed. Every rule whose pattern matches a
+/// texture's path applies, in the order they're listed.
+pub static POST_PROCESSING: Lazy<Vec<(String, Vec<PostProcess>)>> = Lazy::new(|| {
+    CONFIG
+        .post_processing()
+        .iter()
+        .map(|(pattern, transforms)| {
+            let transforms = transforms
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<Vec<PostProcess>, CloneableError>>()
+                .unwrap_or_else(|e| {
+                    panic!("Invalid value for [post_process] {}: {:?}", pattern, e)
+                });
+            (pattern.to_owned(), transforms)
+        })
+        .collect()
+});
+
+/// The height of each frame of `image`, if it's a vertical animation strip.
+fn frame_height(image: &Pixmap) -> u32 {
+    if image.height() > image.width() && image.height().is_multiple_of(image.width()) {
+        image.width()
+    } else {
+        image.height()
+    }
+}
+
+/// Multiplies the color channels of a pixel, keeping its alpha.
+fn scale(pixel: PremultipliedColorU8, factor: f32) -> PremultipliedColorU8 {
+    let channel = |value: u8| (value as f32 * factor).round().clamp(0.0, 255.0) as u8;
+    PremultipliedColorU8::from_rgba(
+        channel(pixel.red()),
+        channel(pixel.green()),
+        channel(pixel.blue()),
+        pixel.alpha(),
+    )
+    .unwrap()
+}
+
+fn sharpen(image: &mut Pixmap, strength: u8) {
+    let amount = strength as f32 / 100.0;
+    let (width, height) = (image.width() as i64, image.height() as i64);
+    let original: Vec<ComparableColor> = image.pixels().iter().copied().map(Into::into).collect();
+    let at = |x: i64, y: i64| {
+        original[(y.clamp(0, height - 1) * width + x.clamp(0, width - 1)) as usize]
+    };
+    for (index, pixel) in image.pixels_mut().iter_mut().enumerate() {
+        let (x, y) = (index as i64 % width, index as i64 / width);
+        let center = at(x, y);
+        if center.alpha() == 0 {
+            continue;
+        }
+        let neighbors = [at(x - 1, y), at(x + 1, y), at(x, y - 1), at(x, y + 1)];
+        // Weighted by alpha, so that the color of a transparent neighbor, which is meaningless,
+        // doesn't darken the edge of a shape
+        let total_alpha: f32 = neighbors.iter().map(|color| color.alpha() as f32).sum();
+        if total_alpha == 0.0 {
+            continue;
+        }
+        let channel = |get: fn(&ComparableColor) -> u8| {
+            let mean = neighbors
+                .iter()
+                .map(|color| get(color) as f32 * color.alpha() as f32)
+                .sum::<f32>()
+                / total_alpha;
+            let value = get(&center) as f32;
+            (value + amount * (value - mean)).round().clamp(0.0, 255.0) as u8
+        };
+        *pixel = rgba(
+            channel(ComparableColor::red),
+            channel(ComparableColor::green),
+            channel(ComparableColor::blue),
+            center.alpha(),
+        )
+        .into();
+    }
+}
+
+fn vignette(image: &mut Pixmap, strength: u8) {
+    let amount = strength as f32 / 100.0;
+    let width = image.width();
+    let frame_height = frame_height(image);
+    let (center_x, center_y) = (width as f32 / 2.0, frame_height as f32 / 2.0);
+    let corner_squared = center_x * center_x + center_y * center_y;
+    for (index, pixel) in image.pixels_mut().iter_mut().enumerate() {
+        let x = (index as u32 % width) as f32 + 0.5 - center_x;
+        let y = (index as u32 / width % frame_height) as f32 + 0.5 - center_y;
+        *pixel = scale(*pixel, 1.0 - amount * (x * x + y * y) / corner_squared);
+    }
+}
+
+fn scanlines(image: &mut Pixmap, strength: u8) {
+    let factor = 1.0 - strength as f32 / 100.0;
+    let width = image.width();
+    let texel = (width / *GRID_SIZE).max(1);
+    for (index, pixel) in image.pixels_mut().iter_mut().enumerate() {
+        if (index as u32 / width / texel) % 2 == 1 {
+            *pixel = scale(*pixel, factor);
+        }
+    }
+}
+
+impl PostProcess {
+    pub fn apply(&self, image: &mut Pixmap) {
+        match *self {
+            PostProcess::Sharpen(strength) => sharpen(image, strength),
+            PostProcess::Vignette(strength) => vignette(image, strength),
+            PostProcess::Scanlines(strength) => scanlines(image, strength),
+        }
+    }
+}
+
+/// Applies every transform in [POST_PROCESSING] whose pattern matches `file_path`. If any did,
+/// returns the color type and bit depth to encode the image with, since the colors predicted for
+/// it are no longer accurate.
+pub fn post_process(image: &mut Pixmap, file_path: &str) -> Option<(ColorType, BitDepth)> {
+    let transforms: Vec<PostProcess> = POST_PROCESSING
+        .iter()
+        .filter(|(pattern, _)| path_matches(pattern, file_path))
+        .flat_map(|(_, transforms)| transforms.iter().copied())
+        .collect();
+    if transforms.is_empty() {
+        return None;
+    }
+    info!("Post-processing {} with {:?}", file_path, transforms);
+    for transform in transforms {
+        transform.apply(image);
+    }
+    let mut colors: Vec<ComparableColor> = image
+        .pixels()
+        .iter()
+        .copied()
+        .map(ComparableColor::from)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    colors.sort();
+    Some(color_description_to_mode(
+        &ColorDescription::SpecifiedColors(Arcow::from_owned(colors)),
+        file_path,
+    ))
+}
+
+#[test]
+fn test_post_process() {
+    use crate::image_tasks::color::{c, gray};
+
+    assert_eq!(
+        "sharpen:30".parse::<PostProcess>().unwrap(),
+        PostProcess::Sharpen(30)
+    );
+    assert_eq!(
+        "vignette".parse::<PostProcess>().unwrap(),
+        PostProcess::Vignette(50)
+    );
+    assert!("scanlines:101".parse::<PostProcess>().is_err());
+    assert!("blur:10".parse::<PostProcess>().is_err());
+
+    let filled = |width: u32, height: u32, color: ComparableColor| {
+        let mut image = Pixmap::new(width, height).unwrap();
+        image.pixels_mut().fill(color.into());
+        image
+    };
+
+    // A flat image has nothing to sharpen, but a lone dot stands out more
+    let mut flat = filled(4, 4, c(0x7f7f7f));
+    PostProcess::Sharpen(100).apply(&mut flat);
+    assert!(flat
+        .pixels()
+        .iter()
+        .all(|pixel| ComparableColor::from(*pixel) == c(0x7f7f7f)));
+    let mut dot = filled(4, 4, gray(0x40));
+    dot.pixels_mut()[5] = gray(0x80).into();
+    PostProcess::Sharpen(100).apply(&mut dot);
+    assert!(ComparableColor::from(dot.pixels()[5]).red() > 0x80);
+    // Transparent pixels around a shape don't make a dark halo at its edge
+    let mut shape = filled(4, 4, ComparableColor::TRANSPARENT);
+    shape.pixels_mut()[5] = c(0x7f7f7f).into();
+    shape.pixels_mut()[6] = c(0x7f7f7f).into();
+    PostProcess::Sharpen(100).apply(&mut shape);
+    assert_eq!(ComparableColor::from(shape.pixels()[5]), c(0x7f7f7f));
+    assert_eq!(
+        ComparableColor::from(shape.pixels()[0]),
+        ComparableColor::TRANSPARENT
+    );
+
+    let mut vignetted = filled(4, 8, ComparableColor::WHITE);
+    PostProcess::Vignette(50).apply(&mut vignetted);
+    let red = |image: &Pixmap, x: u32, y: u32| image.pixel(x, y).unwrap().red();
+    assert!(red(&vignetted, 0, 0) < red(&vignetted, 1, 1));
+    // Each frame of an animation gets its own vignette
+    assert_eq!(red(&vignetted, 0, 0), red(&vignetted, 0, 4));
+
+    let mut scanned = filled(*GRID_SIZE, *GRID_SIZE, ComparableColor::WHITE);
+    PostProcess::Scanlines(100).apply(&mut scanned);
+    assert_eq!(red(&scanned, 0, 0), 0xff);
+    assert_eq!(red(&scanned, 0, 1), 0);
+}
@@ -0,0 +1,373 @@
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read, Write};
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use oxipng::Options;
+use resvg::tiny_skia::Pixmap;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::compression::zip_options_for;
+use crate::image_tasks::output_path::texture_name;
+use crate::image_tasks::png_output::{METADATA_ZIP_OPTIONS, PNG_ZIP_OPTIONS};
+use crate::image_tasks::task_spec::PackId;
+use crate::image_tasks::upscale::downscale_image;
+use crate::image_tasks::verify::expect_png;
+use crate::image_tasks::zip_layout::ZIP_LAYOUT;
+use crate::{anyhoo, flag_present, option_value};
+
+/// The largest resource pack that Minecraft will download from a Realm or server, in bytes.
+pub const REALMS_MAX_PACK_BYTES: u64 = 250 * 1024 * 1024;
+
+/// Whether `--fit-realms` was given, so that a pack that's too large for Realms is shrunk until it
+/// fits instead of only being warned about.
+pub static FIT_REALMS: Lazy<bool> = Lazy::new(|| flag_present("fit-realms"));
+
+/// Set with `--fit-realms-categories`, such as `entity,painting`: the category subfolders, as
+/// [category_of] names them, whose textures `--fit-realms` may shrink, so that others such as
+/// `gui` keep their resolution. If it's not set, textures of any category may be shrunk.
+pub static FIT_REALMS_CATEGORIES: Lazy<Option<Vec<Box<str>>>> = Lazy::new(|| {
+    option_value("fit-realms-categories").map(|list| {
+        list.split(',')
+            .map(str::trim)
+            .filter(|category| !category.is_empty())
+            .map(Box::from)
+            .collect()
+    })
+});
+
+/// The category of the texture at `path`, such as `block` or `entity`, which is the first folder
+/// of its name whatever folder [crate::image_tasks::output_path::PATH_TEMPLATE] puts it in; or
+/// `metadata` for a top-level file that isn't a texture.
+pub(crate) fn category_of(path: &str) -> Box<str> {
+    match texture_name(path) {
+        Some(name) => name
+            .rsplit_once(':')
+            .map_or(&*name, |(_, name)| name)
+            .split_once('/')
+            .map_or("other", |(category, _)| category)
+            .into(),
+        None if path.contains('/') => "other".into(),
+        None => "metadata".into(),
+    }
+}
+
+/// Returns true if the ZIP file of `pack` is small enough for Realms. Otherwise, warns how far over
+/// the limit it is and which category subfolders take up the most space.
+pub fn check_realms_size(pack: PackId, zip_contents: &[u8]) -> Result<bool, CloneableError> {
+    let size = zip_contents.len() as u64;
+    if size <= REALMS_MAX_PACK_BYTES {
+        info!(
+            "{} ZIP file is {} bytes under the Realms limit",
+            pack,
+            REALMS_MAX_PACK_BYTES - size
+        );
+        return Ok(true);
+    }
+    let mut archive = ZipArchive::new(Cursor::new(zip_contents))?;
+    let mut by_category: BTreeMap<Box<str>, u64> = BTreeMap::new();
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        *by_category.entry(category_of(entry.name())).or_default() += entry.compressed_size();
+    }
+    let mut by_category: Vec<(Box<str>, u64)> = by_category.into_iter().collect();
+    by_category.sort_by_key(|(_, bytes)| u64::MAX - bytes);
+    warn!(
+        "{} ZIP file is {} bytes over the Realms limit of {} bytes{}; by category: {}",
+        pack,
+        size - REALMS_MAX_PACK_BYTES,
+        REALMS_MAX_PACK_BYTES,
+        if *FIT_REALMS {
+            ""
+        } else {
+            " (use --fit-realms to shrink the largest textures until it fits)"
+        },
+        by_category
+            .iter()
+            .map(|(category, bytes)| format!("{} {} bytes", category, bytes))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(false)
+}
+
+/// Re-encodes a PNG at oxipng's highest effort, and at half the resolution as well if `downscale`
+/// is true, as the contents of every path in `paths`.
+fn shrink_png(
+    pack: PackId,
+    paths: &[Box<str>],
+    png: &[u8],
+    downscale: bool,
+) -> Result<Vec<u8>, CloneableError> {
+    let mut image = Pixmap::decode_png(png)?;
+    if downscale {
+        image = downscale_image(&image, 2)?.unwrap_or_clone();
+    }
+    let shrunk = oxipng::optimize_from_memory(&image.encode_png()?, &Options::max_compression())?;
+    for path in paths {
+        expect_png(pack, path, &shrunk, image.width(), image.height());
+    }
+    Ok(shrunk)
+}
+
+/// The `.png.mcmeta` file of an animation whose PNG is downscaled to half its resolution: any
+/// frame width and height it declares are halved, so that it still has the same frames. Returns
+/// `None` if it declares neither, since then the frames are square and scale with the PNG, or an
+/// error if one of them is odd.
+fn downscaled_mcmeta(mcmeta: &[u8]) -> Result<Option<Vec<u8>>, CloneableError> {
+    let mut value: serde_json::Value = serde_json::from_slice(mcmeta)?;
+    let mut changed = false;
+    if let Some(animation) = value
+        .get_mut("animation")
+        .and_then(serde_json::Value::as_object_mut)
+    {
+        for key in ["width", "height"] {
+            if let Some(size) = animation.get(key).and_then(serde_json::Value::as_u64) {
+                if size % 2 != 0 {
+                    return Err(anyhoo!(
+                        "Can't halve an animation frame {} of {}",
+                        key,
+                        size
+                    ));
+                }
+                animation.insert(key.into(), (size / 2).into());
+                changed = true;
+            }
+        }
+    }
+    Ok(changed.then(|| serde_json::to_vec(&value)).transpose()?)
+}
+
+/// PNGs in a ZIP file with the same contents, such as a texture and its copies, which are shrunk
+/// together so that they stay identical.
+struct SamePngs {
+    paths: Vec<Box<str>>,
+    compressed_size: u64,
+    contents: Vec<u8>,
+}
+
+/// Rewrites the ZIP file of `pack` so that it fits within [REALMS_MAX_PACK_BYTES], by re-encoding
+/// its largest PNGs in `categories`, or in any category if that's `None`, first at a higher
+/// compression level and then, if that isn't enough, at half their resolution along with any
+/// `.png.mcmeta` file that declares their frame size. Files with identical contents are shrunk
+/// together. Returns the new ZIP file and the paths that were shrunk.
+pub fn fit_to_realms(
+    pack: PackId,
+    zip_contents: Vec<u8>,
+    categories: Option<&[Box<str>]>,
+) -> Result<(Vec<u8>, Vec<Box<str>>), CloneableError> {
+    fit_to_size(pack, zip_contents, REALMS_MAX_PACK_BYTES, categories)
+}
+
+fn fit_to_size(
+    pack: PackId,
+    zip_contents: Vec<u8>,
+    max_bytes: u64,
+    categories: Option<&[Box<str>]>,
+) -> Result<(Vec<u8>, Vec<Box<str>>), CloneableError> {
+    let mut excess = (zip_contents.len() as u64).saturating_sub(max_bytes);
+    let mut archive = ZipArchive::new(Cursor::new(zip_contents))?;
+    let mut pngs: Vec<SamePngs> = Vec::new();
+    let mut pngs_by_crc: HashMap<u32, Vec<usize>> = HashMap::new();
+    let mut png_index: HashMap<Box<str>, usize> = HashMap::new();
+    let mut mcmetas: HashMap<Box<str>, Vec<u8>> = HashMap::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let path: Box<str> = entry.name().into();
+        if path.ends_with(".png.mcmeta") {
+            let mut mcmeta = Vec::new();
+            entry.read_to_end(&mut mcmeta)?;
+            mcmetas.insert(path, mcmeta);
+        } else if path.ends_with(".png") {
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents)?;
+            // A matching CRC-32 alone doesn't make two files the same
+            let same_crc = pngs_by_crc.entry(entry.crc32()).or_default();
+            let same = same_crc
+                .iter()
+                .copied()
+                .find(|same| pngs[*same].contents == contents);
+            let same = same.unwrap_or_else(|| {
+                pngs.push(SamePngs {
+                    paths: Vec::new(),
+                    compressed_size: entry.compressed_size(),
+                    contents,
+                });
+                same_crc.push(pngs.len() - 1);
+                pngs.len() - 1
+            });
+            pngs[same].paths.push(path.clone());
+            png_index.insert(path, same);
+        }
+    }
+    let mut order: Vec<usize> = (0..pngs.len())
+        .filter(|index| {
+            categories.is_none_or(|categories| {
+                pngs[*index]
+                    .paths
+                    .iter()
+                    .any(|path| categories.contains(&category_of(path)))
+            })
+        })
+        .collect();
+    order.sort_by_key(|index| {
+        (
+            u64::MAX - pngs[*index].compressed_size,
+            &pngs[*index].paths[0],
+        )
+    });
+    let mut replacements: HashMap<usize, Vec<u8>> = HashMap::new();
+    let mut mcmeta_replacements: HashMap<Box<str>, Vec<u8>> = HashMap::new();
+    'passes: for downscale in [false, true] {
+        'pngs: for index in order.iter() {
+            if excess == 0 {
+                break 'passes;
+            }
+            let same = &pngs[*index];
+            let already_shrunk = replacements.get(index).map(Vec::len);
+            if !downscale && already_shrunk.is_some() {
+                continue;
+            }
+            if downscale {
+                let mut new_mcmetas = Vec::new();
+                for path in same.paths.iter() {
+                    let mcmeta_path: Box<str> = format!("{}.mcmeta", path).into();
+                    let Some(mcmeta) = mcmetas.get(&mcmeta_path) else {
+                        continue;
+                    };
+                    match downscaled_mcmeta(mcmeta) {
+                        Ok(Some(new_mcmeta)) => new_mcmetas.push((mcmeta_path, new_mcmeta)),
+                        Ok(None) => {}
+                        Err(error) => {
+                            warn!("Not downscaling {}: {:?}", path, error);
+                            continue 'pngs;
+                        }
+                    }
+                }
+                mcmeta_replacements.extend(new_mcmetas);
+            }
+            let shrunk = shrink_png(pack, &same.paths, &same.contents, downscale)?;
+            let before = already_shrunk.map_or(same.compressed_size, |len| len as u64);
+            let saved = before.saturating_sub(shrunk.len() as u64) * same.paths.len() as u64;
+            info!("Shrinking {} saves {} bytes", same.paths[0], saved);
+            excess = excess.saturating_sub(saved);
+            replacements.insert(*index, shrunk);
+        }
+    }
+    if excess > 0 {
+        warn!(
+            "ZIP file is still about {} bytes over the limit after shrinking every PNG{}",
+            excess,
+            if categories.is_some() {
+                " in --fit-realms-categories"
+            } else {
+                ""
+            }
+        );
+    }
+    let mut out = ZipWriter::new(Cursor::new(Vec::new()));
+    let mut shrunk_paths = Vec::new();
+    for index in 0..archive.len() {
+        let entry = archive.by_index_raw(index)?;
+        let path: Box<str> = entry.name().into();
+        let (replacement, default_options) = match png_index.get(&path) {
+            Some(same) => (replacements.get(same), PNG_ZIP_OPTIONS.to_owned()),
+            None => (
+                mcmeta_replacements.get(&path),
+                METADATA_ZIP_OPTIONS.to_owned(),
+            ),
+        };
+        match replacement {
+            Some(contents) => {
+                drop(entry);
+                let options = zip_options_for(&path, contents, default_options);
+                out.start_file(&*path, ZIP_LAYOUT.aligned(options))?;
+                out.write_all(contents)?;
+                shrunk_paths.push(path);
+            }
+            None => ZIP_LAYOUT.copy_entry(&mut out, entry)?,
+        }
+    }
+    Ok((out.finish()?.into_inner(), shrunk_paths))
+}
+
+#[test]
+fn test_category_of() {
+    use crate::image_tasks::output_path::texture_path;
+
+    assert_eq!(&*category_of(&texture_path("block/stone")), "block");
+    assert_eq!(
+        &*category_of(&texture_path("entity/villager/villager")),
+        "entity"
+    );
+    assert_eq!(&*category_of(&texture_path("mymod:item/gem")), "item");
+    assert_eq!(&*category_of("pack.mcmeta"), "metadata");
+    assert_eq!(
+        &*category_of("assets/minecraft/models/block/stone.json"),
+        "other"
+    );
+}
+
+#[test]
+fn test_fit_to_size() {
+    use crate::image_tasks::color::ComparableColor;
+    use crate::image_tasks::output_path::texture_path;
+    use zip::write::SimpleFileOptions;
+
+    let png = |width: u32, height: u32| {
+        let mut image = Pixmap::new(width, height).unwrap();
+        image.fill(ComparableColor::RED.into());
+        image.encode_png().unwrap()
+    };
+    let stone = texture_path("block/stone");
+    let stone_copy = texture_path("block/stone_copy");
+    let strip = texture_path("entity/strip");
+    let strip_mcmeta = format!("{}.mcmeta", strip);
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (path, contents) in [
+        (&*stone, png(4, 4)),
+        (&*stone_copy, png(4, 4)),
+        (&*strip, png(4, 8)),
+        (
+            &*strip_mcmeta,
+            b"{\"animation\":{\"width\":4,\"height\":4}}".to_vec(),
+        ),
+    ] {
+        writer
+            .start_file(path, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(&contents).unwrap();
+    }
+    let zip = writer.finish().unwrap().into_inner();
+    let read = |zip: &[u8], path: &str| {
+        let mut contents = Vec::new();
+        ZipArchive::new(Cursor::new(zip))
+            .unwrap()
+            .by_name(path)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        contents
+    };
+
+    let (fitted, shrunk) = fit_to_size(PackId::default(), zip.clone(), 1, None).unwrap();
+    assert_eq!(shrunk.len(), 4);
+    assert_eq!(read(&fitted, &stone), read(&fitted, &stone_copy));
+    let strip_image = Pixmap::decode_png(&read(&fitted, &strip)).unwrap();
+    assert_eq!((strip_image.width(), strip_image.height()), (2, 4));
+    let mcmeta: serde_json::Value = serde_json::from_slice(&read(&fitted, &strip_mcmeta)).unwrap();
+    assert_eq!(mcmeta["animation"]["width"], 2);
+    assert_eq!(mcmeta["animation"]["height"], 2);
+
+    // Only the textures of the given categories are shrunk
+    let entity = ["entity".into()];
+    let (fitted, shrunk) = fit_to_size(PackId::default(), zip, 1, Some(&entity)).unwrap();
+    assert_eq!(shrunk, [strip, strip_mcmeta.into()]);
+    assert_eq!(read(&fitted, &stone), png(4, 4));
+
+    assert!(downscaled_mcmeta(b"{\"animation\":{\"frametime\":2}}")
+        .unwrap()
+        .is_none());
+    assert!(downscaled_mcmeta(b"{\"animation\":{\"height\":3}}").is_err());
+}
@@ -0,0 +1,172 @@
+use std::fmt::{Display, Formatter};
+
+use itertools::Itertools;
+use resvg::tiny_skia::Pixmap;
+use tracing::instrument;
+
+use crate::image_tasks::color::{premultiplied_diff, rgba, ComparableColor};
+use crate::image_tasks::{allocate_pixmap_for_overwrite, MaybeFromPool};
+
+/// Colors to replace, each with the color to replace it with. Kept sorted by the color replaced,
+/// without any that would be replaced by themselves, so that equal maps make equal task specs.
+#[derive(Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct PaletteMap(Box<[(ComparableColor, ComparableColor)]>);
+
+impl PaletteMap {
+    /// If a color is listed more than once, its first replacement is used.
+    pub fn new<T: IntoIterator<Item = (ComparableColor, ComparableColor)>>(pairs: T) -> PaletteMap {
+        let mut pairs: Vec<_> = pairs.into_iter().collect();
+        pairs.sort_by_key(|(from, _)| *from);
+        pairs.dedup_by_key(|(from, _)| *from);
+        pairs.retain(|(from, to)| from != to);
+        PaletteMap(pairs.into())
+    }
+
+    /// Replaces each color of one palette with the color at the same index in another, such as
+    /// the shades of white wool with those of another dye.
+    pub fn between(from: &[ComparableColor], to: &[ComparableColor]) -> PaletteMap {
+        PaletteMap::new(from.iter().copied().zip(to.iter().copied()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn pairs(&self) -> &[(ComparableColor, ComparableColor)] {
+        &self.0
+    }
+
+    /// What `color` becomes: its replacement if it has one, otherwise itself.
+    pub fn get(&self, color: ComparableColor) -> ComparableColor {
+        match self.0.binary_search_by_key(&color, |(from, _)| *from) {
+            Ok(index) => self.0[index].1,
+            Err(_) => color,
+        }
+    }
+
+    /// What a pixel of `color` becomes: its replacement if it has one, or if it's an entry's color
+    /// made more transparent, as at the anti-aliased edge of a shape painted with that color, that
+    /// entry's replacement made more transparent in proportion. Otherwise, it's left as it is.
+    pub fn recolor(&self, color: ComparableColor) -> ComparableColor {
+        let replaced = self.get(color);
+        if replaced != color || color.alpha == 0 {
+            return replaced;
+        }
+        self.0
+            .iter()
+            .filter(|(from, _)| from.alpha > color.alpha)
+            .map(|(from, to)| {
+                let faded = rgba(from.red, from.green, from.blue, color.alpha);
+                (premultiplied_diff(faded, color), from, to)
+            })
+            .filter(|(diff, _, _)| *diff <= FADED_MATCH_TOLERANCE)
+            .min_by_key(|(diff, _, _)| *diff)
+            .map_or(color, |(_, from, to)| {
+                let alpha = (color.alpha as u32 * to.alpha as u32 + from.alpha as u32 / 2)
+                    / from.alpha as u32;
+                rgba(to.red, to.green, to.blue, alpha as u8)
+            })
+    }
+
+    /// Applying `self` and then `after`, as one map.
+    pub fn then(&self, after: &PaletteMap) -> PaletteMap {
+        PaletteMap::new(
+            self.0
+                .iter()
+                .map(|(from, to)| (*from, after.get(*to)))
+                .chain(after.0.iter().copied()),
+        )
+    }
+
+    /// Returns a copy with `f` applied to every color, both those replaced and their replacements.
+    pub fn map_colors<F: Fn(ComparableColor) -> ComparableColor>(&self, f: &F) -> PaletteMap {
+        PaletteMap::new(self.0.iter().map(|(from, to)| (f(*from), f(*to))))
+    }
+}
+
+impl Display for PaletteMap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            self.0
+                .iter()
+                .map(|(from, to)| format!("{}>{}", from, to))
+                .join(",")
+        )
+    }
+}
+
+/// How far, in any premultiplied channel, a pixel may be from an entry's color made as transparent
+/// as the pixel for [PaletteMap::recolor] to match them, since premultiplying rounds.
+const FADED_MATCH_TOLERANCE: u8 = 1;
+
+/// Replaces every pixel whose color `mapping` lists, including at the anti-aliased edges of shapes
+/// painted in those colors; see [PaletteMap::recolor]. Pixels blended from two colors aren't
+/// changed, so this is meant for images drawn with a fixed palette over transparency, such as a
+/// dyed block's master texture.
+#[instrument(skip(source, mapping))]
+pub fn remap(source: &Pixmap, mapping: &PaletteMap) -> MaybeFromPool<Pixmap> {
+    let mut out = allocate_pixmap_for_overwrite(source.width(), source.height());
+    for (out_pixel, pixel) in out.pixels_mut().iter_mut().zip(source.pixels()) {
+        let color = ComparableColor::from(*pixel);
+        let mapped = mapping.recolor(color);
+        *out_pixel = if mapped == color {
+            *pixel
+        } else {
+            mapped.into()
+        };
+    }
+    out
+}
+
+#[test]
+fn test_remap() {
+    use crate::image_tasks::color::c;
+
+    let mapping = PaletteMap::new([
+        (ComparableColor::WHITE, c(0x8a3a00)),
+        (ComparableColor::BLACK, ComparableColor::BLACK),
+        (ComparableColor::RED, ComparableColor::BLUE),
+        (ComparableColor::WHITE, ComparableColor::GREEN),
+    ]);
+    assert_eq!(mapping.pairs().len(), 2);
+    assert_eq!(mapping.get(ComparableColor::WHITE), c(0x8a3a00));
+    assert_eq!(mapping.get(ComparableColor::BLACK), ComparableColor::BLACK);
+    let back = PaletteMap::new([
+        (c(0x8a3a00), ComparableColor::WHITE),
+        (ComparableColor::BLUE, ComparableColor::GREEN),
+        (ComparableColor::WHITE, ComparableColor::GREEN),
+    ]);
+    let both = mapping.then(&back);
+    assert_eq!(both.get(ComparableColor::WHITE), ComparableColor::WHITE);
+    assert_eq!(both.get(ComparableColor::RED), ComparableColor::GREEN);
+    assert_eq!(both.get(c(0x8a3a00)), ComparableColor::WHITE);
+    assert_eq!(
+        PaletteMap::new([(ComparableColor::RED, ComparableColor::TRANSPARENT)]).to_string(),
+        "#ff0000ff>transparent"
+    );
+    assert!(PaletteMap::between(&[ComparableColor::RED], &[ComparableColor::RED]).is_empty());
+
+    let mut source = Pixmap::new(2, 2).unwrap();
+    source.pixels_mut()[0] = ComparableColor::WHITE.into();
+    source.pixels_mut()[1] = ComparableColor::BLACK.into();
+    source.pixels_mut()[2] = ComparableColor::RED.into();
+    source.pixels_mut()[3] = (ComparableColor::WHITE * 0.5).into();
+    let out = remap(&source, &mapping);
+    let colors: Vec<ComparableColor> = out.pixels().iter().map(|pixel| (*pixel).into()).collect();
+    assert_eq!(
+        colors[..3],
+        [c(0x8a3a00), ComparableColor::BLACK, ComparableColor::BLUE]
+    );
+    // A half-transparent edge pixel becomes the replacement at half the opacity
+    assert!(premultiplied_diff(colors[3], c(0x8a3a00) * 0.5) <= FADED_MATCH_TOLERANCE);
+    assert_eq!(mapping.recolor(ComparableColor::WHITE * 0.5), colors[3]);
+    assert_eq!(
+        mapping.recolor(ComparableColor::TRANSPARENT),
+        ComparableColor::TRANSPARENT
+    );
+    // A color that's not an entry's, even made more transparent, is left as it is
+    let gray = rgba(0x80, 0x80, 0x80, 0x80);
+    assert_eq!(mapping.recolor(gray), gray);
+}
@@ -7,7 +7,7 @@ use tracing::instrument;
 
 use crate::image_tasks::cloneable::{Arcow, CloneableError, SimpleArcow};
 use crate::image_tasks::color::ComparableColor;
-use crate::image_tasks::MaybeFromPool::NotFromPool;
+use crate::image_tasks::memory_timeline::MemoryKind;
 use crate::image_tasks::{allocate_pixmap_empty, MaybeFromPool};
 static TILE_SIZE_MASK_POOL: Lazy<LinearObjectPool<Mask>> = Lazy::new(|| {
     LinearObjectPool::new(
@@ -23,7 +23,7 @@ static GRID_SIZE_MASK_POOL: Lazy<LinearObjectPool<Mask>> = Lazy::new(|| {
     LinearObjectPool::new(
         || {
             info!("Allocating a grid-size Mask for pool");
-            new_mask_uninit(GRID_SIZE, GRID_SIZE)
+            new_mask_uninit(*GRID_SIZE, *GRID_SIZE)
         },
         |_| {}, // don't need to reset because we always overwrite
     )
@@ -32,7 +32,7 @@ static GRID_SIZE_MASK_POOL: Lazy<LinearObjectPool<Mask>> = Lazy::new(|| {
 pub fn prewarm_mask_pool() {
     GRID_SIZE_MASK_POOL.pull();
     let tile_size = *TILE_SIZE;
-    if tile_size != GRID_SIZE {
+    if tile_size != *GRID_SIZE {
         TILE_SIZE_MASK_POOL.pull();
     }
 }
@@ -60,24 +60,20 @@ fn new_mask_uninit(width: u32, height: u32) -> Mask {
 }
 
 pub fn allocate_mask_for_overwrite(width: u32, height: u32) -> MaybeFromPool<Mask> {
-    if width == GRID_SIZE && height == GRID_SIZE {
+    if width == *GRID_SIZE && height == *GRID_SIZE {
         info!("Borrowing a grid-size Mask from pool");
-        MaybeFromPool::FromPool {
-            reusable: GRID_SIZE_MASK_POOL.pull(),
-        }
+        MaybeFromPool::from_pool(GRID_SIZE_MASK_POOL.pull(), MemoryKind::GridSizePool)
     } else {
         let tile_size = *TILE_SIZE;
         if width == tile_size && height == tile_size {
             info!("Borrowing a tile-size Mask from pool");
-            MaybeFromPool::FromPool {
-                reusable: TILE_SIZE_MASK_POOL.pull(),
-            }
+            MaybeFromPool::from_pool(TILE_SIZE_MASK_POOL.pull(), MemoryKind::TileSizePool)
         } else {
             info!(
                 "Allocating a Mask outside pool for unusual size {}x{}",
                 width, height
             );
-            NotFromPool(new_mask_uninit(width, height))
+            MaybeFromPool::not_from_pool(new_mask_uninit(width, height))
         }
     }
 }
@@ -136,12 +132,11 @@ fn test_alpha_channel() {
 #[test]
 fn test_paint() {
     use crate::image_tasks::color::c;
-    use crate::image_tasks::MaybeFromPool::NotFromPool;
     use resvg::tiny_skia::{FillRule, Paint};
     use resvg::tiny_skia::{PathBuilder, Transform};
 
     let side_length = 128;
-    let pixmap = &mut NotFromPool(Pixmap::new(side_length, side_length).unwrap());
+    let pixmap = &mut MaybeFromPool::not_from_pool(Pixmap::new(side_length, side_length).unwrap());
     let circle = PathBuilder::from_circle(64.0, 64.0, 50.0).unwrap();
     pixmap.fill_path(
         &circle,
@@ -0,0 +1,212 @@
+use std::fmt::Write;
+use std::fs;
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use resvg::tiny_skia::Pixmap;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::color::{perceptual_distance, ComparableColor};
+use crate::image_tasks::output_path::texture_name;
+use crate::image_tasks::task_spec::PackId;
+use crate::texture_base::material::MaterialCategory;
+use crate::{option_value, parsed_option};
+
+/// Block textures whose seams score at least this much are reported; see [SeamScore]. Bevels
+/// that deliberately outline each block, with a highlight on one side and a shadow on the other,
+/// score up to about 0.3.
+const DEFAULT_SEAM_THRESHOLD: f32 = 0.3;
+
+/// The CSV file named by `--seam-report`, if any. Seams are only measured when it's set.
+static SEAM_REPORT: Lazy<Option<String>> = Lazy::new(|| option_value("seam-report"));
+
+/// How much more a block texture changes across the edge where two copies of it meet than between
+/// neighboring rows or columns inside it, in units of [perceptual_distance]. A texture that's
+/// drawn to tile seamlessly scores about 0. Only loud seams are a problem: bordered textures meet
+/// a copy of their border, which usually scores low because the opposite sides match.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SeamScore {
+    /// Between the right edge and the left edge of the next copy.
+    pub left_right: f32,
+    /// Between the bottom edge and the top edge of the next copy, averaged over animation frames.
+    pub top_bottom: f32,
+}
+
+impl SeamScore {
+    pub fn worst(&self) -> f32 {
+        self.left_right.max(self.top_bottom)
+    }
+}
+
+/// Mean distance between each pair of pixels.
+fn mean_distance(pairs: impl Iterator<Item = (ComparableColor, ComparableColor)>) -> f32 {
+    let (sum, count) = pairs.fold((0.0, 0usize), |(sum, count), (first, second)| {
+        (sum + perceptual_distance(first, second), count + 1)
+    });
+    if count == 0 {
+        0.0
+    } else {
+        sum / count as f32
+    }
+}
+
+/// Measures one frame at a time when the image is a vertical animation strip, since each frame is
+/// tiled on its own.
+pub fn measure_seams(image: &Pixmap) -> SeamScore {
+    let width = image.width();
+    let frame_height = if image.height().is_multiple_of(width) {
+        width
+    } else {
+        image.height()
+    };
+    let pixel = |x: u32, y: u32| ComparableColor::from(image.pixel(x, y).unwrap());
+    let edge_columns =
+        mean_distance((0..image.height()).map(|y| (pixel(width - 1, y), pixel(0, y))));
+    let inner_columns = mean_distance(
+        (0..image.height())
+            .flat_map(|y| (1..width).map(move |x| (x, y)))
+            .map(|(x, y)| (pixel(x - 1, y), pixel(x, y))),
+    );
+    let frame_tops = (0..image.height()).step_by(frame_height as usize);
+    let edge_rows = mean_distance(
+        frame_tops
+            .clone()
+            .flat_map(|top| (0..width).map(move |x| (x, top)))
+            .map(|(x, top)| (pixel(x, top + frame_height - 1), pixel(x, top))),
+    );
+    let inner_rows = mean_distance(
+        frame_tops
+            .flat_map(|top| {
+                (top + 1..top + frame_height).flat_map(move |y| (0..width).map(move |x| (x, y)))
+            })
+            .map(|(x, y)| (pixel(x, y - 1), pixel(x, y))),
+    );
+    SeamScore {
+        left_right: (edge_columns - inner_columns).max(0.0),
+        top_bottom: (edge_rows - inner_rows).max(0.0),
+    }
+}
+
+/// Scores of every block texture encoded so far, with the pack and path of the texture.
+type PathsAndScores = Vec<(PackId, Box<str>, SeamScore)>;
+
+static SEAMS: Lazy<Mutex<PathsAndScores>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Called with each finished image. Only block textures are measured, since items and particles
+/// aren't tiled.
+pub fn record_seams(pack: PackId, file_path: &str, image: &Pixmap) {
+    if SEAM_REPORT.is_none()
+        || !texture_name(file_path)
+            .is_some_and(|name| MaterialCategory::of_path(&name) == Some(MaterialCategory::Block))
+    {
+        return;
+    }
+    let score = measure_seams(image);
+    SEAMS.lock().push((pack, file_path.into(), score));
+}
+
+fn to_csv(seams: &mut [(PackId, Box<str>, SeamScore)], threshold: f32) -> String {
+    seams.sort_by(
+        |(first_pack, first_path, first), (second_pack, second_path, second)| {
+            second
+                .worst()
+                .total_cmp(&first.worst())
+                .then(first_path.cmp(second_path))
+                .then(first_pack.cmp(second_pack))
+        },
+    );
+    let mut csv = String::from("pack,texture,left_right,top_bottom\n");
+    for (pack, file_path, score) in seams
+        .iter()
+        .filter(|(_, _, score)| score.worst() >= threshold)
+    {
+        writeln!(
+            csv,
+            "{},{},{:.4},{:.4}",
+            pack, file_path, score.left_right, score.top_bottom
+        )
+        .unwrap();
+    }
+    csv
+}
+
+/// Called once every image has been encoded. Writes the block textures whose seams score at least
+/// `--seam-threshold` to the CSV file named by `--seam-report`, worst first.
+pub fn finish_seam_report() -> Result<(), CloneableError> {
+    let Some(report_path) = &*SEAM_REPORT else {
+        return Ok(());
+    };
+    let threshold = parsed_option("seam-threshold").unwrap_or(DEFAULT_SEAM_THRESHOLD);
+    let mut seams = SEAMS.lock();
+    let seamed = seams
+        .iter()
+        .filter(|(_, _, score)| score.worst() >= threshold)
+        .count();
+    if seamed > 0 {
+        warn!(
+            "{} of {} block textures have visible seams; see {}",
+            seamed,
+            seams.len(),
+            report_path
+        );
+    } else {
+        info!("None of {} block textures have visible seams", seams.len());
+    }
+    fs::write(report_path, to_csv(&mut seams, threshold))?;
+    Ok(())
+}
+
+#[test]
+fn test_measure_seams() {
+    use crate::image_tasks::color::c;
+
+    // Columns alternate between two colors, so left and right edges match as well as any
+    // neighbors
+    let mut striped = Pixmap::new(4, 4).unwrap();
+    for (index, pixel) in striped.pixels_mut().iter_mut().enumerate() {
+        *pixel = if index % 2 == 0 {
+            ComparableColor::BLACK
+        } else {
+            ComparableColor::WHITE
+        }
+        .into();
+    }
+    let score = measure_seams(&striped);
+    assert_eq!(score.left_right, 0.0);
+    assert_eq!(score.top_bottom, 0.0);
+
+    // A gradient from top to bottom jumps from light back to dark at the bottom of each of its
+    // two frames
+    let mut gradient = Pixmap::new(4, 8).unwrap();
+    for (index, pixel) in gradient.pixels_mut().iter_mut().enumerate() {
+        let row = (index / 4 % 4) as u8;
+        *pixel = c(0x555555 * row as u32).into();
+    }
+    let score = measure_seams(&gradient);
+    assert_eq!(score.left_right, 0.0);
+    assert!(score.top_bottom > 0.5, "{:?}", score);
+
+    let pack = PackId {
+        tile_size: 32,
+        high_contrast: false,
+    };
+    let mut seams = vec![
+        (pack, "a.png".into(), score),
+        (
+            pack,
+            "b.png".into(),
+            SeamScore {
+                left_right: 0.01,
+                top_bottom: 0.0,
+            },
+        ),
+    ];
+    assert_eq!(
+        to_csv(&mut seams, DEFAULT_SEAM_THRESHOLD),
+        format!(
+            "pack,texture,left_right,top_bottom\n32x32,a.png,0.0000,{:.4}\n",
+            score.top_bottom
+        )
+    );
+}
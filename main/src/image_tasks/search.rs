@@ -0,0 +1,242 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+
+use crate::image_tasks::color::ComparableColor;
+use crate::image_tasks::task_spec::{FileOutputTaskSpec, ToAlphaChannelTaskSpec, ToPixmapTaskSpec};
+use crate::texture_base::material::MaterialGroup;
+
+/// What `OcHd-RustBuild search <term>` looks for. A term that parses as a color
+/// matches textures that paint or stack on that color, whatever its alpha; anything else matches
+/// output paths that contain it and textures with a layer of exactly that name.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SearchTerm {
+    Color(ComparableColor),
+    Text(String),
+}
+
+impl From<&str> for SearchTerm {
+    fn from(value: &str) -> Self {
+        match value.parse() {
+            Ok(color) => SearchTerm::Color(color),
+            Err(_) => SearchTerm::Text(value.to_owned()),
+        }
+    }
+}
+
+/// A texture that matched a [SearchTerm], with the material group it's defined in.
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct SearchMatch {
+    pub group: &'static str,
+    pub path: Box<str>,
+    pub reason: String,
+}
+
+impl Display for SearchMatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:24} {}  ({})", self.group, self.path, self.reason)
+    }
+}
+
+/// The SVGs, rasters and other textures that an image is built from, and the colors it paints.
+#[derive(Default)]
+pub(crate) struct Ingredients {
+    pub(crate) layers: BTreeSet<String>,
+    /// The layers that are [ToPixmapTaskSpec::TextureOf] names.
+    pub(crate) textures: BTreeSet<String>,
+    colors: BTreeSet<(u8, u8, u8)>,
+}
+
+impl Ingredients {
+    /// The ingredients of the image a task writes, or of its original if it's a copy.
+    pub(crate) fn of(task: &FileOutputTaskSpec) -> Ingredients {
+        Ingredients::of_pixmap(source_image(task))
+    }
+
+    /// The ingredients of one image.
+    pub(crate) fn of_pixmap(spec: &ToPixmapTaskSpec) -> Ingredients {
+        let mut ingredients = Ingredients::default();
+        ingredients.add_pixmap(spec);
+        ingredients
+    }
+
+    fn add_color(&mut self, color: ComparableColor) {
+        if color.alpha() != 0 {
+            self.colors
+                .insert((color.red(), color.green(), color.blue()));
+        }
+    }
+
+    fn add_pixmap(&mut self, spec: &ToPixmapTaskSpec) {
+        match spec {
+            ToPixmapTaskSpec::Animate {
+                background, frames, ..
+            } => {
+                self.add_pixmap(background);
+                frames.iter().for_each(|frame| self.add_pixmap(frame));
+            }
+            ToPixmapTaskSpec::FromSvg { source } => {
+                self.layers.insert(source.to_string());
+            }
+            ToPixmapTaskSpec::FromRaster { source } => {
+                self.layers.insert(source.to_string());
+            }
+            ToPixmapTaskSpec::TextureOf { name } => {
+                self.layers.insert(name.to_string());
+                self.textures.insert(name.to_string());
+            }
+            ToPixmapTaskSpec::PaintAlphaChannel { base, color } => {
+                self.add_color(*color);
+                self.add_alpha(base);
+            }
+            ToPixmapTaskSpec::StackLayerOnColor {
+                background,
+                foreground,
+            } => {
+                self.add_color(*background);
+                self.add_pixmap(foreground);
+            }
+            ToPixmapTaskSpec::StackLayerOnLayer {
+                background,
+                foreground,
+            } => {
+                self.add_pixmap(background);
+                self.add_pixmap(foreground);
+            }
+            ToPixmapTaskSpec::UpscaleFromGridSize { base }
+            | ToPixmapTaskSpec::OnGrid { base, .. }
+            | ToPixmapTaskSpec::DetailAtLeast { base, .. }
+            | ToPixmapTaskSpec::CropAndScale { base, .. }
+            | ToPixmapTaskSpec::Rotate { base, .. }
+            | ToPixmapTaskSpec::Flip { base, .. }
+            | ToPixmapTaskSpec::ShiftColors { base, .. } => self.add_pixmap(base),
+            ToPixmapTaskSpec::Remap { base, mapping } => {
+                mapping
+                    .pairs()
+                    .iter()
+                    .for_each(|(_, to)| self.add_color(*to));
+                self.add_pixmap(base);
+            }
+            ToPixmapTaskSpec::PlaceOnSheet { placements, .. } => placements
+                .iter()
+                .for_each(|(layer, _)| self.add_pixmap(layer)),
+        }
+    }
+
+    fn add_alpha(&mut self, spec: &ToAlphaChannelTaskSpec) {
+        match spec {
+            ToAlphaChannelTaskSpec::MakeSemitransparent { base, .. }
+            | ToAlphaChannelTaskSpec::UpscaleFromGridSize { base }
+            | ToAlphaChannelTaskSpec::Dither { base, .. } => self.add_alpha(base),
+            ToAlphaChannelTaskSpec::FromPixmap { base } => self.add_pixmap(base),
+            ToAlphaChannelTaskSpec::StackAlphaOnAlpha {
+                background,
+                foreground,
+            } => {
+                self.add_alpha(background);
+                self.add_alpha(foreground);
+            }
+            ToAlphaChannelTaskSpec::StackAlphaOnBackground { foreground, .. } => {
+                self.add_alpha(foreground)
+            }
+        }
+    }
+}
+
+fn source_image(task: &FileOutputTaskSpec) -> &ToPixmapTaskSpec {
+    match task {
+        FileOutputTaskSpec::PngOutput { base, .. } => base,
+        FileOutputTaskSpec::Copy { original, .. } => source_image(original),
+    }
+}
+
+/// Every path the task writes to, including the original's if it's a copy.
+fn all_paths(task: &FileOutputTaskSpec) -> Vec<Box<str>> {
+    let mut paths = task.get_paths();
+    if let FileOutputTaskSpec::Copy { original, .. } = task {
+        paths.extend(all_paths(original));
+    }
+    paths
+}
+
+/// Finds every texture in the given groups that matches `term`, sorted by group and path. Each
+/// path a copy is written to is searched, with the image of its original.
+pub fn search(groups: &[(&'static str, &MaterialGroup)], term: &SearchTerm) -> Vec<SearchMatch> {
+    let mut matches = Vec::new();
+    for (group, material_group) in groups {
+        for task in material_group.tasks.iter() {
+            let ingredients = Ingredients::of(task);
+            for path in all_paths(task) {
+                let reason = match term {
+                    SearchTerm::Color(color) => ingredients
+                        .colors
+                        .contains(&(color.red(), color.green(), color.blue()))
+                        .then(|| format!("uses color {}", color)),
+                    SearchTerm::Text(text) => {
+                        if path.contains(&**text) {
+                            Some("path".to_owned())
+                        } else if ingredients.layers.contains(text) {
+                            Some(format!("uses layer {}", text))
+                        } else {
+                            None
+                        }
+                    }
+                };
+                if let Some(reason) = reason {
+                    matches.push(SearchMatch {
+                        group,
+                        path,
+                        reason,
+                    });
+                }
+            }
+        }
+    }
+    matches.sort();
+    matches
+}
+
+#[test]
+fn test_search() {
+    use crate::image_tasks::color::c;
+    use crate::image_tasks::task_spec::{alias_task, out_task, paint_svg_task};
+    use crate::stack;
+
+    let group = MaterialGroup::new(vec![vec![
+        out_task(
+            "block/bricks",
+            stack!(
+                paint_svg_task("bricks", c(0x8a3a00)),
+                paint_svg_task("borderDotted", c(0x404040) * 0.5)
+            ),
+        ),
+        alias_task(
+            out_task("block/stone", paint_svg_task("borderSolid", c(0x404040))),
+            ["block/smooth_stone"],
+        ),
+    ]
+    .into_boxed_slice()]);
+    let groups = [("block/test", &group)];
+    let paths = |term: &str| {
+        search(&groups, &term.into())
+            .into_iter()
+            .map(|found| found.path.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        paths("borderDotted"),
+        ["assets/minecraft/textures/block/bricks.png"]
+    );
+    assert_eq!(
+        paths("#404040"),
+        [
+            "assets/minecraft/textures/block/bricks.png",
+            "assets/minecraft/textures/block/smooth_stone.png",
+            "assets/minecraft/textures/block/stone.png",
+        ]
+    );
+    assert_eq!(
+        paths("smooth"),
+        ["assets/minecraft/textures/block/smooth_stone.png"]
+    );
+    assert!(paths("border").is_empty());
+}
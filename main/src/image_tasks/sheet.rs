@@ -0,0 +1,140 @@
+use std::fmt::{Display, Formatter};
+
+use resvg::tiny_skia::{Pixmap, PixmapPaint, Transform};
+use tracing::instrument;
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::crop::TileRect;
+use crate::image_tasks::{allocate_pixmap_empty, allocate_pixmap_for_overwrite, MaybeFromPool};
+
+/// A rectangle on a sheet that's larger than a tile, such as an entity texture, measured in texels
+/// (sixteenths of a tile) so that it matches the UV coordinates of the vanilla model.
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct SheetRect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl SheetRect {
+    pub const fn new(x: u16, y: u16, width: u16, height: u16) -> SheetRect {
+        assert!(width > 0 && height > 0);
+        SheetRect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Left, top, width and height in pixels, for a tile with the given side length.
+    fn to_pixels(self, tile_size: u32) -> (u32, u32, u32, u32) {
+        let unit = tile_size / TileRect::UNITS;
+        (
+            self.x as u32 * unit,
+            self.y as u32 * unit,
+            self.width as u32 * unit,
+            self.height as u32 * unit,
+        )
+    }
+}
+
+impl Display for SheetRect {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{}x{}", self.x, self.y, self.width, self.height)
+    }
+}
+
+/// Scales each tile-sized layer into its rectangle on a transparent sheet `width` by `height`
+/// texels, drawing later layers over earlier ones. Uses nearest-neighbor sampling, like
+/// [crate::image_tasks::crop::crop_and_scale].
+#[instrument(skip(layers))]
+pub fn place_on_sheet<'a>(
+    width: u16,
+    height: u16,
+    layers: impl IntoIterator<Item = (&'a Pixmap, SheetRect)>,
+    tile_size: u32,
+) -> Result<MaybeFromPool<Pixmap>, CloneableError> {
+    if !tile_size.is_multiple_of(TileRect::UNITS) {
+        return Err(anyhoo!(
+            "Can't place layers in texels on a {}px tile",
+            tile_size
+        ));
+    }
+    let (_, _, sheet_width, sheet_height) =
+        SheetRect::new(0, 0, width, height).to_pixels(tile_size);
+    let mut out = allocate_pixmap_empty(sheet_width, sheet_height);
+    for (layer, rect) in layers {
+        if layer.width() != tile_size || layer.height() != tile_size {
+            return Err(anyhoo!(
+                "Expected a {}px tile to place at {}, but got {}x{}",
+                tile_size,
+                rect,
+                layer.width(),
+                layer.height()
+            ));
+        }
+        let (x, y, rect_width, rect_height) = rect.to_pixels(tile_size);
+        if x + rect_width > sheet_width || y + rect_height > sheet_height {
+            return Err(anyhoo!("{} is outside a {}x{} sheet", rect, width, height));
+        }
+        let mut scaled = allocate_pixmap_for_overwrite(rect_width, rect_height);
+        let layer_pixels = layer.pixels();
+        let scaled_pixels = scaled.pixels_mut();
+        for scaled_y in 0..rect_height {
+            let layer_y = scaled_y * tile_size / rect_height;
+            for scaled_x in 0..rect_width {
+                let layer_x = scaled_x * tile_size / rect_width;
+                scaled_pixels[(scaled_y * rect_width + scaled_x) as usize] =
+                    layer_pixels[(layer_y * tile_size + layer_x) as usize];
+            }
+        }
+        out.draw_pixmap(
+            x as i32,
+            y as i32,
+            scaled.as_ref(),
+            &PixmapPaint::default(),
+            Transform::default(),
+            None,
+        );
+    }
+    Ok(out)
+}
+
+#[test]
+fn test_place_on_sheet() {
+    use crate::image_tasks::color::{c, ComparableColor};
+
+    let mut red = Pixmap::new(32, 32).unwrap();
+    red.fill(ComparableColor::RED.into());
+    let mut half_blue = Pixmap::new(32, 32).unwrap();
+    for pixel in half_blue.pixels_mut()[..32 * 16].iter_mut() {
+        *pixel = c(0x0000ff).into();
+    }
+    let out = place_on_sheet(
+        64,
+        32,
+        [
+            (&red, SheetRect::new(8, 8, 8, 10)),
+            (&half_blue, SheetRect::new(8, 8, 8, 10)),
+            (&red, SheetRect::new(60, 0, 4, 4)),
+        ],
+        32,
+    )
+    .unwrap();
+    assert_eq!(out.width(), 128);
+    assert_eq!(out.height(), 64);
+    let pixel = |x: u32, y: u32| ComparableColor::from(out.pixels()[(y * 128 + x) as usize]);
+    assert_eq!(pixel(15, 15), ComparableColor::TRANSPARENT);
+    // The blue top half of the later layer covers the red one
+    assert_eq!(pixel(16, 16), c(0x0000ff));
+    assert_eq!(pixel(31, 25), c(0x0000ff));
+    assert_eq!(pixel(16, 26), ComparableColor::RED);
+    assert_eq!(pixel(31, 35), ComparableColor::RED);
+    assert_eq!(pixel(32, 16), ComparableColor::TRANSPARENT);
+    assert_eq!(pixel(127, 0), ComparableColor::RED);
+    assert!(place_on_sheet(64, 32, [(&red, SheetRect::new(60, 0, 8, 8))], 32).is_err());
+    assert!(place_on_sheet(64, 32, [(&red, SheetRect::new(0, 0, 16, 16))], 16).is_err());
+}
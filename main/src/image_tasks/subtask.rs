@@ -0,0 +1,84 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::task::{Context, Poll};
+
+use log::info;
+use tokio::task::{spawn, JoinError, JoinHandle};
+
+/// How many subtasks have been aborted because nothing was waiting for them any more.
+static CANCELLED_SUBTASKS: AtomicUsize = AtomicUsize::new(0);
+
+/// A task spawned by another so that part of its work runs in parallel, which is aborted if this
+/// handle is dropped before it finishes. A bare [JoinHandle] would let the subtask run to
+/// completion after the task awaiting it was aborted or had failed, holding its pooled pixmaps
+/// until then; this way they're returned as soon as nothing needs the result.
+pub struct Subtask<T>(JoinHandle<T>);
+
+impl<T> Future for Subtask<T> {
+    type Output = Result<T, JoinError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+impl<T> Drop for Subtask<T> {
+    fn drop(&mut self) {
+        if !self.0.is_finished() {
+            CANCELLED_SUBTASKS.fetch_add(1, Ordering::Relaxed);
+            self.0.abort();
+        }
+    }
+}
+
+/// Spawns `future` as a [Subtask] of the current task.
+pub fn spawn_subtask<F>(future: F) -> Subtask<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    Subtask(spawn(future))
+}
+
+/// Logs how many subtasks were cancelled. Called once the build has finished.
+pub fn finish_subtask_report() {
+    let cancelled = CANCELLED_SUBTASKS.load(Ordering::Relaxed);
+    if cancelled > 0 {
+        info!(
+            "Cancelled {} subtasks whose results were no longer needed",
+            cancelled
+        );
+    }
+}
+
+#[test]
+fn test_subtask() {
+    use std::sync::Arc;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    assert_eq!(runtime.block_on(spawn_subtask(async { 7 })).unwrap(), 7);
+
+    // Dropping the handle aborts the subtask, which drops what it holds
+    let held = Arc::new(());
+    let held_by_subtask = held.clone();
+    runtime.block_on(async move {
+        let subtask = spawn_subtask(async move {
+            let _held = held_by_subtask;
+            std::future::pending::<()>().await
+        });
+        tokio::task::yield_now().await;
+        assert_eq!(Arc::strong_count(&held), 2);
+        drop(subtask);
+        for _ in 0..100 {
+            if Arc::strong_count(&held) == 1 {
+                break;
+            }
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(Arc::strong_count(&held), 1);
+    });
+    assert!(CANCELLED_SUBTASKS.load(Ordering::Relaxed) >= 1);
+}
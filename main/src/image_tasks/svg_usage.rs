@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::search::Ingredients;
+use crate::image_tasks::task_spec::{FileOutputTaskSpec, SVG_DIR};
+use crate::option_value;
+
+/// The CSV file named by `--svg-usage-report`, if any.
+pub static SVG_USAGE_REPORT: Lazy<Option<String>> = Lazy::new(|| option_value("svg-usage-report"));
+
+/// How many output textures use one SVG.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SvgUses {
+    /// Textures that still use it after [crate::image_tasks::dead_layers::eliminate_dead_layers].
+    pub live: usize,
+    /// Textures that only used it in layers hidden under opaque ones, which were removed.
+    pub dead: usize,
+}
+
+/// Usage of every SVG in [SVG_DIR], so that the ones no material needs can be removed from it.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SvgUsage {
+    svgs: BTreeMap<Box<str>, SvgUses>,
+}
+
+impl SvgUsage {
+    /// Counts the textures that use each SVG in `svg_names`, given the output tasks as they were
+    /// before and after dead-layer elimination.
+    pub fn new<'a, T: IntoIterator<Item = &'a str>>(
+        svg_names: T,
+        unpruned: &[FileOutputTaskSpec],
+        pruned: &[FileOutputTaskSpec],
+    ) -> SvgUsage {
+        let mut svgs: BTreeMap<Box<str>, SvgUses> = svg_names
+            .into_iter()
+            .map(|name| (name.into(), SvgUses::default()))
+            .collect();
+        let mut count = |tasks: &[FileOutputTaskSpec], increment: fn(&mut SvgUses)| {
+            for task in tasks {
+                for layer in Ingredients::of(task).layers {
+                    if let Some(uses) = svgs.get_mut(&*layer) {
+                        increment(uses);
+                    }
+                }
+            }
+        };
+        count(unpruned, |uses| uses.dead += 1);
+        count(pruned, |uses| {
+            uses.dead -= 1;
+            uses.live += 1;
+        });
+        SvgUsage { svgs }
+    }
+
+    /// SVGs that no texture uses at all.
+    pub fn orphans(&self) -> impl Iterator<Item = &str> {
+        self.svgs
+            .iter()
+            .filter(|(_, uses)| **uses == SvgUses::default())
+            .map(|(name, _)| &**name)
+    }
+
+    /// SVGs that are only used in layers that end up hidden.
+    pub fn dead(&self) -> impl Iterator<Item = &str> {
+        self.svgs
+            .iter()
+            .filter(|(_, uses)| uses.live == 0 && uses.dead > 0)
+            .map(|(name, _)| &**name)
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("svg,live_textures,dead_textures,status\n");
+        for (name, uses) in self.svgs.iter() {
+            let status = if uses.live > 0 {
+                "used"
+            } else if uses.dead > 0 {
+                "dead"
+            } else {
+                "orphan"
+            };
+            writeln!(csv, "{},{},{},{}", name, uses.live, uses.dead, status).unwrap();
+        }
+        csv
+    }
+}
+
+/// The name of every SVG in [SVG_DIR], as [crate::image_tasks::task_spec::from_svg_task] takes it.
+pub fn svg_names() -> impl Iterator<Item = &'static str> {
+    SVG_DIR
+        .files()
+        .filter(|file| {
+            file.path()
+                .extension()
+                .is_some_and(|extension| extension == "svg")
+        })
+        .filter_map(|file| file.path().file_stem()?.to_str())
+}
+
+/// Writes the CSV file named by `--svg-usage-report`, and warns about the SVGs nothing uses.
+pub fn write_svg_usage_report(
+    unpruned: &[FileOutputTaskSpec],
+    pruned: &[FileOutputTaskSpec],
+) -> Result<(), CloneableError> {
+    let Some(report_path) = &*SVG_USAGE_REPORT else {
+        return Ok(());
+    };
+    let usage = SvgUsage::new(svg_names(), unpruned, pruned);
+    let orphans: Vec<&str> = usage.orphans().collect();
+    let dead: Vec<&str> = usage.dead().collect();
+    if !orphans.is_empty() {
+        warn!("{} SVGs aren't used: {}", orphans.len(), orphans.join(", "));
+    }
+    if !dead.is_empty() {
+        warn!(
+            "{} SVGs are only used in hidden layers: {}",
+            dead.len(),
+            dead.join(", ")
+        );
+    }
+    info!("Writing SVG usage to {}", report_path);
+    fs::write(report_path, usage.to_csv())?;
+    Ok(())
+}
+
+#[test]
+fn test_svg_usage() {
+    use crate::image_tasks::color::ComparableColor;
+    use crate::image_tasks::task_spec::{out_task, paint_svg_task, ToPixmapTaskSpec};
+
+    let hidden = out_task(
+        "block/hidden",
+        ToPixmapTaskSpec::StackLayerOnLayer {
+            background: paint_svg_task("bricks", ComparableColor::RED).into(),
+            foreground: ToPixmapTaskSpec::StackLayerOnColor {
+                background: ComparableColor::BLACK,
+                foreground: paint_svg_task("borderSolid", ComparableColor::WHITE).into(),
+            }
+            .into(),
+        },
+    );
+    let pruned_hidden = out_task(
+        "block/hidden",
+        ToPixmapTaskSpec::StackLayerOnColor {
+            background: ComparableColor::BLACK,
+            foreground: paint_svg_task("borderSolid", ComparableColor::WHITE).into(),
+        },
+    );
+    let usage = SvgUsage::new(
+        ["bricks", "borderSolid", "circle24"],
+        &[hidden],
+        &[pruned_hidden],
+    );
+    assert_eq!(usage.orphans().collect::<Vec<_>>(), ["circle24"]);
+    assert_eq!(usage.dead().collect::<Vec<_>>(), ["bricks"]);
+    assert_eq!(
+        usage.to_csv(),
+        "svg,live_textures,dead_textures,status\n\
+        borderSolid,1,0,used\n\
+        bricks,0,1,dead\n\
+        circle24,0,0,orphan\n"
+    );
+    assert!(svg_names().any(|name| name == "borderSolid"));
+}
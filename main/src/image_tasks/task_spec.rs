@@ -1,10 +1,10 @@
-use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
 use std::fmt::{Debug, Display, Formatter};
 use std::future::ready;
 use std::hash::Hash;
 use std::mem::replace;
+use std::panic::Location;
 
 use futures_util::future::{join_all, BoxFuture, Shared};
 use futures_util::FutureExt;
@@ -13,41 +13,60 @@ use std::sync::Arc;
 use BitDepth::Sixteen;
 use ColorType::GrayscaleAlpha;
 
-use crate::{debug_assert_unreachable, GRID_SIZE, TILE_SIZE};
+use crate::{debug_assert_unreachable, flag_present, GRID_SIZE};
 use include_dir::{include_dir, Dir};
 use itertools::Itertools;
 
-use log::info;
+use log::{error, info};
+use once_cell::sync::Lazy;
 use oxipng::BitDepth::{Eight, Four, One, Two};
 use oxipng::ColorType;
 use oxipng::ColorType::{Grayscale, Indexed, RGB, RGBA};
-use oxipng::{BitDepth, RGB16, RGBA8};
+use oxipng::{BitDepth, RGBA8};
 use parking_lot::Mutex;
 
 use resvg::tiny_skia::{Mask, Pixmap};
 use tokio::task::JoinSet;
 use zip::ZipWriter;
 
-use crate::image_tasks::animate::animate;
+use crate::image_tasks::alpha_debug::{write_alpha_debug, ALPHA_DEBUG_DIR};
+use crate::image_tasks::animate::{animate, FrameTiming, SheetLayout};
+use crate::image_tasks::cache::{cache_key, cached_png, record_cached_png, CACHE_DIR};
 use crate::image_tasks::cloneable::Arcow::Borrowing;
-use crate::image_tasks::cloneable::{Arcow, Name, SimpleArcow};
-use crate::image_tasks::color::{gray, ComparableColor, BIT_DEPTH_FOR_CHANNEL};
+use crate::image_tasks::cloneable::{Arcow, CloneableError, Name, SimpleArcow};
+use crate::image_tasks::color::{gray, transparency_sentinel, ComparableColor, BIT_DEPTH_FOR_CHANNEL};
+use crate::image_tasks::color_shift::{shift_colors, ColorShift};
+use crate::image_tasks::crop::{crop_and_scale, TileRect};
+use crate::image_tasks::dir_output::DirectoryOutput;
+use crate::image_tasks::dither::dither_alpha;
+use crate::image_tasks::from_raster::from_raster;
 use crate::image_tasks::from_svg::{from_svg, COLOR_SVGS, SEMITRANSPARENCY_FREE_SVGS};
+use crate::image_tasks::intern::{Arena, Internable, Interned};
 use crate::image_tasks::make_semitransparent::{
     make_semitransparent, ALPHA_MULTIPLICATION_TABLE, ALPHA_STACKING_TABLE,
 };
-use crate::image_tasks::png_output::{copy_out_to_out, png_output, ZipBufferRaw};
+use crate::image_tasks::output_path::texture_path;
+use crate::image_tasks::output_sink::{copy_in_sinks, write_to_sinks, OutputSink, ZipOutput};
+use crate::image_tasks::overrides::{override_path, write_override};
+use crate::image_tasks::png_output::{encode_png, ZipBufferRaw};
+use crate::image_tasks::remap::{remap, PaletteMap};
 use crate::image_tasks::repaint::{paint, pixmap_to_mask};
+use crate::image_tasks::search::Ingredients;
+use crate::image_tasks::sheet::{place_on_sheet, SheetRect};
 use crate::image_tasks::stack::{
     stack_alpha_on_alpha, stack_alpha_on_background, stack_layer_on_background,
     stack_layer_on_layer,
 };
+use crate::image_tasks::subtask::spawn_subtask;
 use crate::image_tasks::task_spec::ColorDescription::{Rgb, SpecifiedColors};
 use crate::image_tasks::task_spec::ToAlphaChannelTaskSpec::StackAlphaOnAlpha;
 use crate::image_tasks::task_spec::ToPixmapTaskSpec::UpscaleFromGridSize;
 use crate::image_tasks::task_spec::Transparency::{AlphaChannel, Binary, Opaque};
-use crate::image_tasks::upscale::{upscale_image, upscale_mask};
-use crate::image_tasks::MaybeFromPool;
+use crate::image_tasks::transform::{flip, rotate, Axis};
+use crate::image_tasks::upscale::{downscale_image, upscale_image, upscale_mask};
+use crate::image_tasks::verify::expect_copy;
+use crate::image_tasks::{allocate_pixmap_empty, MaybeFromPool};
+use crate::texture_base::version::legacy_names;
 use crate::u8set::U8BitSet;
 
 pub trait TaskSpecTraits<T: Clone>: Clone + Debug + Display + Ord + Eq + Hash {
@@ -65,40 +84,74 @@ impl TaskSpecTraits<MaybeFromPool<Pixmap>> for ToPixmapTaskSpec {
             info!("Matched an existing node: {}", name);
             return existing_future.to_owned();
         }
+        if let ToPixmapTaskSpec::TextureOf { name } = self {
+            // Share the named texture's node rather than adding another with the same output
+            return ctx.resolve_texture(name).add_to(ctx, tile_size);
+        }
         if let UpscaleFromGridSize { .. } = self {
             // Fall through; let expressions can't be inverted
-        } else if tile_size != GRID_SIZE && self.is_grid_perfect(ctx) {
+        } else if tile_size > *GRID_SIZE
+            && tile_size.is_multiple_of(*GRID_SIZE)
+            && self.is_grid_perfect(ctx)
+        {
             return UpscaleFromGridSize {
                 base: self.to_owned().into(),
             }
             .add_to(ctx, tile_size);
         }
         let task = match self {
-            ToPixmapTaskSpec::None { .. } => {
-                debug_assert_unreachable("Tried to add None task to graph")
-            }
-            ToPixmapTaskSpec::Animate { background, frames } => {
+            ToPixmapTaskSpec::Animate {
+                background,
+                frames,
+                layout,
+                ..
+            } => {
+                let layout = *layout;
                 let background_future = background.add_to(ctx, tile_size);
-                let background_color_desc_future = background.get_color_description_task(ctx);
+                let background_analysis_future = background.get_analysis_task(ctx, tile_size);
                 let frame_futures: Box<[BasicTask<MaybeFromPool<Pixmap>>]> = frames
                     .iter()
                     .map(|frame| frame.add_to(ctx, tile_size))
                     .collect();
-                background_color_desc_future
+                background_analysis_future
                     .then(
-                        async move |background_desc: SimpleArcow<ColorDescription>| {
-                            let background_opaque = background_desc.transparency() == Opaque;
+                        async move |background_analysis: SimpleArcow<PixmapAnalysis>| {
+                            let background_opaque =
+                                background_analysis.colors.transparency() == Opaque;
                             let background = background_future.await;
-                            animate(&background, frame_futures, !background_opaque).await
+                            animate(&background, frame_futures, layout, !background_opaque).await
                         },
                     )
                     .boxed()
             }
-            ToPixmapTaskSpec::FromSvg { source } => {
-                let source = source.to_string();
-                async move { Arcow::SharingRef(from_svg(source, tile_size).unwrap().into()) }
-                    .boxed()
+            // Grid-perfect SVGs are already exact at every size, since they're upscaled from
+            // GRID_SIZE
+            ToPixmapTaskSpec::FromSvg { source } => match ctx.svg_render_size.to_owned() {
+                Some(render_size)
+                    if render_size > tile_size
+                        && render_size.is_multiple_of(tile_size)
+                        && !self.is_grid_perfect(ctx) =>
+                {
+                    let full_size_future = self.add_to(ctx, render_size);
+                    full_size_future
+                        .then(async move |full_size: SimpleArcow<MaybeFromPool<Pixmap>>| {
+                            Arcow::from_owned(
+                                downscale_image(&full_size, render_size / tile_size).unwrap(),
+                            )
+                        })
+                        .boxed()
+                }
+                _ => {
+                    let source = source.to_string();
+                    async move { Arcow::SharingRef(from_svg(source, tile_size).unwrap().into()) }
+                        .boxed()
+                }
+            },
+            ToPixmapTaskSpec::FromRaster { source } => {
+                let source = source.to_owned();
+                async move { Arcow::from_owned(from_raster(&source, tile_size).unwrap()) }.boxed()
             }
+            ToPixmapTaskSpec::TextureOf { .. } => unreachable!("Resolved above"),
             ToPixmapTaskSpec::StackLayerOnColor {
                 background,
                 foreground,
@@ -120,12 +173,14 @@ impl TaskSpecTraits<MaybeFromPool<Pixmap>> for ToPixmapTaskSpec {
             } => {
                 let bg_future = background.add_to(ctx, tile_size);
                 let fg_future = foreground.add_to(ctx, tile_size);
-                let mut join_set = JoinSet::new();
-                join_set.spawn(bg_future);
-                join_set.spawn(fg_future);
+                // Spawned so that both layers render in parallel, but awaited in order, since
+                // whichever finishes first isn't necessarily the background. If one fails, the
+                // other is aborted when its handle is dropped.
+                let bg_handle = spawn_subtask(bg_future);
+                let fg_handle = spawn_subtask(fg_future);
                 async move {
-                    let mut bg_image = join_set.join_next().await.unwrap().unwrap();
-                    let fg_image = join_set.join_next().await.unwrap().unwrap();
+                    let mut bg_image = bg_handle.await.unwrap();
+                    let fg_image = fg_handle.await.unwrap();
                     stack_layer_on_layer(&mut bg_image, &fg_image).await;
                     bg_image
                 }
@@ -141,18 +196,133 @@ impl TaskSpecTraits<MaybeFromPool<Pixmap>> for ToPixmapTaskSpec {
                     .boxed()
             }
             UpscaleFromGridSize { base } => {
-                let base_future = base.add_to(ctx, GRID_SIZE);
-                if tile_size == GRID_SIZE {
+                let base_future = base.add_to(ctx, *GRID_SIZE);
+                if tile_size == *GRID_SIZE {
                     return base_future;
                 }
                 base_future
                     .then(
                         async move |base_image: SimpleArcow<MaybeFromPool<Pixmap>>| {
-                            Arcow::from_owned(upscale_image(base_image.deref(), tile_size).unwrap())
+                            Arcow::from_owned(
+                                upscale_image(base_image.deref(), tile_size / *GRID_SIZE).unwrap(),
+                            )
+                        },
+                    )
+                    .boxed()
+            }
+            ToPixmapTaskSpec::OnGrid { base, grid_size } => {
+                let grid_size = *grid_size;
+                if tile_size <= grid_size || !tile_size.is_multiple_of(grid_size) {
+                    return base.add_to(ctx, tile_size);
+                }
+                base.add_to(ctx, grid_size)
+                    .then(
+                        async move |base_image: SimpleArcow<MaybeFromPool<Pixmap>>| {
+                            Arcow::from_owned(
+                                upscale_image(base_image.deref(), tile_size / grid_size).unwrap(),
+                            )
+                        },
+                    )
+                    .boxed()
+            }
+            ToPixmapTaskSpec::DetailAtLeast {
+                base,
+                min_tile_size,
+            } => {
+                if tile_size >= *min_tile_size {
+                    return base.add_to(ctx, tile_size);
+                }
+                ready(Arcow::from_owned(allocate_pixmap_empty(
+                    tile_size, tile_size,
+                )))
+                .boxed()
+            }
+            ToPixmapTaskSpec::CropAndScale { base, from, to } => {
+                let base_future = base.add_to(ctx, tile_size);
+                let (from, to) = (*from, *to);
+                base_future
+                    .then(
+                        async move |base_image: SimpleArcow<MaybeFromPool<Pixmap>>| {
+                            Arcow::from_owned(crop_and_scale(&base_image, from, to).unwrap())
+                        },
+                    )
+                    .boxed()
+            }
+            ToPixmapTaskSpec::Rotate {
+                base,
+                quarter_turns,
+            } => {
+                let quarter_turns = *quarter_turns;
+                base.add_to(ctx, tile_size)
+                    .then(
+                        async move |base_image: SimpleArcow<MaybeFromPool<Pixmap>>| {
+                            Arcow::from_owned(rotate(&base_image, quarter_turns))
+                        },
+                    )
+                    .boxed()
+            }
+            ToPixmapTaskSpec::Flip { base, axis } => {
+                let axis = *axis;
+                base.add_to(ctx, tile_size)
+                    .then(
+                        async move |base_image: SimpleArcow<MaybeFromPool<Pixmap>>| {
+                            Arcow::from_owned(flip(&base_image, axis))
+                        },
+                    )
+                    .boxed()
+            }
+            ToPixmapTaskSpec::Remap { base, mapping } => {
+                let mapping = mapping.to_owned();
+                base.add_to(ctx, tile_size)
+                    .then(
+                        async move |base_image: SimpleArcow<MaybeFromPool<Pixmap>>| {
+                            Arcow::from_owned(remap(&base_image, &mapping))
+                        },
+                    )
+                    .boxed()
+            }
+            ToPixmapTaskSpec::ShiftColors { base, shift } => {
+                let shift = *shift;
+                base.add_to(ctx, tile_size)
+                    .then(
+                        async move |base_image: SimpleArcow<MaybeFromPool<Pixmap>>| {
+                            Arcow::from_owned(shift_colors(&base_image, shift))
                         },
                     )
                     .boxed()
             }
+            ToPixmapTaskSpec::PlaceOnSheet {
+                width,
+                height,
+                placements,
+            } => {
+                let (width, height) = (*width, *height);
+                // Spawned so that the layers render in parallel, but placed in order, since later
+                // ones may overlap earlier ones
+                let layer_handles: Vec<_> = placements
+                    .iter()
+                    .map(|(layer, rect)| (spawn_subtask(layer.add_to(ctx, tile_size)), *rect))
+                    .collect();
+                async move {
+                    let mut layers = Vec::with_capacity(layer_handles.len());
+                    for (handle, rect) in layer_handles {
+                        layers.push((handle.await.unwrap(), rect));
+                    }
+                    Arcow::from_owned(
+                        place_on_sheet(
+                            width,
+                            height,
+                            layers.iter().map(|(image, rect)| {
+                                let image: &Pixmap = image;
+                                (image, *rect)
+                            }),
+                            tile_size,
+                        )
+                        .unwrap(),
+                    )
+                }
+                .boxed()
+            }
         };
         info!("Adding node: {}", name);
         let task = task.shared();
@@ -174,7 +344,10 @@ impl TaskSpecTraits<MaybeFromPool<Mask>> for ToAlphaChannelTaskSpec {
         }
         if let ToAlphaChannelTaskSpec::UpscaleFromGridSize { .. } = self {
             // Fall through; let expressions can't be inverted
-        } else if tile_size != GRID_SIZE && self.is_grid_perfect(ctx) {
+        } else if tile_size > *GRID_SIZE
+            && tile_size.is_multiple_of(*GRID_SIZE)
+            && self.is_grid_perfect(ctx)
+        {
             return ToAlphaChannelTaskSpec::UpscaleFromGridSize {
                 base: self.to_owned().into(),
             }
@@ -240,16 +413,41 @@ impl TaskSpecTraits<MaybeFromPool<Mask>> for ToAlphaChannelTaskSpec {
                     .boxed()
             }
             ToAlphaChannelTaskSpec::UpscaleFromGridSize { base } => {
-                let base_future = base.add_to(ctx, GRID_SIZE);
-                if tile_size == GRID_SIZE {
+                let base_future = base.add_to(ctx, *GRID_SIZE);
+                if tile_size == *GRID_SIZE {
                     return base_future;
                 }
                 base_future
                     .then(async move |base_mask: SimpleArcow<MaybeFromPool<Mask>>| {
-                        Arcow::from_owned(upscale_mask(base_mask.deref(), tile_size).unwrap())
+                        Arcow::from_owned(
+                            upscale_mask(base_mask.deref(), tile_size / *GRID_SIZE).unwrap(),
+                        )
                     })
                     .boxed()
             }
+            ToAlphaChannelTaskSpec::Dither { base, coverage } => {
+                let base_future = base.add_to(ctx, tile_size);
+                let coverage = *coverage;
+                base_future
+                    .then(async move |base_result: SimpleArcow<MaybeFromPool<Mask>>| {
+                        base_result.consume(|mut channel| {
+                            dither_alpha(&mut channel, coverage);
+                            Arcow::from_owned(channel)
+                        })
+                    })
+                    .boxed()
+            }
+        };
+        let task = if ALPHA_DEBUG_DIR.is_some() {
+            let id = self.node_id();
+            let spec = name.to_owned();
+            task.then(async move |mask: SimpleArcow<MaybeFromPool<Mask>>| {
+                write_alpha_debug(id, &spec, tile_size, &mask);
+                mask
+            })
+            .boxed()
+        } else {
+            task
         };
         info!("Adding node: {}", name);
         let task = task.shared();
@@ -265,41 +463,154 @@ impl TaskSpecTraits<()> for FileOutputTaskSpec {
             info!("Matched an existing node: {}", name);
             return existing_future.to_owned();
         }
+        if let FileOutputTaskSpec::PngOutput {
+            base,
+            destination_name,
+            ..
+        } = self
+            && let Some(override_file) = override_path(destination_name)
+        {
+            let destination_path = self.get_path();
+            let sinks = ctx.output_sinks();
+            let expected_width = expected_width(base, tile_size);
+            let pack = ctx.pack;
+            info!("Adding override node: {}", name);
+            let task = async move {
+                write_override(
+                    &override_file,
+                    pack,
+                    destination_path,
+                    expected_width,
+                    &sinks,
+                )
+                .await
+                .unwrap();
+                Arcow::from_owned(())
+            }
+            .boxed()
+            .shared();
+            ctx.output_task_to_future_map
+                .insert(self.to_owned(), task.to_owned());
+            return task;
+        }
+        let png_cache_key = match self {
+            FileOutputTaskSpec::PngOutput {
+                base, require_gray, ..
+            } if CACHE_DIR.is_some() => Some(cache_key(base, *require_gray, tile_size, ctx)),
+            _ => None,
+        };
+        if let FileOutputTaskSpec::PngOutput { base, .. } = self
+            && let Some(key) = png_cache_key
+            && let Some(png) = cached_png(
+                ctx.pack,
+                &self.get_path(),
+                key,
+                expected_width(base, tile_size),
+            )
+        {
+            let destination_path = self.get_path();
+            let sinks = ctx.output_sinks();
+            let timing = base.frame_timing();
+            info!("Adding cached node: {}", name);
+            let task = async move {
+                write_to_sinks(&sinks, &destination_path, png)
+                    .await
+                    .unwrap();
+                write_mcmeta(&sinks, &destination_path, timing)
+                    .await
+                    .unwrap();
+                Arcow::from_owned(())
+            }
+            .boxed()
+            .shared();
+            ctx.output_task_to_future_map
+                .insert(self.to_owned(), task.to_owned());
+            return task;
+        }
         let task = match self {
-            FileOutputTaskSpec::PngOutput { base, .. } => {
-                let base_color_desc_future = base.get_color_description_task(ctx);
+            FileOutputTaskSpec::PngOutput {
+                base, require_gray, ..
+            } => {
+                let require_gray = *require_gray;
+                let base_analysis_future = base.get_analysis_task(ctx, tile_size);
                 let base_size = if base.is_grid_perfect(ctx) {
-                    GRID_SIZE
+                    *GRID_SIZE
                 } else {
                     tile_size
                 };
                 let base_future = base.add_to(ctx, base_size);
                 let destination_path = self.get_path();
                 let base_name = base.to_string();
-                let zip_ref = ctx.zip_writer.clone();
-                base_color_desc_future
-                    .then(
-                        async move |base_color_desc: SimpleArcow<ColorDescription>| {
-                            color_description_to_mode(&base_color_desc, &base_name)
-                        },
-                    )
-                    .then(async move |(color_type, bit_depth)| {
+                let sinks = ctx.output_sinks();
+                let timing = base.frame_timing();
+                let pack = ctx.pack;
+                base_analysis_future
+                    .then(async move |base_analysis: SimpleArcow<PixmapAnalysis>| {
+                        let check_pixels_gray =
+                            require_gray && check_gray(&base_analysis.colors, &base_name);
+                        let (color_type, bit_depth) =
+                            color_description_to_mode(&base_analysis.colors, &base_name);
+                        (color_type, bit_depth, check_pixels_gray)
+                    })
+                    .then(async move |(color_type, bit_depth, check_pixels_gray)| {
                         let base_result = base_future.await;
-                        base_result.consume(|image| {
-                            png_output(image, color_type, bit_depth, destination_path,
-                                       zip_ref).unwrap();
-                            Arcow::from_owned(())
-                        })
+                        if check_pixels_gray
+                            && let Some(color) = base_result
+                                .pixels()
+                                .iter()
+                                .copied()
+                                .map(ComparableColor::from)
+                                .find(|color| !color.is_gray())
+                        {
+                            panic!(
+                                "{} must be grayscale, but contains {}",
+                                destination_path, color
+                            );
+                        }
+                        let png = base_result
+                            .consume(|image| {
+                                encode_png(image, color_type, bit_depth, pack, &destination_path)
+                            })
+                            .unwrap();
+                        if let Some(key) = png_cache_key {
+                            record_cached_png(&destination_path, key, &png);
+                        }
+                        write_to_sinks(&sinks, &destination_path, png)
+                            .await
+                            .unwrap();
+                        write_mcmeta(&sinks, &destination_path, timing)
+                            .await
+                            .unwrap();
+                        Arcow::from_owned(())
                     })
                     .boxed()
             }
-            FileOutputTaskSpec::Copy { original, .. } => {
+            FileOutputTaskSpec::Copy {
+                original,
+                link_names,
+            } => {
                 let base_future = original.add_to(ctx, tile_size);
-                let link = self.get_path();
+                let links: Vec<Box<str>> =
+                    link_names.iter().map(|name| texture_path(name)).collect();
                 let original_path = original.get_path();
+                let sinks = ctx.output_sinks();
+                let timing = original.frame_timing();
+                let pack = ctx.pack;
                 base_future
                     .then(async move |_| {
-                        copy_out_to_out(original_path, link, ).unwrap();
+                        for link in links {
+                            copy_in_sinks(&sinks, &original_path, &link).await.unwrap();
+                            expect_copy(pack, &original_path, &link);
+                            if timing.is_some() {
+                                copy_in_sinks(
+                                    &sinks,
+                                    &format!("{}.mcmeta", original_path),
+                                    &format!("{}.mcmeta", link),
+                                )
+                                .await
+                                .unwrap();
+                            }
+                        }
                         Arcow::from_owned(())
                     })
                     .boxed()
@@ -313,6 +624,16 @@ impl TaskSpecTraits<()> for FileOutputTaskSpec {
     }
 }
 
+/// How wide the PNG of `base` should be at `tile_size`, or None if it can be any width. Sheets can
+/// be, so only other textures are checked.
+fn expected_width(base: &ToPixmapTaskSpec, tile_size: u32) -> Option<u32> {
+    match base {
+        ToPixmapTaskSpec::PlaceOnSheet { .. } | ToPixmapTaskSpec::CropAndScale { .. } => None,
+        ToPixmapTaskSpec::Animate { layout, .. } if *layout != SheetLayout::Vertical => None,
+        _ => Some(tile_size),
+    }
+}
+
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub struct TileSized<T> {
     inner: T,
@@ -333,51 +654,150 @@ where
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum ToPixmapTaskSpec {
     Animate {
-        background: Box<ToPixmapTaskSpec>,
+        background: Interned<ToPixmapTaskSpec>,
         frames: Box<[ToPixmapTaskSpec]>,
+        layout: SheetLayout,
+        /// If set, the frames are played as an animation, and a `.png.mcmeta` file that tells
+        /// Minecraft how is written beside the PNG. Sheets that aren't animations leave it unset.
+        timing: Option<FrameTiming>,
     },
     FromSvg {
         source: Name,
     },
+    /// A PNG layer; see [crate::image_tasks::from_raster::from_raster].
+    FromRaster {
+        source: Name,
+    },
+    /// The image that another material writes to `name`, such as `block/deepslate`. Resolved when
+    /// the graph is built, so that materials don't have to refer to each other's statics.
+    TextureOf {
+        name: Name,
+    },
     PaintAlphaChannel {
-        base: Box<ToAlphaChannelTaskSpec>,
+        base: Interned<ToAlphaChannelTaskSpec>,
         color: ComparableColor,
     },
     StackLayerOnColor {
         background: ComparableColor,
-        foreground: Box<ToPixmapTaskSpec>,
+        foreground: Interned<ToPixmapTaskSpec>,
     },
     StackLayerOnLayer {
-        background: Box<ToPixmapTaskSpec>,
-        foreground: Box<ToPixmapTaskSpec>,
+        background: Interned<ToPixmapTaskSpec>,
+        foreground: Interned<ToPixmapTaskSpec>,
     },
     UpscaleFromGridSize {
-        base: Box<ToPixmapTaskSpec>,
+        base: Interned<ToPixmapTaskSpec>,
+    },
+    /// A layer drawn on a grid of `grid_size` texels per side rather than [GRID_SIZE], so that it's
+    /// rendered exactly at that size and then upscaled to the tile size.
+    OnGrid {
+        base: Interned<ToPixmapTaskSpec>,
+        grid_size: u32,
+    },
+    /// A layer that's only drawn at tile sizes of at least `min_tile_size`, and is transparent at
+    /// smaller ones; see [crate::detail_at_least!].
+    DetailAtLeast {
+        base: Interned<ToPixmapTaskSpec>,
+        min_tile_size: u32,
+    },
+    /// The `from` region of the base image, scaled into the `to` region of a transparent one; see
+    /// [crate::image_tasks::crop::crop_and_scale].
+    CropAndScale {
+        base: Interned<ToPixmapTaskSpec>,
+        from: TileRect,
+        to: TileRect,
+    },
+    /// The base image turned clockwise by `quarter_turns` right angles, from 1 to 3; see
+    /// [rotate_task].
+    Rotate {
+        base: Interned<ToPixmapTaskSpec>,
+        quarter_turns: u8,
+    },
+    /// The base image mirrored across `axis`; see [flip_task].
+    Flip {
+        base: Interned<ToPixmapTaskSpec>,
+        axis: Axis,
+    },
+    /// The base image with its colors replaced according to `mapping`, so that variants such as
+    /// dyed blocks can share one rendered master; see [remap_task].
+    Remap {
+        base: Interned<ToPixmapTaskSpec>,
+        mapping: PaletteMap,
+    },
+    /// The base image with the hue, saturation and lightness of every color shifted, such as an
+    /// animal's coat color made from its template; see [shift_colors_task].
+    ShiftColors {
+        base: Interned<ToPixmapTaskSpec>,
+        shift: ColorShift,
+    },
+    /// Tile-sized layers scaled into regions of a sheet `width` by `height` texels, such as an
+    /// entity texture; see [crate::image_tasks::sheet::place_on_sheet].
+    PlaceOnSheet {
+        width: u16,
+        height: u16,
+        placements: Box<[(ToPixmapTaskSpec, SheetRect)]>,
     },
-    None,
 }
 
 /// [TaskSpec] for a task that produces an [AlphaChannel].
 #[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
 pub enum ToAlphaChannelTaskSpec {
     MakeSemitransparent {
-        base: Box<ToAlphaChannelTaskSpec>,
+        base: Interned<ToAlphaChannelTaskSpec>,
         alpha: u8,
     },
     FromPixmap {
         base: ToPixmapTaskSpec,
     },
     StackAlphaOnAlpha {
-        background: Box<ToAlphaChannelTaskSpec>,
-        foreground: Box<ToAlphaChannelTaskSpec>,
+        background: Interned<ToAlphaChannelTaskSpec>,
+        foreground: Interned<ToAlphaChannelTaskSpec>,
     },
     StackAlphaOnBackground {
         background: u8,
-        foreground: Box<ToAlphaChannelTaskSpec>,
+        foreground: Interned<ToAlphaChannelTaskSpec>,
     },
     UpscaleFromGridSize {
-        base: Box<ToAlphaChannelTaskSpec>,
+        base: Interned<ToAlphaChannelTaskSpec>,
     },
+    /// Makes every pixel fully opaque or fully transparent with an ordered dither, keeping about
+    /// `coverage` sixteenths of the base's opacity; see [dither_alpha].
+    Dither {
+        base: Interned<ToAlphaChannelTaskSpec>,
+        coverage: u8,
+    },
+}
+
+/// The ID of a [ToPixmapTaskSpec] or [ToAlphaChannelTaskSpec] in its type's [Arena].
+pub type NodeId = u32;
+
+static PIXMAP_TASK_ARENA: Lazy<Arena<ToPixmapTaskSpec>> = Lazy::new(Arena::default);
+static ALPHA_TASK_ARENA: Lazy<Arena<ToAlphaChannelTaskSpec>> = Lazy::new(Arena::default);
+
+impl Internable for ToPixmapTaskSpec {
+    fn arena() -> &'static Arena<Self> {
+        &PIXMAP_TASK_ARENA
+    }
+}
+
+impl Internable for ToAlphaChannelTaskSpec {
+    fn arena() -> &'static Arena<Self> {
+        &ALPHA_TASK_ARENA
+    }
+}
+
+impl ToPixmapTaskSpec {
+    /// Interns this spec if it isn't already; equal specs always get the same ID.
+    pub fn node_id(&self) -> NodeId {
+        Interned::from_ref(self).id()
+    }
+}
+
+impl ToAlphaChannelTaskSpec {
+    /// Interns this spec if it isn't already; equal specs always get the same ID.
+    pub fn node_id(&self) -> NodeId {
+        Interned::from_ref(self).id()
+    }
 }
 
 /// [TaskSpec] for a task that doesn't produce a heap object as output.
@@ -386,32 +806,147 @@ pub enum FileOutputTaskSpec {
     PngOutput {
         base: ToPixmapTaskSpec,
         destination_name: Name,
+        /// If true, the output must be grayscale, e.g. because it's a biome-tint overlay.
+        require_gray: bool,
+        /// How Minecraft draws this texture, if declared; see [audit_render_layers].
+        render_layer: Option<RenderLayer>,
     },
+    /// Copies the output of another task, which is only rendered and compressed once, to one or
+    /// more other paths.
     Copy {
         original: Box<FileOutputTaskSpec>,
-        link_name: Name,
+        link_names: Box<[Name]>,
     },
 }
 
+/// Writes the `.png.mcmeta` file that makes Minecraft play the PNG at `png_path` as an animation,
+/// if it has a [FrameTiming].
+async fn write_mcmeta(
+    sinks: &[Arc<dyn OutputSink>],
+    png_path: &str,
+    timing: Option<FrameTiming>,
+) -> Result<(), CloneableError> {
+    match timing {
+        Some(timing) => {
+            write_to_sinks(
+                sinks,
+                &format!("{}.mcmeta", png_path),
+                timing.mcmeta().into_bytes(),
+            )
+            .await
+        }
+        None => Ok(()),
+    }
+}
+
 impl FileOutputTaskSpec {
+    /// The [FrameTiming] of the image this task writes, or of its original if it's a copy. An
+    /// override is written without a `.png.mcmeta` file, since it may not have the same frames.
+    pub fn frame_timing(&self) -> Option<FrameTiming> {
+        match self {
+            FileOutputTaskSpec::PngOutput {
+                destination_name, ..
+            } if override_path(destination_name).is_some() => None,
+            FileOutputTaskSpec::PngOutput { base, .. } => base.frame_timing(),
+            FileOutputTaskSpec::Copy { original, .. } => original.frame_timing(),
+        }
+    }
+
+    /// Declares the [RenderLayer] of the image this task writes, or of its original if it's a copy.
+    pub fn in_render_layer(self, layer: RenderLayer) -> FileOutputTaskSpec {
+        match self {
+            FileOutputTaskSpec::PngOutput {
+                base,
+                destination_name,
+                require_gray,
+                ..
+            } => FileOutputTaskSpec::PngOutput {
+                base,
+                destination_name,
+                require_gray,
+                render_layer: Some(layer),
+            },
+            FileOutputTaskSpec::Copy {
+                original,
+                link_names,
+            } => FileOutputTaskSpec::Copy {
+                original: Box::new(original.in_render_layer(layer)),
+                link_names,
+            },
+        }
+    }
+
+    /// Returns the path this task writes to; for a [FileOutputTaskSpec::Copy], the first one.
     pub(crate) fn get_path(&self) -> Box<str> {
         match self {
             FileOutputTaskSpec::PngOutput {
                 destination_name, ..
-            } => {
-                let mut out_path = ASSET_DIR.to_string();
-                out_path.push_str(destination_name);
-                out_path.push_str(".png");
-                out_path
+            } => texture_path(destination_name),
+            FileOutputTaskSpec::Copy { link_names, .. } => texture_path(&link_names[0]),
+        }
+    }
+
+    /// Returns a copy of this task with `f` applied to the image it writes, or to its original's if
+    /// it's a copy.
+    pub fn map_base(
+        &self,
+        f: &impl Fn(&ToPixmapTaskSpec) -> ToPixmapTaskSpec,
+    ) -> FileOutputTaskSpec {
+        match self {
+            FileOutputTaskSpec::PngOutput {
+                base,
+                destination_name,
+                require_gray,
+                render_layer,
+            } => FileOutputTaskSpec::PngOutput {
+                base: f(base),
+                destination_name: destination_name.to_owned(),
+                require_gray: *require_gray,
+                render_layer: *render_layer,
+            },
+            FileOutputTaskSpec::Copy {
+                original,
+                link_names,
+            } => FileOutputTaskSpec::Copy {
+                original: Box::new(original.map_base(f)),
+                link_names: link_names.to_owned(),
+            },
+        }
+    }
+
+    /// Applies [ToPixmapTaskSpec::map_colors] to the image this task writes, or to its original if
+    /// it's a copy.
+    pub fn map_colors<F: Fn(ComparableColor) -> ComparableColor>(
+        &self,
+        f: &F,
+    ) -> FileOutputTaskSpec {
+        self.map_base(&|base| base.map_colors(f))
+    }
+
+    /// Returns every path this task writes to.
+    pub fn get_paths(&self) -> Vec<Box<str>> {
+        match self {
+            FileOutputTaskSpec::PngOutput { .. } => vec![self.get_path()],
+            FileOutputTaskSpec::Copy { link_names, .. } => {
+                link_names.iter().map(|name| texture_path(name)).collect()
             }
-            FileOutputTaskSpec::Copy { link_name, .. } => {
-                let mut out_path = ASSET_DIR.to_string();
-                out_path.push_str(link_name);
-                out_path.push_str(".png");
-                out_path
+        }
+    }
+
+    /// Returns the colors and alpha values predicted for the image this task writes at
+    /// `tile_size`, or `None` for a [FileOutputTaskSpec::Copy], whose colors are those of its
+    /// original.
+    pub fn analysis(
+        &self,
+        ctx: &mut TaskGraphBuildingContext,
+        tile_size: u32,
+    ) -> Option<BasicTask<PixmapAnalysis>> {
+        match self {
+            FileOutputTaskSpec::PngOutput { base, .. } => {
+                Some(base.get_analysis_task(ctx, tile_size))
             }
+            FileOutputTaskSpec::Copy { .. } => None,
         }
-        .into_boxed_str()
     }
 }
 
@@ -463,10 +998,27 @@ macro_rules! anyhoo {
 impl Display for ToPixmapTaskSpec {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            ToPixmapTaskSpec::Animate { background, frames } => {
-                write!(f, "animate({};{})", background, frames.iter().join(";"))
-            }
+            ToPixmapTaskSpec::Animate {
+                background,
+                frames,
+                layout: SheetLayout::Vertical,
+                ..
+            } => write!(f, "animate({};{})", background, frames.iter().join(";")),
+            ToPixmapTaskSpec::Animate {
+                background,
+                frames,
+                layout,
+                ..
+            } => write!(
+                f,
+                "animate[{}]({};{})",
+                layout,
+                background,
+                frames.iter().join(";")
+            ),
             ToPixmapTaskSpec::FromSvg { source } => f.write_str(source),
+            ToPixmapTaskSpec::FromRaster { source } => write!(f, "raster({})", source),
+            ToPixmapTaskSpec::TextureOf { name } => write!(f, "texture_of({})", name),
             ToPixmapTaskSpec::PaintAlphaChannel { base, color } => {
                 if let ToAlphaChannelTaskSpec::FromPixmap { base: base_of_base } = &**base {
                     write!(f, "{}@{}", *base_of_base, color)
@@ -486,12 +1038,50 @@ impl Display for ToPixmapTaskSpec {
             } => {
                 write!(f, "({}+{})", background, foreground)
             }
-            ToPixmapTaskSpec::None {} => {
-                write!(f, "None")
-            }
             UpscaleFromGridSize { base } => {
                 write!(f, "upscale({})", base)
             }
+            ToPixmapTaskSpec::OnGrid { base, grid_size } => {
+                write!(f, "grid{}({})", grid_size, base)
+            }
+            ToPixmapTaskSpec::DetailAtLeast {
+                base,
+                min_tile_size,
+            } => {
+                write!(f, "detail{}({})", min_tile_size, base)
+            }
+            ToPixmapTaskSpec::CropAndScale { base, from, to } => {
+                write!(f, "crop[{}->{}]({})", from, to, base)
+            }
+            ToPixmapTaskSpec::Rotate {
+                base,
+                quarter_turns,
+            } => {
+                write!(f, "rotate{}({})", *quarter_turns as u32 * 90, base)
+            }
+            ToPixmapTaskSpec::Flip { base, axis } => {
+                write!(f, "flip{}({})", axis, base)
+            }
+            ToPixmapTaskSpec::Remap { base, mapping } => {
+                write!(f, "remap[{}]({})", mapping, base)
+            }
+            ToPixmapTaskSpec::ShiftColors { base, shift } => {
+                write!(f, "shift[{}]({})", shift, base)
+            }
+            ToPixmapTaskSpec::PlaceOnSheet {
+                width,
+                height,
+                placements,
+            } => write!(
+                f,
+                "sheet[{}x{}]({})",
+                width,
+                height,
+                placements
+                    .iter()
+                    .map(|(layer, rect)| format!("{}:{}", rect, layer))
+                    .join(";")
+            ),
         }
     }
 }
@@ -500,9 +1090,15 @@ impl Display for FileOutputTaskSpec {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str(&match self {
             FileOutputTaskSpec::PngOutput { .. } => self.get_path(),
-            FileOutputTaskSpec::Copy { original, .. } => {
-                format!("symlink({} -> {})", self.get_path(), original.get_path()).into_boxed_str()
-            }
+            FileOutputTaskSpec::Copy {
+                original,
+                link_names,
+            } => format!(
+                "symlink({} -> {})",
+                link_names.iter().map(|name| texture_path(name)).join(", "),
+                original.get_path()
+            )
+            .into_boxed_str(),
         })
     }
 }
@@ -531,6 +1127,9 @@ impl Display for ToAlphaChannelTaskSpec {
             ToAlphaChannelTaskSpec::UpscaleFromGridSize { base } => {
                 write!(f, "upscale({})", base)
             }
+            ToAlphaChannelTaskSpec::Dither { base, coverage } => {
+                write!(f, "dither({},{}/16)", base, coverage)
+            }
         }
     }
 }
@@ -567,6 +1166,14 @@ pub enum ColorDescription {
     Rgb(Transparency),
 }
 
+/// What [ToPixmapTaskSpec::get_analysis_task] predicts about an image.
+#[derive(Clone)]
+pub struct PixmapAnalysis {
+    pub colors: ColorDescription,
+    /// The alpha values that [Self::colors] can have.
+    pub alphas: U8BitSet,
+}
+
 impl Transparency {
     pub fn stack_on(&self, other: &Transparency) -> Transparency {
         if *self == Opaque || *other == Opaque {
@@ -607,6 +1214,24 @@ impl ColorDescription {
         }
     }
 
+    /// The alpha values that pixels with these colors can have.
+    pub fn possible_alphas(&self) -> U8BitSet {
+        match self.transparency() {
+            AlphaChannel => match self {
+                Rgb(_) => U8BitSet::all_u8s(),
+                SpecifiedColors(colors) => {
+                    if colors.len() <= BINARY_SEARCH_THRESHOLD {
+                        colors.iter().map(|color| color.alpha()).collect()
+                    } else {
+                        alphas_of_sorted(colors)
+                    }
+                }
+            },
+            Binary => U8BitSet::from_iter([0, u8::MAX]),
+            Opaque => U8BitSet::from_iter([u8::MAX]),
+        }
+    }
+
     pub async fn cap_indexed(
         &mut self,
         max_colors: usize,
@@ -692,6 +1317,109 @@ pub enum Transparency {
     AlphaChannel,
 }
 
+/// Which of Minecraft's render passes draws a block texture, which limits its transparency.
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum RenderLayer {
+    /// Drawn without blending, so any transparent pixel shows up black or as garbage.
+    Solid,
+    /// Pixels are either drawn or discarded, so semitransparent pixels lose their alpha.
+    Cutout,
+    /// Blended, so any alpha is allowed.
+    Translucent,
+}
+
+impl Display for RenderLayer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            RenderLayer::Solid => "solid",
+            RenderLayer::Cutout => "cutout",
+            RenderLayer::Translucent => "translucent",
+        })
+    }
+}
+
+impl RenderLayer {
+    pub fn allows(&self, transparency: Transparency) -> bool {
+        match self {
+            RenderLayer::Solid => transparency == Opaque,
+            RenderLayer::Cutout => transparency != AlphaChannel,
+            RenderLayer::Translucent => true,
+        }
+    }
+}
+
+/// Checks the predicted transparency at `tile_size` of every output whose [RenderLayer] is
+/// declared, and fails with a list of every one that the game would draw wrong.
+pub async fn audit_render_layers(
+    tasks: &[FileOutputTaskSpec],
+    ctx: &mut TaskGraphBuildingContext,
+    tile_size: u32,
+) -> Result<(), CloneableError> {
+    let mut violations = BTreeSet::new();
+    for mut task in tasks {
+        // An alias is drawn in the same layer as its original, which may not be listed separately
+        while let FileOutputTaskSpec::Copy { original, .. } = task {
+            task = original.as_ref();
+        }
+        let FileOutputTaskSpec::PngOutput {
+            base,
+            render_layer: Some(render_layer),
+            ..
+        } = task
+        else {
+            continue;
+        };
+        let transparency = base
+            .get_analysis_task(ctx, tile_size)
+            .await
+            .colors
+            .transparency();
+        if !render_layer.allows(transparency) {
+            violations.insert(format!(
+                "{} is {} but has {:?} transparency",
+                task.get_path(),
+                render_layer,
+                transparency
+            ));
+        }
+    }
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhoo!(
+            "{} textures can't be drawn in their render layers:\n{}",
+            violations.len(),
+            violations.into_iter().join("\n")
+        ))
+    }
+}
+
+#[test]
+fn test_audit_render_layers() {
+    let binary = from_svg_task("borderSolid");
+    let opaque = ToPixmapTaskSpec::StackLayerOnColor {
+        background: ComparableColor::WHITE,
+        foreground: binary.clone().into(),
+    };
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let mut ctx = TaskGraphBuildingContext::new();
+    let mut audit =
+        |tasks: Vec<FileOutputTaskSpec>| runtime.block_on(audit_render_layers(&tasks, &mut ctx));
+    assert!(audit(vec![
+        out_task("block/a", opaque).in_render_layer(RenderLayer::Solid),
+        out_task("block/b", binary.clone()).in_render_layer(RenderLayer::Cutout),
+        out_task("block/c", binary.clone()),
+    ])
+    .is_ok());
+    let wrong_layer = out_task("block/d", binary).in_render_layer(RenderLayer::Solid);
+    assert!(audit(vec![wrong_layer.to_owned()]).is_err());
+    assert!(audit(vec![alias_task(wrong_layer, ["block/f"])]).is_err());
+    assert!(audit(vec![
+        out_task("block/e", from_svg_task("circle24")).in_render_layer(RenderLayer::Cutout)
+    ])
+    .is_err());
+}
+
 fn palette_bit_depth(len: usize) -> BitDepth {
     if len <= 2 {
         One
@@ -725,14 +1453,23 @@ pub const fn channel_to_bit_depth(input: u8, depth: BitDepth) -> u16 {
 const ALL_U8S: &[u8; u8::MAX as usize + 1] = &ALPHA_MULTIPLICATION_TABLE[u8::MAX as usize];
 
 impl ToAlphaChannelTaskSpec {
-    fn get_possible_alpha_values(&self, ctx: &mut TaskGraphBuildingContext) -> BasicTask<U8BitSet> {
-        if let Some(alpha_vec) = ctx.alpha_task_to_alpha_map.get(self) {
+    pub(crate) fn get_possible_alpha_values(
+        &self,
+        ctx: &mut TaskGraphBuildingContext,
+        tile_size: u32,
+    ) -> BasicTask<U8BitSet> {
+        let id = self.node_id();
+        if let Some(alpha_vec) = ctx
+            .alpha_task_to_alpha_map
+            .get(&tile_size)
+            .and_then(|map| map.get(&id))
+        {
             return alpha_vec.to_owned();
         }
         let alpha_vec: BasicTask<U8BitSet> = match self {
             ToAlphaChannelTaskSpec::MakeSemitransparent { alpha, base } => {
                 let alpha = *alpha;
-                let base_alphas_task = base.get_possible_alpha_values(ctx);
+                let base_alphas_task = base.get_possible_alpha_values(ctx, tile_size);
                 base_alphas_task
                     .then(async move |base_alphas: SimpleArcow<U8BitSet>| {
                         Arcow::from_owned(multiply_alpha_vec(base_alphas.deref().to_owned(), alpha))
@@ -740,13 +1477,17 @@ impl ToAlphaChannelTaskSpec {
                     .boxed()
                     .shared()
             }
-            ToAlphaChannelTaskSpec::FromPixmap { base } => base.get_possible_alpha_values(ctx),
+            ToAlphaChannelTaskSpec::FromPixmap { base } => base
+                .get_analysis_task(ctx, tile_size)
+                .map(|analysis| Arcow::from_owned(analysis.alphas))
+                .boxed()
+                .shared(),
             StackAlphaOnAlpha {
                 background,
                 foreground,
             } => {
-                let bg_task = background.get_possible_alpha_values(ctx);
-                let fg_task = foreground.get_possible_alpha_values(ctx);
+                let bg_task = background.get_possible_alpha_values(ctx, tile_size);
+                let fg_task = foreground.get_possible_alpha_values(ctx, tile_size);
                 let bg_and_fg = join_all([bg_task, fg_task]);
                 bg_and_fg
                     .then(async move |mut bg_and_fg: Vec<SimpleArcow<U8BitSet>>| {
@@ -762,7 +1503,7 @@ impl ToAlphaChannelTaskSpec {
                 foreground,
             } => {
                 let background_alpha = *background_alpha;
-                let fg_task = foreground.get_possible_alpha_values(ctx);
+                let fg_task = foreground.get_possible_alpha_values(ctx, tile_size);
                 fg_task
                     .then(async move |fg: SimpleArcow<U8BitSet>| {
                         Arcow::from_owned(stack_alpha_vecs(
@@ -774,11 +1515,33 @@ impl ToAlphaChannelTaskSpec {
                     .shared()
             }
             ToAlphaChannelTaskSpec::UpscaleFromGridSize { base } => {
-                base.get_possible_alpha_values(ctx)
+                base.get_possible_alpha_values(ctx, tile_size)
+            }
+            ToAlphaChannelTaskSpec::Dither { base, coverage } => {
+                let coverage = *coverage;
+                let base_alphas_task = base.get_possible_alpha_values(ctx, tile_size);
+                base_alphas_task
+                    .then(async move |base_alphas: SimpleArcow<U8BitSet>| {
+                        let mut alphas = U8BitSet::new();
+                        for alpha in *base_alphas {
+                            // Each dither threshold is a whole number of sixteenths
+                            if (alpha as u32 * coverage as u32) < 16 * u8::MAX as u32 {
+                                alphas.insert(0);
+                            }
+                            if alpha as u32 * coverage as u32 > 0 {
+                                alphas.insert(u8::MAX);
+                            }
+                        }
+                        Arcow::from_owned(alphas)
+                    })
+                    .boxed()
+                    .shared()
             }
         };
         ctx.alpha_task_to_alpha_map
-            .insert(self.to_owned(), alpha_vec.to_owned());
+            .entry(tile_size)
+            .or_default()
+            .insert(id, alpha_vec.to_owned());
         alpha_vec
     }
 
@@ -794,6 +1557,7 @@ impl ToAlphaChannelTaskSpec {
                 foreground.is_grid_perfect(ctx)
             }
             ToAlphaChannelTaskSpec::UpscaleFromGridSize { .. } => true,
+            ToAlphaChannelTaskSpec::Dither { base, .. } => base.is_grid_perfect(ctx),
         }
     }
 }
@@ -810,7 +1574,21 @@ fn get_grayscale_bit_depth(colors: &[ComparableColor]) -> BitDepth {
     grayscale_bit_depth
 }
 
-fn color_description_to_mode(
+/// Panics if `color_description` shows that the task can produce a non-gray color. Returns true if
+/// the task has too many colors to tell in advance, so the caller needs to check the actual pixels.
+fn check_gray(color_description: &ColorDescription, task_name: &str) -> bool {
+    match color_description {
+        SpecifiedColors(colors) => {
+            if let Some(color) = colors.iter().find(|color| !color.is_gray()) {
+                panic!("{} must be grayscale, but can contain {}", task_name, color);
+            }
+            false
+        }
+        Rgb(_) => true,
+    }
+}
+
+pub(crate) fn color_description_to_mode(
     color_description: &ColorDescription,
     task_name: &str,
 ) -> (ColorType, BitDepth) {
@@ -837,7 +1615,9 @@ fn color_description_to_mode(
                         ),
                         Binary => (
                             RGB {
-                                transparent_color: Some(RGB16::new(0xc0c0, 0xffff, 0x3e3e)),
+                                transparent_color: Some(
+                                    transparency_sentinel(colors.iter().copied()).to_rgb16(),
+                                ),
                             },
                             Eight,
                         ),
@@ -952,9 +1732,10 @@ fn color_description_to_mode(
             },
             Eight,
         ),
+        // The colors aren't known yet, so encode_png will check for a collision.
         Rgb(Binary) => (
             RGB {
-                transparent_color: Some(RGB16::new(0xc0c0, 0xffff, 0x3e3e)),
+                transparent_color: Some(ComparableColor::RESERVED_FOR_TRANSPARENCY.to_rgb16()),
             },
             Eight,
         ),
@@ -962,56 +1743,22 @@ fn color_description_to_mode(
     }
 }
 
-fn contains_alpha(vec: &[ComparableColor], needle_alpha: u8) -> bool {
-    // Optimizations for the fact that fully-transparent can only appear once.
-    let least_alpha = vec[0].alpha();
-    if least_alpha == 0 {
-        if needle_alpha == 0 {
-            return true;
-        }
-        if vec.len() == 1 {
-            return false;
-        }
-        let next_least_alpha = vec[1].alpha();
-        debug_assert_ne!(0, next_least_alpha, "Transparent color included twice");
-        match needle_alpha.cmp(&next_least_alpha) {
-            Ordering::Less => return false,
-            Ordering::Equal => return true,
-            Ordering::Greater => {}
+/// Collects the alpha values of colors that are sorted by alpha. Each run of colors with the same
+/// alpha is skipped with a binary search, so this takes at most 256 searches however long the
+/// slice is.
+fn alphas_of_sorted(colors: &[ComparableColor]) -> U8BitSet {
+    debug_assert!(colors.windows(2).all(|window| window[0] < window[1]));
+    let mut alphas = U8BitSet::new();
+    let mut remaining = colors;
+    while let Some(first) = remaining.first() {
+        let alpha = first.alpha();
+        alphas.insert(alpha);
+        if alpha == u8::MAX {
+            break;
         }
-    } else {
-        match needle_alpha.cmp(&least_alpha) {
-            Ordering::Less => return false,
-            Ordering::Equal => return true,
-            Ordering::Greater => {}
-        }
-    }
-
-    // Check against upper limit of range
-    let greatest_alpha = vec[vec.len() - 1].alpha();
-    match needle_alpha.cmp(&greatest_alpha) {
-        Ordering::Less => {}
-        Ordering::Equal => return true,
-        Ordering::Greater => return false,
-    }
-
-    match vec.binary_search(&ComparableColor {
-        alpha: needle_alpha,
-        red: 0,
-        green: 0,
-        blue: 0,
-    }) {
-        Ok(_) => true,
-        Err(insert_black_index) => match vec[insert_black_index..].binary_search(&ComparableColor {
-            alpha: needle_alpha,
-            red: u8::MAX,
-            green: u8::MAX,
-            blue: u8::MAX,
-        }) {
-            Ok(_) => true,
-            Err(insert_white_index) => insert_white_index > 0,
-        },
+        remaining = &remaining[remaining.partition_point(|color| color.alpha() <= alpha)..];
     }
+    alphas
 }
 
 pub fn contains_semitransparency(vec: &[ComparableColor]) -> bool {
@@ -1063,15 +1810,20 @@ const fn create_black_to_transparent() -> [ComparableColor; u8::MAX as usize + 1
 impl ToPixmapTaskSpec {
     /// If true, this texture has no gradients, diagonals or curves, so it can be rendered at a
     /// smaller size.
-    pub(crate) fn is_grid_perfect(&self, ctx: &mut TaskGraphBuildingContext) -> bool {
+    pub fn is_grid_perfect(&self, ctx: &mut TaskGraphBuildingContext) -> bool {
         match self {
-            ToPixmapTaskSpec::Animate { background, frames } => {
+            ToPixmapTaskSpec::Animate {
+                background, frames, ..
+            } => {
                 background.is_grid_perfect(ctx)
                     && frames.iter().all(|frame| frame.is_grid_perfect(ctx))
             }
             ToPixmapTaskSpec::FromSvg { source } => {
                 SEMITRANSPARENCY_FREE_SVGS.contains(&&**source) && !COLOR_SVGS.contains(&&**source)
             }
+            // Resampling to a size that isn't a multiple of the source isn't grid-perfect
+            ToPixmapTaskSpec::FromRaster { .. } => false,
+            ToPixmapTaskSpec::TextureOf { name } => ctx.resolve_texture(name).is_grid_perfect(ctx),
             ToPixmapTaskSpec::PaintAlphaChannel { base, .. } => base.is_grid_perfect(ctx),
             ToPixmapTaskSpec::StackLayerOnColor { foreground, .. } => {
                 foreground.is_grid_perfect(ctx)
@@ -1081,47 +1833,85 @@ impl ToPixmapTaskSpec {
                 foreground,
             } => background.is_grid_perfect(ctx) && foreground.is_grid_perfect(ctx),
             UpscaleFromGridSize { .. } => true,
-            ToPixmapTaskSpec::None => {
-                debug_assert_unreachable("ToPixmapTaskSpec::None::is_grid_perfect()")
-            }
+            // A finer grid than GRID_SIZE would be blurred by rendering at GRID_SIZE, and so would
+            // a base that isn't grid-perfect itself
+            ToPixmapTaskSpec::OnGrid { base, grid_size } => {
+                GRID_SIZE.is_multiple_of(*grid_size) && base.is_grid_perfect(ctx)
+            }
+            // Rendering at GRID_SIZE would leave the detail out at every tile size, unless it's
+            // drawn at GRID_SIZE too
+            ToPixmapTaskSpec::DetailAtLeast {
+                base,
+                min_tile_size,
+            } => *min_tile_size <= *GRID_SIZE && base.is_grid_perfect(ctx),
+            // Whole pixels only move, so upscaling commutes with these
+            ToPixmapTaskSpec::Rotate { base, .. } | ToPixmapTaskSpec::Flip { base, .. } => {
+                base.is_grid_perfect(ctx)
+            }
+            ToPixmapTaskSpec::Remap { base, .. } | ToPixmapTaskSpec::ShiftColors { base, .. } => {
+                base.is_grid_perfect(ctx)
+            }
+            // Scaling by a factor that isn't a whole number drops different rows and columns at
+            // different sizes
+            ToPixmapTaskSpec::CropAndScale { .. } | ToPixmapTaskSpec::PlaceOnSheet { .. } => false,
         }
     }
 
-    /// Used in [TaskSpec::add_to] to deduplicate certain tasks that are redundant.
-    fn get_color_description_task(
+    /// Predicts the colors and alpha values of this image at `tile_size` in one pass, so that each
+    /// node only has one analysis task per size. Used in [TaskSpec::add_to] to deduplicate certain
+    /// tasks that are redundant.
+    pub(crate) fn get_analysis_task(
         &self,
         ctx: &mut TaskGraphBuildingContext,
-    ) -> BasicTask<ColorDescription> {
-        if let Some(desc) = ctx.pixmap_task_to_color_map.get(self) {
-            return (*desc).to_owned();
+        tile_size: u32,
+    ) -> BasicTask<PixmapAnalysis> {
+        let id = self.node_id();
+        if let Some(analysis) = ctx
+            .pixmap_task_to_analysis_map
+            .get(&tile_size)
+            .and_then(|map| map.get(&id))
+        {
+            return analysis.to_owned();
         }
-        let side_length = if *TILE_SIZE == GRID_SIZE || self.is_grid_perfect(ctx) {
-            GRID_SIZE
+        let side_length = if tile_size == *GRID_SIZE || self.is_grid_perfect(ctx) {
+            *GRID_SIZE
         } else {
-            *TILE_SIZE
+            tile_size
         };
         let mut pixels = side_length as usize * side_length as usize;
         #[allow(clippy::type_complexity)]
         let task: BoxFuture<SimpleArcow<ColorDescription>> = match self {
-            ToPixmapTaskSpec::None => {
-                debug_assert_unreachable("ToPixmapTaskSpec::None::get_color_description_task()")
-            }
-            ToPixmapTaskSpec::Animate { background, frames } => {
-                pixels *= frames.len();
-                let background_desc_task = background.get_color_description_task(ctx);
-                let mut frame_desc_join_set = JoinSet::new();
+            ToPixmapTaskSpec::Animate {
+                background,
+                frames,
+                layout,
+                ..
+            } => {
+                let frame_count = frames.len() as u32;
+                let (width, height) = layout.size(frame_count, side_length, side_length);
+                pixels = width as usize * height as usize;
+                let has_gaps = layout.has_gaps(frame_count);
+                let background_analysis_task = background.get_analysis_task(ctx, tile_size);
+                let mut frame_analysis_join_set = JoinSet::new();
                 (*frames)
                     .iter()
-                    .map(|frame| frame.get_color_description_task(ctx))
+                    .map(|frame| frame.get_analysis_task(ctx, tile_size))
                     .for_each(|task| {
-                        frame_desc_join_set.spawn(task);
+                        frame_analysis_join_set.spawn(task);
                     });
-                background_desc_task
+                background_analysis_task
                     .then(
-                        async move |background_desc: SimpleArcow<ColorDescription>| {
-                            let mut current_desc = background_desc.deref().to_owned();
-                            while let Some(Ok(frame_desc)) = frame_desc_join_set.join_next().await {
-                                current_desc = current_desc.put_adjacent(frame_desc.deref());
+                        async move |background_analysis: SimpleArcow<PixmapAnalysis>| {
+                            let mut current_desc = background_analysis.colors.to_owned();
+                            if has_gaps {
+                                current_desc = current_desc.put_adjacent(&SpecifiedColors(
+                                    Arcow::from_owned(vec![ComparableColor::TRANSPARENT]),
+                                ));
+                            }
+                            while let Some(Ok(frame_analysis)) =
+                                frame_analysis_join_set.join_next().await
+                            {
+                                current_desc = current_desc.put_adjacent(&frame_analysis.colors);
                             }
                             Arcow::from_owned(current_desc)
                         },
@@ -1142,8 +1932,16 @@ impl ToPixmapTaskSpec {
                 }))
                 .boxed()
             }
+            ToPixmapTaskSpec::FromRaster { .. } => {
+                ready(Arcow::from_borrowed(&RGBA_DESCRIPTION)).boxed()
+            }
+            ToPixmapTaskSpec::TextureOf { name } => ctx
+                .resolve_texture(name)
+                .get_analysis_task(ctx, tile_size)
+                .map(|analysis| Arcow::from_owned(analysis.colors.to_owned()))
+                .boxed(),
             ToPixmapTaskSpec::PaintAlphaChannel { color, base } => {
-                let base_task = base.get_possible_alpha_values(ctx);
+                let base_task = base.get_possible_alpha_values(ctx, tile_size);
                 let color = *color;
                 let alpha_array = ALPHA_MULTIPLICATION_TABLE[color.alpha() as usize];
                 base_task
@@ -1169,10 +1967,10 @@ impl ToPixmapTaskSpec {
                 foreground,
             } => {
                 let background = *background;
-                let fg_task = foreground.get_color_description_task(ctx);
+                let fg_task = foreground.get_analysis_task(ctx, tile_size);
                 fg_task
-                    .then(async move |fg: SimpleArcow<ColorDescription>| {
-                        Arcow::from_owned(fg.stack_on(
+                    .then(async move |fg: SimpleArcow<PixmapAnalysis>| {
+                        Arcow::from_owned(fg.colors.stack_on(
                             &SpecifiedColors(Arcow::from_owned(vec![background])),
                             pixels + 1,
                         ))
@@ -1183,68 +1981,162 @@ impl ToPixmapTaskSpec {
                 background,
                 foreground,
             } => {
-                let bg_task = background.get_color_description_task(ctx);
-                let fg_task = foreground.get_color_description_task(ctx);
+                let bg_task = background.get_analysis_task(ctx, tile_size);
+                let fg_task = foreground.get_analysis_task(ctx, tile_size);
                 async move {
-                    Arcow::from_owned(fg_task
-                        .await
-                        .stack_on(&*bg_task.await, pixels + 1))
-                }.boxed()
-            }
-            UpscaleFromGridSize { base } => Box::pin(base.get_color_description_task(ctx)),
-        };
+                    Arcow::from_owned(
+                        fg_task
+                            .await
+                            .colors
+                            .stack_on(&bg_task.await.colors, pixels + 1),
+                    )
+                }
+                .boxed()
+            }
+            UpscaleFromGridSize { base }
+            | ToPixmapTaskSpec::OnGrid { base, .. }
+            | ToPixmapTaskSpec::Rotate { base, .. }
+            | ToPixmapTaskSpec::Flip { base, .. } => base
+                .get_analysis_task(ctx, tile_size)
+                .map(|analysis| Arcow::from_owned(analysis.colors.to_owned()))
+                .boxed(),
+            ToPixmapTaskSpec::DetailAtLeast {
+                base,
+                min_tile_size,
+            } => {
+                if tile_size >= *min_tile_size {
+                    base.get_analysis_task(ctx, tile_size)
+                        .map(|analysis| Arcow::from_owned(analysis.colors.to_owned()))
+                        .boxed()
+                } else {
+                    ready(Arcow::from_owned(SpecifiedColors(Arcow::from_owned(vec![
+                        ComparableColor::TRANSPARENT,
+                    ]))))
+                    .boxed()
+                }
+            }
+            ToPixmapTaskSpec::Remap { base, mapping } => {
+                let base_task = base.get_analysis_task(ctx, tile_size);
+                let mapping = mapping.to_owned();
+                base_task
+                    .then(async move |base_analysis: SimpleArcow<PixmapAnalysis>| {
+                        Arcow::from_owned(match &base_analysis.colors {
+                            SpecifiedColors(colors) => {
+                                let mut mapped: Vec<ComparableColor> =
+                                    colors.iter().map(|color| mapping.recolor(*color)).collect();
+                                mapped.sort();
+                                mapped.dedup();
+                                SpecifiedColors(Arcow::from_owned(mapped))
+                            }
+                            // Any of the replacements may appear
+                            Rgb(_) => base_analysis.colors.put_adjacent(&SpecifiedColors(
+                                Arcow::from_owned(
+                                    mapping.pairs().iter().map(|(_, to)| *to).collect(),
+                                ),
+                            )),
+                        })
+                    })
+                    .boxed()
+            }
+            ToPixmapTaskSpec::ShiftColors { base, shift } => {
+                let shift = *shift;
+                base.get_analysis_task(ctx, tile_size)
+                    .map(move |base_analysis| {
+                        Arcow::from_owned(match &base_analysis.colors {
+                            SpecifiedColors(colors) => {
+                                let mut shifted: Vec<ComparableColor> =
+                                    colors.iter().map(|color| shift.apply(*color)).collect();
+                                shifted.sort();
+                                shifted.dedup();
+                                SpecifiedColors(Arcow::from_owned(shifted))
+                            }
+                            // Alpha doesn't change, so neither does the transparency
+                            Rgb(transparency) => Rgb(*transparency),
+                        })
+                    })
+                    .boxed()
+            }
+            ToPixmapTaskSpec::CropAndScale { base, to, .. } => {
+                let base_task = base.get_analysis_task(ctx, tile_size);
+                let covers_tile = *to == TileRect::FULL;
+                base_task
+                    .then(async move |base_analysis: SimpleArcow<PixmapAnalysis>| {
+                        Arcow::from_owned(if covers_tile {
+                            base_analysis.colors.to_owned()
+                        } else {
+                            base_analysis
+                                .colors
+                                .put_adjacent(&SpecifiedColors(Arcow::from_owned(vec![
+                                    ComparableColor::TRANSPARENT,
+                                ])))
+                        })
+                    })
+                    .boxed()
+            }
+            ToPixmapTaskSpec::PlaceOnSheet {
+                width,
+                height,
+                placements,
+            } => {
+                let texel_size = side_length as usize / TileRect::UNITS as usize;
+                pixels = *width as usize * *height as usize * texel_size * texel_size;
+                let layer_analysis_tasks: Vec<_> = placements
+                    .iter()
+                    .map(|(layer, _)| layer.get_analysis_task(ctx, tile_size))
+                    .collect();
+                async move {
+                    // Every layer may be drawn over any of the ones before it, or over the
+                    // transparent parts of the sheet
+                    let mut current_desc =
+                        SpecifiedColors(Arcow::from_owned(vec![ComparableColor::TRANSPARENT]));
+                    for layer_analysis_task in layer_analysis_tasks {
+                        let layer_analysis = layer_analysis_task.await;
+                        current_desc = current_desc.put_adjacent(
+                            &layer_analysis.colors.stack_on(&current_desc, pixels + 1),
+                        );
+                    }
+                    Arcow::from_owned(current_desc)
+                }
+                .boxed()
+            }
+        };
         let image_task = self.add_to(ctx, side_length);
         let wrapped_task = async move {
-            let mut uncapped = task.await;
-            uncapped.cap_indexed(pixels, image_task).await;
-            uncapped
+            let mut colors = task.await;
+            colors.cap_indexed(pixels, image_task).await;
+            Arcow::from_owned(colors.consume(|colors| PixmapAnalysis {
+                alphas: colors.possible_alphas(),
+                colors,
+            }))
         }
         .boxed()
         .shared();
-        ctx.pixmap_task_to_color_map
-            .insert(self.to_owned(), wrapped_task.to_owned());
+        ctx.pixmap_task_to_analysis_map
+            .entry(tile_size)
+            .or_default()
+            .insert(id, wrapped_task.to_owned());
         wrapped_task
     }
 
-    fn get_possible_alpha_values(&self, ctx: &mut TaskGraphBuildingContext) -> BasicTask<U8BitSet> {
-        if let Some(alphas) = ctx.pixmap_task_to_alpha_map.get(self) {
-            alphas.to_owned()
-        } else {
-            let color_task = self.get_color_description_task(ctx);
-            let task = color_task
-                .then(async move |colors: SimpleArcow<ColorDescription>| {
-                    Arcow::from_owned({
-                        match colors.transparency() {
-                            AlphaChannel => match &*colors {
-                                Rgb(_) => U8BitSet::all_u8s(),
-                                SpecifiedColors(colors) => {
-                                    if colors.len() <= BINARY_SEARCH_THRESHOLD {
-                                        colors.iter().map(|color| color.alpha()).collect()
-                                    } else {
-                                        ALL_U8S
-                                            .iter()
-                                            .copied()
-                                            .filter(|alpha| contains_alpha(colors, *alpha))
-                                            .collect()
-                                    }
-                                }
-                            },
-                            Binary => U8BitSet::from_iter([0, u8::MAX]),
-                            Opaque => U8BitSet::from_iter([u8::MAX]),
-                        }
-                    })
-                })
-                .boxed()
-                .shared();
-            ctx.pixmap_task_to_alpha_map
-                .insert(self.to_owned(), task.to_owned());
-            task
+    /// The [FrameTiming] of this image, if it's an animation; animations that are upscaled, drawn
+    /// on a grid or recolored are still animations.
+    pub fn frame_timing(&self) -> Option<FrameTiming> {
+        match self {
+            ToPixmapTaskSpec::Animate { timing, .. } => *timing,
+            UpscaleFromGridSize { base }
+            | ToPixmapTaskSpec::OnGrid { base, .. }
+            | ToPixmapTaskSpec::DetailAtLeast { base, .. }
+            | ToPixmapTaskSpec::Remap { base, .. }
+            | ToPixmapTaskSpec::ShiftColors { base, .. } => base.frame_timing(),
+            _ => None,
         }
     }
 
     pub fn alpha_and_color(&self) -> Option<(ToAlphaChannelTaskSpec, ComparableColor)> {
         match self {
             ToPixmapTaskSpec::Animate { .. } => None,
+            ToPixmapTaskSpec::FromRaster { .. } => None,
+            ToPixmapTaskSpec::TextureOf { .. } => None,
             ToPixmapTaskSpec::FromSvg { source } => {
                 if COLOR_SVGS.contains(&&**source) {
                     None
@@ -1257,8 +2149,20 @@ impl ToPixmapTaskSpec {
                     ))
                 }
             }
-            ToPixmapTaskSpec::PaintAlphaChannel { base, color } => Some((*base.to_owned(), *color)),
+            ToPixmapTaskSpec::PaintAlphaChannel { base, color } => {
+                Some(((**base).to_owned(), *color))
+            }
             ToPixmapTaskSpec::StackLayerOnColor { .. } => None,
+            ToPixmapTaskSpec::CropAndScale { .. } => None,
+            ToPixmapTaskSpec::PlaceOnSheet { .. } => None,
+            ToPixmapTaskSpec::OnGrid { .. } => None,
+            ToPixmapTaskSpec::DetailAtLeast { .. } => None,
+            ToPixmapTaskSpec::Rotate { .. } => None,
+            ToPixmapTaskSpec::Flip { .. } => None,
+            // Only colors that match exactly are replaced, so partly transparent pixels of a
+            // painted layer may not be
+            ToPixmapTaskSpec::Remap { .. } => None,
+            ToPixmapTaskSpec::ShiftColors { .. } => None,
             ToPixmapTaskSpec::StackLayerOnLayer {
                 background,
                 foreground,
@@ -1266,6 +2170,7 @@ impl ToPixmapTaskSpec {
                 if let Some((bg_alpha, bg_color)) = background.alpha_and_color()
                     && let Some((fg_alpha, fg_color)) = foreground.alpha_and_color()
                     && bg_color == fg_color
+                    && bg_color.alpha() == u8::MAX
                 {
                     Some((
                         StackAlphaOnAlpha {
@@ -1290,11 +2195,204 @@ impl ToPixmapTaskSpec {
                     None
                 }
             }
-            ToPixmapTaskSpec::None => {
-                debug_assert_unreachable("ToPixmapTaskSpec::None::alpha_and_color()")
+        }
+    }
+}
+
+impl ToPixmapTaskSpec {
+    /// Returns a copy of this spec with each pixmap it's made from replaced by `pixmap`'s result
+    /// and each alpha channel by `alpha`'s; only direct children are passed, so a transformation
+    /// recurses by calling itself from them. Everything else about the spec is kept.
+    pub fn map_children(
+        &self,
+        pixmap: &impl Fn(&ToPixmapTaskSpec) -> ToPixmapTaskSpec,
+        alpha: &impl Fn(&ToAlphaChannelTaskSpec) -> ToAlphaChannelTaskSpec,
+    ) -> ToPixmapTaskSpec {
+        match self {
+            ToPixmapTaskSpec::Animate {
+                background,
+                frames,
+                layout,
+                timing,
+            } => ToPixmapTaskSpec::Animate {
+                background: pixmap(background).into(),
+                frames: frames.iter().map(pixmap).collect(),
+                layout: *layout,
+                timing: *timing,
+            },
+            ToPixmapTaskSpec::PaintAlphaChannel { base, color } => {
+                ToPixmapTaskSpec::PaintAlphaChannel {
+                    base: alpha(base).into(),
+                    color: *color,
+                }
+            }
+            ToPixmapTaskSpec::StackLayerOnColor {
+                background,
+                foreground,
+            } => ToPixmapTaskSpec::StackLayerOnColor {
+                background: *background,
+                foreground: pixmap(foreground).into(),
+            },
+            ToPixmapTaskSpec::StackLayerOnLayer {
+                background,
+                foreground,
+            } => ToPixmapTaskSpec::StackLayerOnLayer {
+                background: pixmap(background).into(),
+                foreground: pixmap(foreground).into(),
+            },
+            UpscaleFromGridSize { base } => UpscaleFromGridSize {
+                base: pixmap(base).into(),
+            },
+            ToPixmapTaskSpec::OnGrid { base, grid_size } => ToPixmapTaskSpec::OnGrid {
+                base: pixmap(base).into(),
+                grid_size: *grid_size,
+            },
+            ToPixmapTaskSpec::DetailAtLeast {
+                base,
+                min_tile_size,
+            } => ToPixmapTaskSpec::DetailAtLeast {
+                base: pixmap(base).into(),
+                min_tile_size: *min_tile_size,
+            },
+            ToPixmapTaskSpec::CropAndScale { base, from, to } => ToPixmapTaskSpec::CropAndScale {
+                base: pixmap(base).into(),
+                from: *from,
+                to: *to,
+            },
+            ToPixmapTaskSpec::Rotate {
+                base,
+                quarter_turns,
+            } => ToPixmapTaskSpec::Rotate {
+                base: pixmap(base).into(),
+                quarter_turns: *quarter_turns,
+            },
+            ToPixmapTaskSpec::Flip { base, axis } => ToPixmapTaskSpec::Flip {
+                base: pixmap(base).into(),
+                axis: *axis,
+            },
+            ToPixmapTaskSpec::Remap { base, mapping } => ToPixmapTaskSpec::Remap {
+                base: pixmap(base).into(),
+                mapping: mapping.to_owned(),
+            },
+            ToPixmapTaskSpec::ShiftColors { base, shift } => ToPixmapTaskSpec::ShiftColors {
+                base: pixmap(base).into(),
+                shift: *shift,
+            },
+            ToPixmapTaskSpec::PlaceOnSheet {
+                width,
+                height,
+                placements,
+            } => ToPixmapTaskSpec::PlaceOnSheet {
+                width: *width,
+                height: *height,
+                placements: placements
+                    .iter()
+                    .map(|(layer, rect)| (pixmap(layer), *rect))
+                    .collect(),
+            },
+            ToPixmapTaskSpec::FromSvg { .. }
+            | ToPixmapTaskSpec::FromRaster { .. }
+            | ToPixmapTaskSpec::TextureOf { .. } => self.to_owned(),
+        }
+    }
+
+    /// Returns a copy of this spec with every pixmap in it, including itself, replaced by `f`'s
+    /// result when that's [Some]. A replacement isn't rewritten any further.
+    pub fn rewrite(
+        &self,
+        f: &impl Fn(&ToPixmapTaskSpec) -> Option<ToPixmapTaskSpec>,
+    ) -> ToPixmapTaskSpec {
+        f(self).unwrap_or_else(|| {
+            self.map_children(&|child| child.rewrite(f), &|child| child.rewrite(f))
+        })
+    }
+
+    /// Returns a copy of this spec with `f` applied to every color it paints or stacks on. Colors
+    /// that come from SVGs and rasters aren't part of the spec, so they don't change.
+    pub fn map_colors<F: Fn(ComparableColor) -> ComparableColor>(&self, f: &F) -> ToPixmapTaskSpec {
+        match self.map_children(&|child| child.map_colors(f), &|child| child.map_colors(f)) {
+            ToPixmapTaskSpec::PaintAlphaChannel { base, color } => {
+                ToPixmapTaskSpec::PaintAlphaChannel {
+                    base,
+                    color: f(color),
+                }
+            }
+            ToPixmapTaskSpec::StackLayerOnColor {
+                background,
+                foreground,
+            } => ToPixmapTaskSpec::StackLayerOnColor {
+                background: f(background),
+                foreground,
+            },
+            ToPixmapTaskSpec::Remap { base, mapping } => ToPixmapTaskSpec::Remap {
+                base,
+                mapping: mapping.map_colors(f),
+            },
+            other => other,
+        }
+    }
+}
+
+impl ToAlphaChannelTaskSpec {
+    /// Like [ToPixmapTaskSpec::map_children], for the pixmaps and alpha channels this alpha
+    /// channel is made from.
+    pub fn map_children(
+        &self,
+        pixmap: &impl Fn(&ToPixmapTaskSpec) -> ToPixmapTaskSpec,
+        alpha: &impl Fn(&ToAlphaChannelTaskSpec) -> ToAlphaChannelTaskSpec,
+    ) -> ToAlphaChannelTaskSpec {
+        match self {
+            ToAlphaChannelTaskSpec::MakeSemitransparent {
+                base,
+                alpha: opacity,
+            } => ToAlphaChannelTaskSpec::MakeSemitransparent {
+                base: alpha(base).into(),
+                alpha: *opacity,
+            },
+            ToAlphaChannelTaskSpec::FromPixmap { base } => {
+                ToAlphaChannelTaskSpec::FromPixmap { base: pixmap(base) }
+            }
+            StackAlphaOnAlpha {
+                background,
+                foreground,
+            } => StackAlphaOnAlpha {
+                background: alpha(background).into(),
+                foreground: alpha(foreground).into(),
+            },
+            ToAlphaChannelTaskSpec::StackAlphaOnBackground {
+                background,
+                foreground,
+            } => ToAlphaChannelTaskSpec::StackAlphaOnBackground {
+                background: *background,
+                foreground: alpha(foreground).into(),
+            },
+            ToAlphaChannelTaskSpec::UpscaleFromGridSize { base } => {
+                ToAlphaChannelTaskSpec::UpscaleFromGridSize {
+                    base: alpha(base).into(),
+                }
             }
+            ToAlphaChannelTaskSpec::Dither { base, coverage } => ToAlphaChannelTaskSpec::Dither {
+                base: alpha(base).into(),
+                coverage: *coverage,
+            },
         }
     }
+
+    /// Applies [ToPixmapTaskSpec::rewrite] to every pixmap this alpha channel comes from.
+    pub fn rewrite(
+        &self,
+        f: &impl Fn(&ToPixmapTaskSpec) -> Option<ToPixmapTaskSpec>,
+    ) -> ToAlphaChannelTaskSpec {
+        self.map_children(&|child| child.rewrite(f), &|child| child.rewrite(f))
+    }
+
+    /// Applies [ToPixmapTaskSpec::map_colors] to every pixmap this alpha channel comes from.
+    pub fn map_colors<F: Fn(ComparableColor) -> ComparableColor>(
+        &self,
+        f: &F,
+    ) -> ToAlphaChannelTaskSpec {
+        self.map_children(&|child| child.map_colors(f), &|child| child.map_colors(f))
+    }
 }
 
 impl From<ToPixmapTaskSpec> for ToAlphaChannelTaskSpec {
@@ -1305,37 +2403,190 @@ impl From<ToPixmapTaskSpec> for ToAlphaChannelTaskSpec {
 
 pub type BasicTask<T> = Shared<BoxFuture<'static, SimpleArcow<T>>>;
 
+/// Which of the packs built in one run an output belongs to. Outputs of different packs share
+/// paths, so whatever is recorded about an output to check or report on later is kept per pack.
+#[derive(Copy, Clone, Debug, Default, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct PackId {
+    pub tile_size: u32,
+    /// Whether it's the pack that `--high-contrast` builds alongside the main one.
+    pub high_contrast: bool,
+}
+
+impl Display for PackId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.tile_size, self.tile_size)?;
+        if self.high_contrast {
+            f.write_str("-high-contrast")?;
+        }
+        Ok(())
+    }
+}
+
+/// Maps from task specs are keyed by tile size and [NodeId], rather than by the specs themselves.
 pub struct TaskGraphBuildingContext {
-    pixmap_task_to_future_map:
-        HashMap<u32, HashMap<ToPixmapTaskSpec, BasicTask<MaybeFromPool<Pixmap>>>>,
-    alpha_task_to_future_map:
-        HashMap<u32, HashMap<ToAlphaChannelTaskSpec, BasicTask<MaybeFromPool<Mask>>>>,
+    pixmap_task_to_future_map: HashMap<u32, HashMap<NodeId, BasicTask<MaybeFromPool<Pixmap>>>>,
+    alpha_task_to_future_map: HashMap<u32, HashMap<NodeId, BasicTask<MaybeFromPool<Mask>>>>,
     pub output_task_to_future_map: HashMap<FileOutputTaskSpec, BasicTask<()>>,
-    pixmap_task_to_color_map: HashMap<ToPixmapTaskSpec, BasicTask<ColorDescription>>,
-    alpha_task_to_alpha_map: HashMap<ToAlphaChannelTaskSpec, BasicTask<U8BitSet>>,
-    pixmap_task_to_alpha_map: HashMap<ToPixmapTaskSpec, BasicTask<U8BitSet>>,
-    zip_writer: Arc<Mutex<ZipWriter<ZipBufferRaw>>>
+    pixmap_task_to_analysis_map: HashMap<u32, HashMap<NodeId, BasicTask<PixmapAnalysis>>>,
+    alpha_task_to_alpha_map: HashMap<u32, HashMap<NodeId, BasicTask<U8BitSet>>>,
+    pub zip_writer: Arc<Mutex<ZipWriter<ZipBufferRaw>>>,
+    /// If set, output files are written here as loose files instead of to [Self::zip_writer].
+    pub output_dir: Option<Arc<DirectoryOutput>>,
+    /// If set along with [Self::zip_writer], each output file is also written here as soon as it's
+    /// finished, so that a game pointed at this directory can reload textures during the build.
+    pub mirror_dir: Option<Arc<DirectoryOutput>>,
+    /// If set, SVGs that would be rendered at a smaller size that divides this one are rendered at
+    /// this size instead and then downscaled, so that every size in the graph shares one render.
+    /// Grid-perfect SVGs are exempt, since they're rendered at [GRID_SIZE] and upscaled anyway.
+    pub svg_render_size: Option<u32>,
+    /// What each [ToPixmapTaskSpec::TextureOf] name refers to; see [Self::add_texture_names].
+    texture_names: HashMap<Box<str>, ToPixmapTaskSpec>,
+    /// The pack that output tasks added from now on belong to. Set along with
+    /// [Self::start_another_pack].
+    pub pack: PackId,
+}
+
+impl Default for TaskGraphBuildingContext {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TaskGraphBuildingContext {
-    pub(crate) fn new() -> Self {
+    pub fn new() -> Self {
         TaskGraphBuildingContext {
             pixmap_task_to_future_map: HashMap::new(),
             alpha_task_to_future_map: HashMap::new(),
             output_task_to_future_map: HashMap::new(),
-            pixmap_task_to_color_map: HashMap::new(),
+            pixmap_task_to_analysis_map: HashMap::new(),
             alpha_task_to_alpha_map: HashMap::new(),
-            pixmap_task_to_alpha_map: HashMap::new(),
-            zip_writer: Arc::new(Mutex::new(ZipWriter::new(ZipBufferRaw::new(vec![]))))
+            zip_writer: Arc::new(Mutex::new(ZipWriter::new(ZipBufferRaw::new(vec![])))),
+            output_dir: None,
+            mirror_dir: None,
+            svg_render_size: None,
+            texture_names: HashMap::new(),
+            pack: PackId::default(),
+        }
+    }
+
+    /// Where output files go: [Self::output_dir] if it's set, or else [Self::zip_writer] and
+    /// [Self::mirror_dir] if that's set.
+    pub fn output_sinks(&self) -> Vec<Arc<dyn OutputSink>> {
+        match &self.output_dir {
+            Some(output_dir) => vec![output_dir.clone()],
+            None => {
+                let mut sinks: Vec<Arc<dyn OutputSink>> = Vec::with_capacity(2);
+                if let Some(mirror_dir) = &self.mirror_dir {
+                    sinks.push(mirror_dir.clone());
+                }
+                sinks.push(Arc::new(ZipOutput::new(self.zip_writer.clone())));
+                sinks
+            }
+        }
+    }
+
+    /// Starts another pack built from the same graph, such as one for another tile size. Output
+    /// tasks added from now on write to a new [Self::zip_writer], but images that were already
+    /// added at the size they're needed at, such as grid-perfect ones, aren't rendered again.
+    pub fn start_another_pack(&mut self) {
+        self.zip_writer = Arc::new(Mutex::new(ZipWriter::new(ZipBufferRaw::new(vec![]))));
+        self.output_task_to_future_map.clear();
+    }
+
+    /// Makes the image each of these tasks writes available to [ToPixmapTaskSpec::TextureOf] under
+    /// its destination name. Must be called with every output task before any is added.
+    pub fn add_texture_names(&mut self, tasks: &[FileOutputTaskSpec]) {
+        fn source_image(task: &FileOutputTaskSpec) -> &ToPixmapTaskSpec {
+            match task {
+                FileOutputTaskSpec::PngOutput { base, .. } => base,
+                FileOutputTaskSpec::Copy { original, .. } => source_image(original),
+            }
+        }
+        for task in tasks {
+            match task {
+                FileOutputTaskSpec::PngOutput {
+                    base,
+                    destination_name,
+                    ..
+                } => {
+                    self.texture_names
+                        .insert((**destination_name).into(), base.to_owned());
+                }
+                FileOutputTaskSpec::Copy {
+                    original,
+                    link_names,
+                } => {
+                    self.add_texture_names(std::slice::from_ref(&**original));
+                    for name in link_names.iter() {
+                        self.texture_names
+                            .insert((**name).into(), source_image(original).to_owned());
+                    }
+                }
+            }
         }
     }
 
+    /// Whether a texture with this name was passed to [Self::add_texture_names].
+    pub fn has_texture(&self, name: &str) -> bool {
+        self.texture_names.contains_key(name)
+    }
+
+    pub(crate) fn resolve_texture(&self, name: &str) -> ToPixmapTaskSpec {
+        self.texture_names
+            .get(name)
+            .unwrap_or_else(|| {
+                panic!(
+                    "texture_of({}) doesn't match any texture being built; is that material \
+                    missing, disabled by the config, or not in the target version?",
+                    name
+                )
+            })
+            .to_owned()
+    }
+
+    /// The first [ToPixmapTaskSpec::TextureOf] name among `ingredients`, or used by the textures
+    /// they name, that isn't a texture being built.
+    pub(crate) fn missing_texture(&self, ingredients: Ingredients) -> Option<String> {
+        ingredients
+            .textures
+            .into_iter()
+            .find_map(|name| match self.texture_names.get(&*name) {
+                None => Some(name),
+                Some(texture) => self.missing_texture(Ingredients::of_pixmap(texture)),
+            })
+    }
+
+    /// Drops the tasks whose images use [ToPixmapTaskSpec::TextureOf] with a texture that isn't
+    /// being built, such as one in a group that the config disables, since [Self::resolve_texture]
+    /// would panic on it. Logs an error for each. Call after [Self::add_texture_names].
+    pub fn without_missing_textures(
+        &self,
+        tasks: Vec<FileOutputTaskSpec>,
+    ) -> Vec<FileOutputTaskSpec> {
+        tasks
+            .into_iter()
+            .filter(|task| match self.missing_texture(Ingredients::of(task)) {
+                Some(name) => {
+                    error!(
+                        "Skipping {} because it uses texture_of({}), which isn't being built; is \
+                        that material disabled by the config, or not in the target version?",
+                        task, name
+                    );
+                    false
+                }
+                None => true,
+            })
+            .collect()
+    }
+
     pub fn get_pixmap_future(
         &self,
         tile_size: u32,
         task: &ToPixmapTaskSpec,
     ) -> Option<&BasicTask<MaybeFromPool<Pixmap>>> {
-        self.pixmap_task_to_future_map.get(&tile_size)?.get(task)
+        self.pixmap_task_to_future_map
+            .get(&tile_size)?
+            .get(&task.node_id())
     }
 
     pub fn get_alpha_future(
@@ -1343,7 +2594,31 @@ impl TaskGraphBuildingContext {
         tile_size: u32,
         task: &ToAlphaChannelTaskSpec,
     ) -> Option<&BasicTask<MaybeFromPool<Mask>>> {
-        self.alpha_task_to_future_map.get(&tile_size)?.get(task)
+        self.alpha_task_to_future_map
+            .get(&tile_size)?
+            .get(&task.node_id())
+    }
+
+    /// The tile sizes that `task` has been added to the graph at, in ascending order.
+    pub fn pixmap_tile_sizes(&self, task: &ToPixmapTaskSpec) -> Vec<u32> {
+        let id = task.node_id();
+        self.pixmap_task_to_future_map
+            .iter()
+            .filter(|(_, map_for_tile_size)| map_for_tile_size.contains_key(&id))
+            .map(|(tile_size, _)| *tile_size)
+            .sorted()
+            .collect()
+    }
+
+    /// The tile sizes that `task` has been added to the graph at, in ascending order.
+    pub fn alpha_tile_sizes(&self, task: &ToAlphaChannelTaskSpec) -> Vec<u32> {
+        let id = task.node_id();
+        self.alpha_task_to_future_map
+            .iter()
+            .filter(|(_, map_for_tile_size)| map_for_tile_size.contains_key(&id))
+            .map(|(tile_size, _)| *tile_size)
+            .sorted()
+            .collect()
     }
 
     pub fn insert_pixmap_future(
@@ -1352,13 +2627,14 @@ impl TaskGraphBuildingContext {
         task: ToPixmapTaskSpec,
         value: BasicTask<MaybeFromPool<Pixmap>>,
     ) {
+        let id = Interned::new(task).id();
         match self.pixmap_task_to_future_map.get_mut(&tile_size) {
             Some(map_for_tile_size) => {
-                map_for_tile_size.insert(task, value);
+                map_for_tile_size.insert(id, value);
             }
             None => {
                 let mut new_map = HashMap::new();
-                new_map.insert(task, value);
+                new_map.insert(id, value);
                 self.pixmap_task_to_future_map.insert(tile_size, new_map);
             }
         }
@@ -1370,13 +2646,14 @@ impl TaskGraphBuildingContext {
         task: ToAlphaChannelTaskSpec,
         value: BasicTask<MaybeFromPool<Mask>>,
     ) {
+        let id = Interned::new(task).id();
         match self.alpha_task_to_future_map.get_mut(&tile_size) {
             Some(map_for_tile_size) => {
-                map_for_tile_size.insert(task, value);
+                map_for_tile_size.insert(id, value);
             }
             None => {
                 let mut new_map = HashMap::new();
-                new_map.insert(task, value);
+                new_map.insert(id, value);
                 self.alpha_task_to_future_map.insert(tile_size, new_map);
             }
         }
@@ -1384,8 +2661,11 @@ impl TaskGraphBuildingContext {
 }
 
 pub const SVG_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/svg");
+pub const RASTER_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/raster");
 pub const METADATA_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/metadata");
 
+/// Where textures in the `minecraft` namespace go with the default
+/// [crate::image_tasks::output_path::PATH_TEMPLATE].
 pub const ASSET_DIR: &str = "assets/minecraft/textures/";
 
 pub fn from_svg_task<T: Into<Name>>(name: T) -> ToPixmapTaskSpec {
@@ -1394,13 +2674,183 @@ pub fn from_svg_task<T: Into<Name>>(name: T) -> ToPixmapTaskSpec {
     }
 }
 
+pub fn from_raster_task<T: Into<Name>>(name: T) -> ToPixmapTaskSpec {
+    ToPixmapTaskSpec::FromRaster {
+        source: name.into(),
+    }
+}
+
+pub fn texture_of<T: Into<Name>>(name: T) -> ToPixmapTaskSpec {
+    ToPixmapTaskSpec::TextureOf { name: name.into() }
+}
+
+pub fn crop_task(base: ToPixmapTaskSpec, from: TileRect, to: TileRect) -> ToPixmapTaskSpec {
+    ToPixmapTaskSpec::CropAndScale {
+        base: base.into(),
+        from,
+        to,
+    }
+}
+
+/// Turns `base` clockwise by `quarter_turns` right angles, so that a material can reuse an SVG
+/// facing another way. Turns of the same layer are combined, and whole turns are left out, so
+/// that equal images are the same node.
+pub fn rotate_task(base: ToPixmapTaskSpec, quarter_turns: u8) -> ToPixmapTaskSpec {
+    let (base, quarter_turns) = match base {
+        ToPixmapTaskSpec::Rotate {
+            base: base_of_base,
+            quarter_turns: base_turns,
+        } => ((*base_of_base).to_owned(), quarter_turns % 4 + base_turns),
+        base => (base, quarter_turns),
+    };
+    match quarter_turns % 4 {
+        0 => base,
+        quarter_turns => ToPixmapTaskSpec::Rotate {
+            base: base.into(),
+            quarter_turns,
+        },
+    }
+}
+
+/// Mirrors `base` across `axis`, so that a material can reuse an SVG instead of a mirrored copy.
+/// Flipping twice across the same axis is left out, and across both is a half turn, so that equal
+/// images are the same node.
+pub fn flip_task(base: ToPixmapTaskSpec, axis: Axis) -> ToPixmapTaskSpec {
+    match base {
+        ToPixmapTaskSpec::Flip {
+            base: base_of_base,
+            axis: base_axis,
+        } => {
+            if base_axis == axis {
+                (*base_of_base).to_owned()
+            } else {
+                rotate_task((*base_of_base).to_owned(), 2)
+            }
+        }
+        base => ToPixmapTaskSpec::Flip {
+            base: base.into(),
+            axis,
+        },
+    }
+}
+
+/// Replaces the colors of `base` according to `mapping`, so that one rendered master texture can
+/// be recolored for each variant instead of stacking every layer again. Remapping a remapped image
+/// combines the maps, and an empty map is left out.
+pub fn remap_task(base: ToPixmapTaskSpec, mapping: PaletteMap) -> ToPixmapTaskSpec {
+    let (base, mapping) = match base {
+        ToPixmapTaskSpec::Remap {
+            base: base_of_base,
+            mapping: first,
+        } => ((*base_of_base).to_owned(), first.then(&mapping)),
+        base => (base, mapping),
+    };
+    if mapping.is_empty() {
+        base
+    } else {
+        ToPixmapTaskSpec::Remap {
+            base: base.into(),
+            mapping,
+        }
+    }
+}
+
+/// Shifts the colors of `base` by `shift`, so that variants that differ only in color, such as an
+/// animal's coats, can share one rendered template. A shift that changes nothing is left out.
+pub fn shift_colors_task(base: ToPixmapTaskSpec, shift: ColorShift) -> ToPixmapTaskSpec {
+    if shift.is_none() {
+        base
+    } else {
+        ToPixmapTaskSpec::ShiftColors {
+            base: base.into(),
+            shift,
+        }
+    }
+}
+
+/// Declares that `base` is drawn on a grid of `grid_size` texels per side, such as 64 for a
+/// material with finer detail than [GRID_SIZE] allows.
+pub fn on_grid(grid_size: u32, base: ToPixmapTaskSpec) -> ToPixmapTaskSpec {
+    ToPixmapTaskSpec::OnGrid {
+        base: base.into(),
+        grid_size,
+    }
+}
+
+pub fn detail_at_least(min_tile_size: u32, base: ToPixmapTaskSpec) -> ToPixmapTaskSpec {
+    ToPixmapTaskSpec::DetailAtLeast {
+        base: base.into(),
+        min_tile_size,
+    }
+}
+
+pub fn sheet_task<T: IntoIterator<Item = (ToPixmapTaskSpec, SheetRect)>>(
+    width: u16,
+    height: u16,
+    placements: T,
+) -> ToPixmapTaskSpec {
+    ToPixmapTaskSpec::PlaceOnSheet {
+        width,
+        height,
+        placements: placements.into_iter().collect(),
+    }
+}
+
 pub fn svg_alpha_task<T: Into<Name>>(name: T) -> ToAlphaChannelTaskSpec {
     ToAlphaChannelTaskSpec::FromPixmap {
         base: from_svg_task(name),
     }
 }
 
+// Each of these rewrite rules can be turned off, to find out which one is responsible when a
+// texture comes out wrong.
+
+/// Painting a layer that's already painted an opaque color just repaints its alpha channel. Turned
+/// off by `--no-paint-collapse`.
+static PAINT_COLLAPSE: Lazy<bool> = Lazy::new(|| !flag_present("no-paint-collapse"));
+
+/// Stacking two layers that are both upscaled from [GRID_SIZE] upscales their stack instead.
+/// Turned off by `--no-upscale-merging`.
+static UPSCALE_MERGING: Lazy<bool> = Lazy::new(|| !flag_present("no-upscale-merging"));
+
+/// Stacking two layers painted the same opaque color paints the stack of their alpha channels
+/// instead, and [stack_alpha] flattens and sorts its layers. Turned off by
+/// `--no-alpha-restacking`.
+static ALPHA_RESTACKING: Lazy<bool> = Lazy::new(|| !flag_present("no-alpha-restacking"));
+
+/// Results of [stack], [paint_task] and [stack_alpha] by the IDs of their inputs. Materials build
+/// the same subtrees many times over, so this way each one is only simplified once, and every
+/// copy of it shares the same interned children.
+static STACK_CACHE: Lazy<Mutex<HashMap<(NodeId, NodeId), Interned<ToPixmapTaskSpec>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static PAINT_CACHE: Lazy<Mutex<HashMap<(NodeId, ComparableColor), Interned<ToPixmapTaskSpec>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+static STACK_ALPHA_CACHE: Lazy<Mutex<HashMap<Box<[NodeId]>, Interned<ToAlphaChannelTaskSpec>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Returns the cached value for `key`, or computes and caches it. The lock isn't held while
+/// computing, since `compute` may build other subtrees.
+fn hash_cons<K: Eq + Hash, T: Internable + Clone>(
+    cache: &Mutex<HashMap<K, Interned<T>>>,
+    key: K,
+    compute: impl FnOnce() -> T,
+) -> T {
+    if let Some(cached) = cache.lock().get(&key) {
+        return (**cached).to_owned();
+    }
+    let value = Interned::new(compute());
+    let result = (*value).to_owned();
+    cache.lock().insert(key, value);
+    result
+}
+
 pub fn paint_task(base: ToAlphaChannelTaskSpec, color: ComparableColor) -> ToPixmapTaskSpec {
+    hash_cons(&PAINT_CACHE, (base.node_id(), color), || {
+        paint_task_uncached(base, color)
+    })
+}
+
+fn paint_task_uncached(base: ToAlphaChannelTaskSpec, color: ComparableColor) -> ToPixmapTaskSpec {
     if let ToAlphaChannelTaskSpec::FromPixmap {
         base: ref base_base,
     } = base
@@ -1416,16 +2866,16 @@ pub fn paint_task(base: ToAlphaChannelTaskSpec, color: ComparableColor) -> ToPix
                 base: base_base_base,
                 color: base_color,
             } => {
-                if base_color.alpha() == u8::MAX {
+                if *PAINT_COLLAPSE && base_color.alpha() == u8::MAX {
                     info!("Simplified {}@{} -> {}", base, color, base_base_base);
-                    return paint_task(*base_base_base.to_owned(), color);
+                    return paint_task((**base_base_base).to_owned(), color);
                 }
             }
             _ => {}
         }
     }
     ToPixmapTaskSpec::PaintAlphaChannel {
-        base: Box::new(base),
+        base: base.into(),
         color,
     }
 }
@@ -1440,9 +2890,10 @@ where
         from_svg_task(name)
     } else {
         ToPixmapTaskSpec::PaintAlphaChannel {
-            base: Box::new(ToAlphaChannelTaskSpec::FromPixmap {
+            base: ToAlphaChannelTaskSpec::FromPixmap {
                 base: from_svg_task(name),
-            }),
+            }
+            .into(),
             color,
         }
     }
@@ -1452,6 +2903,323 @@ pub fn out_task<T: Into<Name>>(name: T, base: ToPixmapTaskSpec) -> FileOutputTas
     FileOutputTaskSpec::PngOutput {
         base,
         destination_name: name.into(),
+        require_gray: false,
+        render_layer: None,
+    }
+}
+
+/// Emits the output of `original` under each of the given names as well.
+pub fn alias_task<T: Into<Name>, U: IntoIterator<Item = T>>(
+    original: FileOutputTaskSpec,
+    names: U,
+) -> FileOutputTaskSpec {
+    let link_names: Box<[Name]> = names.into_iter().map(T::into).collect();
+    debug_assert!(
+        !link_names.is_empty(),
+        "Alias task for {} has no names",
+        original
+    );
+    FileOutputTaskSpec::Copy {
+        original: Box::new(original),
+        link_names,
+    }
+}
+
+/// Returns a copy of `task` under the older names of its output, as given by [legacy_names], if it
+/// has any.
+pub fn legacy_name_alias(task: &FileOutputTaskSpec) -> Option<FileOutputTaskSpec> {
+    let name = match task {
+        FileOutputTaskSpec::PngOutput {
+            destination_name, ..
+        } => destination_name,
+        FileOutputTaskSpec::Copy { link_names, .. } => &link_names[0],
+    };
+    let legacy_names = legacy_names(name);
+    if legacy_names.is_empty() {
+        None
+    } else {
+        // Give the namespace explicitly, or else texture_path would map the old name to the new
+        // one.
+        Some(alias_task(
+            task.to_owned(),
+            legacy_names
+                .into_iter()
+                .map(|name| format!("minecraft:{}", name)),
+        ))
+    }
+}
+
+#[test]
+fn test_legacy_name_alias() {
+    let alias =
+        legacy_name_alias(&out_task("block/short_grass", from_svg_task("borderSolid"))).unwrap();
+    let FileOutputTaskSpec::Copy { link_names, .. } = alias else {
+        panic!("Not an alias: {}", alias);
+    };
+    assert_eq!(
+        link_names
+            .iter()
+            .map(|name| texture_path(name))
+            .collect::<Vec<_>>(),
+        ["assets/minecraft/textures/block/grass.png".into()]
+    );
+    assert!(legacy_name_alias(&out_task("block/stone", from_svg_task("borderSolid"))).is_none());
+}
+
+#[test]
+fn test_legacy_name_aliases_have_distinct_paths() {
+    use crate::materials::ALL_MATERIALS;
+    use crate::texture_base::material::Material;
+    use std::collections::HashSet;
+
+    let mut out_tasks = ALL_MATERIALS.get_output_tasks().into_vec();
+    let legacy_aliases: Vec<FileOutputTaskSpec> =
+        out_tasks.iter().flat_map(legacy_name_alias).collect();
+    assert!(!legacy_aliases.is_empty());
+    out_tasks.extend(legacy_aliases);
+    let mut paths = HashSet::new();
+    for task in out_tasks {
+        let task_paths = match &task {
+            FileOutputTaskSpec::PngOutput { .. } => vec![task.get_path()],
+            FileOutputTaskSpec::Copy { link_names, .. } => {
+                link_names.iter().map(|name| texture_path(name)).collect()
+            }
+        };
+        for path in task_paths {
+            assert!(
+                paths.insert(path.to_owned()),
+                "Duplicate output path: {}",
+                path
+            );
+        }
+    }
+}
+
+#[test]
+fn test_hash_consing() {
+    let dots = paint_svg_task("dots0", ComparableColor::WHITE);
+    let border = paint_svg_task("borderSolid", ComparableColor::BLACK);
+    let first = stack(dots.to_owned(), border.to_owned());
+    let second = stack(dots.to_owned(), border.to_owned());
+    assert_eq!(first.node_id(), second.node_id());
+    assert_ne!(first.node_id(), stack(border, dots).node_id());
+
+    let alpha = |name| ToAlphaChannelTaskSpec::FromPixmap {
+        base: from_svg_task(name),
+    };
+    assert_eq!(
+        stack_alpha(vec![alpha("dots0"), alpha("streaks")]).node_id(),
+        stack_alpha(vec![alpha("streaks"), alpha("dots0")]).node_id()
+    );
+    assert_eq!(
+        paint_task(alpha("dots0"), ComparableColor::RED).node_id(),
+        paint_task(alpha("dots0"), ComparableColor::RED).node_id()
+    );
+}
+
+#[test]
+fn test_texture_of() {
+    let original = out_task("block/stone", from_svg_task("borderSolid"));
+    let mut ctx = TaskGraphBuildingContext::new();
+    ctx.add_texture_names(&[alias_task(original, ["block/smooth_stone"])]);
+    let texture = texture_of("block/smooth_stone");
+    assert!(texture.is_grid_perfect(&mut ctx));
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let _guard = runtime.enter();
+    let original_future = from_svg_task("borderSolid").add_to(&mut ctx, *GRID_SIZE);
+    assert!(texture
+        .add_to(&mut ctx, *GRID_SIZE)
+        .ptr_eq(&original_future));
+    let missing = std::panic::catch_unwind(|| {
+        texture_of("block/dirt").is_grid_perfect(&mut TaskGraphBuildingContext::new())
+    });
+    assert!(missing.is_err());
+
+    // Tasks that use a missing texture, directly or through another texture_of(), are dropped
+    let stone = alias_task(
+        out_task("block/stone", from_svg_task("borderSolid")),
+        ["block/smooth_stone"],
+    );
+    let uses_stone = out_task("block/stone_bricks", texture_of("block/smooth_stone"));
+    let uses_dirt = out_task("block/coarse_dirt", texture_of("block/dirt"));
+    let uses_coarse_dirt = out_task("block/rooted_dirt", texture_of("block/coarse_dirt"));
+    let tasks = vec![stone, uses_stone, uses_dirt, uses_coarse_dirt];
+    let mut ctx = TaskGraphBuildingContext::new();
+    ctx.add_texture_names(&tasks);
+    assert_eq!(ctx.without_missing_textures(tasks.clone()), tasks[..2]);
+}
+
+#[test]
+fn test_analysis() {
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let _guard = runtime.enter();
+    let mut ctx = TaskGraphBuildingContext::new();
+    let painted = paint_svg_task("borderSolid", ComparableColor::STONE);
+    let analysis = runtime.block_on(painted.get_analysis_task(&mut ctx, 32));
+    assert_eq!(analysis.colors.transparency(), Binary);
+    assert_eq!(analysis.alphas, U8BitSet::from_iter([0, u8::MAX]));
+    assert!(painted
+        .get_analysis_task(&mut ctx, 32)
+        .ptr_eq(&ctx.pixmap_task_to_analysis_map[&32][&painted.node_id()]));
+    // Each size is analysed separately, since images that aren't grid-perfect can have more colors
+    // at larger sizes
+    assert!(!painted
+        .get_analysis_task(&mut ctx, 64)
+        .ptr_eq(&ctx.pixmap_task_to_analysis_map[&32][&painted.node_id()]));
+}
+
+#[test]
+fn test_on_grid() {
+    let mut ctx = TaskGraphBuildingContext::new();
+    let coarse = on_grid(*GRID_SIZE / 2, from_svg_task("borderSolid"));
+    assert!(coarse.is_grid_perfect(&mut ctx));
+    // Declaring a coarse grid doesn't make a curve grid-perfect
+    let coarse_curve = on_grid(*GRID_SIZE / 2, from_svg_task("circle32"));
+    assert!(!coarse_curve.is_grid_perfect(&mut ctx));
+    let fine = on_grid(*GRID_SIZE * 2, from_svg_task("borderSolid"));
+    assert!(!fine.is_grid_perfect(&mut ctx));
+    assert!(!stack(fine.to_owned(), from_svg_task("bigDotsTop")).is_grid_perfect(&mut ctx));
+    assert_eq!(
+        fine.to_string(),
+        format!("grid{}(borderSolid)", *GRID_SIZE * 2)
+    );
+}
+
+#[test]
+fn test_detail_at_least() {
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let _guard = runtime.enter();
+    let mut ctx = TaskGraphBuildingContext::new();
+    let detail = crate::detail_at_least!(64, from_svg_task("borderSolid")).unwrap();
+    assert_eq!(detail.to_string(), "detail64(borderSolid)");
+    assert!(!detail.is_grid_perfect(&mut ctx));
+    // The same spec is drawn at one size and left out at another
+    let small = runtime.block_on(detail.add_to(&mut ctx, 32));
+    assert!(small.pixels().iter().all(|pixel| pixel.alpha() == 0));
+    let large = runtime.block_on(detail.add_to(&mut ctx, 64));
+    assert!(large.pixels().iter().any(|pixel| pixel.alpha() != 0));
+    let small_analysis = runtime.block_on(detail.get_analysis_task(&mut ctx, 32));
+    assert_eq!(small_analysis.alphas, U8BitSet::from_iter([0]));
+    assert!(crate::detail_at_least!(64, None::<ToPixmapTaskSpec>).is_none());
+}
+
+#[test]
+fn test_rotate_and_flip_tasks() {
+    let mut ctx = TaskGraphBuildingContext::new();
+    let arrow = from_svg_task("arrowUp");
+    let turned = rotate_task(arrow.to_owned(), 1);
+    assert_eq!(turned.to_string(), "rotate90(arrowUp)");
+    assert_eq!(
+        turned.is_grid_perfect(&mut ctx),
+        arrow.is_grid_perfect(&mut ctx)
+    );
+    assert_eq!(rotate_task(turned.to_owned(), 3), arrow);
+    assert_eq!(rotate_task(arrow.to_owned(), 4), arrow);
+    assert_eq!(
+        rotate_task(turned, 2).node_id(),
+        rotate_task(arrow.to_owned(), 3).node_id()
+    );
+    let mirrored = flip_task(arrow.to_owned(), Axis::Horizontal);
+    assert_eq!(mirrored.to_string(), "fliph(arrowUp)");
+    assert_eq!(flip_task(mirrored.to_owned(), Axis::Horizontal), arrow);
+    assert_eq!(flip_task(mirrored, Axis::Vertical), rotate_task(arrow, 2));
+}
+
+#[test]
+fn test_remap_task() {
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let _guard = runtime.enter();
+    let mut ctx = TaskGraphBuildingContext::new();
+    let white = ToPixmapTaskSpec::StackLayerOnColor {
+        background: ComparableColor::WHITE,
+        foreground: paint_svg_task("borderSolid", ComparableColor::BLACK).into(),
+    };
+    let to_red = PaletteMap::new([(ComparableColor::WHITE, ComparableColor::RED)]);
+    let red = remap_task(white.to_owned(), to_red.to_owned());
+    assert_eq!(red.to_string(), format!("remap[{}]({})", to_red, white));
+    assert_eq!(
+        red.is_grid_perfect(&mut ctx),
+        white.is_grid_perfect(&mut ctx)
+    );
+    assert_eq!(remap_task(white.to_owned(), PaletteMap::default()), white);
+    let back = PaletteMap::new([(ComparableColor::RED, ComparableColor::WHITE)]);
+    let ToPixmapTaskSpec::Remap { base, mapping } = remap_task(red.to_owned(), back) else {
+        panic!("Expected one remap");
+    };
+    assert_eq!(*base, white);
+    assert_eq!(mapping.get(ComparableColor::WHITE), ComparableColor::WHITE);
+    let analysis = runtime.block_on(red.get_analysis_task(&mut ctx, 32));
+    let SpecifiedColors(colors) = &analysis.colors else {
+        panic!("Expected specified colors");
+    };
+    assert!(colors.contains(&ComparableColor::RED));
+    assert!(!colors.contains(&ComparableColor::WHITE));
+    assert_eq!(analysis.colors.transparency(), Opaque);
+    // The anti-aliased edges of a painted shape are recolored too
+    let painted = paint_task(svg_alpha_task("bonemealSmall"), ComparableColor::WHITE);
+    let red = remap_task(painted, to_red);
+    let analysis = runtime.block_on(red.get_analysis_task(&mut ctx, 32));
+    let SpecifiedColors(colors) = &analysis.colors else {
+        panic!("Expected specified colors");
+    };
+    assert!(colors
+        .iter()
+        .all(|color| color.alpha() == 0 || (color.red(), color.green()) == (u8::MAX, 0)));
+}
+
+#[test]
+fn test_shift_colors_task() {
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let _guard = runtime.enter();
+    let mut ctx = TaskGraphBuildingContext::new();
+    let red = ToPixmapTaskSpec::StackLayerOnColor {
+        background: ComparableColor::RED,
+        foreground: paint_svg_task("borderSolid", ComparableColor::BLACK).into(),
+    };
+    assert_eq!(shift_colors_task(red.to_owned(), ColorShift::NONE), red);
+    let shift = ColorShift::new(120, 0, 0);
+    let green = shift_colors_task(red.to_owned(), shift);
+    assert_eq!(green.to_string(), format!("shift[{}]({})", shift, red));
+    let analysis = runtime.block_on(green.get_analysis_task(&mut ctx, 32));
+    let SpecifiedColors(colors) = &analysis.colors else {
+        panic!("Expected specified colors");
+    };
+    assert!(colors.contains(&ComparableColor::GREEN));
+    assert!(colors.contains(&ComparableColor::BLACK));
+    assert!(!colors.contains(&ComparableColor::RED));
+    assert_eq!(analysis.colors.transparency(), Opaque);
+}
+
+#[test]
+fn test_frame_timing() {
+    let animation = ToPixmapTaskSpec::Animate {
+        background: from_svg_task("borderSolid").into(),
+        frames: Box::new([
+            from_svg_task("bigDotsTop"),
+            from_svg_task("bigDotsTopLeftBottomRight"),
+        ]),
+        layout: SheetLayout::Vertical,
+        timing: Some(FrameTiming::new(8)),
+    };
+    let original = out_task("block/magma", on_grid(*GRID_SIZE, animation));
+    assert_eq!(original.frame_timing(), Some(FrameTiming::new(8)));
+    assert_eq!(
+        alias_task(original, ["block/magma_block"]).frame_timing(),
+        Some(FrameTiming::new(8))
+    );
+    assert_eq!(
+        out_task("block/stone", from_svg_task("borderSolid")).frame_timing(),
+        None
+    );
+}
+
+/// Like [out_task], but the build fails if the image turns out to contain any non-gray color.
+pub fn gray_out_task<T: Into<Name>>(name: T, base: ToPixmapTaskSpec) -> FileOutputTaskSpec {
+    FileOutputTaskSpec::PngOutput {
+        base,
+        destination_name: name.into(),
+        require_gray: true,
+        render_layer: None,
     }
 }
 
@@ -1463,13 +3231,26 @@ fn stack_alpha_presorted(mut layers: Vec<ToAlphaChannelTaskSpec>) -> ToAlphaChan
             let last = layers.remove(x - 1);
             StackAlphaOnAlpha {
                 background: stack_alpha_presorted(layers).into(),
-                foreground: Box::new(last),
+                foreground: last.into(),
             }
         }
     }
 }
 
-pub fn stack_alpha(mut layers: Vec<ToAlphaChannelTaskSpec>) -> ToAlphaChannelTaskSpec {
+/// Stacks alpha channels, which gives the same result in any order, so they're put in a canonical
+/// order first unless [ALPHA_RESTACKING] is off.
+pub fn stack_alpha(layers: Vec<ToAlphaChannelTaskSpec>) -> ToAlphaChannelTaskSpec {
+    let mut key: Box<[NodeId]> = layers.iter().map(ToAlphaChannelTaskSpec::node_id).collect();
+    if *ALPHA_RESTACKING {
+        key.sort_unstable();
+    }
+    hash_cons(&STACK_ALPHA_CACHE, key, || stack_alpha_uncached(layers))
+}
+
+fn stack_alpha_uncached(mut layers: Vec<ToAlphaChannelTaskSpec>) -> ToAlphaChannelTaskSpec {
+    if !*ALPHA_RESTACKING {
+        return stack_alpha_presorted(layers);
+    }
     let mut upscale_layers = Vec::with_capacity(layers.len());
     let mut non_upscale_layers = Vec::with_capacity(layers.len());
     while !layers.is_empty() {
@@ -1478,11 +3259,11 @@ pub fn stack_alpha(mut layers: Vec<ToAlphaChannelTaskSpec>) -> ToAlphaChannelTas
                 background,
                 foreground,
             } => {
-                layers.push(*background);
-                layers.push(*foreground);
+                layers.push((*background).to_owned());
+                layers.push((*foreground).to_owned());
             }
-            ToAlphaChannelTaskSpec::UpscaleFromGridSize { base } => {
-                upscale_layers.push(*base);
+            ToAlphaChannelTaskSpec::UpscaleFromGridSize { base } if *UPSCALE_MERGING => {
+                upscale_layers.push((*base).to_owned());
             }
             layer => non_upscale_layers.push(layer),
         }
@@ -1504,9 +3285,13 @@ fn try_simplify_pair(
 ) -> Result<ToPixmapTaskSpec, (ToPixmapTaskSpec, ToPixmapTaskSpec)> {
     let background_desc = background.to_string();
     let foreground_desc = foreground.to_string();
-    if let Some((bg_alpha, bg_color)) = background.alpha_and_color()
+    // Only valid for an opaque color; otherwise the colored layers' alpha would be multiplied by
+    // the color's alpha before stacking instead of after
+    if *ALPHA_RESTACKING
+        && let Some((bg_alpha, bg_color)) = background.alpha_and_color()
         && let Some((fg_alpha, fg_color)) = foreground.alpha_and_color()
         && bg_color == fg_color
+        && bg_color.alpha() == u8::MAX
     {
         let simplified = paint_task(stack_alpha(vec![bg_alpha, fg_alpha]), bg_color);
         info!(
@@ -1514,11 +3299,12 @@ fn try_simplify_pair(
             background_desc, foreground_desc, simplified
         );
         Ok(simplified)
-    } else if let UpscaleFromGridSize { base: bg_base } = &background
+    } else if *UPSCALE_MERGING
+        && let UpscaleFromGridSize { base: bg_base } = &background
         && let UpscaleFromGridSize { base: fg_base } = &foreground
     {
         let simplified = UpscaleFromGridSize {
-            base: stack(*bg_base.to_owned(), *fg_base.to_owned()).into(),
+            base: stack((**bg_base).to_owned(), (**fg_base).to_owned()).into(),
         };
         info!(
             "Simplified ({},{}) -> {}",
@@ -1530,7 +3316,49 @@ fn try_simplify_pair(
     }
 }
 
+/// A layer passed to [stack!] or [stack_on!]. Layers that are `None` are left out of the stack, so
+/// that materials with optional decorations don't need a placeholder task.
+pub trait OptionalLayer {
+    fn into_layer(self) -> Option<ToPixmapTaskSpec>;
+}
+
+impl<T: Into<ToPixmapTaskSpec>> OptionalLayer for T {
+    fn into_layer(self) -> Option<ToPixmapTaskSpec> {
+        Some(self.into())
+    }
+}
+
+impl OptionalLayer for Option<ToPixmapTaskSpec> {
+    fn into_layer(self) -> Option<ToPixmapTaskSpec> {
+        self
+    }
+}
+
+/// Stacks the layers that are present from bottom to top.
+///
+/// # Panics
+/// If none are present, e.g. because every layer was a `None` decoration.
+/// The panic gives the location of the [stack!] that produced no layers, so that the material can
+/// be fixed.
+#[track_caller]
+pub fn stack_layers<T: IntoIterator<Item = Option<ToPixmapTaskSpec>>>(
+    layers: T,
+) -> ToPixmapTaskSpec {
+    match layers.into_iter().flatten().reduce(stack) {
+        Some(stacked) => stacked,
+        None => panic!("No layers to stack at {}", Location::caller()),
+    }
+}
+
 pub fn stack(background: ToPixmapTaskSpec, foreground: ToPixmapTaskSpec) -> ToPixmapTaskSpec {
+    hash_cons(
+        &STACK_CACHE,
+        (background.node_id(), foreground.node_id()),
+        || stack_uncached(background, foreground),
+    )
+}
+
+fn stack_uncached(background: ToPixmapTaskSpec, foreground: ToPixmapTaskSpec) -> ToPixmapTaskSpec {
     match try_simplify_pair(background, foreground) {
         Ok(simplified) => simplified,
         Err((background, foreground)) => {
@@ -1539,11 +3367,11 @@ pub fn stack(background: ToPixmapTaskSpec, foreground: ToPixmapTaskSpec) -> ToPi
                 foreground: fg_fg,
             } = foreground
             {
-                return match try_simplify_pair(background, *fg_bg) {
-                    Ok(simplified) => stack(simplified, *fg_fg),
+                return match try_simplify_pair(background, (*fg_bg).to_owned()) {
+                    Ok(simplified) => stack(simplified, (*fg_fg).to_owned()),
                     Err((background, fg_bg)) => ToPixmapTaskSpec::StackLayerOnLayer {
                         background: background.into(),
-                        foreground: stack(fg_bg, *fg_fg).into(),
+                        foreground: stack(fg_bg, (*fg_fg).to_owned()).into(),
                     },
                 };
             }
@@ -1552,17 +3380,17 @@ pub fn stack(background: ToPixmapTaskSpec, foreground: ToPixmapTaskSpec) -> ToPi
                 foreground: bg_fg,
             } = background
             {
-                return match try_simplify_pair(*bg_fg, foreground) {
-                    Ok(simplified) => stack(*bg_bg, simplified),
+                return match try_simplify_pair((*bg_fg).to_owned(), foreground) {
+                    Ok(simplified) => stack((*bg_bg).to_owned(), simplified),
                     Err((bg_fg, foreground)) => ToPixmapTaskSpec::StackLayerOnLayer {
-                        background: stack(*bg_bg, bg_fg).into(),
+                        background: stack((*bg_bg).to_owned(), bg_fg).into(),
                         foreground: foreground.into(),
                     },
                 };
             }
             ToPixmapTaskSpec::StackLayerOnLayer {
-                background: Box::new(background),
-                foreground: Box::new(foreground),
+                background: background.into(),
+                foreground: foreground.into(),
             }
         }
     }
@@ -1570,25 +3398,22 @@ pub fn stack(background: ToPixmapTaskSpec, foreground: ToPixmapTaskSpec) -> ToPi
 
 #[macro_export]
 macro_rules! stack {
-    ( $first_layer:expr, $second_layer:expr $(,)? ) => {
-        $crate::image_tasks::task_spec::stack($first_layer.into(), $second_layer.into())
+    ( $( $layers:expr ),+ $(,)? ) => {
+        $crate::image_tasks::task_spec::stack_layers([
+            $( $crate::image_tasks::task_spec::OptionalLayer::into_layer($layers) ),+
+        ])
     };
-    ( $first_layer:expr, $second_layer:expr, $( $more_layers:expr ),+ $(,)? ) => {{
-        let mut layers_so_far = $crate::stack!($first_layer, $second_layer);
-        $( layers_so_far = $crate::stack!(layers_so_far, $more_layers); )+
-        layers_so_far
-    }};
 }
 
 #[macro_export]
 macro_rules! stack_on {
     ( $background:expr, $foreground:expr $(,)? ) => {
         if $background == $crate::image_tasks::color::ComparableColor::TRANSPARENT {
-            $foreground
+            $crate::stack!($foreground)
         } else {
             $crate::image_tasks::task_spec::ToPixmapTaskSpec::StackLayerOnColor {
                 background: $background,
-                foreground: Box::new($foreground.into())
+                foreground: $crate::stack!($foreground).into()
             }
         }
     };
@@ -1597,6 +3422,17 @@ macro_rules! stack_on {
     }};
 }
 
+/// A layer for [stack!] or [stack_on!] that's only drawn when the tile size is at least
+/// `$min_tile_size`, so that high-resolution builds can add detail that wouldn't fit on the grid.
+/// It's transparent at smaller sizes, so that one definition serves every size built in a run.
+#[macro_export]
+macro_rules! detail_at_least {
+    ( $min_tile_size:expr, $layer:expr $(,)? ) => {
+        $crate::image_tasks::task_spec::OptionalLayer::into_layer($layer)
+            .map(|layer| $crate::image_tasks::task_spec::detail_at_least($min_tile_size, layer))
+    };
+}
+
 #[macro_export]
 macro_rules! paint_stack {
     ( $color:expr, $( $layers:expr ),* $(,)? ) => {
@@ -1625,7 +3461,7 @@ impl Mul<f32> for ToAlphaChannelTaskSpec {
             self
         } else {
             ToAlphaChannelTaskSpec::MakeSemitransparent {
-                base: Box::new(self),
+                base: self.into(),
                 alpha: (rhs * 255.0 + 0.5) as u8,
             }
         }
@@ -1646,14 +3482,246 @@ impl Mul<ComparableColor> for ToPixmapTaskSpec {
         match &self {
             ToPixmapTaskSpec::PaintAlphaChannel { base, .. } => {
                 ToPixmapTaskSpec::PaintAlphaChannel {
-                    base: Box::new(*base.to_owned()),
+                    base: base.to_owned(),
                     color: rhs,
                 }
             }
             _ => ToPixmapTaskSpec::PaintAlphaChannel {
-                base: Box::new(ToAlphaChannelTaskSpec::FromPixmap { base: self }),
+                base: ToAlphaChannelTaskSpec::FromPixmap { base: self }.into(),
                 color: rhs,
             },
         }
     }
 }
+
+/// Renders every material's outputs in memory (at [GRID_SIZE] when they're grid-perfect) and checks
+/// their dimensions, their PNG encoding and the predicted colors. Slow, so it only runs with
+/// `--features render-smoke-tests`.
+#[cfg(feature = "render-smoke-tests")]
+#[test]
+fn test_render_all_materials() {
+    use crate::image_tasks::color::premultiplied_diff;
+    use crate::materials::ALL_MATERIALS;
+    use crate::texture_base::material::Material;
+    use crate::TILE_SIZE;
+    use std::collections::HashSet;
+
+    // Rendering SVGs and blending them rounds more than once, so allow a few units per channel
+    const MAX_PREDICTION_ERROR: u8 = 8;
+
+    let runtime = tokio::runtime::Builder::new_multi_thread().build().unwrap();
+    let mut failures = Vec::new();
+    runtime.block_on(async {
+        let mut ctx = TaskGraphBuildingContext::new();
+        let tasks = ALL_MATERIALS.get_output_tasks();
+        ctx.add_texture_names(&tasks);
+        for task in tasks.iter() {
+            let FileOutputTaskSpec::PngOutput { base, .. } = task else {
+                continue;
+            };
+            let path = task.get_path();
+            let size = if base.is_grid_perfect(&mut ctx) {
+                *GRID_SIZE
+            } else {
+                *TILE_SIZE
+            };
+            let color_desc = base
+                .get_analysis_task(&mut ctx, *TILE_SIZE)
+                .await
+                .colors
+                .to_owned();
+            let image = base.add_to(&mut ctx, size).await;
+            if image.width() != size || image.height() % size != 0 {
+                failures.push(format!(
+                    "{} has dimensions {}x{}",
+                    path,
+                    image.width(),
+                    image.height()
+                ));
+                continue;
+            }
+            let actual_colors: HashSet<ComparableColor> = image
+                .pixels()
+                .iter()
+                .copied()
+                .map(ComparableColor::from)
+                .collect();
+            match &color_desc {
+                SpecifiedColors(predicted_colors) => {
+                    let unpredicted = actual_colors
+                        .iter()
+                        .filter_map(|color| {
+                            let discrepancy = predicted_colors
+                                .iter()
+                                .map(|predicted| premultiplied_diff(*predicted, *color))
+                                .min()
+                                .unwrap_or(u8::MAX);
+                            (discrepancy > MAX_PREDICTION_ERROR)
+                                .then(|| format!("{} (off by {})", color, discrepancy))
+                        })
+                        .join(", ");
+                    if !unpredicted.is_empty() {
+                        failures.push(format!("{} has unpredicted colors {}", path, unpredicted));
+                    }
+                }
+                Rgb(Opaque) => {
+                    if actual_colors.iter().any(|color| color.alpha() != u8::MAX) {
+                        failures.push(format!("{} was predicted opaque but isn't", path));
+                    }
+                }
+                Rgb(Binary) => {
+                    if actual_colors.iter().any(|color| !color.is_binary_alpha()) {
+                        failures.push(format!("{} was predicted binary-alpha but isn't", path));
+                    }
+                }
+                Rgb(AlphaChannel) => {}
+            }
+            let (color_type, bit_depth) = color_description_to_mode(&color_desc, &path);
+            let (width, height) = (image.width(), image.height());
+            let png = image
+                .consume(|image| encode_png(image, color_type, bit_depth, PackId::default(), &path))
+                .unwrap();
+            match png::Decoder::new(&*png).read_info() {
+                Ok(reader) => {
+                    let info = reader.info();
+                    if (info.width, info.height) != (width, height) {
+                        failures.push(format!(
+                            "{} decodes as {}x{} instead of {}x{}",
+                            path, info.width, info.height, width, height
+                        ));
+                    }
+                }
+                Err(e) => failures.push(format!("{} doesn't decode: {}", path, e)),
+            }
+        }
+    });
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[cfg(test)]
+fn arb_palette() -> impl proptest::strategy::Strategy<Value = Vec<ComparableColor>> {
+    use proptest::prelude::*;
+
+    proptest::collection::vec(arb_color(), 1..4).prop_map(|mut colors| {
+        colors.sort();
+        colors.dedup();
+        colors
+    })
+}
+
+/// A 4x4 image whose pixels all come from a random palette, along with that palette.
+#[cfg(test)]
+fn arb_image() -> impl proptest::strategy::Strategy<Value = (Vec<ComparableColor>, Pixmap)> {
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    (arb_palette(), proptest::collection::vec(any::<Index>(), 16)).prop_map(|(palette, indices)| {
+        let mut pixmap = Pixmap::new(4, 4).unwrap();
+        for (pixel, index) in pixmap.pixels_mut().iter_mut().zip(indices) {
+            *pixel = (*index.get(&palette)).into();
+        }
+        (palette, pixmap)
+    })
+}
+
+#[cfg(test)]
+fn specified_colors(palette: Vec<ComparableColor>) -> ColorDescription {
+    SpecifiedColors(Arcow::from_owned(palette))
+}
+
+#[cfg(test)]
+fn colors_of(desc: &ColorDescription) -> &[ComparableColor] {
+    let SpecifiedColors(colors) = desc else {
+        panic!("Colors weren't specified");
+    };
+    colors
+}
+
+/// Checks that every color in `actual` is within `tolerance` of a color in `predicted`.
+#[cfg(test)]
+fn check_colors_predicted(
+    actual: &[ComparableColor],
+    predicted: &ColorDescription,
+    tolerance: u8,
+) -> Result<(), proptest::test_runner::TestCaseError> {
+    use crate::image_tasks::color::premultiplied_diff;
+
+    let predicted_colors = colors_of(predicted);
+    for color in actual {
+        proptest::prop_assert!(
+            predicted_colors
+                .iter()
+                .any(|predicted| premultiplied_diff(*predicted, *color) <= tolerance),
+            "{} isn't in {}",
+            color,
+            predicted_colors.iter().join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+use crate::image_tasks::color::arb_color;
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_stack_on_matches_compositing(
+        (background_palette, mut background) in arb_image(),
+        (foreground_palette, foreground) in arb_image()
+    ) {
+        let predicted = specified_colors(foreground_palette)
+            .stack_on(&specified_colors(background_palette), usize::MAX);
+        stack_layer_on_layer(&mut background, &foreground)
+            .now_or_never()
+            .unwrap();
+        let actual: Vec<ComparableColor> = background
+            .pixels()
+            .iter()
+            .copied()
+            .map(ComparableColor::from)
+            .collect();
+        check_colors_predicted(&actual, &predicted, 2)?;
+        match predicted.transparency() {
+            Opaque => proptest::prop_assert!(actual.iter().all(|color| color.alpha() == u8::MAX)),
+            Binary => proptest::prop_assert!(actual.iter().all(ComparableColor::is_binary_alpha)),
+            AlphaChannel => {}
+        }
+    }
+
+    #[test]
+    fn test_stack_on_is_associative(
+        bottom in arb_palette(),
+        middle in arb_palette(),
+        top in arb_palette()
+    ) {
+        let bottom = specified_colors(bottom);
+        let middle = specified_colors(middle);
+        let top = specified_colors(top);
+        let bottom_first = top.stack_on(&middle.stack_on(&bottom, usize::MAX), usize::MAX);
+        let top_first = top.stack_on(&middle, usize::MAX).stack_on(&bottom, usize::MAX);
+        // Each grouping rounds twice
+        check_colors_predicted(colors_of(&bottom_first), &top_first, 2)?;
+        check_colors_predicted(colors_of(&top_first), &bottom_first, 2)?;
+    }
+
+    #[test]
+    fn test_alphas_of_sorted(colors in proptest::collection::vec(arb_color(), 0..64)) {
+        let mut colors = colors;
+        colors.sort();
+        colors.dedup();
+        let expected: U8BitSet = colors.iter().map(|color| color.alpha()).collect();
+        proptest::prop_assert_eq!(alphas_of_sorted(&colors), expected);
+    }
+
+    #[test]
+    fn test_put_adjacent(first in arb_palette(), second in arb_palette(), third in arb_palette()) {
+        let first = specified_colors(first);
+        let second = specified_colors(second);
+        let third = specified_colors(third);
+        let forward = first.put_adjacent(&second).put_adjacent(&third);
+        let backward = third.put_adjacent(&second.put_adjacent(&first));
+        proptest::prop_assert_eq!(colors_of(&forward), colors_of(&backward));
+        proptest::prop_assert_eq!(forward.transparency(), backward.transparency());
+    }
+}
@@ -0,0 +1,141 @@
+use log::{info, warn};
+use once_cell::sync::Lazy;
+
+use crate::image_tasks::output_path::texture_name;
+use crate::image_tasks::task_spec::FileOutputTaskSpec;
+use crate::option_value;
+
+/// Which textures to build, from the comma-separated glob patterns of `--only` and `--exclude`,
+/// such as `--only 'block/oak_*,item/*_boat'`. Textures that are filtered out are never added to
+/// the graph, so neither is anything that only they are made from.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TextureFilter {
+    only: Vec<String>,
+    exclude: Vec<String>,
+}
+
+static TEXTURE_FILTER: Lazy<TextureFilter> = Lazy::new(|| {
+    TextureFilter::new(
+        option_value("only").as_deref(),
+        option_value("exclude").as_deref(),
+    )
+});
+
+fn patterns(list: Option<&str>) -> Vec<String> {
+    list.into_iter()
+        .flat_map(|list| list.split(','))
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty())
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Whether `text` matches `pattern`, in which `?` matches any character but `/`, `*` any run of
+/// them, and `**` any run of characters at all.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|start| glob_match(rest, &text[start..])),
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&start| start == 0 || text[start - 1] != b'/')
+            .any(|start| glob_match(rest, &text[start..])),
+        [b'?', rest @ ..] => text
+            .split_first()
+            .is_some_and(|(first, text)| *first != b'/' && glob_match(rest, text)),
+        [expected, rest @ ..] => text
+            .split_first()
+            .is_some_and(|(first, text)| first == expected && glob_match(rest, text)),
+    }
+}
+
+/// Whether `pattern` matches `path`, either in full or as a texture name such as `block/oak_log`
+/// for `assets/minecraft/textures/block/oak_log.png`.
+pub(crate) fn path_matches(pattern: &str, path: &str) -> bool {
+    glob_match(pattern.as_bytes(), path.as_bytes())
+        || texture_name(path).is_some_and(|name| glob_match(pattern.as_bytes(), name.as_bytes()))
+}
+
+impl TextureFilter {
+    pub fn new(only: Option<&str>, exclude: Option<&str>) -> TextureFilter {
+        TextureFilter {
+            only: patterns(only),
+            exclude: patterns(exclude),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.exclude.is_empty()
+    }
+
+    fn any_matches(patterns: &[String], path: &str) -> bool {
+        patterns.iter().any(|pattern| path_matches(pattern, path))
+    }
+
+    /// Whether a texture at this path should be built.
+    pub fn accepts(&self, path: &str) -> bool {
+        (self.only.is_empty() || Self::any_matches(&self.only, path))
+            && !Self::any_matches(&self.exclude, path)
+    }
+
+    /// Keeps the tasks that write at least one accepted path. A copy that's kept still writes its
+    /// original, since that's where it's copied from.
+    pub fn apply(&self, tasks: Vec<FileOutputTaskSpec>) -> Vec<FileOutputTaskSpec> {
+        let total = tasks.len();
+        let kept: Vec<FileOutputTaskSpec> = tasks
+            .into_iter()
+            .filter(|task| task.get_paths().iter().any(|path| self.accepts(path)))
+            .collect();
+        if kept.is_empty() {
+            warn!(
+                "--only and --exclude don't leave any of {} textures to build",
+                total
+            );
+        } else {
+            info!(
+                "Building {} of {} textures that --only and --exclude select",
+                kept.len(),
+                total
+            );
+        }
+        kept
+    }
+}
+
+/// Applies [TEXTURE_FILTER], if `--only` or `--exclude` is set, to the output tasks.
+pub fn filter_textures(tasks: Vec<FileOutputTaskSpec>) -> Vec<FileOutputTaskSpec> {
+    if TEXTURE_FILTER.is_empty() {
+        tasks
+    } else {
+        TEXTURE_FILTER.apply(tasks)
+    }
+}
+
+#[test]
+fn test_texture_filter() {
+    use crate::image_tasks::output_path::texture_path;
+    use crate::image_tasks::task_spec::{alias_task, from_svg_task, out_task};
+
+    assert!(glob_match(b"block/oak_*", b"block/oak_log_top"));
+    assert!(!glob_match(b"*_log", b"block/oak_log"));
+    assert!(glob_match(b"**_log", b"block/oak_log"));
+    assert!(glob_match(b"block/???", b"block/tnt"));
+    assert!(!glob_match(b"block/???", b"block/tnt_top"));
+
+    let filter = TextureFilter::new(Some("block/oak_*, item/*"), Some("*/*_top"));
+    assert!(filter.accepts(&texture_path("block/oak_log")));
+    assert!(!filter.accepts(&texture_path("block/oak_log_top")));
+    assert!(filter.accepts(&texture_path("item/stick")));
+    assert!(!filter.accepts(&texture_path("block/stone")));
+    assert!(TextureFilter::new(None, Some("")).is_empty());
+
+    let stone = out_task("block/stone", from_svg_task("borderSolid"));
+    let kept = TextureFilter::new(Some("block/oak_planks"), None).apply(vec![
+        stone.to_owned(),
+        alias_task(stone, ["block/oak_planks"]),
+    ]);
+    assert_eq!(kept.len(), 1);
+    assert_eq!(
+        kept[0].get_path(),
+        "assets/minecraft/textures/block/oak_planks.png".into()
+    );
+}
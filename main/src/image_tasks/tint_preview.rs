@@ -0,0 +1,133 @@
+use std::fs;
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use resvg::tiny_skia::{Pixmap, PixmapPaint, PremultipliedColorU8, Transform};
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::color::{rgba, ComparableColor};
+use crate::image_tasks::task_spec::{texture_of, TaskGraphBuildingContext, TaskSpecTraits};
+use crate::image_tasks::{allocate_pixmap_empty, MaybeFromPool};
+use crate::option_value;
+
+/// The PNG file named by `--tint-preview`, if any.
+pub static TINT_PREVIEW: Lazy<Option<String>> = Lazy::new(|| option_value("tint-preview"));
+
+/// Power levels of redstone dust, from 0 to this inclusive.
+pub const MAX_POWER: u8 = 15;
+
+/// The grayscale textures that the game tints by the power level of the redstone dust.
+pub const REDSTONE_DUST_TEXTURES: [&str; 3] = [
+    "block/redstone_dust_dot",
+    "block/redstone_dust_line0",
+    "block/redstone_dust_line1",
+];
+
+/// The color that the game multiplies redstone dust by at a power level.
+pub fn redstone_power_tint(power: u8) -> ComparableColor {
+    let fraction = power.min(MAX_POWER) as f32 / MAX_POWER as f32;
+    let red = fraction * 0.6 + if power == 0 { 0.3 } else { 0.4 };
+    let green = (fraction * fraction * 0.7 - 0.5).max(0.0);
+    let blue = (fraction * fraction * 0.6 - 0.7).max(0.0);
+    let to_u8 = |channel: f32| (channel.clamp(0.0, 1.0) * u8::MAX as f32).round() as u8;
+    rgba(to_u8(red), to_u8(green), to_u8(blue), u8::MAX)
+}
+
+/// Multiplies every pixel by `tint`, as the game does to a grayscale texture.
+pub fn tint(gray: &Pixmap, tint: ComparableColor) -> MaybeFromPool<Pixmap> {
+    let mut out = allocate_pixmap_empty(gray.width(), gray.height());
+    let multiply = |channel: u8, by: u8| (channel as u16 * by as u16 / u8::MAX as u16) as u8;
+    for (out_pixel, pixel) in out.pixels_mut().iter_mut().zip(gray.pixels()) {
+        *out_pixel = PremultipliedColorU8::from_rgba(
+            multiply(pixel.red(), tint.red()),
+            multiply(pixel.green(), tint.green()),
+            multiply(pixel.blue(), tint.blue()),
+            pixel.alpha(),
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Checks that a texture meant for tinting is gray, and warns if it never reaches white, since
+/// then even fully-powered dust is darker than the tint.
+fn check_tintable(name: &str, gray: &Pixmap) -> Result<(), CloneableError> {
+    let mut lightest = 0;
+    for pixel in gray
+        .pixels()
+        .iter()
+        .map(|pixel| ComparableColor::from(*pixel))
+    {
+        if !pixel.is_gray() {
+            return Err(anyhoo!("{} is tinted, but contains {}", name, pixel));
+        }
+        if pixel.alpha() != 0 {
+            lightest = lightest.max(pixel.red());
+        }
+    }
+    if lightest != 0 && lightest != u8::MAX {
+        warn!(
+            "{} is at most {}/{} white, so it's darker than its tint",
+            name,
+            lightest,
+            u8::MAX
+        );
+    }
+    Ok(())
+}
+
+/// Writes a sheet with a row for each of the [REDSTONE_DUST_TEXTURES] that's being built, tinted
+/// for each power level from 0 on the left to [MAX_POWER] on the right.
+pub async fn write_tint_preview(
+    ctx: &mut TaskGraphBuildingContext,
+    tile_size: u32,
+) -> Result<(), CloneableError> {
+    let Some(preview_path) = &*TINT_PREVIEW else {
+        return Ok(());
+    };
+    let names: Vec<&str> = REDSTONE_DUST_TEXTURES
+        .into_iter()
+        .filter(|name| ctx.has_texture(name))
+        .collect();
+    if names.is_empty() {
+        warn!("Not writing a tint preview, since no redstone dust is being built");
+        return Ok(());
+    }
+    let columns = MAX_POWER as u32 + 1;
+    let mut sheet = allocate_pixmap_empty(columns * tile_size, names.len() as u32 * tile_size);
+    for (row, name) in names.iter().enumerate() {
+        let gray = texture_of(*name).add_to(ctx, tile_size).await;
+        check_tintable(name, &gray)?;
+        for power in 0..=MAX_POWER {
+            sheet.draw_pixmap(
+                (power as u32 * tile_size) as i32,
+                (row as u32 * tile_size) as i32,
+                tint(&gray, redstone_power_tint(power)).as_ref(),
+                &PixmapPaint::default(),
+                Transform::default(),
+                None,
+            );
+        }
+    }
+    info!("Writing tint preview to {}", preview_path);
+    fs::write(preview_path, sheet.encode_png()?)?;
+    Ok(())
+}
+
+#[test]
+fn test_tint() {
+    use crate::image_tasks::color::{c, gray};
+
+    assert_eq!(redstone_power_tint(0), c(0x4d0000));
+    assert_eq!(redstone_power_tint(MAX_POWER), c(0xff3300));
+    let mut overlay = Pixmap::new(2, 1).unwrap();
+    overlay.pixels_mut()[0] = ComparableColor::WHITE.into();
+    overlay.pixels_mut()[1] = (gray(0x80) * 0.5).into();
+    let tinted = tint(&overlay, redstone_power_tint(MAX_POWER));
+    assert_eq!(ComparableColor::from(tinted.pixels()[0]), c(0xff3300));
+    assert_eq!(tinted.pixels()[1].alpha(), overlay.pixels()[1].alpha());
+    assert!(check_tintable("block/redstone_dust_dot", &overlay).is_ok());
+    overlay.pixels_mut()[1] = ComparableColor::RED.into();
+    assert!(check_tintable("block/redstone_dust_dot", &overlay).is_err());
+}
@@ -0,0 +1,102 @@
+use std::fmt::{Display, Formatter};
+
+use resvg::tiny_skia::Pixmap;
+use tracing::instrument;
+
+use crate::image_tasks::{allocate_pixmap_for_overwrite, MaybeFromPool};
+
+/// The line that [flip] mirrors an image across.
+#[derive(Copy, Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub enum Axis {
+    /// Swaps left and right.
+    Horizontal,
+    /// Swaps top and bottom.
+    Vertical,
+}
+
+impl Display for Axis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Axis::Horizontal => "h",
+            Axis::Vertical => "v",
+        })
+    }
+}
+
+/// Turns an image clockwise by `quarter_turns` right angles. The width and height swap if it's an
+/// odd number of them.
+#[instrument(skip(source))]
+pub fn rotate(source: &Pixmap, quarter_turns: u8) -> MaybeFromPool<Pixmap> {
+    let (width, height) = (source.width(), source.height());
+    let (out_width, out_height) = if quarter_turns % 2 == 0 {
+        (width, height)
+    } else {
+        (height, width)
+    };
+    let mut out = allocate_pixmap_for_overwrite(out_width, out_height);
+    let source_pixels = source.pixels();
+    let out_pixels = out.pixels_mut();
+    for y in 0..out_height {
+        for x in 0..out_width {
+            let (source_x, source_y) = match quarter_turns % 4 {
+                0 => (x, y),
+                1 => (y, height - 1 - x),
+                2 => (width - 1 - x, height - 1 - y),
+                _ => (width - 1 - y, x),
+            };
+            out_pixels[(y * out_width + x) as usize] =
+                source_pixels[(source_y * width + source_x) as usize];
+        }
+    }
+    out
+}
+
+/// Mirrors an image across `axis`.
+#[instrument(skip(source))]
+pub fn flip(source: &Pixmap, axis: Axis) -> MaybeFromPool<Pixmap> {
+    let (width, height) = (source.width(), source.height());
+    let mut out = allocate_pixmap_for_overwrite(width, height);
+    let source_pixels = source.pixels();
+    let out_pixels = out.pixels_mut();
+    for y in 0..height {
+        for x in 0..width {
+            let (source_x, source_y) = match axis {
+                Axis::Horizontal => (width - 1 - x, y),
+                Axis::Vertical => (x, height - 1 - y),
+            };
+            out_pixels[(y * width + x) as usize] =
+                source_pixels[(source_y * width + source_x) as usize];
+        }
+    }
+    out
+}
+
+#[test]
+fn test_rotate_and_flip() {
+    use crate::image_tasks::color::ComparableColor;
+
+    // 2x3, with a white pixel at the top left and a red one at the bottom left
+    let mut source = Pixmap::new(2, 3).unwrap();
+    source.pixels_mut()[0] = ComparableColor::WHITE.into();
+    source.pixels_mut()[4] = ComparableColor::RED.into();
+    let colors = |image: &Pixmap| -> Vec<ComparableColor> {
+        image.pixels().iter().map(|pixel| (*pixel).into()).collect()
+    };
+    let (w, r, t) = (
+        ComparableColor::WHITE,
+        ComparableColor::RED,
+        ComparableColor::TRANSPARENT,
+    );
+
+    let turned = rotate(&source, 1);
+    assert_eq!((turned.width(), turned.height()), (3, 2));
+    assert_eq!(colors(&turned), [r, t, w, t, t, t]);
+    assert_eq!(colors(&rotate(&source, 2)), [t, r, t, t, t, w]);
+    let turned_back = rotate(&turned, 3);
+    assert_eq!(colors(&turned_back), colors(&source));
+    assert_eq!(colors(&rotate(&source, 4)), colors(&source));
+
+    assert_eq!(colors(&flip(&source, Axis::Horizontal)), [t, w, t, t, t, r]);
+    assert_eq!(colors(&flip(&source, Axis::Vertical)), [r, t, t, t, w, t]);
+    assert_eq!(Axis::Horizontal.to_string(), "h");
+}
@@ -1,16 +1,17 @@
 use crate::image_tasks::cloneable::CloneableError;
 use crate::image_tasks::repaint::allocate_mask_for_overwrite;
 use crate::image_tasks::{allocate_pixmap_for_overwrite, MaybeFromPool};
-use resvg::tiny_skia::{Mask, Pixmap};
+use crate::anyhoo;
+use resvg::tiny_skia::{Mask, Pixmap, PremultipliedColorU8};
 use std::iter::repeat;
 use tracing::instrument;
 
 #[instrument(skip(source))]
 pub fn upscale_image(
     source: &Pixmap,
-    new_width: u32,
+    scale_factor: u32,
 ) -> Result<MaybeFromPool<Pixmap>, CloneableError> {
-    let scale_factor = new_width / source.width();
+    let new_width = scale_factor * source.width();
     let new_height = scale_factor * source.height();
     let mut out_scanline = Vec::with_capacity(new_width as usize);
     let mut out = allocate_pixmap_for_overwrite(new_width, new_height);
@@ -30,8 +31,11 @@ pub fn upscale_image(
     Ok(out)
 }
 
-pub fn upscale_mask(source: &Mask, new_width: u32) -> Result<MaybeFromPool<Mask>, CloneableError> {
-    let scale_factor = new_width / source.width();
+pub fn upscale_mask(
+    source: &Mask,
+    scale_factor: u32,
+) -> Result<MaybeFromPool<Mask>, CloneableError> {
+    let new_width = scale_factor * source.width();
     let new_height = scale_factor * source.height();
     let mut out_scanline = Vec::with_capacity(new_width as usize);
     let mut out = allocate_mask_for_overwrite(new_width, new_height);
@@ -53,3 +57,71 @@ pub fn upscale_mask(source: &Mask, new_width: u32) -> Result<MaybeFromPool<Mask>
     }
     Ok(out)
 }
+
+/// Shrinks the image by averaging each `scale_factor`-by-`scale_factor` block of pixels, which is
+/// exact for shapes aligned to that block size and anti-aliases everything else.
+#[instrument(skip(source))]
+pub fn downscale_image(
+    source: &Pixmap,
+    scale_factor: u32,
+) -> Result<MaybeFromPool<Pixmap>, CloneableError> {
+    if scale_factor == 0
+        || !source.width().is_multiple_of(scale_factor)
+        || !source.height().is_multiple_of(scale_factor)
+    {
+        return Err(anyhoo!(
+            "Can't downscale a {}x{} image by a factor of {}",
+            source.width(),
+            source.height(),
+            scale_factor
+        ));
+    }
+    let new_width = source.width() / scale_factor;
+    let new_height = source.height() / scale_factor;
+    let block_pixels = scale_factor * scale_factor;
+    let mut out = allocate_pixmap_for_overwrite(new_width, new_height);
+    for out_y in 0..new_height {
+        for out_x in 0..new_width {
+            let mut sums = [0u32; 4];
+            for y in out_y * scale_factor..(out_y + 1) * scale_factor {
+                for x in out_x * scale_factor..(out_x + 1) * scale_factor {
+                    let pixel = source.pixel(x, y).unwrap();
+                    sums[0] += pixel.red() as u32;
+                    sums[1] += pixel.green() as u32;
+                    sums[2] += pixel.blue() as u32;
+                    sums[3] += pixel.alpha() as u32;
+                }
+            }
+            let [red, green, blue, alpha] =
+                sums.map(|sum| ((sum + block_pixels / 2) / block_pixels) as u8);
+            // Each premultiplied channel's sum is at most the alpha sum, and both are rounded the
+            // same way, so the result is still a valid premultiplied color
+            out.pixels_mut()[(out_y * new_width + out_x) as usize] =
+                PremultipliedColorU8::from_rgba(red, green, blue, alpha).unwrap();
+        }
+    }
+    Ok(out)
+}
+
+#[test]
+fn test_downscale_image() {
+    use crate::image_tasks::color::ComparableColor;
+
+    let mut source = Pixmap::new(4, 2).unwrap();
+    source.pixels_mut()[0] = ComparableColor::WHITE.into();
+    source.pixels_mut()[2] = ComparableColor::BLACK.into();
+    source.pixels_mut()[3] = ComparableColor::BLACK.into();
+    source.pixels_mut()[6] = ComparableColor::BLACK.into();
+    source.pixels_mut()[7] = ComparableColor::BLACK.into();
+    let out = downscale_image(&source, 2).unwrap();
+    assert_eq!((out.width(), out.height()), (2, 1));
+    assert_eq!(
+        ComparableColor::from(out.pixels()[0]),
+        ComparableColor::WHITE * 0.25
+    );
+    assert_eq!(
+        ComparableColor::from(out.pixels()[1]),
+        ComparableColor::BLACK
+    );
+    assert!(downscale_image(&source, 3).is_err());
+}
@@ -0,0 +1,80 @@
+use std::fs::File;
+use std::io::{Read, Seek};
+
+use log::info;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use zip::ZipArchive;
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::task_spec::{from_raster_task, ToPixmapTaskSpec, ASSET_DIR};
+use crate::option_value;
+
+/// Prefix of a [ToPixmapTaskSpec::FromRaster] source that names a texture in the vanilla jar
+/// rather than a PNG file.
+pub const VANILLA_PREFIX: &str = "vanilla:";
+
+/// The Minecraft client jar named by `--vanilla-jar`, if any.
+static VANILLA_JAR: Lazy<Option<Mutex<ZipArchive<File>>>> = Lazy::new(|| {
+    option_value("vanilla-jar").map(|path| {
+        info!("Reading vanilla textures from {}", path);
+        let archive = File::open(&path)
+            .map_err(CloneableError::from)
+            .and_then(|file| ZipArchive::new(file).map_err(CloneableError::from))
+            .unwrap_or_else(|e| panic!("Invalid value for --vanilla-jar: {:?}", e));
+        Mutex::new(archive)
+    })
+});
+
+/// A vanilla texture such as `block/stone`, scaled to the tile size like any other raster layer,
+/// for packs that only overlay details onto the vanilla art. Needs `--vanilla-jar`.
+pub fn vanilla_task(texture: &str) -> ToPixmapTaskSpec {
+    from_raster_task(format!("{}{}", VANILLA_PREFIX, texture))
+}
+
+fn read_texture<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    texture: &str,
+) -> Result<Vec<u8>, CloneableError> {
+    let path = format!("{}{}.png", ASSET_DIR, texture);
+    let mut entry = archive
+        .by_name(&path)
+        .map_err(|_| anyhoo!("{} isn't in the vanilla jar", path))?;
+    let mut png = Vec::with_capacity(entry.size() as usize);
+    entry.read_to_end(&mut png)?;
+    Ok(png)
+}
+
+/// Reads the PNG for a texture such as `block/stone` from the vanilla jar.
+pub fn vanilla_png(texture: &str) -> Result<Vec<u8>, CloneableError> {
+    let jar = VANILLA_JAR
+        .as_ref()
+        .ok_or_else(|| anyhoo!("Vanilla texture {} needs --vanilla-jar", texture))?;
+    read_texture(&mut jar.lock(), texture)
+}
+
+#[test]
+fn test_read_texture() {
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+    use zip::ZipWriter;
+
+    let mut jar = ZipWriter::new(Cursor::new(Vec::new()));
+    jar.start_file(
+        "assets/minecraft/textures/block/stone.png",
+        SimpleFileOptions::default(),
+    )
+    .unwrap();
+    jar.write_all(b"not really a PNG").unwrap();
+    let mut jar = ZipArchive::new(jar.finish().unwrap()).unwrap();
+    assert_eq!(
+        read_texture(&mut jar, "block/stone").unwrap(),
+        b"not really a PNG"
+    );
+    assert!(read_texture(&mut jar, "block/dirt").is_err());
+    assert_eq!(
+        vanilla_task("block/stone").to_string(),
+        "raster(vanilla:block/stone)"
+    );
+}
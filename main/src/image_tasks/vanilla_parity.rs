@@ -0,0 +1,203 @@
+use std::fmt::Write;
+use std::fs;
+
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use resvg::tiny_skia::Pixmap;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::output_path::texture_name;
+use crate::image_tasks::task_spec::PackId;
+use crate::image_tasks::vanilla::vanilla_png;
+use crate::{option_value, parsed_option};
+
+/// Textures whose silhouettes diverge at least this much from vanilla are reported; see
+/// [silhouette_divergence]. A redesign that keeps the vanilla outline scores near 0, while an SVG
+/// exported with the wrong viewbox, which draws the outline at the wrong size or place, scores
+/// well above this.
+const DEFAULT_PARITY_THRESHOLD: f32 = 0.4;
+
+/// Texels at least this opaque count as part of a silhouette.
+const OPAQUE_ALPHA: u8 = 128;
+
+/// The CSV file named by `--vanilla-parity-report`, if any. Silhouettes are only compared when
+/// it's set, and only for textures that are also in the jar named by `--vanilla-jar`.
+static PARITY_REPORT: Lazy<Option<String>> = Lazy::new(|| option_value("vanilla-parity-report"));
+
+/// The first frame of `image`, if it's a vertical animation strip, as its width and height.
+fn first_frame_size(image: &Pixmap) -> (u32, u32) {
+    if image.height() > image.width() && image.height().is_multiple_of(image.width()) {
+        (image.width(), image.width())
+    } else {
+        (image.width(), image.height())
+    }
+}
+
+/// Which texels of the first frame of `image` are opaque, when it's downsampled to `width` by
+/// `height` by averaging the alpha of the pixels each texel covers.
+fn silhouette(image: &Pixmap, width: u32, height: u32) -> Vec<bool> {
+    let (frame_width, frame_height) = first_frame_size(image);
+    let mut texels = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height {
+        let top = y * frame_height / height;
+        let bottom = ((y + 1) * frame_height / height).max(top + 1);
+        for x in 0..width {
+            let left = x * frame_width / width;
+            let right = ((x + 1) * frame_width / width).max(left + 1);
+            let (sum, count) = (top..bottom)
+                .flat_map(|y| (left..right).map(move |x| (x, y)))
+                .map(|(x, y)| image.pixel(x, y).unwrap().alpha() as u32)
+                .fold((0, 0), |(sum, count), alpha| (sum + alpha, count + 1));
+            texels.push(sum >= OPAQUE_ALPHA as u32 * count);
+        }
+    }
+    texels
+}
+
+/// How differently shaped the first frames of two textures are: 0 if the same texels are opaque
+/// in both at the size of `vanilla`, rising to 1 if none of them are, or if the frames' aspect
+/// ratios differ. Computed as one minus the intersection over union of the opaque texels.
+pub fn silhouette_divergence(generated: &Pixmap, vanilla: &Pixmap) -> f32 {
+    let (generated_width, generated_height) = first_frame_size(generated);
+    let (width, height) = first_frame_size(vanilla);
+    if generated_width as u64 * height as u64 != generated_height as u64 * width as u64 {
+        return 1.0;
+    }
+    let (both, either) = silhouette(generated, width, height)
+        .into_iter()
+        .zip(silhouette(vanilla, width, height))
+        .fold((0usize, 0usize), |(both, either), (first, second)| {
+            (
+                both + (first && second) as usize,
+                either + (first || second) as usize,
+            )
+        });
+    if either == 0 {
+        0.0
+    } else {
+        1.0 - both as f32 / either as f32
+    }
+}
+
+/// Divergence of every texture compared so far, with the pack and path of the texture.
+type PathsAndDivergences = Vec<(PackId, Box<str>, f32)>;
+
+static DIVERGENCES: Lazy<Mutex<PathsAndDivergences>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Called with each finished image. Textures that vanilla doesn't have are skipped.
+pub fn record_vanilla_parity(pack: PackId, file_path: &str, image: &Pixmap) {
+    if PARITY_REPORT.is_none() {
+        return;
+    }
+    let Some(texture) = texture_name(file_path) else {
+        return;
+    };
+    let Ok(vanilla) = vanilla_png(&texture).and_then(|png| Ok(Pixmap::decode_png(&png)?)) else {
+        return;
+    };
+    let divergence = silhouette_divergence(image, &vanilla);
+    DIVERGENCES
+        .lock()
+        .push((pack, file_path.into(), divergence));
+}
+
+fn to_csv(divergences: &mut [(PackId, Box<str>, f32)], threshold: f32) -> String {
+    divergences.sort_by(
+        |(first_pack, first_path, first), (second_pack, second_path, second)| {
+            second
+                .total_cmp(first)
+                .then(first_path.cmp(second_path))
+                .then(first_pack.cmp(second_pack))
+        },
+    );
+    let mut csv = String::from("pack,texture,silhouette_divergence\n");
+    for (pack, file_path, divergence) in divergences
+        .iter()
+        .filter(|(_, _, divergence)| *divergence >= threshold)
+    {
+        writeln!(csv, "{},{},{:.4}", pack, file_path, divergence).unwrap();
+    }
+    csv
+}
+
+/// Called once every image has been encoded. Writes the textures whose silhouettes diverge from
+/// vanilla by at least `--vanilla-parity-threshold` to the CSV file named by
+/// `--vanilla-parity-report`, worst first.
+pub fn finish_vanilla_parity_report() -> Result<(), CloneableError> {
+    let Some(report_path) = &*PARITY_REPORT else {
+        return Ok(());
+    };
+    let threshold = parsed_option("vanilla-parity-threshold").unwrap_or(DEFAULT_PARITY_THRESHOLD);
+    let mut divergences = DIVERGENCES.lock();
+    if divergences.is_empty() {
+        warn!("No textures could be compared to vanilla; is --vanilla-jar set?");
+    }
+    let diverged = divergences
+        .iter()
+        .filter(|(_, _, divergence)| *divergence >= threshold)
+        .count();
+    if diverged > 0 {
+        warn!(
+            "{} of {} textures have a different shape than in vanilla; see {}",
+            diverged,
+            divergences.len(),
+            report_path
+        );
+    } else {
+        info!(
+            "All {} textures have the same shape as in vanilla",
+            divergences.len()
+        );
+    }
+    fs::write(report_path, to_csv(&mut divergences, threshold))?;
+    Ok(())
+}
+
+#[test]
+fn test_silhouette_divergence() {
+    use crate::image_tasks::color::ComparableColor;
+
+    // Opaque wherever `opaque` is true of the coordinates, on a transparent background
+    let image = |width: u32, height: u32, opaque: &dyn Fn(u32, u32) -> bool| {
+        let mut image = Pixmap::new(width, height).unwrap();
+        for (index, pixel) in image.pixels_mut().iter_mut().enumerate() {
+            let (x, y) = (index as u32 % width, index as u32 / width);
+            if opaque(x, y) {
+                *pixel = ComparableColor::BLACK.into();
+            }
+        }
+        image
+    };
+    let vanilla = image(16, 16, &|x, _| x < 8);
+    assert_eq!(
+        silhouette_divergence(&image(64, 64, &|x, _| x < 32), &vanilla),
+        0.0
+    );
+    // An animation is compared by its first frame
+    assert_eq!(
+        silhouette_divergence(&image(64, 128, &|x, y| x < 32 || y >= 64), &vanilla),
+        0.0
+    );
+    assert_eq!(
+        silhouette_divergence(&image(64, 64, &|x, _| x >= 32), &vanilla),
+        1.0
+    );
+    // Drawn in only the top-left quarter, as if the viewbox were twice too big
+    let shrunk = silhouette_divergence(&image(64, 64, &|x, y| x < 16 && y < 32), &vanilla);
+    assert!((shrunk - 0.75).abs() < 1e-6);
+    assert_eq!(
+        silhouette_divergence(&image(64, 32, &|_, _| true), &vanilla),
+        1.0
+    );
+
+    let pack = PackId {
+        tile_size: 32,
+        high_contrast: true,
+    };
+    let mut divergences = vec![(pack, "a.png".into(), 0.1), (pack, "b.png".into(), 0.9)];
+    assert_eq!(
+        to_csv(&mut divergences, 0.4),
+        "pack,texture,silhouette_divergence\n32x32-high-contrast,b.png,0.9000\n"
+    );
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+use itertools::Itertools;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use png::{BitDepth, ColorType, Decoder};
+use zip::ZipArchive;
+
+use crate::anyhoo;
+use crate::flag_present;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::task_spec::PackId;
+
+/// Whether `--verify` was given, so that [verify_zip] will run once the ZIP file is finished.
+pub static VERIFY_ARCHIVE: Lazy<bool> = Lazy::new(|| flag_present("verify"));
+
+/// What an output PNG looked like when it was encoded.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ExpectedPng {
+    pub width: u32,
+    pub height: u32,
+    pub color_type: ColorType,
+    pub bit_depth: BitDepth,
+    pub crc32: u32,
+}
+
+static EXPECTED_PNGS: Lazy<Mutex<HashMap<(PackId, Box<str>), ExpectedPng>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Records the PNG that was encoded for `file_path` in `pack`, if `--verify` was given.
+pub fn expect_png(pack: PackId, file_path: &str, png: &[u8], width: u32, height: u32) {
+    if !*VERIFY_ARCHIVE {
+        return;
+    }
+    let header = Decoder::new(png)
+        .read_info()
+        .map(|reader| (reader.info().color_type, reader.info().bit_depth));
+    match header {
+        Ok((color_type, bit_depth)) => {
+            EXPECTED_PNGS.lock().insert(
+                (pack, file_path.into()),
+                ExpectedPng {
+                    width,
+                    height,
+                    color_type,
+                    bit_depth,
+                    crc32: crc32fast::hash(png),
+                },
+            );
+        }
+        Err(e) => warn!("Can't read the PNG just encoded for {}: {}", file_path, e),
+    }
+}
+
+/// Records that `dest_path` should be identical to `source_path` in `pack`.
+pub fn expect_copy(pack: PackId, source_path: &str, dest_path: &str) {
+    if !*VERIFY_ARCHIVE {
+        return;
+    }
+    let mut expected = EXPECTED_PNGS.lock();
+    if let Some(source) = expected.get(&(pack, source_path.into())).copied() {
+        expected.insert((pack, dest_path.into()), source);
+    }
+}
+
+/// Reopens the finished ZIP file of `pack` and reads every entry, which checks it against its
+/// stored CRC. Every PNG is also decoded and compared with what [expect_png] recorded for it in
+/// that pack, and every PNG recorded for it must be present. Returns how many entries were checked,
+/// or an error listing every problem found.
+pub fn verify_zip(pack: PackId, zip_contents: &[u8]) -> Result<usize, CloneableError> {
+    let all_expected = EXPECTED_PNGS.lock();
+    let expected: HashMap<&str, &ExpectedPng> = all_expected
+        .iter()
+        .filter(|((expected_pack, _), _)| *expected_pack == pack)
+        .map(|((_, path), expected)| (&**path, expected))
+        .collect();
+    let mut archive = ZipArchive::new(Cursor::new(zip_contents))?;
+    let mut problems = Vec::new();
+    let mut contents = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive.by_index(index)?;
+        let name = entry.name().to_owned();
+        let stored_crc32 = entry.crc32();
+        contents.clear();
+        if let Err(e) = entry.read_to_end(&mut contents) {
+            problems.push(format!("{}: {}", name, e));
+            continue;
+        }
+        if !name.ends_with(".png") {
+            continue;
+        }
+        if let Err(problem) = check_png(&contents, stored_crc32, expected.get(&*name).copied()) {
+            problems.push(format!("{}: {}", name, problem));
+        }
+    }
+    for name in expected.keys().sorted() {
+        if archive.index_for_name(name).is_none() {
+            problems.push(format!("{}: missing", name));
+        }
+    }
+    if problems.is_empty() {
+        info!(
+            "Verified all {} entries in the {} ZIP file",
+            archive.len(),
+            pack
+        );
+        Ok(archive.len())
+    } else {
+        Err(anyhoo!(
+            "{} problems found in the {} ZIP file:\n{}",
+            problems.len(),
+            pack,
+            problems.join("\n")
+        ))
+    }
+}
+
+fn check_png(
+    contents: &[u8],
+    stored_crc32: u32,
+    expected: Option<&ExpectedPng>,
+) -> Result<(), String> {
+    let mut reader = Decoder::new(contents)
+        .read_info()
+        .map_err(|e| e.to_string())?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut pixels).map_err(|e| e.to_string())?;
+    let Some(expected) = expected else {
+        return Ok(());
+    };
+    if stored_crc32 != expected.crc32 {
+        return Err(format!(
+            "CRC is {:08x}, but the encoded PNG's was {:08x}",
+            stored_crc32, expected.crc32
+        ));
+    }
+    let found = (frame.width, frame.height, frame.color_type, frame.bit_depth);
+    let wanted = (
+        expected.width,
+        expected.height,
+        expected.color_type,
+        expected.bit_depth,
+    );
+    if found != wanted {
+        return Err(format!(
+            "decoded as {}x{} {:?} at {:?}, but should be {}x{} {:?} at {:?}",
+            found.0, found.1, found.2, found.3, wanted.0, wanted.1, wanted.2, wanted.3
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_check_png() {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, 2, 1);
+    encoder.set_color(ColorType::Grayscale);
+    encoder.set_depth(BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(&[0, 255])
+        .unwrap();
+    let expected = ExpectedPng {
+        width: 2,
+        height: 1,
+        color_type: ColorType::Grayscale,
+        bit_depth: BitDepth::Eight,
+        crc32: crc32fast::hash(&png),
+    };
+    assert_eq!(check_png(&png, expected.crc32, Some(&expected)), Ok(()));
+    assert!(check_png(&png, expected.crc32, None).is_ok());
+    assert!(check_png(&png, expected.crc32 ^ 1, Some(&expected)).is_err());
+    let wider = ExpectedPng {
+        width: 4,
+        ..expected
+    };
+    assert!(check_png(&png, expected.crc32, Some(&wider)).is_err());
+    assert!(check_png(&png[..png.len() - 20], expected.crc32, None).is_err());
+}
@@ -0,0 +1,171 @@
+use std::io::{Cursor, Read, Write};
+
+use once_cell::sync::Lazy;
+use zip::read::ZipFile;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::{flag_present, parsed_option};
+
+/// The extra field that [SimpleFileOptions::with_alignment] pads a local header with, so that the
+/// entry's data starts at a multiple of the alignment.
+const ALIGNMENT_FIELD_ID: u16 = 0xd935;
+
+/// Set with `--zip-align <bytes>` and `--zip-strip-extra`. Applied where the entries of a finished
+/// ZIP file are written: by [crate::image_tasks::png_output::finish_zip], and again by
+/// [crate::image_tasks::realms::fit_to_realms] if it rewrites the file.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ZipLayout {
+    /// Uncompressed entries' data starts at a multiple of this many bytes, so that a launcher or
+    /// tool can map a texture from the archive into memory and read it in place.
+    pub align: Option<u16>,
+    /// The finished file has no extra fields other than alignment padding, and no comments, for
+    /// the smallest archive whose bytes depend only on the entries' names and contents. Entries
+    /// are copied without them anyway; this checks that none that can't be dropped, such as a
+    /// ZIP64 field, was written.
+    pub strip_extra: bool,
+}
+
+pub static ZIP_LAYOUT: Lazy<ZipLayout> = Lazy::new(|| {
+    let align = parsed_option::<u16>("zip-align");
+    assert!(
+        align.is_none_or(|align| align.is_power_of_two()),
+        "--zip-align must be a power of two"
+    );
+    ZipLayout {
+        align: align.filter(|align| *align > 1),
+        strip_extra: flag_present("zip-strip-extra"),
+    }
+});
+
+/// The IDs of the fields in an extra field block.
+fn extra_field_ids(extra: &[u8]) -> Vec<u16> {
+    let mut ids = Vec::new();
+    let mut rest = extra;
+    while rest.len() >= 4 {
+        ids.push(u16::from_le_bytes([rest[0], rest[1]]));
+        let len = 4 + u16::from_le_bytes([rest[2], rest[3]]) as usize;
+        rest = rest.get(len..).unwrap_or_default();
+    }
+    ids
+}
+
+impl ZipLayout {
+    /// Adds a raw entry of another ZIP file to `out`. A stored entry is written again so that its
+    /// data is aligned; it doesn't need compressing, so this is as cheap as a raw copy.
+    pub fn copy_entry(
+        &self,
+        out: &mut ZipWriter<Cursor<Vec<u8>>>,
+        mut entry: ZipFile,
+    ) -> Result<(), CloneableError> {
+        match self.align {
+            Some(align) if entry.compression() == CompressionMethod::Stored => {
+                let mut data = Vec::with_capacity(entry.size() as usize);
+                entry.read_to_end(&mut data)?;
+                let name: Box<str> = entry.name().into();
+                drop(entry);
+                let options = SimpleFileOptions::default()
+                    .compression_method(CompressionMethod::Stored)
+                    .with_alignment(align);
+                out.start_file(&*name, options)?;
+                out.write_all(&data)?;
+            }
+            _ => out.raw_copy_file(entry)?,
+        }
+        Ok(())
+    }
+
+    /// `options`, aligned if that's set. Only worth it for a stored entry, but a compressed one
+    /// is at most `align` bytes bigger for it.
+    pub fn aligned(&self, options: SimpleFileOptions) -> SimpleFileOptions {
+        match self.align {
+            Some(align) => options.with_alignment(align),
+            None => options,
+        }
+    }
+
+    /// With `strip_extra`, fails if a finished ZIP file has a comment or an extra field other than
+    /// alignment padding, rather than dropping a field that readers may need.
+    pub fn check(&self, zip: &[u8]) -> Result<(), CloneableError> {
+        if !self.strip_extra {
+            return Ok(());
+        }
+        let mut archive = ZipArchive::new(Cursor::new(zip))?;
+        if !archive.comment().is_empty() {
+            return Err(anyhoo!("ZIP file has a comment, despite --zip-strip-extra"));
+        }
+        for index in 0..archive.len() {
+            let entry = archive.by_index_raw(index)?;
+            let ids = extra_field_ids(entry.extra_data().unwrap_or_default());
+            if let Some(id) = ids.iter().find(|id| **id != ALIGNMENT_FIELD_ID) {
+                return Err(anyhoo!(
+                    "{} has an extra field with ID {:#06x}, despite --zip-strip-extra",
+                    entry.name(),
+                    id
+                ));
+            }
+            if !entry.comment().is_empty() {
+                return Err(anyhoo!(
+                    "{} has a comment, despite --zip-strip-extra",
+                    entry.name()
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_zip_layout() {
+    let contents: [(&str, CompressionMethod, &[u8]); 3] = [
+        ("pack.mcmeta", CompressionMethod::Deflated, b"{}"),
+        ("a.png", CompressionMethod::Stored, b"odd length"),
+        ("assets/b.png", CompressionMethod::Stored, b"xyz"),
+    ];
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer.set_comment("built by a test");
+    for (name, method, data) in contents {
+        writer
+            .start_file(
+                name,
+                SimpleFileOptions::default().compression_method(method),
+            )
+            .unwrap();
+        writer.write_all(data).unwrap();
+    }
+    let zip = writer.finish().unwrap().into_inner();
+
+    let layout = ZipLayout {
+        align: Some(64),
+        strip_extra: true,
+    };
+    assert!(layout.check(&zip).is_err());
+    let mut source = ZipArchive::new(Cursor::new(&zip[..])).unwrap();
+    let mut out = ZipWriter::new(Cursor::new(Vec::new()));
+    for index in 0..source.len() {
+        layout
+            .copy_entry(&mut out, source.by_index_raw(index).unwrap())
+            .unwrap();
+    }
+    let aligned = out.finish().unwrap().into_inner();
+    assert_eq!(layout.check(&aligned), Ok(()));
+    let mut archive = ZipArchive::new(Cursor::new(&aligned[..])).unwrap();
+    for (index, (name, method, expected)) in contents.into_iter().enumerate() {
+        let mut file = archive.by_index(index).unwrap();
+        assert_eq!(file.name(), name);
+        assert_eq!(file.compression(), method);
+        if method == CompressionMethod::Stored {
+            assert_eq!(file.data_start() % 64, 0);
+        }
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).unwrap();
+        assert_eq!(data, expected);
+    }
+
+    assert_eq!(
+        extra_field_ids(&[0x35, 0xd9, 2, 0, 64, 0, 1, 0, 0, 0]),
+        [0xd935, 1]
+    );
+}
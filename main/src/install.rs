@@ -0,0 +1,119 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{info, warn};
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::option_value;
+use crate::texture_base::version::TARGET_VERSION;
+
+/// Returns the directory that `--install` writes the pack to: the value of `--install-dir` if it's
+/// set, or else the `resourcepacks` folder of the default Minecraft launcher on this platform.
+pub fn resourcepacks_dir() -> Result<PathBuf, CloneableError> {
+    if let Some(dir) = option_value("install-dir") {
+        return Ok(PathBuf::from(dir));
+    }
+    let minecraft_dir = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA").ok_or_else(|| anyhoo!("APPDATA isn't set"))?)
+            .join(".minecraft")
+    } else {
+        let home = PathBuf::from(env::var_os("HOME").ok_or_else(|| anyhoo!("HOME isn't set"))?);
+        if cfg!(target_os = "macos") {
+            home.join("Library/Application Support/minecraft")
+        } else {
+            home.join(".minecraft")
+        }
+    };
+    Ok(minecraft_dir.join("resourcepacks"))
+}
+
+fn build_name_prefix(tile_size: u32) -> String {
+    format!("OcHD-{}x{}", tile_size, tile_size)
+}
+
+/// Name for the installed ZIP file, which includes this crate's version and any
+/// `--target-version` so that builds for different game versions can be installed side by side.
+pub fn install_file_name(tile_size: u32) -> String {
+    let mut name = format!(
+        "{}-{}",
+        build_name_prefix(tile_size),
+        env!("CARGO_PKG_VERSION")
+    );
+    if let Some(target_version) = *TARGET_VERSION {
+        name.push_str(&format!("-mc{}", target_version));
+    }
+    name.push_str(".zip");
+    name
+}
+
+/// Writes the finished ZIP file to `dir`, creating it if needed. With `remove_old_builds`, also
+/// deletes any other build of the pack at the same tile size from that directory.
+pub fn install(
+    zip_contents: &[u8],
+    tile_size: u32,
+    dir: &Path,
+    remove_old_builds: bool,
+) -> Result<PathBuf, CloneableError> {
+    fs::create_dir_all(dir)?;
+    let file_name = install_file_name(tile_size);
+    let path = dir.join(&file_name);
+    fs::write(&path, zip_contents)?;
+    info!("Installed pack to {}", path.to_string_lossy());
+    if remove_old_builds {
+        let prefix = build_name_prefix(tile_size);
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let Some(name) = name.to_str() else {
+                continue;
+            };
+            if name != file_name && is_build_of(name, &prefix) {
+                info!("Removing old build {}", name);
+                if let Err(e) = fs::remove_file(entry.path()) {
+                    warn!("Failed to remove old build {}: {}", name, e);
+                }
+            }
+        }
+    }
+    Ok(path)
+}
+
+/// Matches `OcHD-32x32.zip` as written to the output directory, and the versioned names from
+/// [install_file_name], but not other tile sizes.
+fn is_build_of(name: &str, prefix: &str) -> bool {
+    name.strip_prefix(prefix)
+        .and_then(|rest| rest.strip_suffix(".zip"))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+}
+
+#[test]
+fn test_install() {
+    let dir = env::temp_dir().join(format!("ochd-test-install-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for name in [
+        "OcHD-32x32.zip",
+        "OcHD-32x32-0.0.1.zip",
+        "OcHD-320x320-0.0.1.zip",
+        "OcHD-32x32-notes.txt",
+    ] {
+        fs::write(dir.join(name), []).unwrap();
+    }
+    let path = install(b"zip", 32, &dir, true).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"zip");
+    let mut remaining: Vec<String> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    remaining.sort();
+    assert_eq!(
+        remaining,
+        [
+            "OcHD-320x320-0.0.1.zip",
+            &install_file_name(32),
+            "OcHD-32x32-notes.txt"
+        ]
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
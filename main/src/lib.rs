@@ -0,0 +1,148 @@
+#![feature(absolute_path)]
+#![feature(const_type_id)]
+#![feature(let_chains)]
+#![feature(macro_metavar_expr)]
+#![feature(const_trait_impl)]
+#![feature(lazy_cell)]
+#![feature(async_closure)]
+#![feature(future_join)]
+#![feature(array_chunks)]
+#![feature(const_fn_floating_point_arithmetic)]
+
+use std::hint::unreachable_unchecked;
+use std::str::FromStr;
+
+use log::info;
+use tokio::task::JoinSet;
+
+#[cfg(not(any(test, clippy, fuzzing)))]
+use once_cell::sync::Lazy;
+
+pub mod cli;
+pub mod config;
+pub mod image_tasks;
+pub mod install;
+pub mod materials;
+pub mod profile;
+pub mod texture_base;
+pub mod u8set;
+
+/// How many texels per side most SVGs are drawn on, unless `--grid-size` says otherwise.
+pub const DEFAULT_GRID_SIZE: u32 = 32;
+
+/// The size that grid-perfect images are rendered at before they're upscaled to the tile size. Set
+/// with `--grid-size`, e.g. to 16 when the source SVGs are drawn on a 16-texel grid; images drawn
+/// on a finer grid can declare it with [image_tasks::task_spec::ToPixmapTaskSpec::OnGrid].
+#[cfg(not(any(test, clippy, fuzzing)))]
+pub static GRID_SIZE: Lazy<u32> = Lazy::new(|| {
+    let grid_size = parsed_option("grid-size").unwrap_or(DEFAULT_GRID_SIZE);
+    assert!(
+        grid_size > 0 && TILE_SIZES.iter().all(|size| size.is_multiple_of(grid_size)),
+        "--grid-size must divide every tile size"
+    );
+    grid_size
+});
+
+#[cfg(any(test, clippy, fuzzing))]
+pub const GRID_SIZE: &u32 = &DEFAULT_GRID_SIZE;
+
+/// The tile sizes given on the command line (see [cli::Target]), or the `--profile`'s tile size if
+/// they're left out, or [cli::MIN_TILE_SIZE] for a subcommand that doesn't build the pack. Building
+/// several sizes in one run shares the images that are rendered at [GRID_SIZE] among them. Sorted
+/// from smallest to largest.
+#[cfg(not(any(test, clippy, fuzzing)))]
+pub static TILE_SIZES: Lazy<Box<[u32]>> = Lazy::new(|| {
+    cli::CLI
+        .tile_sizes()
+        .or_else(|| profile::selected_profile().map(|profile| vec![profile.tile_size]))
+        .or_else(|| (!cli::CLI.needs_tile_size()).then(|| vec![cli::MIN_TILE_SIZE]))
+        .unwrap_or_else(|| {
+            cli::usage_error(
+                clap::error::ErrorKind::MissingRequiredArgument,
+                "a tile size is required unless --profile sets one",
+            )
+        })
+        .into_boxed_slice()
+});
+
+#[cfg(any(test, clippy, fuzzing))]
+pub const TILE_SIZES: &[u32] = &[128];
+
+/// The largest of [TILE_SIZES]. Choices that are made once per run, such as which size of pixmap
+/// is pooled and how hard to compress PNGs, are made for it.
+#[cfg(not(any(test, clippy, fuzzing)))]
+pub static TILE_SIZE: Lazy<u32> = Lazy::new(|| *TILE_SIZES.last().unwrap());
+
+#[cfg(any(test, clippy, fuzzing))]
+pub const TILE_SIZE: &u32 = &128;
+
+/// Returns the value of the option `--<name>` from the command line, or else of the environment
+/// variable `OCHD_<NAME>`, as [cli::BuildOptions] reads them, or else of `<name>` in the
+/// [config::CONFIG] file, or else the value the [profile::selected_profile] gives it.
+pub fn option_value(name: &str) -> Option<String> {
+    cli::CLI
+        .options()
+        .value(name)
+        .map(str::to_owned)
+        .or_else(|| configured_value(name))
+}
+
+/// The value of `<name>` in the [config::CONFIG] file, or else the one the
+/// [profile::selected_profile] gives it.
+fn configured_value(name: &str) -> Option<String> {
+    config::CONFIG
+        .option_value(name)
+        .map(str::to_owned)
+        .or_else(|| {
+            profile::selected_profile()?
+                .option_value(name)
+                .map(str::to_owned)
+        })
+}
+
+/// Whether the option `--<name>` is turned on: it is if [option_value] finds it, unless its value
+/// is `false`. For options that don't need a value; `--<name>` on its own means `--<name>=true`.
+pub fn flag_present(name: &str) -> bool {
+    option_value(name).is_some_and(|value| value != "false")
+}
+
+/// Parses the value of an option found by [option_value], panicking with a useful message if it's
+/// present but malformed.
+pub fn parsed_option<T: FromStr>(name: &str) -> Option<T> {
+    option_value(name).map(|value| parse_option(name, value))
+}
+
+/// Parses `<name>` from the config file or the profile as [parsed_option] does, for the options
+/// that [cli::Cli] declares itself, such as `--jobs`, whose command-line and environment values are
+/// already in its fields.
+pub fn parsed_configured_option<T: FromStr>(name: &str) -> Option<T> {
+    configured_value(name).map(|value| parse_option(name, value))
+}
+
+fn parse_option<T: FromStr>(name: &str, value: String) -> T {
+    value
+        .parse()
+        .unwrap_or_else(|_| panic!("Invalid value for --{}: {}", name, value))
+}
+
+#[allow(unreachable_code)]
+#[allow(unused_variables)]
+#[inline(always)]
+pub const fn debug_assert_unreachable(msg: &'static str) -> ! {
+    if cfg!(debug_assertions) {
+        panic!("{}", msg);
+    }
+    unsafe { unreachable_unchecked() }
+}
+
+pub fn remove_finished<T: 'static>(task_futures: &mut JoinSet<T>) {
+    while task_futures.try_join_next().is_some() {
+        info!("try_join_next received a finished task");
+    }
+}
+
+pub async fn join_all<T: 'static>(mut join_set: JoinSet<T>) {
+    while join_set.join_next().await.is_some() {
+        remove_finished(&mut join_set);
+    }
+}
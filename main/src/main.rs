@@ -1,42 +1,72 @@
 #![feature(absolute_path)]
-#![feature(const_type_id)]
-#![feature(let_chains)]
-#![feature(macro_metavar_expr)]
-#![feature(const_trait_impl)]
-#![feature(lazy_cell)]
-#![feature(async_closure)]
-#![feature(future_join)]
-#![feature(array_chunks)]
-
-use std::path::{absolute, PathBuf};
+
+use std::path::{absolute, Path, PathBuf};
 use std::time::{Duration, Instant};
 
 use log::{info, warn};
-use texture_base::material::Material;
-use tokio::runtime::{Builder, Handle};
+use ochd::texture_base::material::Material;
+use parking_lot::Mutex;
+use tokio::runtime::{Builder, Handle, Runtime};
 
-use crate::image_tasks::task_spec::{
-    FileOutputTaskSpec, TaskGraphBuildingContext, TaskSpecTraits, METADATA_DIR,
+use ochd::image_tasks::task_spec::{
+    audit_render_layers, legacy_name_alias, FileOutputTaskSpec, PackId, TaskGraphBuildingContext,
+    TaskSpecTraits, METADATA_DIR,
 };
 
-mod image_tasks;
-mod materials;
-mod texture_base;
-mod u8set;
-
-use crate::image_tasks::png_output::{copy_in_to_out};
-use crate::image_tasks::prewarm_pixmap_pool;
-use crate::image_tasks::repaint::prewarm_mask_pool;
 use futures_util::FutureExt;
-use image_tasks::cloneable::CloneableError;
-use include_dir::{Dir, DirEntry};
-#[cfg(not(any(test, clippy)))]
-use once_cell::sync::Lazy;
-#[cfg(not(any(test, clippy)))]
-use std::env;
+use itertools::Itertools;
+use ochd::cli::{Command, CLI};
+use ochd::config::CONFIG;
+use ochd::image_tasks::alpha_debug::finish_alpha_debug;
+use ochd::image_tasks::block_usage::trim_to_block_usage;
+use ochd::image_tasks::build_stats::{BuildStats, EntrySize, SizeManifest};
+use ochd::image_tasks::cache::finish_cache_report;
+use ochd::image_tasks::cloneable::CloneableError;
+use ochd::image_tasks::color_budget::COLOR_BUDGET;
+use ochd::image_tasks::correction_report::finish_correction_report;
+use ochd::image_tasks::dead_layers::eliminate_dead_layers;
+use ochd::image_tasks::debug_bundle::{finish_debug_bundle, start_debug_bundle};
+use ochd::image_tasks::dir_output::{DirectoryOutput, DEFAULT_MAX_CONCURRENT_WRITES};
+use ochd::image_tasks::dither::dither_shading;
+use ochd::image_tasks::expr::parse_expr;
+use ochd::image_tasks::from_svg::SVG_OVERRIDE_DIR;
+use ochd::image_tasks::graph_export::write_task_graph;
+use ochd::image_tasks::grid_check::verify_grid_perfect_svgs;
+use ochd::image_tasks::high_contrast::high_contrast_output;
+use ochd::image_tasks::memory_timeline::{finish_memory_timeline, sample_memory_timeline};
+use ochd::image_tasks::metadata::{
+    finish_metadata_copies, spawn_metadata_copies, validate_metadata,
+};
+use ochd::image_tasks::overrides::finish_override_report;
+use ochd::image_tasks::pack_meta::{check_pack_icon, spawn_pack_metadata};
+use ochd::image_tasks::palette_export::{PackPalette, PaletteFormat};
+use ochd::image_tasks::png_budget::{expect_pngs, finish_png_budget_report};
+use ochd::image_tasks::png_output::{finish_zip, ZipBufferRaw};
+use ochd::image_tasks::prewarm_pixmap_pool;
+use ochd::image_tasks::realms::{
+    check_realms_size, fit_to_realms, FIT_REALMS, FIT_REALMS_CATEGORIES,
+};
+use ochd::image_tasks::repaint::prewarm_mask_pool;
+use ochd::image_tasks::seam_report::finish_seam_report;
+use ochd::image_tasks::search::{search, SearchTerm};
+use ochd::image_tasks::subtask::finish_subtask_report;
+use ochd::image_tasks::svg_usage::{write_svg_usage_report, SVG_USAGE_REPORT};
+use ochd::image_tasks::texture_filter::filter_textures;
+use ochd::image_tasks::tint_preview::write_tint_preview;
+use ochd::image_tasks::vanilla_parity::finish_vanilla_parity_report;
+use ochd::image_tasks::verify::{verify_zip, VERIFY_ARCHIVE};
+use ochd::image_tasks::zip_layout::ZIP_LAYOUT;
+use ochd::install::{install, resourcepacks_dir};
+use ochd::texture_base::theme::THEME;
+use ochd::{
+    anyhoo, flag_present, join_all, materials, option_value, parsed_configured_option,
+    parsed_option, remove_finished, GRID_SIZE, TILE_SIZE, TILE_SIZES,
+};
 use std::fs;
 use std::fs::{create_dir_all, File};
-use std::hint::unreachable_unchecked;
+use std::mem::replace;
+use std::ops::DerefMut;
+use std::sync::Arc;
 use std::thread::available_parallelism;
 
 use tikv_jemallocator::Jemalloc;
@@ -44,82 +74,98 @@ use tokio::task::JoinSet;
 use tokio::time::sleep;
 
 use tracing_subscriber::fmt::format::FmtSpan;
-
-const GRID_SIZE: u32 = 32;
-
-#[cfg(not(any(test, clippy)))]
-static ARGS: Lazy<Vec<String>> = Lazy::new(|| env::args().collect());
-
-#[cfg(not(any(test, clippy)))]
-static TILE_SIZE: Lazy<u32> = Lazy::new(|| {
-    ARGS.get(1)
-        .expect("Usage: OcHd-RustBuild <tile-size>")
-        .parse::<u32>()
-        .expect("Tile size (first command-line argument) must be an integer")
-});
-
-#[cfg(any(test, clippy))]
-const TILE_SIZE: &u32 = &128;
+use zip::ZipWriter;
 
 #[global_allocator]
 static ALLOCATOR: Jemalloc = Jemalloc;
 
-#[allow(unreachable_code)]
-#[allow(unused_variables)]
-#[inline(always)]
-pub const fn debug_assert_unreachable(msg: &'static str) -> ! {
-    if cfg!(debug_assertions) {
-        panic!("{}", msg);
-    }
-    unsafe { unreachable_unchecked() }
-}
+const MIN_METRICS_INTERVAL: Duration = Duration::from_secs(5);
 
-fn copy_metadata(source_dir: &Dir) {
-    source_dir.entries().iter().for_each(|entry| match entry {
-        DirEntry::Dir(dir) => {
-            copy_metadata(dir);
-        }
-        DirEntry::File(file) => {
-            copy_in_to_out(file, file.path().to_string_lossy().into(), ctx.zip)
-                .expect("Failed to copy a file");
-        }
-    });
-}
+/// Number of small outputs that [add_and_spawn_batch] groups into one task.
+const SMALL_TASK_BATCH_SIZE: usize = 16;
 
-const MIN_METRICS_INTERVAL: Duration = Duration::from_secs(5);
+/// Growth beyond which `--compare-baseline` reports a file, unless overridden with
+/// `--max-size-growth`.
+const DEFAULT_MAX_SIZE_GROWTH: f64 = 0.05;
 
 fn main() -> Result<(), CloneableError> {
     tracing_subscriber::fmt()
         .with_writer(File::create("./log.txt")?)
+        .with_max_level(CLI.log_level)
         .with_span_events(FmtSpan::ACTIVE)
         .init();
-    let out_dir = PathBuf::from("./out");
-    let out_file = out_dir.join(format!("OcHD-{}x{}.zip", *TILE_SIZE, *TILE_SIZE));
-    info!(
-        "Writing output to {}",
-        absolute(&out_file)?.to_string_lossy()
-    );
-    let tile_size: u32 = *TILE_SIZE;
-    info!("Using {} pixels per tile", tile_size);
+    if let Some(svg_dir) = &*SVG_OVERRIDE_DIR {
+        if !svg_dir.is_dir() {
+            return Err(anyhoo!(
+                "--svg-dir {} is not a directory",
+                svg_dir.to_string_lossy()
+            ));
+        }
+        info!(
+            "Using SVGs from {} in place of the built-in ones",
+            absolute(svg_dir)?.to_string_lossy()
+        );
+    }
+    validate_metadata(&METADATA_DIR)?;
     let mut runtime = Builder::new_multi_thread();
     runtime.enable_time();
-    match available_parallelism() {
-        Ok(parallelism) => {
-            let adjusted_parallelism = parallelism.get() + 1;
-            if adjusted_parallelism.count_ones() <= 1 {
-                warn!(
-                    "Adjusting CPU count from {} to {}",
-                    parallelism, adjusted_parallelism
-                );
-                // Compensate for missed CPU core on m7g.16xlarge
-                runtime.worker_threads(adjusted_parallelism);
-            } else {
-                info!("Rayon thread pool has {} threads", parallelism);
-            }
+    match CLI.jobs.or_else(|| parsed_configured_option("workers")) {
+        Some(workers) => {
+            info!("Using {} worker threads as configured", workers);
+            runtime.worker_threads(workers as usize);
         }
-        Err(e) => warn!("Unable to get available parallelism: {}", e),
+        None => match available_parallelism() {
+            Ok(parallelism) => {
+                let adjusted_parallelism = parallelism.get() + 1;
+                if adjusted_parallelism.count_ones() <= 1
+                    && parsed_option("adjust-parallelism").unwrap_or(true)
+                {
+                    warn!(
+                        "Adjusting CPU count from {} to {}",
+                        parallelism, adjusted_parallelism
+                    );
+                    // Compensate for missed CPU core on m7g.16xlarge
+                    runtime.worker_threads(adjusted_parallelism);
+                } else {
+                    info!("Rayon thread pool has {} threads", parallelism);
+                }
+            }
+            Err(e) => warn!("Unable to get available parallelism: {}", e),
+        },
+    }
+    if let Some(blocking_threads) = parsed_option::<usize>("blocking-threads") {
+        info!("Using up to {} blocking threads", blocking_threads);
+        runtime.max_blocking_threads(blocking_threads);
     }
     let runtime = runtime.build()?;
+    match &CLI.command {
+        Some(Command::Palette { file, .. }) => return export_palette(&runtime, file),
+        Some(Command::Search { term, .. }) => return search_materials(term),
+        Some(Command::Preview { expr, file, .. }) => return render_expr(&runtime, expr, file),
+        Some(Command::List(_)) => return list_textures(),
+        Some(Command::Build(_)) | None => {}
+    }
+    check_pack_icon()?;
+    let output_dir = CLI
+        .out
+        .clone()
+        .or_else(|| parsed_configured_option("output-dir"));
+    let out_dir = output_dir.clone().unwrap_or_else(|| PathBuf::from("./out"));
+    let out_file = |tile_size: u32| out_dir.join(format!("OcHD-{}x{}.zip", tile_size, tile_size));
+    let high_contrast_out_file =
+        |tile_size: u32| out_dir.join(format!("OcHD-HighContrast-{}x{}.zip", tile_size, tile_size));
+    let loose_files_dir = out_dir.clone();
+    if output_dir.is_some() && TILE_SIZES.len() > 1 {
+        return Err(anyhoo!(
+            "--out can only be used with one tile size, not {:?}",
+            *TILE_SIZES
+        ));
+    }
+    info!(
+        "Writing output to {}",
+        absolute(output_dir.as_ref().unwrap_or(&out_file(*TILE_SIZE)))?.to_string_lossy()
+    );
+    info!("Using {:?} pixels per tile", *TILE_SIZES);
     runtime.spawn(async move {
         loop {
             sleep(MIN_METRICS_INTERVAL).await;
@@ -159,58 +205,432 @@ fn main() -> Result<(), CloneableError> {
             log_metric_per_worker!(m, worker_total_busy_duration);
         }
     });
+    runtime.spawn(sample_memory_timeline());
     let start_time = Instant::now();
     let handle = runtime.handle();
     let _ = handle.enter();
     let mut task_futures = JoinSet::new();
+    let mut ctx: TaskGraphBuildingContext = TaskGraphBuildingContext::new();
+    ctx.output_dir = output_dir.map(|output_dir| {
+        Arc::new(DirectoryOutput::new(
+            output_dir,
+            parsed_option("max-concurrent-writes").unwrap_or(DEFAULT_MAX_CONCURRENT_WRITES),
+        ))
+    });
+    ctx.svg_render_size = parsed_option("render-svgs-at");
+    if let Some(mirror_dir) = option_value("also-write-dir") {
+        if ctx.output_dir.is_some() {
+            warn!("Ignoring --also-write-dir, since --out is set");
+        } else if TILE_SIZES.len() > 1 {
+            warn!("Ignoring --also-write-dir, since more than one tile size is being built");
+        } else {
+            ctx.mirror_dir = Some(Arc::new(DirectoryOutput::new(
+                PathBuf::from(mirror_dir),
+                parsed_option("max-concurrent-writes").unwrap_or(DEFAULT_MAX_CONCURRENT_WRITES),
+            )));
+        }
+    }
+    // With --high-contrast, a second pack is built from the same materials in its own context, so
+    // that its nodes and texture_of() names don't mix with the main pack's
+    let mut high_contrast_ctx = if !flag_present("high-contrast") {
+        None
+    } else if ctx.output_dir.is_some() {
+        warn!("Ignoring --high-contrast, since --out is set");
+        None
+    } else {
+        let mut high_contrast_ctx = TaskGraphBuildingContext::new();
+        high_contrast_ctx.svg_render_size = ctx.svg_render_size;
+        Some(high_contrast_ctx)
+    };
+    let metadata_sinks = ctx.output_sinks();
+    let metadata_high_contrast_sinks = high_contrast_ctx
+        .as_ref()
+        .map(|high_contrast_ctx| high_contrast_ctx.output_sinks());
+    let created_dir = out_dir.clone();
     task_futures.spawn_on(
-        async {
+        async move {
             prewarm_pixmap_pool();
             prewarm_mask_pool();
             info!("Caches prewarmed");
-            create_dir_all(out_dir).expect("Failed to create output directory");
+            create_dir_all(created_dir).expect("Failed to create output directory");
             info!("Output directory built");
-            copy_metadata(&METADATA_DIR);
-            info!("Metadata copied");
         },
         handle,
     );
-    handle.block_on(async {
-        let mut ctx: TaskGraphBuildingContext = TaskGraphBuildingContext::new();
-        let out_tasks = materials::ALL_MATERIALS.get_output_tasks();
-        let mut small_tasks = Vec::with_capacity(out_tasks.len());
-        for task in out_tasks.into_vec().into_iter() {
-            let small = match task {
-                FileOutputTaskSpec::PngOutput { ref base, .. } => {
-                    tile_size > GRID_SIZE && base.is_grid_perfect(&mut ctx)
-                }
-                FileOutputTaskSpec::Copy { .. } => true,
+    let writing_zip = ctx.output_dir.is_none();
+    let packs = handle.block_on(async {
+        spawn_metadata_copies(&METADATA_DIR, &metadata_sinks, &mut task_futures);
+        if let Some(high_contrast_sinks) = metadata_high_contrast_sinks {
+            spawn_metadata_copies(&METADATA_DIR, &high_contrast_sinks, &mut task_futures);
+        }
+        let mut out_tasks = all_output_tasks();
+        if let Some(theme) = THEME.as_ref() {
+            out_tasks = out_tasks.iter().map(|task| theme.apply(task)).collect();
+        }
+        if flag_present("dithered-shading") {
+            out_tasks = out_tasks.iter().map(dither_shading).collect();
+        }
+        ctx.add_texture_names(&out_tasks);
+        // Before anything renders, since texture_of() a disabled group's texture would panic
+        let out_tasks = ctx.without_missing_textures(out_tasks);
+        let unpruned_tasks = SVG_USAGE_REPORT.is_some().then(|| out_tasks.clone());
+        let out_tasks = eliminate_dead_layers(out_tasks, &mut ctx, &TILE_SIZES).await;
+        if let Some(unpruned_tasks) = unpruned_tasks {
+            write_svg_usage_report(&unpruned_tasks, &out_tasks)?;
+        }
+        // So that texture_of() shares the pruned graph
+        ctx.add_texture_names(&out_tasks);
+        // Before filtering, like the main pack's, since pack.png may use a texture that's left out
+        if let Some(high_contrast_ctx) = high_contrast_ctx.as_mut() {
+            let high_contrast_tasks: Vec<FileOutputTaskSpec> =
+                out_tasks.iter().map(high_contrast_output).collect();
+            high_contrast_ctx.add_texture_names(&high_contrast_tasks);
+        }
+        // After adding the names, since a texture that's trimmed may still be used by another
+        let out_tasks = trim_to_block_usage(out_tasks);
+        let out_tasks = filter_textures(out_tasks);
+        start_debug_bundle(&out_tasks)?;
+        for &tile_size in TILE_SIZES.iter() {
+            audit_render_layers(&out_tasks, &mut ctx, tile_size).await?;
+            if let Some(budget) = COLOR_BUDGET.as_ref() {
+                budget.audit(&out_tasks, &mut ctx, tile_size).await?;
+            }
+        }
+        verify_grid_perfect_svgs(&out_tasks, &mut ctx)?;
+        write_tint_preview(&mut ctx, *TILE_SIZE).await?;
+        let high_contrast_tasks: Option<Vec<FileOutputTaskSpec>> = high_contrast_ctx
+            .is_some()
+            .then(|| out_tasks.iter().map(high_contrast_output).collect());
+        let pngs_per_size = out_tasks
+            .iter()
+            .chain(high_contrast_tasks.iter().flatten())
+            .filter(|task| matches!(task, FileOutputTaskSpec::PngOutput { .. }))
+            .count();
+        expect_pngs(pngs_per_size * TILE_SIZES.len());
+        // Every size is added to the same graph, so that images rendered at GRID_SIZE for one
+        // size are reused by the others
+        let mut packs = Vec::with_capacity(TILE_SIZES.len());
+        for (index, &tile_size) in TILE_SIZES.iter().enumerate() {
+            ctx.pack = PackId {
+                tile_size,
+                high_contrast: false,
+            };
+            if index > 0 {
+                ctx.start_another_pack();
+                spawn_metadata_copies(&METADATA_DIR, &ctx.output_sinks(), &mut task_futures);
+            }
+            spawn_pack_metadata(&mut ctx, tile_size, &mut task_futures);
+            let mut pack = Pack {
+                tile_size,
+                zip: ctx.zip_writer.clone(),
+                high_contrast_zip: None,
             };
-            if small {
-                small_tasks.push(task);
-            } else {
-                add_and_spawn(&task, &mut task_futures, tile_size, &mut ctx);
+            if let Some(high_contrast_ctx) = high_contrast_ctx.as_mut()
+                && let Some(high_contrast_tasks) = &high_contrast_tasks
+            {
+                high_contrast_ctx.pack = PackId {
+                    tile_size,
+                    high_contrast: true,
+                };
+                if index > 0 {
+                    high_contrast_ctx.start_another_pack();
+                    spawn_metadata_copies(
+                        &METADATA_DIR,
+                        &high_contrast_ctx.output_sinks(),
+                        &mut task_futures,
+                    );
+                }
+                spawn_pack_metadata(high_contrast_ctx, tile_size, &mut task_futures);
+                pack.high_contrast_zip = Some(high_contrast_ctx.zip_writer.clone());
+                for task in high_contrast_tasks.iter() {
+                    add_and_spawn(task, &mut task_futures, tile_size, high_contrast_ctx);
+                }
+                info!(
+                    "All {}x{} high-contrast output tasks added to graph",
+                    tile_size, tile_size
+                );
+            }
+            packs.push(pack);
+            let mut small_tasks = Vec::with_capacity(out_tasks.len());
+            for task in out_tasks.iter() {
+                let small = match task {
+                    FileOutputTaskSpec::PngOutput { base, .. } => {
+                        tile_size > *GRID_SIZE && base.is_grid_perfect(&mut ctx)
+                    }
+                    FileOutputTaskSpec::Copy { .. } => true,
+                };
+                if small {
+                    small_tasks.push(task.to_owned());
+                } else {
+                    add_and_spawn(task, &mut task_futures, tile_size, &mut ctx);
+                }
             }
+            info!(
+                "All {}x{} large output tasks added to graph",
+                tile_size, tile_size
+            );
+            for batch in small_tasks.chunks(SMALL_TASK_BATCH_SIZE) {
+                add_and_spawn_batch(batch, &mut task_futures, &mut ctx);
+            }
+            info!(
+                "All {}x{} small output tasks added to graph",
+                tile_size, tile_size
+            );
         }
-        info!("All large output tasks added to graph");
-        small_tasks.into_iter().for_each(|task| {
-            add_and_spawn(&task, &mut task_futures, GRID_SIZE, &mut ctx);
-        });
+        write_task_graph(&ctx)?;
         drop(ctx);
-        info!("All small output tasks added to graph");
+        drop(high_contrast_ctx);
         remove_finished(&mut task_futures);
         join_all(task_futures).await;
-    });
-    let zip_contents = ZIP
-        .lock()
-        .deref_mut()
-        .finish()
-        .expect("Failed to finalize ZIP file")
-        .into_inner();
-    info!("ZIP file size is {} bytes", zip_contents.len());
-    drop(runtime); // Aborts any background tasks
-    fs::write(out_file.as_path(), zip_contents)?;
+        finish_metadata_copies()?;
+        Ok::<_, CloneableError>(packs)
+    })?;
+    if writing_zip {
+        let mut finished_zips = Vec::with_capacity(packs.len());
+        for pack in packs {
+            let zip_contents = pack.finish(&high_contrast_out_file(pack.tile_size))?;
+            finished_zips.push((pack.tile_size, zip_contents));
+        }
+        drop(runtime); // Aborts any background tasks
+        for (tile_size, zip_contents) in finished_zips {
+            fs::write(out_file(tile_size).as_path(), &zip_contents)?;
+            // Only the largest size, so that the files that --stats-json, --write-manifest and
+            // --compare-baseline name describe the same pack as when one size is built
+            if tile_size == *TILE_SIZE {
+                report_build_stats(BuildStats::entry_sizes_in_zip(&zip_contents)?)?;
+            }
+            if *VERIFY_ARCHIVE {
+                verify_zip(
+                    PackId {
+                        tile_size,
+                        high_contrast: false,
+                    },
+                    &zip_contents,
+                )?;
+            }
+            if flag_present("install") {
+                install(
+                    &zip_contents,
+                    tile_size,
+                    &resourcepacks_dir()?,
+                    flag_present("remove-old-builds"),
+                )?;
+            }
+        }
+    } else {
+        drop(runtime); // Aborts any background tasks
+        report_build_stats(BuildStats::entry_sizes_in_dir(&loose_files_dir)?)?;
+        if flag_present("install") {
+            warn!("Ignoring --install, since --out is set");
+        }
+    }
     info!("Finished after {} ns", start_time.elapsed().as_nanos());
+    finish_override_report();
+    finish_cache_report();
+    finish_png_budget_report();
+    finish_subtask_report();
+    finish_memory_timeline()?;
+    finish_seam_report()?;
+    finish_vanilla_parity_report()?;
+    finish_debug_bundle()?;
+    finish_alpha_debug()?;
+    finish_correction_report()
+}
+
+/// The ZIP files being built for one tile size.
+struct Pack {
+    tile_size: u32,
+    zip: Arc<Mutex<ZipWriter<ZipBufferRaw>>>,
+    high_contrast_zip: Option<Arc<Mutex<ZipWriter<ZipBufferRaw>>>>,
+}
+
+impl Pack {
+    /// Finalizes the ZIP files, each fitted to the Realms limit if `--fit-realms` is set, writes
+    /// the high-contrast one if any to `high_contrast_out_file`, and returns the contents of the
+    /// main one.
+    fn finish(&self, high_contrast_out_file: &Path) -> Result<Vec<u8>, CloneableError> {
+        let zip_contents = finish_zip(replace(
+            self.zip.lock().deref_mut(),
+            ZipWriter::new(ZipBufferRaw::new(vec![])),
+        ))
+        .expect("Failed to finalize ZIP file");
+        info!(
+            "{}x{} ZIP file size is {} bytes",
+            self.tile_size,
+            self.tile_size,
+            zip_contents.len()
+        );
+        let zip_contents = fit_zip_to_realms(
+            PackId {
+                tile_size: self.tile_size,
+                high_contrast: false,
+            },
+            zip_contents,
+        )?;
+        ZIP_LAYOUT.check(&zip_contents)?;
+        if let Some(high_contrast_zip) = &self.high_contrast_zip {
+            let high_contrast_contents = finish_zip(replace(
+                high_contrast_zip.lock().deref_mut(),
+                ZipWriter::new(ZipBufferRaw::new(vec![])),
+            ))
+            .expect("Failed to finalize high-contrast ZIP file");
+            info!(
+                "High-contrast ZIP file size is {} bytes",
+                high_contrast_contents.len()
+            );
+            let high_contrast = PackId {
+                tile_size: self.tile_size,
+                high_contrast: true,
+            };
+            let high_contrast_contents = fit_zip_to_realms(high_contrast, high_contrast_contents)?;
+            ZIP_LAYOUT.check(&high_contrast_contents)?;
+            fs::write(high_contrast_out_file, &high_contrast_contents)?;
+            if *VERIFY_ARCHIVE {
+                verify_zip(high_contrast, &high_contrast_contents)?;
+            }
+        }
+        Ok(zip_contents)
+    }
+}
+
+/// Checks a finished ZIP file against the Realms limit, and if it's over and `--fit-realms` is set,
+/// returns it shrunk to fit.
+fn fit_zip_to_realms(pack: PackId, zip_contents: Vec<u8>) -> Result<Vec<u8>, CloneableError> {
+    if check_realms_size(pack, &zip_contents)? || !*FIT_REALMS {
+        return Ok(zip_contents);
+    }
+    let (fitted, shrunk_paths) =
+        fit_to_realms(pack, zip_contents, FIT_REALMS_CATEGORIES.as_deref())?;
+    info!(
+        "Shrank {} files to fit the Realms limit; {} ZIP file size is now {} bytes: {:?}",
+        shrunk_paths.len(),
+        pack,
+        fitted.len(),
+        shrunk_paths
+    );
+    Ok(fitted)
+}
+
+/// Every output task the materials define, along with their aliases under legacy names.
+fn all_output_tasks() -> Vec<FileOutputTaskSpec> {
+    let mut out_tasks = materials::ALL_MATERIALS.get_output_tasks().into_vec();
+    CONFIG.warn_unknown_groups();
+    let legacy_aliases: Vec<FileOutputTaskSpec> =
+        out_tasks.iter().flat_map(legacy_name_alias).collect();
+    out_tasks.extend(legacy_aliases);
+    out_tasks
+}
+
+/// Runs `OcHd-RustBuild list [<tile-size>]`, which prints the path of every file that `--only`,
+/// `--exclude` and `--block-usage` leave to build, in order.
+fn list_textures() -> Result<(), CloneableError> {
+    let out_tasks = filter_textures(trim_to_block_usage(all_output_tasks()));
+    let paths: Vec<_> = out_tasks
+        .iter()
+        .flat_map(FileOutputTaskSpec::get_paths)
+        .sorted()
+        .collect();
+    for path in paths.iter() {
+        println!("{}", path);
+    }
+    info!("Listed {} files", paths.len());
+    Ok(())
+}
+
+/// Runs `OcHd-RustBuild palette <file> [<tile-size>]`, which writes out every color the pack is
+/// predicted to use instead of the pack itself. The format comes from the file's extension.
+fn export_palette(runtime: &Runtime, path: &Path) -> Result<(), CloneableError> {
+    let format = PaletteFormat::for_path(path)?;
+    let palette = runtime.block_on(async {
+        let mut ctx = TaskGraphBuildingContext::new();
+        PackPalette::collect(&materials::named_groups(), &mut ctx, *TILE_SIZE).await
+    });
+    info!(
+        "Writing {} colors to {}",
+        palette.len(),
+        path.to_string_lossy()
+    );
+    fs::write(path, palette.export(format)?)?;
+    Ok(())
+}
+
+/// Runs `OcHd-RustBuild search <term> [<tile-size>]`, which lists the textures whose path contains
+/// the term, that use an SVG or other layer with that name, or that paint with that color if it's
+/// one, along with the group of materials each is defined in.
+fn search_materials(term: &str) -> Result<(), CloneableError> {
+    let term: SearchTerm = term.into();
+    let matches = search(&materials::named_groups(), &term);
+    for found in matches.iter() {
+        println!("{}", found);
+    }
+    info!("{} textures matched {:?}", matches.len(), term);
+    Ok(())
+}
+
+/// Runs `OcHd-RustBuild preview <expr> <out.png> [<tile-size>] [--size <size>]`, which renders one
+/// expression as [parse_expr] reads it to a PNG, at the tile size unless `--size` is given.
+fn render_expr(runtime: &Runtime, expr: &str, path: &Path) -> Result<(), CloneableError> {
+    let size = parsed_option("size").unwrap_or_else(|| *TILE_SIZE);
+    let spec = parse_expr(expr)?;
+    info!("Rendering {} at {}", spec, size);
+    let png = runtime.block_on(async {
+        let mut ctx = TaskGraphBuildingContext::new();
+        // So that texture_of() can refer to any texture in the pack
+        ctx.add_texture_names(&materials::ALL_MATERIALS.get_output_tasks());
+        spec.add_to(&mut ctx, size).await.encode_png()
+    })?;
+    fs::write(path, png)?;
+    info!("Wrote {}", path.to_string_lossy());
+    Ok(())
+}
+
+/// Prints a summary of the output's size, and writes it as JSON to the file named by
+/// `--stats-json`, if any. With `--write-manifest`, also writes the size of every file; with
+/// `--compare-baseline`, lists the files that grew by more than `--max-size-growth` (a fraction)
+/// since the build that wrote the given manifest.
+fn report_build_stats(entries: Vec<EntrySize>) -> Result<(), CloneableError> {
+    let manifest = SizeManifest::new(&entries);
+    let stats = BuildStats::new(entries, &materials::named_groups());
+    print!("{}", stats.summary());
+    if let Some(path) = option_value("stats-json") {
+        fs::write(path, stats.to_json())?;
+    }
+    if let Some(path) = option_value("write-manifest") {
+        fs::write(path, manifest.to_json())?;
+    }
+    if let Some(path) = option_value("compare-baseline") {
+        let baseline: SizeManifest = fs::read_to_string(&path)?.parse()?;
+        let max_growth = parsed_option("max-size-growth").unwrap_or(DEFAULT_MAX_SIZE_GROWTH);
+        let regressions = manifest.regressions_from(&baseline, max_growth);
+        if regressions.is_empty() {
+            println!(
+                "No file grew by more than {:.0}% since {}",
+                max_growth * 100.0,
+                path
+            );
+        } else {
+            warn!(
+                "{} files grew by more than {:.0}%",
+                regressions.len(),
+                max_growth * 100.0
+            );
+            println!(
+                "{} files grew by more than {:.0}% since {}:",
+                regressions.len(),
+                max_growth * 100.0,
+                path
+            );
+            for regression in regressions {
+                println!(
+                    "  {:+8} bytes ({} -> {})  {}",
+                    regression.current as i64 - regression.baseline as i64,
+                    regression.baseline,
+                    regression.current,
+                    regression.path
+                );
+            }
+        }
+    }
     Ok(())
 }
 
@@ -227,14 +647,25 @@ fn add_and_spawn(
         .expect("Error adding task to graph");
 }
 
-fn remove_finished<T: 'static>(task_futures: &mut JoinSet<T>) {
-    while task_futures.try_join_next().is_some() {
-        info!("try_join_next received a finished task");
-    }
-}
-
-pub async fn join_all<T: 'static>(mut join_set: JoinSet<T>) {
-    while join_set.join_next().await.is_some() {
-        remove_finished(&mut join_set);
-    }
+/// Spawns one task that adds several grid-size outputs in turn, so that the worker running it
+/// reuses the same encoding buffers for all of them, and the scheduler has far fewer tasks to
+/// juggle.
+fn add_and_spawn_batch(
+    tasks: &[FileOutputTaskSpec],
+    task_futures: &mut JoinSet<()>,
+    ctx: &mut TaskGraphBuildingContext,
+) {
+    let futures: Vec<_> = tasks
+        .iter()
+        .map(|task| task.add_to(ctx, *GRID_SIZE))
+        .collect();
+    task_futures
+        .build_task()
+        .name(&format!("{} and {} more", tasks[0], tasks.len() - 1))
+        .spawn(async move {
+            for future in futures {
+                future.await;
+            }
+        })
+        .expect("Error adding task to graph");
 }
@@ -1,3 +1,4 @@
+use crate::group;
 use crate::image_tasks::color::{c, ComparableColor};
 use crate::image_tasks::task_spec::{from_svg_task, paint_svg_task};
 use crate::materials::block::axe::wood::{BIRCH, DARK_OAK, OAK, OAK_COLOR, SPRUCE};
@@ -7,11 +8,12 @@ use crate::materials::block::bare_hand::simple_bare_hand_block::{
 use crate::paint_stack;
 use crate::single_texture_block;
 use crate::stack_on;
-use crate::texture_base::material::SingleLayerMaterial;
-use crate::{copy_block, group};
+use crate::texture_base::material::{introduced_in, SingleLayerMaterial};
+use crate::texture_base::version::MinecraftVersion;
 
 single_texture_block!(
-    CRAFTING_TABLE_SIDE = ComparableColor::TRANSPARENT,
+    CRAFTING_TABLE_SIDE,
+    CRAFTING_TABLE_FRONT = ComparableColor::TRANSPARENT,
     stack_on!(
         OAK.color,
         paint_svg_task("waves2", OAK.highlight),
@@ -20,7 +22,6 @@ single_texture_block!(
     paint_svg_task("borderSolid", OAK.highlight),
     paint_svg_task("craftingSide", DARK_OAK.color)
 );
-copy_block!(CRAFTING_TABLE_FRONT = CRAFTING_TABLE_SIDE, "");
 single_texture_block!(
     CRAFTING_TABLE_TOP = OAK.color,
     paint_svg_task("waves", OAK.highlight),
@@ -36,6 +37,7 @@ single_texture_block!(
     paint_svg_task("railTies", OAK.highlight)
 );
 single_texture_block!(BOOKSHELF = OAK.color, from_svg_task("bookShelves"));
+const CHISELED_BOOKSHELF_VERSION: MinecraftVersion = MinecraftVersion::new(1, 20, 0);
 single_texture_block!(
     CHISELED_BOOKSHELF_EMPTY = OAK.color,
     from_svg_task("bookShelvesChiseledEmpty")
@@ -153,11 +155,10 @@ single_texture_block!(
 group!(
     SIMPLE_AXE_BLOCK = CRAFTING_TABLE_SIDE,
     CRAFTING_TABLE_TOP,
-    CRAFTING_TABLE_FRONT,
     LADDER,
     BOOKSHELF,
-    CHISELED_BOOKSHELF_EMPTY,
-    CHISELED_BOOKSHELF,
+    introduced_in(&*CHISELED_BOOKSHELF_EMPTY, CHISELED_BOOKSHELF_VERSION),
+    introduced_in(&*CHISELED_BOOKSHELF, CHISELED_BOOKSHELF_VERSION),
     JUKEBOX_TOP,
     JUKEBOX_SIDE,
     NOTE_BLOCK,
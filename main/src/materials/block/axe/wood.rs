@@ -1,13 +1,22 @@
 use crate::image_tasks::color::{c, ComparableColor};
+use crate::image_tasks::crop::TileRect;
 use crate::image_tasks::task_spec::{
-    from_svg_task, out_task, paint_svg_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+    crop_task, from_svg_task, out_task, paint_svg_task, FileOutputTaskSpec, ToPixmapTaskSpec,
 };
-use crate::{group, paint_stack, stack, stack_on};
+use crate::{group, layer_group, paint_stack, stack, stack_on};
 use once_cell::sync::Lazy;
 
 use crate::texture_base::material::{
-    Material, TextureBinaryFunc, TextureSupplier, TextureUnaryFunc, TricolorMaterial,
+    introduced_in, Material, TextureSupplier, TextureUnaryFunc, TricolorMaterial,
 };
+use crate::texture_base::version::PALE_GARDEN_VERSION;
+
+/// Supplies the layers that a wood's door and trapdoor have in common, if it has any.
+pub type DoorCommonLayersSupplier = Box<dyn Fn(&Wood) -> Option<ToPixmapTaskSpec> + Send + Sync>;
+pub type DoorFunc = Box<dyn Fn(&Wood, Option<ToPixmapTaskSpec>) -> ToPixmapTaskSpec + Send + Sync>;
+pub type DoorTopFunc = Box<
+    dyn Fn(&Wood, ToPixmapTaskSpec, Option<ToPixmapTaskSpec>) -> ToPixmapTaskSpec + Send + Sync,
+>;
 
 pub struct Wood {
     pub color: ComparableColor,
@@ -29,12 +38,14 @@ pub struct Wood {
     stripped_log_side: TextureSupplier<Wood>,
     log_top: TextureUnaryFunc<Wood>,
     stripped_log_top: TextureSupplier<Wood>,
-    trapdoor: TextureUnaryFunc<Wood>,
-    door_top: TextureBinaryFunc<Wood>,
-    door_bottom: TextureUnaryFunc<Wood>,
+    trapdoor: DoorFunc,
+    door_top: DoorTopFunc,
+    door_bottom: DoorFunc,
     leaves: TextureSupplier<Wood>,
     sapling: TextureSupplier<Wood>,
-    door_common_layers: TextureSupplier<Wood>,
+    door_common_layers: DoorCommonLayersSupplier,
+    /// Whether the wood has boats and chest boats, which nether fungi don't.
+    has_boats: bool,
 }
 
 impl Wood {
@@ -73,11 +84,7 @@ impl Wood {
     }
 
     pub fn overworld_stripped_log_side(&self) -> ToPixmapTaskSpec {
-        stack!(
-            self.grain(),
-            paint_svg_task("borderSolid", self.shadow),
-            paint_svg_task("borderShortDashes", self.highlight)
-        )
+        stack!(self.grain(), layer_group!("dashedBorder", self.colors()))
     }
 
     pub fn fungus_stripped_log_side(&self) -> ToPixmapTaskSpec {
@@ -123,10 +130,67 @@ impl Wood {
         )
     }
 
+    fn door_textures(&self) -> (ToPixmapTaskSpec, ToPixmapTaskSpec) {
+        let door_common_layers: Option<ToPixmapTaskSpec> = (self.door_common_layers)(self);
+        let door_bottom: ToPixmapTaskSpec = (self.door_bottom)(self, door_common_layers.to_owned());
+        let door_top = (self.door_top)(self, door_bottom.to_owned(), door_common_layers);
+        (door_top, door_bottom)
+    }
+
+    /// Item icons for the wood's door, sign and boats, built by cropping and scaling its block
+    /// textures so that they follow any change to those.
+    pub fn item_icons(&self) -> Box<[FileOutputTaskSpec]> {
+        let (door_top, door_bottom) = self.door_textures();
+        let stripped_log_side = (self.stripped_log_side)(self);
+        let mut icons = vec![
+            out_task(
+                format!("item/{}_door", self.name),
+                stack!(
+                    crop_task(door_top, TileRect::FULL, TileRect::new(4, 0, 8, 8)),
+                    crop_task(door_bottom, TileRect::FULL, TileRect::new(4, 8, 8, 8))
+                ),
+            ),
+            out_task(
+                format!("item/{}_sign", self.name),
+                stack!(
+                    crop_task(
+                        stripped_log_side.to_owned(),
+                        TileRect::new(6, 0, 4, 16),
+                        TileRect::new(7, 10, 2, 6)
+                    ),
+                    crop_task(self.planks(), TileRect::FULL, TileRect::new(1, 2, 14, 8))
+                ),
+            ),
+        ];
+        if self.has_boats {
+            let boat = stack!(
+                crop_task(
+                    self.planks(),
+                    TileRect::new(0, 4, 16, 8),
+                    TileRect::new(1, 8, 14, 4)
+                ),
+                crop_task(
+                    stripped_log_side,
+                    TileRect::new(0, 0, 16, 2),
+                    TileRect::new(0, 6, 16, 2)
+                )
+            );
+            icons.push(out_task(
+                format!("item/{}_chest_boat", self.name),
+                stack!(
+                    boat.to_owned(),
+                    crop_task(self.planks(), TileRect::FULL, TileRect::new(5, 1, 6, 5))
+                ),
+            ));
+            icons.push(out_task(format!("item/{}_boat", self.name), boat));
+        }
+        icons.into_boxed_slice()
+    }
+
     pub fn default_door_top(
         &self,
         door_bottom: ToPixmapTaskSpec,
-        _: ToPixmapTaskSpec,
+        _: Option<ToPixmapTaskSpec>,
     ) -> ToPixmapTaskSpec {
         stack!(door_bottom, from_svg_task("doorKnob"))
     }
@@ -146,10 +210,8 @@ impl TricolorMaterial for Wood {
     }
 }
 
-pub fn empty_task() -> Box<dyn (Fn(&Wood) -> ToPixmapTaskSpec) + Sync + Send> {
-    Box::new(
-        /*door_common_layers*/ |_wood| ToPixmapTaskSpec::None {},
-    )
+pub fn empty_task() -> DoorCommonLayersSupplier {
+    Box::new(/*door_common_layers*/ |_wood| None)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -161,10 +223,10 @@ pub fn overworld_wood(
     bark_color: ComparableColor,
     bark_highlight: ComparableColor,
     bark_shadow: ComparableColor,
-    door_common_layers: TextureSupplier<Wood>,
-    trapdoor: TextureUnaryFunc<Wood>,
-    door_bottom: TextureUnaryFunc<Wood>,
-    door_top: TextureBinaryFunc<Wood>,
+    door_common_layers: DoorCommonLayersSupplier,
+    trapdoor: DoorFunc,
+    door_bottom: DoorFunc,
+    door_top: DoorTopFunc,
     leaves: TextureSupplier<Wood>,
     sapling: TextureSupplier<Wood>,
 ) -> Wood {
@@ -194,6 +256,7 @@ pub fn overworld_wood(
         leaves,
         sapling,
         door_common_layers,
+        has_boats: true,
     }
 }
 
@@ -209,8 +272,8 @@ pub fn nether_fungus(
     leaves_color: ComparableColor,
     leaves_highlight: ComparableColor,
     leaves_shadow: ComparableColor,
-    trapdoor: TextureUnaryFunc<Wood>,
-    door_bottom: TextureUnaryFunc<Wood>,
+    trapdoor: DoorFunc,
+    door_bottom: DoorFunc,
     leaves: TextureSupplier<Wood>,
     sapling: TextureSupplier<Wood>,
 ) -> Wood {
@@ -240,6 +303,7 @@ pub fn nether_fungus(
         door_top: Box::new(Wood::default_door_top),
         leaves,
         sapling,
+        has_boats: false,
     };
 }
 
@@ -253,11 +317,11 @@ pub static ACACIA: Lazy<Wood> = Lazy::new(|| {
         c(0x898977),
         c(0x4a4a39),
         Box::new(/*door_common_layers*/ |_wood| {
-            stack!(
+            Some(stack!(
                 paint_svg_task("borderSolidThick", ACACIA.color),
                 paint_svg_task("borderSolid", ACACIA.highlight),
                 paint_svg_task("bigDiamond", ACACIA.shadow)
-            )
+            ))
         }),
         Box::new(/*trapdoor*/ |_wood, door_common_layers| {
             stack!(
@@ -305,13 +369,13 @@ pub static BIRCH: Lazy<Wood> = Lazy::new(|| {
         ComparableColor::WHITE,
         c(0x5f5f4f),
         Box::new(/*door_common_layers*/ |_wood| {
-            stack_on!(
+            Some(stack_on!(
                 BIRCH.bark_highlight,
                 paint_svg_task("borderSolidExtraThick", BIRCH.color),
                 paint_svg_task("craftingGridSquare", BIRCH.highlight),
                 paint_svg_task("craftingGridSpaces", BIRCH.bark_highlight),
                 paint_svg_task("borderSolid", BIRCH.shadow)
-            )
+            ))
         }),
         Box::new(/*trapdoor*/ |_wood, door_common_layers| {
             stack!(
@@ -363,12 +427,12 @@ pub static DARK_OAK: Lazy<Wood> = Lazy::new(|| {
         c(0x2b2000),
         c(0x624033),
         Box::new(/*door_common_layers*/ |_wood| {
-            stack_on!(
+            Some(stack_on!(
                 DARK_OAK.color,
                 paint_stack!(DARK_OAK.highlight, "borderSolid", "cross"),
                 paint_svg_task("2x2TopLeft", DARK_OAK.shadow),
                 paint_svg_task("borderShortDashes", DARK_OAK.color)
-            )
+            ))
         }),
         Box::new(/*trapdoor*/ |_wood, door_common_layers| {
             stack!(
@@ -408,10 +472,10 @@ pub static JUNGLE: Lazy<Wood> = Lazy::new(|| {
         c(0x2B2000),
         c(0x7b5c39),
         Box::new(/*door_common_layers*/ |_wood| {
-            stack!(
+            Some(stack!(
                 paint_svg_task("doorHingesBig", ComparableColor::STONE_SHADOW),
                 paint_svg_task("doorHinges", ComparableColor::STONE)
-            )
+            ))
         }),
         Box::new(/*trapdoor*/ |_wood, _door_common_layers| {
             stack!(
@@ -461,10 +525,10 @@ pub static MANGROVE: Lazy<Wood> = Lazy::new(|| {
         c(0x624033),
         c(0x4a4a39),
         Box::new(/*door_common_layers*/ |_wood| {
-            stack!(
+            Some(stack!(
                 paint_svg_task("rings2", MANGROVE.shadow),
                 paint_svg_task("borderDotted", MANGROVE.highlight)
-            )
+            ))
         }),
         Box::new(/*trapdoor*/ |_wood, door_common_layers| {
             stack!(
@@ -555,7 +619,7 @@ pub static OAK: Lazy<Wood> = Lazy::new(|| {
         c(0x987849),
         c(0x4a4a39),
         Box::new(/*door_common_layers*/ |_wood| {
-            stack!(
+            Some(stack!(
                 stack!(
                     paint_svg_task("borderSolidThick", OAK.color),
                     paint_svg_task("borderSolid", OAK.highlight)
@@ -565,7 +629,7 @@ pub static OAK: Lazy<Wood> = Lazy::new(|| {
                     paint_svg_task("2x2TopLeft", OAK.shadow),
                     paint_svg_task("borderShortDashes", OAK.color * 0.5)
                 )
-            )
+            ))
         }),
         Box::new(/*trapdoor*/ |_wood, door_common_layers| {
             stack!(
@@ -619,6 +683,67 @@ pub static OAK: Lazy<Wood> = Lazy::new(|| {
         }),
     )
 });
+pub static PALE_OAK: Lazy<Wood> = Lazy::new(|| {
+    let mut base = overworld_wood(
+        "pale_oak",
+        c(0xe4d9d3),
+        c(0xf6efeb),
+        c(0xb7a69e),
+        c(0x58504c),
+        c(0x7b716c),
+        c(0x3b3532),
+        Box::new(/*door_common_layers*/ |_wood| {
+            Some(stack!(
+                paint_svg_task("borderSolidThick", PALE_OAK.shadow),
+                paint_svg_task("borderSolid", PALE_OAK.bark_color),
+                paint_svg_task("craftingGridSpaces", PALE_OAK.shadow)
+            ))
+        }),
+        Box::new(/*trapdoor*/ |_wood, door_common_layers| {
+            stack!(
+                door_common_layers,
+                paint_svg_task("trapdoorHingesBig", ComparableColor::STONE_SHADOW),
+                paint_svg_task("trapdoorHinges", ComparableColor::STONE)
+            )
+        }),
+        Box::new(/*door_bottom*/ |_wood, door_common_layers| {
+            stack_on!(
+                PALE_OAK.color,
+                paint_svg_task("waves", PALE_OAK.highlight),
+                door_common_layers,
+                paint_svg_task("doorHingesBig", ComparableColor::STONE_SHADOW),
+                paint_svg_task("doorHinges", ComparableColor::STONE)
+            )
+        }),
+        Box::new(/*door_top*/ |_wood, door_bottom, _| {
+            stack!(
+                door_bottom,
+                paint_svg_task("craftingSide", PALE_OAK.bark_shadow),
+                from_svg_task("doorKnob")
+            )
+        }),
+        Box::new(/*leaves*/ |_wood| {
+            stack_on!(
+                PALE_OAK.leaves_color,
+                paint_svg_task("leaves5", PALE_OAK.leaves_highlight),
+                paint_svg_task("leaves5a", PALE_OAK.leaves_shadow)
+            )
+        }),
+        Box::new(/*sapling*/ |_wood| {
+            stack!(
+                paint_svg_task("saplingStem", PALE_OAK.bark_color),
+                paint_svg_task("flowerStemBottomBorder", PALE_OAK.bark_shadow),
+                paint_svg_task("saplingLeaves", PALE_OAK.leaves_color)
+            )
+        }),
+    );
+    // Pale oak leaves aren't tinted by the biome, so they have their own colors
+    base.leaves_color = c(0x858a7d);
+    base.leaves_highlight = c(0xa3a899);
+    base.leaves_shadow = c(0x5f6459);
+    base.grain_highlight_strength = 1.0;
+    base
+});
 const FUNGUS_SPOT_COLOR: ComparableColor = c(0xff6500);
 pub const CRIMSON_LEAVES_COLOR: ComparableColor = c(0x7b0000);
 pub const CRIMSON_LEAVES_HIGHLIGHT: ComparableColor = c(0xac2020);
@@ -734,8 +859,8 @@ pub static WARPED: Lazy<Wood> = Lazy::new(|| {
 
 impl Material for Wood {
     fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
-        let door_common_layers: ToPixmapTaskSpec = (self.door_common_layers)(self);
-        let door_bottom: ToPixmapTaskSpec = (self.door_bottom)(self, door_common_layers.to_owned());
+        let door_common_layers: Option<ToPixmapTaskSpec> = (self.door_common_layers)(self);
+        let (door_top, door_bottom) = self.door_textures();
         let stripped_log_side: ToPixmapTaskSpec = (self.stripped_log_side)(self);
         let stripped_log_top: ToPixmapTaskSpec = (self.stripped_log_top)(self);
         Box::new([
@@ -759,10 +884,7 @@ impl Material for Wood {
                 format!("block/{}_trapdoor", self.name),
                 (self.trapdoor)(self, door_common_layers.to_owned()),
             ),
-            out_task(
-                format!("block/{}_door_top", self.name),
-                (self.door_top)(self, door_bottom.to_owned(), door_common_layers),
-            ),
+            out_task(format!("block/{}_door_top", self.name), door_top),
             out_task(format!("block/{}_door_bottom", self.name), door_bottom),
             out_task(
                 format!("block/{}_{}", self.name, self.leaves_synonym),
@@ -784,7 +906,32 @@ group!(
     JUNGLE,
     MANGROVE,
     SPRUCE,
-    OAK
+    OAK,
+    introduced_in(&*PALE_OAK, PALE_GARDEN_VERSION)
 );
 group!(NETHER_FUNGUS = CRIMSON, WARPED);
 group!(WOOD = OVERWORLD_WOOD, NETHER_FUNGUS);
+
+/// The [Wood::item_icons] of a wood, as a separate material so that a config can leave them out.
+pub struct WoodItemIcons(pub &'static Wood);
+
+impl Material for WoodItemIcons {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        self.0.item_icons()
+    }
+}
+
+static PALE_OAK_ITEM_ICONS: Lazy<WoodItemIcons> = Lazy::new(|| WoodItemIcons(&PALE_OAK));
+
+group!(
+    WOOD_ITEM_ICONS = WoodItemIcons(&ACACIA),
+    WoodItemIcons(&BIRCH),
+    WoodItemIcons(&DARK_OAK),
+    WoodItemIcons(&JUNGLE),
+    WoodItemIcons(&MANGROVE),
+    WoodItemIcons(&SPRUCE),
+    WoodItemIcons(&OAK),
+    introduced_in(&*PALE_OAK_ITEM_ICONS, PALE_GARDEN_VERSION),
+    WoodItemIcons(&CRIMSON),
+    WoodItemIcons(&WARPED)
+);
@@ -3,7 +3,11 @@ use crate::image_tasks::task_spec::paint_svg_task;
 use crate::materials::block::axe::giant_mushroom::{
     BROWN_MUSHROOM_BACKGROUND, MUSHROOM_STEM_MAIN_COLOR, RED_MUSHROOM_BACKGROUND,
 };
-use crate::{block_with_colors, copy_block, group, single_layer_block, single_texture_block};
+use crate::materials::block::pickaxe::simple_pickaxe_block::RESIN_BLOCK;
+use crate::materials::block::shovel::simple_soft_earth::PALE_MOSS_BLOCK;
+use crate::texture_base::material::{introduced_in, TricolorMaterial};
+use crate::texture_base::version::{PALE_GARDEN_VERSION, SPRING_TO_LIFE_VERSION};
+use crate::{block_with_colors, group, single_layer_block, single_texture_block, tint_overlay};
 block_with_colors!(
     SUGARCANE = c(0xaadb74),
     c(0x82a859),
@@ -26,9 +30,40 @@ single_texture_block!(
     paint_svg_task("mushroomCapRed", RED_MUSHROOM_BACKGROUND)
 );
 
-single_layer_block!(REDSTONE_DUST_DOT = "redstone", ComparableColor::WHITE);
-single_layer_block!(REDSTONE_DUST_LINE0 = "redstoneLine", ComparableColor::WHITE);
-copy_block!(REDSTONE_DUST_LINE1 = REDSTONE_DUST_LINE0, "");
+// The game tints these by power level; see crate::image_tasks::tint_preview
+tint_overlay!(REDSTONE_DUST_DOT = paint_svg_task("redstone", ComparableColor::WHITE));
+tint_overlay!(
+    REDSTONE_DUST_LINE0,
+    REDSTONE_DUST_LINE1 = paint_svg_task("redstoneLine", ComparableColor::WHITE)
+);
+// Drawn untinted over the dust; transparent, as in vanilla
+single_layer_block!(
+    REDSTONE_DUST_OVERLAY = "redstone",
+    ComparableColor::TRANSPARENT
+);
+
+single_texture_block!(
+    PALE_HANGING_MOSS = ComparableColor::TRANSPARENT,
+    paint_svg_task("wavyVines", PALE_MOSS_BLOCK.shadow()),
+    paint_svg_task("vineBerries", PALE_MOSS_BLOCK.highlight())
+);
+
+single_texture_block!(
+    PALE_HANGING_MOSS_TIP = ComparableColor::TRANSPARENT,
+    paint_svg_task("wavyVinesBottom", PALE_MOSS_BLOCK.shadow())
+);
+
+single_texture_block!(
+    RESIN_CLUMP = ComparableColor::TRANSPARENT,
+    paint_svg_task("bonemealSmall", RESIN_BLOCK.shadow()),
+    paint_svg_task("bonemealSmallNoBorder", RESIN_BLOCK.highlight())
+);
+
+// The game tints this with the biome's dry foliage color
+tint_overlay!(
+    LEAF_LITTER = paint_svg_task("leaves6", ComparableColor::MEDIUM_BIOME_COLORABLE),
+    paint_svg_task("leaves6a", ComparableColor::LIGHT_BIOME_COLORABLE)
+);
 
 const TWISTING_VINE_COLOR: ComparableColor = c(0x008383);
 single_layer_block!(TWISTING_VINES_PLANT = "zigzagSolid", TWISTING_VINE_COLOR);
@@ -55,10 +90,14 @@ group!(
     RED_MUSHROOM,
     REDSTONE_DUST_DOT,
     REDSTONE_DUST_LINE0,
-    REDSTONE_DUST_LINE1,
+    REDSTONE_DUST_OVERLAY,
     TWISTING_VINES_PLANT,
     TWISTING_VINES,
     WEEPING_VINES_PLANT,
     WEEPING_VINES,
-    HONEYCOMB_BLOCK
+    HONEYCOMB_BLOCK,
+    introduced_in(&*PALE_HANGING_MOSS, PALE_GARDEN_VERSION),
+    introduced_in(&*PALE_HANGING_MOSS_TIP, PALE_GARDEN_VERSION),
+    introduced_in(&*RESIN_CLUMP, PALE_GARDEN_VERSION),
+    introduced_in(&*LEAF_LITTER, SPRING_TO_LIFE_VERSION)
 );
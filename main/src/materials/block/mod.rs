@@ -1,6 +1,7 @@
 use crate::group;
+use crate::texture_base::material::MaterialGroup;
 
-mod axe;
+pub(crate) mod axe;
 pub(crate) mod bare_hand;
 mod hoe;
 mod indestructible;
@@ -19,3 +20,20 @@ group!(
     hoe::HOE_BLOCKS,
     bare_hand::BARE_HAND_BLOCKS
 );
+
+/// The members of [ALL_BLOCKS] with their names, for reports that break the pack down by group.
+pub(crate) fn named_groups() -> [(&'static str, &'static MaterialGroup); 8] {
+    [
+        (
+            "block/indestructible",
+            &*indestructible::INDESTRUCTIBLE_BLOCKS,
+        ),
+        ("block/axe", &*axe::AXE_BLOCKS),
+        ("block/liquid", &*liquid::LIQUID_BLOCKS),
+        ("block/shears", &*shears::SHEAR_BLOCKS),
+        ("block/shovel", &*shovel::SHOVEL_BLOCKS),
+        ("block/pickaxe", &*pickaxe::PICKAXE_BLOCKS),
+        ("block/hoe", &*hoe::HOE_BLOCKS),
+        ("block/bare_hand", &*bare_hand::BARE_HAND_BLOCKS),
+    ]
+}
@@ -1,6 +1,14 @@
+use once_cell::sync::Lazy;
+
 use crate::image_tasks::color::{c, ComparableColor};
-use crate::image_tasks::task_spec::{out_task, paint_svg_task, FileOutputTaskSpec};
-use crate::texture_base::material::Material;
+use crate::image_tasks::task_spec::{
+    from_svg_task, out_task, paint_svg_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+};
+use crate::materials::block::pickaxe::ore::COPPER;
+use crate::texture_base::material::{
+    introduced_in, ColorTriad, Material, MaterialState, StatefulMaterial, REDSTONE_ON,
+};
+use crate::texture_base::version::MinecraftVersion;
 use crate::{group, paint_stack, stack, stack_on};
 
 struct CopperOxide {
@@ -38,6 +46,127 @@ impl Material for CopperOxide {
     }
 }
 
+impl CopperOxide {
+    const fn fixtures(&self, prefix: &'static str) -> CopperFixtures {
+        CopperFixtures {
+            prefix,
+            colors: ColorTriad {
+                color: self.color,
+                shadow: self.shadow,
+                highlight: self.highlight,
+            },
+        }
+    }
+}
+
+const COPPER_FIXTURES_VERSION: MinecraftVersion = MinecraftVersion::new(1, 21, 0);
+
+/// Color of the light inside a lit copper bulb, whatever its oxidation.
+const BULB_LIGHT: ComparableColor = c(0xffd27f);
+const BULB_LIGHT_SHADOW: ComparableColor = c(0xe6994a);
+
+/// Doors, trapdoors, grates and bulbs, which 1.21 added for copper at every stage of oxidation.
+struct CopperFixtures {
+    /// Prefix of each output's name, such as `exposed_`; empty for unoxidized copper.
+    prefix: &'static str,
+    colors: ColorTriad,
+}
+
+impl CopperFixtures {
+    fn frame(&self) -> ToPixmapTaskSpec {
+        stack!(
+            paint_stack!(self.colors.color, "borderSolidExtraThick", "cross"),
+            paint_svg_task("borderSolid", self.colors.shadow),
+            paint_svg_task("borderSolidTopLeft", self.colors.highlight)
+        )
+    }
+
+    fn bulb(&self, lit: bool, powered: bool) -> ToPixmapTaskSpec {
+        let (light, light_shadow) = if lit {
+            (BULB_LIGHT, BULB_LIGHT_SHADOW)
+        } else {
+            (self.colors.shadow, self.colors.color)
+        };
+        stack!(
+            stack_on!(light_shadow, paint_svg_task("glow", light)),
+            self.frame(),
+            paint_svg_task(
+                "tinyRing",
+                if powered {
+                    REDSTONE_ON
+                } else {
+                    self.colors.shadow
+                }
+            )
+        )
+    }
+}
+
+impl Material for CopperFixtures {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let door_bottom = stack_on!(
+            self.colors.color,
+            paint_svg_task("streaks", self.colors.highlight),
+            paint_stack!(self.colors.shadow, "borderSolid", "craftingGridSquare"),
+            paint_svg_task("doorHingesBig", self.colors.shadow)
+        );
+        let mut tasks = vec![
+            out_task(
+                format!("block/{}copper_door_top", self.prefix),
+                stack!(
+                    self.frame(),
+                    paint_svg_task("doorHingesBig", self.colors.shadow),
+                    from_svg_task("doorKnob")
+                ),
+            ),
+            out_task(
+                format!("block/{}copper_door_bottom", self.prefix),
+                door_bottom,
+            ),
+            out_task(
+                format!("block/{}copper_trapdoor", self.prefix),
+                stack!(
+                    self.frame(),
+                    paint_svg_task("trapdoorHingesBig", self.colors.shadow)
+                ),
+            ),
+            out_task(
+                format!("block/{}copper_grate", self.prefix),
+                stack!(
+                    paint_stack!(self.colors.color, "borderSolidThick", "gridSpacesCross4x"),
+                    paint_svg_task("borderSolid", self.colors.shadow),
+                    paint_svg_task("borderSolidTopLeft", self.colors.highlight)
+                ),
+            ),
+        ];
+        let bulb = StatefulMaterial {
+            name: format!("{}copper_bulb", self.prefix).into(),
+            states: [
+                (false, false, ""),
+                (true, false, "_lit"),
+                (false, true, "_powered"),
+                (true, true, "_lit_powered"),
+            ]
+            .map(|(lit, powered, suffix)| {
+                let state = MaterialState::new(suffix, self.bulb(lit, powered));
+                if lit {
+                    state.with_emissive(paint_svg_task("glow", BULB_LIGHT))
+                } else {
+                    state
+                }
+            })
+            .into(),
+        };
+        tasks.extend(bulb.get_output_tasks().into_vec());
+        tasks.into_boxed_slice()
+    }
+}
+
+static COPPER_FIXTURES: Lazy<CopperFixtures> = Lazy::new(|| CopperFixtures {
+    prefix: "",
+    colors: COPPER.refined_colors,
+});
+
 const EXPOSED_COPPER: CopperOxide = CopperOxide {
     name: "exposed",
     texture_name: "copper2oxideOneThird",
@@ -60,8 +189,16 @@ const OXIDIZED_COPPER: CopperOxide = CopperOxide {
     highlight: c(0x74BE9C),
 };
 
+const EXPOSED_COPPER_FIXTURES: CopperFixtures = EXPOSED_COPPER.fixtures("exposed_");
+const WEATHERED_COPPER_FIXTURES: CopperFixtures = WEATHERED_COPPER.fixtures("weathered_");
+const OXIDIZED_COPPER_FIXTURES: CopperFixtures = OXIDIZED_COPPER.fixtures("oxidized_");
+
 group!(
     COPPER_OXIDES = EXPOSED_COPPER,
     WEATHERED_COPPER,
-    OXIDIZED_COPPER
+    OXIDIZED_COPPER,
+    introduced_in(&*COPPER_FIXTURES, COPPER_FIXTURES_VERSION),
+    introduced_in(&EXPOSED_COPPER_FIXTURES, COPPER_FIXTURES_VERSION),
+    introduced_in(&WEATHERED_COPPER_FIXTURES, COPPER_FIXTURES_VERSION),
+    introduced_in(&OXIDIZED_COPPER_FIXTURES, COPPER_FIXTURES_VERSION)
 );
@@ -1,3 +1,4 @@
+use crate::image_tasks::animate::{FrameTiming, SheetLayout};
 use crate::image_tasks::color::ComparableColor;
 use crate::image_tasks::task_spec::{from_svg_task, paint_svg_task, ToPixmapTaskSpec};
 use crate::{group, material, single_texture_block, stack};
@@ -47,11 +48,13 @@ single_texture_block!(
 material!(
     BLAST_FURNACE_FRONT_ON = "block",
     ToPixmapTaskSpec::Animate {
-        background: Box::new(BLAST_FURNACE_FRONT_BASE.to_owned()),
+        background: BLAST_FURNACE_FRONT_BASE.to_owned().into(),
         frames: Box::new([
             from_svg_task("blastFurnaceHolesLit"),
             from_svg_task("blastFurnaceHolesLit1")
-        ])
+        ]),
+        layout: SheetLayout::Vertical,
+        timing: Some(FrameTiming::new(10))
     }
 );
 
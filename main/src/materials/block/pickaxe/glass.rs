@@ -1,30 +1,55 @@
 use crate::image_tasks::color::{c, ComparableColor};
+use crate::image_tasks::crop::TileRect;
+use crate::image_tasks::remap::PaletteMap;
 use crate::image_tasks::task_spec::{
-    from_svg_task, paint_svg_task, paint_task, stack_alpha, svg_alpha_task, ToAlphaChannelTaskSpec,
+    crop_task, from_svg_task, paint_svg_task, paint_task, remap_task, stack_alpha, svg_alpha_task,
+    texture_of, ToAlphaChannelTaskSpec, ToPixmapTaskSpec,
 };
 use crate::texture_base::material::SingleLayerMaterial;
-use crate::{dyed_block, group, paint_stack, single_texture_block};
+use crate::{dyed_block, group, material, paint_stack, single_texture_block};
 use once_cell::sync::Lazy;
 
+/// The left border of a glass block, which a pane's edge shows.
+const BLOCK_EDGE: TileRect = TileRect::new(0, 0, 1, 16);
+
+/// Where a pane's edge is drawn on its `_pane_edge` texture, as on its `_pane_top` texture.
+const PANE_EDGE: TileRect = TileRect::new(7, 0, 2, 16);
+
+/// A pane's `_pane_edge` texture, taken from the border of the block so that the two always match.
+fn pane_edge(block: ToPixmapTaskSpec) -> ToPixmapTaskSpec {
+    crop_task(block, BLOCK_EDGE, PANE_EDGE)
+}
+
 const GLASS_PANE_TOP: SingleLayerMaterial = SingleLayerMaterial {
     name: "block/glass_pane_top",
     layer_name: "paneTop",
     color: Some(c(0xa8d5d5)),
 };
 
+/// Every color of stained glass is the white one recolored, so that they share its rendered image.
+fn stained_glass(color: ComparableColor) -> ToPixmapTaskSpec {
+    remap_task(
+        paint_task(STAINED_GLASS_BASE.to_owned(), ComparableColor::WHITE),
+        PaletteMap::new([(ComparableColor::WHITE, color)]),
+    )
+}
+
 static STAINED_GLASS_BASE: Lazy<ToAlphaChannelTaskSpec> =
     Lazy::new(|| ToAlphaChannelTaskSpec::StackAlphaOnBackground {
         background: 0x40,
-        foreground: Box::new(stack_alpha(vec![
+        foreground: stack_alpha(vec![
             svg_alpha_task("borderSolid"),
             svg_alpha_task("streaks"),
-        ])),
+        ])
+        .into(),
     });
 
-dyed_block!(STAINED_GLASS = paint_task(STAINED_GLASS_BASE.to_owned(), color!()));
+dyed_block!(STAINED_GLASS = stained_glass(color!()));
 
 dyed_block!(STAINED_GLASS_PANE_TOP = paint_task(from_svg_task("paneTop").into(), color!()));
 
+dyed_block!(STAINED_GLASS_PANE_EDGE = pane_edge(stained_glass(color!())));
+
 single_texture_block!(
     GLASS = ComparableColor::TRANSPARENT,
     paint_svg_task("borderSolid", ComparableColor::STONE_EXTREME_SHADOW),
@@ -36,10 +61,17 @@ single_texture_block!(
     paint_stack!(ComparableColor::WHITE * 0.25, "borderSolid", "streaks")
 );
 
+material!(
+    GLASS_PANE_EDGE = "block",
+    pane_edge(texture_of("block/glass"))
+);
+
 group!(
     GLASS_VARIANTS = GLASS_PANE_TOP,
+    GLASS_PANE_EDGE,
     GLASS,
     TINTED_GLASS,
     STAINED_GLASS,
-    STAINED_GLASS_PANE_TOP
+    STAINED_GLASS_PANE_TOP,
+    STAINED_GLASS_PANE_EDGE
 );
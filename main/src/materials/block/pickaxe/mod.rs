@@ -1,4 +1,5 @@
 use crate::group;
+use crate::image_tasks::task_spec::RenderLayer::Solid;
 use crate::materials::block::pickaxe::bone_block::BONE_BLOCK;
 use crate::materials::block::pickaxe::concrete::CONCRETE;
 use crate::materials::block::pickaxe::copper_oxide::COPPER_OXIDES;
@@ -12,6 +13,8 @@ use crate::materials::block::pickaxe::ore_base::ORE_BASES;
 use crate::materials::block::pickaxe::polishable::POLISHABLE;
 use crate::materials::block::pickaxe::rail::RAILS;
 use crate::materials::block::pickaxe::simple_pickaxe_block::SIMPLE_PICKAXE_BLOCKS;
+use crate::materials::block::pickaxe::trial_chambers::TRIAL_CHAMBERS;
+use crate::texture_base::material::in_render_layer;
 
 mod bone_block;
 mod concrete;
@@ -26,19 +29,21 @@ pub mod ore_base;
 mod polishable;
 mod rail;
 pub mod simple_pickaxe_block;
+pub mod trial_chambers;
 
 group!(
-    PICKAXE_BLOCKS = ORE_BASES,
+    PICKAXE_BLOCKS = in_render_layer(&*ORE_BASES, Solid),
     SIMPLE_PICKAXE_BLOCKS,
-    ORES,
+    in_render_layer(&*ORES, Solid),
     RAILS,
     POLISHABLE,
     GLASS_VARIANTS,
     COPPER_OXIDES,
-    TERRACOTTA,
-    CONCRETE,
+    in_render_layer(&TERRACOTTA, Solid),
+    in_render_layer(&CONCRETE, Solid),
     NYLIUM,
     BONE_BLOCK,
     FURNACES,
-    MISC_REDSTONE
+    MISC_REDSTONE,
+    TRIAL_CHAMBERS
 );
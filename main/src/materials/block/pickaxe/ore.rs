@@ -8,7 +8,7 @@ use crate::materials::block::pickaxe::ore_base::{
 use crate::texture_base::material::{
     ColorTriad, Material, TextureSupplier, TextureUnaryFunc, TricolorMaterial, REDSTONE_ON,
 };
-use crate::{group, paint_stack, stack, stack_on};
+use crate::{group, layer_group, paint_stack, stack, stack_on};
 use once_cell::sync::Lazy;
 
 pub static OVERWORLD_SUBSTRATES: Lazy<Vec<OreBase>> =
@@ -250,8 +250,7 @@ pub static LAPIS: Lazy<Ore> = Lazy::new(|| {
             LAPIS.colors.highlight,
             paint_svg_task("checksLarge", LAPIS.colors.shadow),
             paint_svg_task("checksSmall", LAPIS.colors.color),
-            paint_svg_task("borderSolid", LAPIS.colors.shadow),
-            paint_svg_task("borderSolidTopLeft", LAPIS.colors.highlight)
+            layer_group!("bevel", LAPIS.colors)
         )
     });
     lapis.ore_block_for_substrate = Box::new(Ore::raw_item_based_ore_block_for_substrate);
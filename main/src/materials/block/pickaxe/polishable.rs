@@ -4,7 +4,7 @@ use crate::image_tasks::task_spec::{
 };
 use crate::materials::block::pickaxe::ore::GOLD;
 use crate::texture_base::material::{ColorTriad, Material, TricolorMaterial};
-use crate::{block_with_colors, group, paint_stack, single_texture_block, stack};
+use crate::{block_with_colors, group, layer_group, paint_stack, single_texture_block, stack};
 
 pub struct PolishableBlock {
     pub name: &'static str,
@@ -18,11 +18,7 @@ impl PolishableBlock {
     }
 
     fn polished_texture(&self) -> ToPixmapTaskSpec {
-        stack!(
-            self.texture(),
-            paint_svg_task("borderSolid", self.colors.shadow),
-            paint_svg_task("borderSolidTopLeft", self.colors.highlight)
-        )
+        stack!(self.texture(), layer_group!("bevel", self.colors))
     }
 }
 
@@ -1,6 +1,7 @@
 use crate::image_tasks::color::{c, ComparableColor};
 use crate::image_tasks::task_spec::{
-    from_svg_task, paint_svg_task, paint_task, stack_alpha, svg_alpha_task, ToPixmapTaskSpec,
+    from_svg_task, on_grid, paint_svg_task, paint_task, stack_alpha, svg_alpha_task, texture_of,
+    ToPixmapTaskSpec,
 };
 use crate::materials::block::pickaxe::ore::{COPPER, QUARTZ};
 use crate::materials::block::pickaxe::ore_base::DEEPSLATE;
@@ -8,16 +9,17 @@ use crate::materials::block::pickaxe::polishable::BLACKSTONE;
 use crate::materials::block::shovel::simple_soft_earth::{
     MOSS_BLOCK, MUD, PACKED_MUD, RED_SAND, SAND,
 };
-use crate::texture_base::material::{SingleTextureMaterial, TricolorMaterial};
+use crate::texture_base::material::{introduced_in, SingleTextureMaterial, TricolorMaterial};
+use crate::texture_base::version::PALE_GARDEN_VERSION;
 use crate::{
-    block_with_colors, group, make_tricolor_block_macro, paint_stack, single_texture_block,
-    stack_alpha, stack_on,
+    block_with_colors, group, layer_group, make_tricolor_block_macro, paint_stack,
+    single_texture_block, stack_alpha, stack_on,
 };
 use once_cell::sync::Lazy;
 
 single_texture_block!(
     DEEPSLATE_BRICKS = ComparableColor::TRANSPARENT,
-    DEEPSLATE.material.texture(),
+    texture_of("block/deepslate"),
     paint_svg_task("bricksSmall", DEEPSLATE.shadow()),
     paint_svg_task("borderDotted", DEEPSLATE.highlight()),
     paint_svg_task("borderDottedBottomRight", DEEPSLATE.shadow())
@@ -25,7 +27,7 @@ single_texture_block!(
 
 single_texture_block!(
     DEEPSLATE_TOP = ComparableColor::TRANSPARENT,
-    DEEPSLATE.material.texture(),
+    texture_of("block/deepslate"),
     paint_svg_task("cross", DEEPSLATE.shadow()),
     paint_svg_task("borderSolid", DEEPSLATE.highlight())
 );
@@ -78,7 +80,10 @@ stone!(
 static COBBLESTONE_BASE: Lazy<ToPixmapTaskSpec> = Lazy::new(|| {
     stack_on!(
         ComparableColor::STONE_HIGHLIGHT,
-        paint_svg_task("checksLarge", ComparableColor::STONE_SHADOW),
+        on_grid(
+            16,
+            paint_svg_task("checksLarge", ComparableColor::STONE_SHADOW)
+        ),
         paint_svg_task("checksSmall", ComparableColor::STONE)
     )
 });
@@ -129,8 +134,7 @@ sandstone!(
 sandstone!(
     CUT_SANDSTONE = color!(),
     paint_svg_task("checksLargeOutline", highlight!()),
-    paint_svg_task("borderSolid", shadow!()),
-    paint_svg_task("borderSolidTopLeft", highlight!()),
+    layer_group!("bevel"),
     paint_svg_task("borderLongDashes", color!())
 );
 
@@ -172,8 +176,7 @@ red_sandstone!(
 red_sandstone!(
     CUT_RED_SANDSTONE = color!(),
     paint_svg_task("checksLarge", highlight!()),
-    paint_svg_task("borderSolid", shadow!()),
-    paint_svg_task("borderSolidTopLeft", highlight!()),
+    layer_group!("bevel"),
     paint_svg_task("borderLongDashes", color!())
 );
 
@@ -223,8 +226,7 @@ basalt!(
     POLISHED_BASALT_TOP = color!(),
     paint_svg_task("bigRoundedSquare", shadow!()),
     paint_svg_task("rings", highlight!()),
-    paint_svg_task("borderSolid", shadow!()),
-    paint_svg_task("borderSolidTopLeft", highlight!()),
+    layer_group!("bevel"),
     paint_svg_task("cross", color!()),
     paint_svg_task("crossDotted", shadow!())
 );
@@ -232,8 +234,7 @@ basalt!(
 basalt!(
     POLISHED_BASALT_SIDE = color!(),
     paint_svg_task("stripesVerticalThick", highlight!()),
-    paint_svg_task("borderSolid", shadow!()),
-    paint_svg_task("borderSolidTopLeft", highlight!())
+    layer_group!("bevel")
 );
 
 block_with_colors!(
@@ -351,8 +352,7 @@ block_with_colors!(
     BLACKSTONE.shadow(),
     BLACKSTONE.highlight(),
     color!(),
-    paint_svg_task("borderSolid", shadow!()),
-    paint_svg_task("borderSolidTopLeft", highlight!()),
+    layer_group!("bevel"),
     paint_svg_task("bricksSmall", shadow!())
 );
 
@@ -435,8 +435,7 @@ purpur!(
     PURPUR_PILLAR_TOP = highlight!(),
     paint_svg_task("circle24", color!() * 0.75),
     paint_svg_task("borderSolidThick", color!()),
-    paint_svg_task("borderSolid", shadow!()),
-    paint_svg_task("borderSolidTopLeft", highlight!())
+    layer_group!("bevel")
 );
 
 block_with_colors!(
@@ -716,6 +715,36 @@ group!(
     ORANGE_GLAZED_TERRACOTTA,
     WHITE_GLAZED_TERRACOTTA
 );
+block_with_colors!(
+    RESIN_BLOCK = c(0xd9651a),
+    c(0x9c3a0b),
+    c(0xf8a035),
+    color!(),
+    paint_stack!(shadow!(), "borderSolid", "bigDotsFillTopLeftBottomRight"),
+    paint_svg_task("bigDotsBottomLeftTopRight", highlight!())
+);
+
+block_with_colors!(
+    RESIN_BRICKS = RESIN_BLOCK.color(),
+    RESIN_BLOCK.shadow(),
+    RESIN_BLOCK.highlight(),
+    color!(),
+    paint_svg_task("checksSmall", highlight!()),
+    paint_svg_task("bricksSmall", shadow!()),
+    paint_svg_task("borderShortDashes", highlight!())
+);
+
+block_with_colors!(
+    CHISELED_RESIN_BRICKS = RESIN_BLOCK.color(),
+    RESIN_BLOCK.shadow(),
+    RESIN_BLOCK.highlight(),
+    color!(),
+    paint_svg_task("borderSolidThick", shadow!()),
+    paint_svg_task("borderSolid", highlight!()),
+    paint_stack!(shadow!(), "bigDiamond", "tinyRing")
+);
+
+group!(RESIN = RESIN_BLOCK, RESIN_BRICKS, CHISELED_RESIN_BRICKS);
 group!(
     MISC_BRICKS = MUD_BRICKS,
     BRICKS,
@@ -746,5 +775,6 @@ group!(
     TERRACOTTA_VARIANTS,
     AMETHYST,
     PURPUR,
-    CUT_COPPER
+    CUT_COPPER,
+    introduced_in(&*RESIN, PALE_GARDEN_VERSION)
 );
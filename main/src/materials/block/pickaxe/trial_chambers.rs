@@ -0,0 +1,296 @@
+use crate::image_tasks::animate::{FrameTiming, SheetLayout};
+use crate::image_tasks::color::{c, ComparableColor};
+use crate::image_tasks::task_spec::{
+    out_task, paint_svg_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+};
+use crate::materials::block::pickaxe::ore::COPPER;
+use crate::texture_base::material::{introduced_in, ColorTriad, Material, TricolorMaterial};
+use crate::texture_base::version::TRICKY_TRIALS_VERSION;
+use crate::{block_with_colors, group, paint_stack, stack, stack_on};
+
+block_with_colors!(
+    TUFF = c(0x6d6d63),
+    c(0x4d4e47),
+    c(0x8b8c80),
+    color!(),
+    paint_svg_task("checksQuarterCircles", shadow!()),
+    paint_svg_task("dots2", highlight!()),
+    paint_svg_task("borderDotted", shadow!())
+);
+
+block_with_colors!(
+    POLISHED_TUFF = TUFF.color(),
+    TUFF.shadow(),
+    TUFF.highlight(),
+    color!(),
+    paint_svg_task("streaks", highlight!()),
+    paint_svg_task("borderSolid", shadow!()),
+    paint_svg_task("borderSolidTopLeft", highlight!())
+);
+
+block_with_colors!(
+    TUFF_BRICKS = TUFF.color(),
+    TUFF.shadow(),
+    TUFF.highlight(),
+    color!(),
+    paint_svg_task("checksSmall", highlight!()),
+    paint_svg_task("bricksSmall", shadow!()),
+    paint_svg_task("borderShortDashes", highlight!())
+);
+
+block_with_colors!(
+    CHISELED_TUFF = TUFF.color(),
+    TUFF.shadow(),
+    TUFF.highlight(),
+    color!(),
+    paint_svg_task("bigRoundedSquare", highlight!()),
+    paint_stack!(shadow!(), "rings2", "borderSolid"),
+    paint_svg_task("borderSolidTopLeft", highlight!())
+);
+
+block_with_colors!(
+    CHISELED_TUFF_TOP = TUFF.color(),
+    TUFF.shadow(),
+    TUFF.highlight(),
+    color!(),
+    paint_svg_task("ringsCentralBullseye", shadow!()),
+    paint_svg_task("borderSolid", highlight!())
+);
+
+block_with_colors!(
+    CHISELED_TUFF_BRICKS = TUFF.color(),
+    TUFF.shadow(),
+    TUFF.highlight(),
+    color!(),
+    paint_svg_task("bricksSmall", shadow!()),
+    paint_svg_task("bigDiamond", highlight!()),
+    paint_stack!(shadow!(), "tinyRing", "borderSolid")
+);
+
+block_with_colors!(
+    CHISELED_TUFF_BRICKS_TOP = TUFF.color(),
+    TUFF.shadow(),
+    TUFF.highlight(),
+    color!(),
+    paint_svg_task("bricksSmall", shadow!()),
+    paint_svg_task("bigRoundedSquare", highlight!()),
+    paint_stack!(shadow!(), "tinyRing", "borderSolid")
+);
+
+/// The tuff and copper rim shared by trial spawners and vaults.
+fn trial_frame() -> ToPixmapTaskSpec {
+    stack!(
+        paint_svg_task("borderSolidThick", TUFF.shadow()),
+        paint_svg_task("borderSolid", COPPER.refined_colors.shadow),
+        paint_svg_task("borderShortDashes", COPPER.refined_colors.highlight)
+    )
+}
+
+/// Each flame frame of the active trial spawner is shown for a quarter of a second.
+const FLICKER_TIMING: FrameTiming = FrameTiming::new(5);
+
+/// Every state of the trial spawner, either normal or ominous.
+struct TrialSpawner {
+    /// Suffix of each output's name: `_ominous` or empty.
+    suffix: &'static str,
+    flame: ColorTriad,
+}
+
+impl TrialSpawner {
+    fn cage(&self) -> ToPixmapTaskSpec {
+        stack!(
+            paint_svg_task("craftingGridSpaces", TUFF.shadow()),
+            trial_frame()
+        )
+    }
+
+    /// Flames behind the bars, flickering between two frames.
+    fn animated(&self, background: ToPixmapTaskSpec, flame: &'static str) -> ToPixmapTaskSpec {
+        ToPixmapTaskSpec::Animate {
+            background: background.into(),
+            frames: Box::new([
+                stack!(
+                    paint_svg_task(flame, self.flame.color),
+                    paint_svg_task("bigDotsTopLeftBottomRight", self.flame.highlight),
+                    self.cage()
+                ),
+                stack!(
+                    paint_svg_task(flame, self.flame.highlight),
+                    paint_svg_task("bigDotsBottomLeftTopRight", self.flame.color),
+                    self.cage()
+                ),
+            ]),
+            layout: SheetLayout::Vertical,
+            timing: Some(FLICKER_TIMING),
+        }
+    }
+}
+
+impl Material for TrialSpawner {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let side_background = stack_on!(
+            ComparableColor::BLACK,
+            paint_svg_task("glow", self.flame.shadow)
+        );
+        let top_background = stack_on!(
+            TUFF.shadow(),
+            paint_svg_task("ringsCentralBullseye", TUFF.color())
+        );
+        let mut tasks = vec![
+            out_task(
+                format!("block/trial_spawner_side_inactive{}", self.suffix),
+                stack_on!(ComparableColor::BLACK, self.cage()),
+            ),
+            out_task(
+                format!("block/trial_spawner_side_active{}", self.suffix),
+                self.animated(side_background, "glow"),
+            ),
+            out_task(
+                format!("block/trial_spawner_top_inactive{}", self.suffix),
+                stack!(top_background.to_owned(), self.cage()),
+            ),
+            out_task(
+                format!("block/trial_spawner_top_active{}", self.suffix),
+                self.animated(top_background.to_owned(), "glow"),
+            ),
+            out_task(
+                format!("block/trial_spawner_top_ejecting_reward{}", self.suffix),
+                self.animated(top_background, "ray"),
+            ),
+        ];
+        if self.suffix.is_empty() {
+            // Ominous spawners share the normal one's bottom
+            tasks.push(out_task(
+                "block/trial_spawner_bottom",
+                stack_on!(
+                    TUFF.shadow(),
+                    paint_svg_task("checksSmall", TUFF.color()),
+                    trial_frame()
+                ),
+            ));
+        }
+        tasks.into_boxed_slice()
+    }
+}
+
+/// Every state of the vault, either normal or ominous.
+struct Vault {
+    /// Suffix of each output's name: `_ominous` or empty.
+    suffix: &'static str,
+    flame: ColorTriad,
+}
+
+impl Vault {
+    fn body(&self) -> ToPixmapTaskSpec {
+        stack_on!(
+            TUFF.color(),
+            paint_svg_task("checksLarge", TUFF.shadow()),
+            trial_frame()
+        )
+    }
+
+    fn front(&self, keyhole: ComparableColor) -> ToPixmapTaskSpec {
+        stack!(
+            self.body(),
+            paint_svg_task("bigRoundedSquare", COPPER.refined_colors.color),
+            paint_svg_task("jigsawLock", keyhole)
+        )
+    }
+
+    fn side(&self, light: ComparableColor) -> ToPixmapTaskSpec {
+        stack!(
+            self.body(),
+            paint_svg_task("craftingGridSquare", COPPER.refined_colors.shadow),
+            paint_svg_task("glow", light)
+        )
+    }
+}
+
+impl Material for Vault {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let top = stack!(
+            self.body(),
+            paint_svg_task("ringsCentralBullseye", COPPER.refined_colors.shadow)
+        );
+        Box::new([
+            out_task(format!("block/vault_top{}", self.suffix), top.to_owned()),
+            out_task(
+                format!("block/vault_top_ejecting{}", self.suffix),
+                stack!(top, paint_svg_task("ray", self.flame.highlight)),
+            ),
+            out_task(
+                format!("block/vault_bottom{}", self.suffix),
+                stack!(
+                    self.body(),
+                    paint_svg_task("checksSmall", self.flame.shadow)
+                ),
+            ),
+            out_task(
+                format!("block/vault_side_off{}", self.suffix),
+                self.side(TUFF.shadow()),
+            ),
+            out_task(
+                format!("block/vault_side_on{}", self.suffix),
+                self.side(self.flame.color),
+            ),
+            out_task(
+                format!("block/vault_front_off{}", self.suffix),
+                self.front(TUFF.shadow()),
+            ),
+            out_task(
+                format!("block/vault_front_on{}", self.suffix),
+                self.front(self.flame.color),
+            ),
+            out_task(
+                format!("block/vault_front_ejecting{}", self.suffix),
+                stack!(
+                    self.front(self.flame.highlight),
+                    paint_svg_task("ray", self.flame.highlight)
+                ),
+            ),
+        ])
+    }
+}
+
+/// Flames of trial spawners and vaults, and the colors of the keys that open vaults.
+pub const TRIAL_FLAME: ColorTriad = ColorTriad {
+    color: c(0xff8a00),
+    shadow: c(0x8a3a00),
+    highlight: c(0xffd27f),
+};
+pub const OMINOUS_FLAME: ColorTriad = ColorTriad {
+    color: c(0x3fb4ff),
+    shadow: c(0x004a7f),
+    highlight: c(0xb9f0ff),
+};
+
+const TRIAL_SPAWNER: TrialSpawner = TrialSpawner {
+    suffix: "",
+    flame: TRIAL_FLAME,
+};
+const OMINOUS_TRIAL_SPAWNER: TrialSpawner = TrialSpawner {
+    suffix: "_ominous",
+    flame: OMINOUS_FLAME,
+};
+const VAULT: Vault = Vault {
+    suffix: "",
+    flame: TRIAL_FLAME,
+};
+const OMINOUS_VAULT: Vault = Vault {
+    suffix: "_ominous",
+    flame: OMINOUS_FLAME,
+};
+
+group!(
+    TRIAL_CHAMBERS = TUFF,
+    introduced_in(&*POLISHED_TUFF, TRICKY_TRIALS_VERSION),
+    introduced_in(&*TUFF_BRICKS, TRICKY_TRIALS_VERSION),
+    introduced_in(&*CHISELED_TUFF, TRICKY_TRIALS_VERSION),
+    introduced_in(&*CHISELED_TUFF_TOP, TRICKY_TRIALS_VERSION),
+    introduced_in(&*CHISELED_TUFF_BRICKS, TRICKY_TRIALS_VERSION),
+    introduced_in(&*CHISELED_TUFF_BRICKS_TOP, TRICKY_TRIALS_VERSION),
+    introduced_in(&TRIAL_SPAWNER, TRICKY_TRIALS_VERSION),
+    introduced_in(&OMINOUS_TRIAL_SPAWNER, TRICKY_TRIALS_VERSION),
+    introduced_in(&VAULT, TRICKY_TRIALS_VERSION),
+    introduced_in(&OMINOUS_VAULT, TRICKY_TRIALS_VERSION)
+);
@@ -1,11 +1,11 @@
 use crate::image_tasks::color::{c, ComparableColor};
-use crate::image_tasks::task_spec::{from_svg_task, paint_svg_task};
+use crate::image_tasks::task_spec::{from_svg_task, paint_svg_task, texture_of};
 use crate::materials::block::shovel::simple_soft_earth::DIRT;
 use crate::materials::block::shovel::simple_soft_earth::POWDER_SNOW;
 use crate::stack;
 use crate::stack_on;
 use crate::texture_base::material::{ground_cover_block, GroundCoverBlock, TricolorMaterial};
-use crate::{block_with_colors, copy_block, group, paint_stack, single_texture_block};
+use crate::{block_with_colors, group, paint_stack, single_texture_block, tint_overlay};
 use once_cell::sync::Lazy;
 
 pub const GRASS_COLOR: ComparableColor = c(0x83b253);
@@ -35,9 +35,8 @@ pub static GRASS_BLOCK: Lazy<GroundCoverBlock> = Lazy::new(|| {
     )
 });
 
-single_texture_block!(
-    GRASS_BLOCK_SIDE_OVERLAY = ComparableColor::TRANSPARENT,
-    paint_svg_task("topPart", ComparableColor::LIGHT_BIOME_COLORABLE),
+tint_overlay!(
+    GRASS_BLOCK_SIDE_OVERLAY = paint_svg_task("topPart", ComparableColor::LIGHT_BIOME_COLORABLE),
     paint_svg_task("veesTop", ComparableColor::MEDIUM_BIOME_COLORABLE)
 );
 
@@ -45,8 +44,9 @@ pub const PODZOL_COLOR: ComparableColor = c(0x6a4418);
 pub const PODZOL_SHADOW: ComparableColor = c(0x4a3018);
 pub const PODZOL_HIGHLIGHT: ComparableColor = c(0x8b5920);
 
-pub static PODZOL: Lazy<GroundCoverBlock> = Lazy::new(|| {
-    ground_cover_block(
+pub static PODZOL: Lazy<GroundCoverBlock> = Lazy::new(|| GroundCoverBlock {
+    top_aliases: &["block/composter_compost"],
+    ..ground_cover_block(
         "podzol",
         "_top",
         &DIRT.material,
@@ -65,8 +65,6 @@ pub static PODZOL: Lazy<GroundCoverBlock> = Lazy::new(|| {
     )
 });
 
-copy_block!(COMPOSTER_COMPOST = PODZOL, "top");
-
 single_texture_block!(
     COMPOSTER_READY = ComparableColor::TRANSPARENT,
     PODZOL.top.to_owned(),
@@ -103,7 +101,7 @@ block_with_colors!(
     POWDER_SNOW.shadow(),
     POWDER_SNOW.highlight(),
     ComparableColor::TRANSPARENT,
-    DIRT.material.texture(),
+    texture_of("block/dirt"),
     paint_svg_task("topPart", color!()),
     paint_svg_task("diagonalChecksTopLeft", shadow!()),
     paint_stack!(
@@ -126,7 +124,6 @@ group!(
     DIRT_GROUND_COVER = GRASS_BLOCK,
     GRASS_BLOCK_SIDE_OVERLAY,
     PODZOL,
-    COMPOSTER_COMPOST,
     COMPOSTER_READY,
     MYCELIUM,
     GRASS_BLOCK_SNOW,
@@ -1,6 +1,7 @@
 use crate::image_tasks::color::{c, ComparableColor};
 use crate::image_tasks::task_spec::{from_svg_task, paint_svg_task};
-use crate::texture_base::material::TricolorMaterial;
+use crate::texture_base::material::{introduced_in, TricolorMaterial};
+use crate::texture_base::version::PALE_GARDEN_VERSION;
 use crate::{block_with_colors, group, paint_stack};
 
 block_with_colors!(
@@ -69,6 +70,15 @@ block_with_colors!(
     paint_stack!(highlight!(), "strokeTopLeftBottomRight4", "borderSolid"),
     paint_svg_task("strokeBottomLeftTopRight4xorBorder", shadow!())
 );
+block_with_colors!(
+    PALE_MOSS_BLOCK,
+    PALE_MOSS_CARPET = c(0x9ba094),
+    c(0x6f7668),
+    c(0xc4c9ba),
+    color!(),
+    paint_stack!(highlight!(), "strokeTopLeftBottomRight4", "borderSolid"),
+    paint_svg_task("strokeBottomLeftTopRight4xorBorder", shadow!())
+);
 block_with_colors!(
     SOUL_SAND = c(0x624033),
     c(0x3F2D23),
@@ -144,6 +154,7 @@ group!(
     CLAY,
     MUD,
     MOSS_BLOCK,
+    introduced_in(&*PALE_MOSS_BLOCK, PALE_GARDEN_VERSION),
     SOUL_SAND,
     SOUL_SOIL,
     PACKED_MUD,
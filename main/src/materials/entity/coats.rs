@@ -0,0 +1,398 @@
+use crate::image_tasks::color::{c, ComparableColor};
+use crate::image_tasks::color_shift::ColorShift;
+use crate::image_tasks::sheet::SheetRect;
+use crate::image_tasks::task_spec::{
+    out_task, paint_svg_task, sheet_task, shift_colors_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+};
+use crate::texture_base::material::{
+    introduced_in, ColorTriad, Material, MaterialCategory, MaterialMetadata,
+};
+use crate::texture_base::version::ARMORED_PAWS_VERSION;
+use crate::{group, stack, stack_on};
+
+/// Fur in the given colors, textured with `pattern` in shadow.
+pub(super) fn fur(colors: ColorTriad, pattern: &'static str) -> ToPixmapTaskSpec {
+    stack_on!(
+        colors.color,
+        paint_svg_task(pattern, colors.shadow),
+        paint_svg_task("dots0", colors.highlight)
+    )
+}
+
+// Horses
+
+const HORSE_SHEET_SIZE: u16 = 64;
+const HORSE_HEAD: SheetRect = SheetRect::new(0, 13, 26, 12);
+const HORSE_MUZZLE: SheetRect = SheetRect::new(0, 25, 18, 10);
+const HORSE_NECK: SheetRect = SheetRect::new(0, 35, 22, 19);
+const HORSE_BODY: SheetRect = SheetRect::new(0, 32, 64, 32);
+const HORSE_LEGS: SheetRect = SheetRect::new(48, 21, 16, 15);
+const HORSE_MANE: SheetRect = SheetRect::new(56, 36, 8, 18);
+const HORSE_TAIL: SheetRect = SheetRect::new(42, 36, 14, 18);
+
+/// A chestnut horse, which the other colors are recolored from.
+const HORSE_COAT: ColorTriad = ColorTriad {
+    color: c(0x8a5a2b),
+    shadow: c(0x6a4220),
+    highlight: c(0xa9743c),
+};
+
+const HORSE_HAIR: ColorTriad = ColorTriad {
+    color: c(0x4a2e17),
+    shadow: c(0x33200f),
+    highlight: c(0x6a4424),
+};
+
+const HORSE_COLORS: &[(&str, ColorShift)] = &[
+    ("white", ColorShift::new(0, -80, 80)),
+    ("creamy", ColorShift::new(5, -20, 50)),
+    ("chestnut", ColorShift::NONE),
+    ("brown", ColorShift::new(0, -20, -30)),
+    ("black", ColorShift::new(0, -80, -80)),
+    ("gray", ColorShift::new(0, -100, 0)),
+    ("darkbrown", ColorShift::new(-5, -10, -55)),
+];
+
+/// The markings that the game draws over any horse color, as (name, color, pattern and where it
+/// goes).
+const HORSE_MARKINGS: &[(&str, ComparableColor, &[(&str, SheetRect)])] = &[
+    (
+        "white",
+        ComparableColor::WHITE,
+        &[
+            ("bottomHalf", HORSE_LEGS),
+            ("strokeTopLeftBottomRight2", HORSE_MUZZLE),
+        ],
+    ),
+    (
+        "whitefield",
+        ComparableColor::WHITE,
+        &[("checksLarge", HORSE_BODY), ("checksLarge", HORSE_NECK)],
+    ),
+    (
+        "whitedots",
+        ComparableColor::WHITE,
+        &[("dots2", HORSE_BODY), ("dots2", HORSE_NECK)],
+    ),
+    (
+        "blackdots",
+        ComparableColor::DARKEST_GRAY,
+        &[("dots3", HORSE_BODY), ("dots3", HORSE_NECK)],
+    ),
+];
+
+fn horse_template() -> ToPixmapTaskSpec {
+    let coat = fur(HORSE_COAT, "streaks");
+    let hair = fur(HORSE_HAIR, "wavyVines");
+    sheet_task(
+        HORSE_SHEET_SIZE,
+        HORSE_SHEET_SIZE,
+        [
+            (coat.to_owned(), HORSE_BODY),
+            (coat.to_owned(), HORSE_NECK),
+            (
+                stack!(
+                    coat.to_owned(),
+                    paint_svg_task("bigDotsTop", HORSE_HAIR.shadow)
+                ),
+                HORSE_HEAD,
+            ),
+            (coat.to_owned(), HORSE_MUZZLE),
+            (coat, HORSE_LEGS),
+            (hair.to_owned(), HORSE_MANE),
+            (hair, HORSE_TAIL),
+        ],
+    )
+}
+
+/// Every horse color and marking. Donkeys and mules have textures of their own.
+struct HorseCoats;
+
+impl Material for HorseCoats {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let mut tasks = Vec::with_capacity(HORSE_COLORS.len() + HORSE_MARKINGS.len());
+        let template = horse_template();
+        for (name, shift) in HORSE_COLORS {
+            tasks.push(out_task(
+                format!("entity/horse/horse_{}", name),
+                shift_colors_task(template.to_owned(), *shift),
+            ));
+        }
+        for (name, color, patterns) in HORSE_MARKINGS {
+            tasks.push(out_task(
+                format!("entity/horse/horse_markings_{}", name),
+                sheet_task(
+                    HORSE_SHEET_SIZE,
+                    HORSE_SHEET_SIZE,
+                    patterns
+                        .iter()
+                        .map(|(pattern, rect)| (paint_svg_task(*pattern, *color), *rect)),
+                ),
+            ));
+        }
+        tasks.into_boxed_slice()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Entity),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+// Wolves
+
+const WOLF_SHEET_WIDTH: u16 = 64;
+const WOLF_SHEET_HEIGHT: u16 = 32;
+const WOLF_HEAD: SheetRect = SheetRect::new(0, 0, 20, 10);
+const WOLF_FACE: SheetRect = SheetRect::new(4, 4, 6, 6);
+const WOLF_SNOUT: SheetRect = SheetRect::new(0, 10, 14, 7);
+const WOLF_EARS: SheetRect = SheetRect::new(16, 14, 6, 3);
+const WOLF_BODY: SheetRect = SheetRect::new(18, 14, 24, 15);
+const WOLF_MANE: SheetRect = SheetRect::new(21, 0, 30, 13);
+const WOLF_LEGS: SheetRect = SheetRect::new(0, 18, 8, 10);
+const WOLF_TAIL: SheetRect = SheetRect::new(9, 18, 8, 10);
+
+/// A brown wolf, which every variant is recolored from.
+const WOLF_FUR: ColorTriad = ColorTriad {
+    color: c(0x9c7b5a),
+    shadow: c(0x7a5f44),
+    highlight: c(0xbfa07e),
+};
+
+/// The pale wolf, which is the only kind from before [ARMORED_PAWS_VERSION] and has no suffix.
+const PALE_WOLF: &[(&str, ColorShift)] = &[("", ColorShift::new(0, -90, 60))];
+
+const WOLF_VARIANTS: &[(&str, ColorShift)] = &[
+    ("_ashen", ColorShift::new(0, -90, 10)),
+    ("_black", ColorShift::new(0, -70, -75)),
+    ("_chestnut", ColorShift::new(-10, 20, -10)),
+    ("_rusty", ColorShift::new(-15, 50, 0)),
+    ("_snowy", ColorShift::new(0, -100, 85)),
+    ("_spotted", ColorShift::new(5, 10, 20)),
+    ("_striped", ColorShift::new(10, -20, 10)),
+    ("_woods", ColorShift::new(0, -10, -40)),
+];
+
+/// The eyes of a wild, tame and angry wolf, which stay the same color whatever the coat.
+fn wolf_eyes(state: &str) -> ToPixmapTaskSpec {
+    match state {
+        "_angry" => stack!(
+            paint_svg_task("veesTop", ComparableColor::BLACK),
+            paint_svg_task("bigDotsTop", c(0xba0000))
+        ),
+        "_tame" => stack!(
+            paint_svg_task("bigDotsTop", ComparableColor::BLACK),
+            paint_svg_task("dots0", ComparableColor::WHITE)
+        ),
+        _ => paint_svg_task("bigDotsTop", ComparableColor::BLACK),
+    }
+}
+
+/// The coat alone, so that every variant and state shifts the same rendered sheet.
+fn wolf_template() -> ToPixmapTaskSpec {
+    let coat = fur(WOLF_FUR, "streaks");
+    sheet_task(
+        WOLF_SHEET_WIDTH,
+        WOLF_SHEET_HEIGHT,
+        [
+            (coat.to_owned(), WOLF_HEAD),
+            (coat.to_owned(), WOLF_SNOUT),
+            (coat.to_owned(), WOLF_EARS),
+            (coat.to_owned(), WOLF_BODY),
+            (
+                stack!(
+                    coat.to_owned(),
+                    paint_svg_task("wavyVines", WOLF_FUR.shadow)
+                ),
+                WOLF_MANE,
+            ),
+            (coat.to_owned(), WOLF_LEGS),
+            (coat, WOLF_TAIL),
+        ],
+    )
+}
+
+/// Wolves in the given colors, each wild, tame and angry.
+struct WolfCoats {
+    variants: &'static [(&'static str, ColorShift)],
+}
+
+impl Material for WolfCoats {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let mut tasks = Vec::with_capacity(3 * self.variants.len());
+        let template = wolf_template();
+        for (variant, shift) in self.variants {
+            let coat = shift_colors_task(template.to_owned(), *shift);
+            for state in ["", "_tame", "_angry"] {
+                let eyes = sheet_task(
+                    WOLF_SHEET_WIDTH,
+                    WOLF_SHEET_HEIGHT,
+                    [(wolf_eyes(state), WOLF_FACE)],
+                );
+                tasks.push(out_task(
+                    format!("entity/wolf/wolf{}{}", variant, state),
+                    stack!(coat.to_owned(), eyes),
+                ));
+            }
+        }
+        tasks.into_boxed_slice()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Entity),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+// Cats
+
+const CAT_SHEET_WIDTH: u16 = 64;
+const CAT_SHEET_HEIGHT: u16 = 32;
+const CAT_HEAD: SheetRect = SheetRect::new(0, 0, 20, 9);
+const CAT_FACE: SheetRect = SheetRect::new(5, 5, 5, 4);
+const CAT_BODY: SheetRect = SheetRect::new(20, 0, 20, 22);
+const CAT_FRONT_LEGS: SheetRect = SheetRect::new(40, 0, 8, 12);
+const CAT_BACK_LEGS: SheetRect = SheetRect::new(8, 13, 8, 8);
+const CAT_TAIL: SheetRect = SheetRect::new(0, 15, 8, 9);
+
+/// A ginger cat, which every breed is recolored from.
+const CAT_FUR: ColorTriad = ColorTriad {
+    color: c(0xc98b4a),
+    shadow: c(0x9c6633),
+    highlight: c(0xe6b27a),
+};
+
+/// Patches of the color that some breeds have on their chest and paws.
+const WHITE_PATCHES: &[(&str, SheetRect)] =
+    &[("bottomHalf", CAT_BODY), ("bottomHalf", CAT_FRONT_LEGS)];
+
+/// Darker fur on the face, legs and tail.
+const POINTS: &[(&str, SheetRect)] = &[
+    ("bigDotsTop", CAT_HEAD),
+    ("bottomHalf", CAT_FRONT_LEGS),
+    ("bottomHalf", CAT_BACK_LEGS),
+    ("bottomHalf", CAT_TAIL),
+];
+
+/// Each breed, as (name, coat color, markings that aren't recolored and their color).
+const CAT_BREEDS: &[(&str, ColorShift, &[(&str, SheetRect)], ComparableColor)] = &[
+    (
+        "tabby",
+        ColorShift::new(0, -50, -20),
+        &[],
+        ComparableColor::WHITE,
+    ),
+    (
+        "black",
+        ColorShift::new(0, -90, -80),
+        WHITE_PATCHES,
+        ComparableColor::WHITE,
+    ),
+    ("red", ColorShift::NONE, &[], ComparableColor::WHITE),
+    ("siamese", ColorShift::new(5, -40, 60), POINTS, c(0x3a2a20)),
+    (
+        "british_shorthair",
+        ColorShift::new(0, -85, 10),
+        &[],
+        ComparableColor::WHITE,
+    ),
+    (
+        "calico",
+        ColorShift::new(0, -30, 60),
+        &[("bigDotsTopLeftBottomRight", CAT_BODY)],
+        c(0x2a2a2a),
+    ),
+    (
+        "persian",
+        ColorShift::new(10, -20, 40),
+        &[],
+        ComparableColor::WHITE,
+    ),
+    ("ragdoll", ColorShift::new(0, -60, 70), POINTS, c(0x6a5a4a)),
+    (
+        "white",
+        ColorShift::new(0, -100, 90),
+        &[],
+        ComparableColor::WHITE,
+    ),
+    (
+        "jellie",
+        ColorShift::new(0, -70, 20),
+        WHITE_PATCHES,
+        ComparableColor::WHITE,
+    ),
+    (
+        "all_black",
+        ColorShift::new(0, -100, -90),
+        &[],
+        ComparableColor::WHITE,
+    ),
+];
+
+/// The coat alone, so that every breed shifts the same rendered sheet.
+fn cat_template() -> ToPixmapTaskSpec {
+    let coat = fur(CAT_FUR, "streaks");
+    sheet_task(
+        CAT_SHEET_WIDTH,
+        CAT_SHEET_HEIGHT,
+        [
+            (coat.to_owned(), CAT_HEAD),
+            (coat.to_owned(), CAT_BODY),
+            (coat.to_owned(), CAT_FRONT_LEGS),
+            (coat.to_owned(), CAT_BACK_LEGS),
+            (coat, CAT_TAIL),
+        ],
+    )
+}
+
+/// Every cat breed, each with the same green eyes.
+struct CatCoats;
+
+impl Material for CatCoats {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let template = cat_template();
+        CAT_BREEDS
+            .iter()
+            .map(|(name, shift, markings, marking_color)| {
+                let mut placements: Vec<_> = markings
+                    .iter()
+                    .map(|(pattern, rect)| (paint_svg_task(*pattern, *marking_color), *rect))
+                    .collect();
+                placements.push((paint_svg_task("bigDotsTop", c(0x5fbf3f)), CAT_FACE));
+                out_task(
+                    format!("entity/cat/{}", name),
+                    stack!(
+                        shift_colors_task(template.to_owned(), *shift),
+                        sheet_task(CAT_SHEET_WIDTH, CAT_SHEET_HEIGHT, placements)
+                    ),
+                )
+            })
+            .collect()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Entity),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+const PALE_WOLF_COATS: WolfCoats = WolfCoats {
+    variants: PALE_WOLF,
+};
+
+const WOLF_VARIANT_COATS: WolfCoats = WolfCoats {
+    variants: WOLF_VARIANTS,
+};
+
+group!(
+    COATS = HorseCoats,
+    PALE_WOLF_COATS,
+    introduced_in(&WOLF_VARIANT_COATS, ARMORED_PAWS_VERSION),
+    CatCoats
+);
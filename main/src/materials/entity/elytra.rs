@@ -0,0 +1,160 @@
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+
+use crate::anyhoo;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::color::{c, ComparableColor};
+use crate::image_tasks::sheet::SheetRect;
+use crate::image_tasks::task_spec::{
+    out_task, paint_svg_task, sheet_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+};
+use crate::materials::entity::coats;
+use crate::parsed_option;
+use crate::texture_base::dyes::DYES;
+use crate::texture_base::material::{ColorTriad, Material, MaterialCategory, MaterialMetadata};
+use crate::{group, stack};
+
+const SHEET_WIDTH: u16 = 64;
+const SHEET_HEIGHT: u16 = 32;
+
+/// Where both the elytra texture and a cape texture put the wings.
+const WINGS: SheetRect = SheetRect::new(22, 0, 24, 22);
+
+/// Where a cape texture puts the cape itself.
+const CAPE: SheetRect = SheetRect::new(0, 0, 22, 17);
+
+/// The patterns that a design can layer over the wings, by the name `--elytra-design` uses.
+const PATTERNS: &[(&str, &str)] = &[
+    ("border", "borderSolid"),
+    ("stripes", "streaks"),
+    ("dots", "dots2"),
+    ("checks", "checksSmall"),
+    ("half", "bottomHalf"),
+    ("diagonal", "strokeTopLeftBottomRight2"),
+    ("rings", "rings"),
+];
+
+/// Parses a dye name such as `purple`, or any color that [ComparableColor] can parse.
+fn parse_dye_or_color(s: &str) -> Result<ComparableColor, CloneableError> {
+    match DYES.iter().find(|(name, _)| *name == s) {
+        Some((_, color)) => Ok(*color),
+        None => s.parse(),
+    }
+}
+
+/// The colors and patterns of the elytra and cape, built up like a banner: a base color, then
+/// any number of patterns drawn over it in order.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ElytraDesign {
+    pub base: ComparableColor,
+    pub patterns: Vec<(&'static str, ComparableColor)>,
+}
+
+impl Default for ElytraDesign {
+    /// The vanilla elytra's colors: gray membranes that darken to purple at the edges.
+    fn default() -> Self {
+        ElytraDesign {
+            base: c(0x8e8e9f),
+            patterns: vec![
+                ("borderSolid", c(0x5a4a78)),
+                ("strokeTopLeftBottomRight2", c(0xa9a9bd)),
+            ],
+        }
+    }
+}
+
+impl FromStr for ElytraDesign {
+    type Err = CloneableError;
+
+    /// Parses a comma-separated base color and patterns, such as `purple,border=white,dots=#ffcc00`,
+    /// where each color is a dye name or a hex color.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',').map(str::trim);
+        let base = parse_dye_or_color(parts.next().unwrap_or_default())?;
+        let patterns = parts
+            .map(|part| {
+                let (pattern, color) = part
+                    .split_once('=')
+                    .ok_or_else(|| anyhoo!("Expected `pattern=color`: {}", part))?;
+                let svg = PATTERNS
+                    .iter()
+                    .find(|(name, _)| *name == pattern.trim())
+                    .map(|(_, svg)| *svg)
+                    .ok_or_else(|| anyhoo!("Unknown elytra pattern: {}", pattern))?;
+                Ok((svg, parse_dye_or_color(color.trim())?))
+            })
+            .collect::<Result<_, CloneableError>>()?;
+        Ok(ElytraDesign { base, patterns })
+    }
+}
+
+impl ElytraDesign {
+    fn wings(&self) -> ToPixmapTaskSpec {
+        let base = ColorTriad::from_base(self.base);
+        let mut layers = coats::fur(base, "veesTop");
+        for (svg, color) in &self.patterns {
+            layers = stack!(layers, paint_svg_task(*svg, *color));
+        }
+        layers
+    }
+}
+
+/// Set with `--elytra-design`; see [ElytraDesign::from_str] for the syntax.
+static ELYTRA_DESIGN: Lazy<ElytraDesign> =
+    Lazy::new(|| parsed_option("elytra-design").unwrap_or_default());
+
+/// The elytra, and a cape in the same design for mods that load capes from a resource pack.
+struct Elytra;
+
+impl Material for Elytra {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let wings = ELYTRA_DESIGN.wings();
+        Box::new([
+            out_task(
+                "entity/elytra",
+                sheet_task(SHEET_WIDTH, SHEET_HEIGHT, [(wings.to_owned(), WINGS)]),
+            ),
+            out_task(
+                "entity/cape/custom",
+                sheet_task(
+                    SHEET_WIDTH,
+                    SHEET_HEIGHT,
+                    [(wings.to_owned(), CAPE), (wings, WINGS)],
+                ),
+            ),
+        ])
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Entity),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+group!(ELYTRA = Elytra);
+
+#[test]
+fn test_elytra_design() {
+    let design: ElytraDesign = "purple, border=white,dots=#ffcc00".parse().unwrap();
+    assert_eq!(design.base, c(0x8900b8));
+    assert_eq!(
+        design.patterns,
+        vec![
+            ("borderSolid", ComparableColor::WHITE),
+            ("dots2", c(0xffcc00))
+        ]
+    );
+    assert_eq!(
+        "#123456".parse::<ElytraDesign>().unwrap(),
+        ElytraDesign {
+            base: c(0x123456),
+            patterns: vec![]
+        }
+    );
+    assert!("purple,sparkles=white".parse::<ElytraDesign>().is_err());
+    assert!("purple,border".parse::<ElytraDesign>().is_err());
+    assert!("mauve".parse::<ElytraDesign>().is_err());
+}
@@ -0,0 +1,63 @@
+use crate::image_tasks::animate::SheetLayout;
+use crate::image_tasks::color::c;
+use crate::image_tasks::crop::TileRect;
+use crate::image_tasks::task_spec::{
+    crop_task, out_task, paint_svg_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+};
+use crate::texture_base::material::{ColorTriad, Material, MaterialCategory, MaterialMetadata};
+use crate::{group, stack};
+
+const ORB: ColorTriad = ColorTriad {
+    color: c(0xb3ff00),
+    shadow: c(0x5c8a00),
+    highlight: c(0xfff870),
+};
+
+/// Sizes of the orbs, in sixteenths of a cell, from the least to the most experience.
+const ORB_SIZES: [u8; 11] = [6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16];
+
+/// The sheet is 4 cells by 4 but only uses the first 11, so it isn't a vertical strip of frames.
+const SHEET_LAYOUT: SheetLayout = SheetLayout::Grid {
+    columns: 4,
+    rows: 4,
+    padding: 0,
+};
+
+fn orb(size: u8) -> ToPixmapTaskSpec {
+    crop_task(
+        stack!(
+            paint_svg_task("circle32", ORB.shadow),
+            paint_svg_task("circle24", ORB.color),
+            paint_svg_task("bigDotsTopLeftBottomRight", ORB.highlight)
+        ),
+        TileRect::FULL,
+        TileRect::centered(size),
+    )
+}
+
+/// Every size of experience orb, which the game picks from by how much experience an orb holds.
+struct ExperienceOrb;
+
+impl Material for ExperienceOrb {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        Box::new([out_task(
+            "entity/experience_orb",
+            ToPixmapTaskSpec::Animate {
+                // Every frame covers the smallest orb
+                background: orb(ORB_SIZES[0]).into(),
+                frames: ORB_SIZES.map(orb).into(),
+                layout: SHEET_LAYOUT,
+                timing: None,
+            },
+        )])
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Entity),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+group!(EXPERIENCE_ORB = ExperienceOrb);
@@ -0,0 +1,12 @@
+mod coats;
+mod elytra;
+mod experience_orb;
+mod villager;
+
+use crate::group;
+use crate::materials::entity::coats::COATS;
+use crate::materials::entity::elytra::ELYTRA;
+use crate::materials::entity::experience_orb::EXPERIENCE_ORB;
+use crate::materials::entity::villager::VILLAGERS;
+
+group!(ALL_ENTITIES = VILLAGERS, COATS, ELYTRA, EXPERIENCE_ORB);
@@ -0,0 +1,274 @@
+use crate::image_tasks::color::{c, ComparableColor};
+use crate::image_tasks::sheet::SheetRect;
+use crate::image_tasks::task_spec::{
+    out_task, paint_svg_task, sheet_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+};
+use crate::texture_base::material::{ColorTriad, Material, MaterialCategory, MaterialMetadata};
+use crate::{group, stack, stack_on};
+
+/// Side length of villager and zombie villager textures, in texels.
+const SHEET_SIZE: u16 = 64;
+
+// Regions of the villager model's UV layout. Boxes whose faces all get the same layer are given as
+// the rectangle that bounds their faces.
+const HEAD_TOP: SheetRect = SheetRect::new(8, 0, 8, 8);
+const HEAD_BOTTOM: SheetRect = SheetRect::new(16, 0, 8, 8);
+const HEAD_FRONT: SheetRect = SheetRect::new(8, 8, 8, 10);
+const HEAD_SIDES_AND_BACK: [SheetRect; 3] = [
+    SheetRect::new(0, 8, 8, 10),
+    SheetRect::new(16, 8, 8, 10),
+    SheetRect::new(24, 8, 8, 10),
+];
+const NOSE: SheetRect = SheetRect::new(24, 0, 8, 6);
+const BODY: SheetRect = SheetRect::new(16, 20, 28, 18);
+const BODY_FRONT: SheetRect = SheetRect::new(22, 26, 8, 12);
+const ROBE: SheetRect = SheetRect::new(0, 38, 28, 24);
+const ROBE_FRONT: SheetRect = SheetRect::new(6, 44, 8, 18);
+const ARMS: [SheetRect; 2] = [
+    SheetRect::new(44, 22, 16, 12),
+    SheetRect::new(40, 38, 24, 8),
+];
+const LEGS: SheetRect = SheetRect::new(0, 22, 16, 16);
+
+/// Directories of the entities that share the villager's UV layout and overlays.
+const VILLAGER_KINDS: [&str; 2] = ["villager", "zombie_villager"];
+
+/// The skin and plain robes that every villager has under its overlays.
+struct VillagerBase {
+    name: &'static str,
+    skin: ColorTriad,
+    clothes: ColorTriad,
+    eyes: ComparableColor,
+}
+
+impl VillagerBase {
+    fn skin(&self) -> ToPixmapTaskSpec {
+        stack_on!(
+            self.skin.color,
+            paint_svg_task("dots0", self.skin.highlight)
+        )
+    }
+
+    fn face(&self) -> ToPixmapTaskSpec {
+        stack!(
+            self.skin(),
+            paint_svg_task("topStripeThick", self.skin.shadow),
+            paint_svg_task("bigDotsTop", self.eyes)
+        )
+    }
+
+    fn clothes(&self) -> ToPixmapTaskSpec {
+        stack_on!(
+            self.clothes.color,
+            paint_svg_task("streaks", self.clothes.shadow),
+            paint_svg_task("borderSolid", self.clothes.shadow)
+        )
+    }
+}
+
+impl Material for VillagerBase {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let mut placements = vec![
+            (self.skin(), HEAD_TOP),
+            (self.skin(), HEAD_BOTTOM),
+            (self.face(), HEAD_FRONT),
+            (self.skin(), NOSE),
+            (self.clothes(), BODY),
+            (self.clothes(), ROBE),
+            (self.clothes(), LEGS),
+        ];
+        placements.extend(HEAD_SIDES_AND_BACK.map(|rect| (self.skin(), rect)));
+        placements.extend(ARMS.map(|rect| (self.skin(), rect)));
+        Box::new([out_task(
+            format!("entity/{}/{}", self.name, self.name),
+            sheet_task(SHEET_SIZE, SHEET_SIZE, placements),
+        )])
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Entity),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+/// Each profession, as (name, hat color if it wears one, apron color, emblem on the chest).
+const PROFESSIONS: &[(&str, Option<ComparableColor>, ComparableColor, Option<&str>)] = &[
+    (
+        "armorer",
+        None,
+        ComparableColor::STONE_EXTREME_SHADOW,
+        Some("hexagon"),
+    ),
+    (
+        "butcher",
+        None,
+        c(0xe9e9e9),
+        Some("bigDotsTopLeftBottomRight"),
+    ),
+    ("cartographer", None, c(0x5f4a2b), Some("cross")),
+    ("cleric", None, c(0x8900b8), Some("diamond1")),
+    ("farmer", Some(c(0xd9c043)), c(0x835400), Some("wheat7")),
+    (
+        "fisherman",
+        Some(c(0xc9b27a)),
+        c(0x2e6e3b),
+        Some("fishBody"),
+    ),
+    ("fletcher", Some(c(0x4a7c2c)), c(0x835400), Some("arrowUp")),
+    ("leatherworker", None, c(0x8a4b2a), Some("borderDotted")),
+    (
+        "librarian",
+        Some(c(0xba0000)),
+        c(0xe9e9e9),
+        Some("bookShelves"),
+    ),
+    (
+        "mason",
+        None,
+        ComparableColor::STONE_EXTREME_SHADOW,
+        Some("bricksSmall"),
+    ),
+    ("nitwit", None, c(0x007c00), None),
+    (
+        "shepherd",
+        Some(c(0x835400)),
+        c(0xeaead0),
+        Some("bigRingsTopLeftBottomRight"),
+    ),
+    (
+        "toolsmith",
+        None,
+        ComparableColor::STONE_EXTREME_SHADOW,
+        Some("chain"),
+    ),
+    ("weaponsmith", None, c(0x1f1f23), Some("triangles1")),
+];
+
+/// Each biome's villager type, as (name, color of the sash and boots).
+const VILLAGER_TYPES: &[(&str, ComparableColor)] = &[
+    ("desert", c(0xd9c043)),
+    ("jungle", c(0x2e7d32)),
+    ("plains", c(0x835400)),
+    ("savanna", c(0xba6a2a)),
+    ("snow", c(0x7777ff)),
+    ("swamp", c(0x3b5c2b)),
+    ("taiga", c(0x4a3a2a)),
+];
+
+/// Profession and biome overlays, which the game draws over either kind of villager's base skin.
+struct VillagerOverlays;
+
+impl VillagerOverlays {
+    fn profession(
+        hat: Option<ComparableColor>,
+        apron: ComparableColor,
+        emblem: Option<&'static str>,
+    ) -> ToPixmapTaskSpec {
+        let apron = ColorTriad::from_base(apron);
+        let mut placements = vec![(
+            stack_on!(
+                apron.color,
+                paint_svg_task("borderSolid", apron.shadow),
+                paint_svg_task("strokeTopLeftBottomRight2", apron.highlight)
+            ),
+            ROBE_FRONT,
+        )];
+        if let Some(emblem) = emblem {
+            placements.push((paint_svg_task(emblem, apron.highlight), BODY_FRONT));
+        }
+        if let Some(hat) = hat {
+            let hat = ColorTriad::from_base(hat);
+            let brim = stack!(
+                paint_svg_task("topPart", hat.color),
+                paint_svg_task("borderSolidTopLeft", hat.highlight)
+            );
+            placements.push((
+                stack_on!(hat.color, paint_svg_task("rings", hat.shadow)),
+                HEAD_TOP,
+            ));
+            placements.push((brim.to_owned(), HEAD_FRONT));
+            placements.extend(HEAD_SIDES_AND_BACK.map(|rect| (brim.to_owned(), rect)));
+        }
+        sheet_task(SHEET_SIZE, SHEET_SIZE, placements)
+    }
+
+    fn villager_type(sash: ComparableColor) -> ToPixmapTaskSpec {
+        let sash = ColorTriad::from_base(sash);
+        sheet_task(
+            SHEET_SIZE,
+            SHEET_SIZE,
+            [
+                (
+                    stack!(
+                        paint_svg_task("strokeTopLeftBottomRight2", sash.color),
+                        paint_svg_task("strokeTopLeftBottomRight", sash.highlight)
+                    ),
+                    BODY_FRONT,
+                ),
+                (paint_svg_task("bottomHalf", sash.shadow), LEGS),
+            ],
+        )
+    }
+}
+
+impl Material for VillagerOverlays {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let mut tasks = Vec::new();
+        for kind in VILLAGER_KINDS {
+            for (name, hat, apron, emblem) in PROFESSIONS {
+                tasks.push(out_task(
+                    format!("entity/{}/profession/{}", kind, name),
+                    VillagerOverlays::profession(*hat, *apron, *emblem),
+                ));
+            }
+            for (name, sash) in VILLAGER_TYPES {
+                tasks.push(out_task(
+                    format!("entity/{}/type/{}", kind, name),
+                    VillagerOverlays::villager_type(*sash),
+                ));
+            }
+        }
+        tasks.into_boxed_slice()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Entity),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+const VILLAGER: VillagerBase = VillagerBase {
+    name: "villager",
+    skin: ColorTriad {
+        color: c(0xbd8b72),
+        shadow: c(0x9c6e56),
+        highlight: c(0xd9a68a),
+    },
+    clothes: ColorTriad {
+        color: c(0x6a4a33),
+        shadow: c(0x4a3324),
+        highlight: c(0x8a6547),
+    },
+    eyes: c(0x2e8b3a),
+};
+
+const ZOMBIE_VILLAGER: VillagerBase = VillagerBase {
+    name: "zombie_villager",
+    skin: ColorTriad {
+        color: c(0x5f9c4a),
+        shadow: c(0x3f6e31),
+        highlight: c(0x7fba62),
+    },
+    clothes: ColorTriad {
+        color: c(0x4a3a2a),
+        shadow: c(0x33281d),
+        highlight: c(0x6a5540),
+    },
+    eyes: c(0xba0000),
+};
+
+group!(VILLAGERS = VILLAGER, ZOMBIE_VILLAGER, VillagerOverlays);
@@ -0,0 +1,99 @@
+use crate::image_tasks::animate::SheetLayout;
+use crate::image_tasks::color::{c, ComparableColor};
+use crate::image_tasks::task_spec::{
+    out_task, paint_svg_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+};
+use crate::texture_base::material::{Material, MaterialCategory, MaterialMetadata};
+use crate::{group, stack};
+
+/// Each status effect shown in the inventory and HUD, as (name, symbol layer, color).
+const MOB_EFFECTS: &[(&str, &str, ComparableColor)] = &[
+    ("speed", "arrowUp", c(0x33ebff)),
+    ("slowness", "snow", c(0x8bafe0)),
+    ("haste", "diamond1", c(0xd9c043)),
+    ("mining_fatigue", "streaks", c(0x4a4217)),
+    ("strength", "triangles1", c(0xffc700)),
+    ("jump_boost", "veesTop", c(0xfdff84)),
+    ("nausea", "ringsSpiral", c(0x551d4a)),
+    ("regeneration", "glow", c(0xcd5cab)),
+    ("resistance", "hexagon", c(0x9146f0)),
+    ("fire_resistance", "torchFlameSmall", c(0xff9900)),
+    ("water_breathing", "bigRingsTopLeftBottomRight", c(0x98dac0)),
+    ("invisibility", "borderDotted", c(0xf6f6f6)),
+    ("blindness", "circle24", c(0x1f1f23)),
+    ("night_vision", "circle32TopLeftBottomRight", c(0xc2ff66)),
+    ("hunger", "wheatTexture7", c(0x587653)),
+    ("weakness", "boneBottomLeftTopRight", c(0x484d48)),
+    ("poison", "dots3", c(0x87a363)),
+    ("wither", "witherSymbol", c(0x736156)),
+    ("absorption", "honeycombNoHalfCells", c(0x2552a5)),
+    ("glowing", "ray", c(0x94a061)),
+    ("levitation", "arrowUpExpanded", c(0xceffff)),
+    ("luck", "emerald", c(0x59c106)),
+    ("unluck", "cross", c(0xc0a44d)),
+    ("slow_falling", "strokeTopLeftBottomRight", c(0xf3cfb9)),
+    ("conduit_power", "ringsCentralBullseye", c(0x1dc2d1)),
+    ("dolphins_grace", "fishBody", c(0x88a3be)),
+    ("bad_omen", "creeperFaceSmall", c(0x0b6138)),
+    ("hero_of_the_village", "emeraldTopLeft", c(0x44ff44)),
+    ("darkness", "circle32", c(0x292721)),
+];
+
+/// Columns of [MOB_EFFECT_SHEET]; the rows are as many as needed.
+const SHEET_COLUMNS: u32 = 8;
+
+/// Padding between the icons on the sheet, in pixels at [crate::GRID_SIZE].
+const SHEET_PADDING: u32 = 2;
+
+/// The disc that every icon's symbol is drawn over.
+fn backdrop() -> ToPixmapTaskSpec {
+    stack!(
+        paint_svg_task("circle28", ComparableColor::DARKEST_GRAY * 0.75),
+        paint_svg_task("circle24", ComparableColor::STONE_EXTREME_SHADOW * 0.5)
+    )
+}
+
+/// A status effect's icon as sprites of its own, and all of them stitched into one sheet for
+/// previewing the set at once.
+pub struct MobEffectIcons;
+
+impl Material for MobEffectIcons {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let mut tasks: Vec<FileOutputTaskSpec> = MOB_EFFECTS
+            .iter()
+            .map(|(name, symbol, color)| {
+                out_task(
+                    format!("mob_effect/{}", name),
+                    stack!(backdrop(), paint_svg_task(*symbol, *color)),
+                )
+            })
+            .collect();
+        let frame_count = MOB_EFFECTS.len() as u32;
+        tasks.push(out_task(
+            "gui/mob_effect_sheet",
+            ToPixmapTaskSpec::Animate {
+                background: backdrop().into(),
+                frames: MOB_EFFECTS
+                    .iter()
+                    .map(|(_, symbol, color)| paint_svg_task(*symbol, *color))
+                    .collect(),
+                layout: SheetLayout::Grid {
+                    columns: SHEET_COLUMNS,
+                    rows: frame_count.div_ceil(SHEET_COLUMNS),
+                    padding: SHEET_PADDING,
+                },
+                timing: None,
+            },
+        ));
+        tasks.into_boxed_slice()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Gui),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+group!(MOB_EFFECT_SHEET = MobEffectIcons);
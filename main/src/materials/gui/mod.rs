@@ -0,0 +1,8 @@
+mod mob_effect;
+mod widgets;
+
+use crate::group;
+use crate::materials::gui::mob_effect::MOB_EFFECT_SHEET;
+use crate::materials::gui::widgets::WIDGETS;
+
+group!(ALL_GUI = MOB_EFFECT_SHEET, WIDGETS);
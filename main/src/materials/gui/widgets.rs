@@ -0,0 +1,122 @@
+use crate::image_tasks::color::{c, ComparableColor};
+use crate::image_tasks::task_spec::{
+    out_task, paint_svg_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+};
+use crate::materials::block::pickaxe::ore::GOLD;
+use crate::texture_base::material::{ColorTriad, Material, MaterialCategory, MaterialMetadata};
+use crate::{group, stack};
+
+/// Colors of a widget that isn't selected, hovered or earned.
+const INACTIVE: ColorTriad = ColorTriad {
+    color: ComparableColor::STONE,
+    shadow: ComparableColor::STONE_EXTREME_SHADOW,
+    highlight: ComparableColor::STONE_EXTREME_HIGHLIGHT,
+};
+
+/// Colors of a widget under the mouse pointer.
+const HOVERED: ColorTriad = ColorTriad {
+    color: ComparableColor::STONE_HIGHLIGHT,
+    shadow: ComparableColor::STONE,
+    highlight: ComparableColor::WHITE,
+};
+
+/// A widget frame in the given state, built from the same three layers whatever its shape: the
+/// `fill`, then the `outline` in shadow and the `accent` in highlight.
+fn widget_frame(
+    fill: &'static str,
+    outline: &'static str,
+    accent: &'static str,
+    colors: ColorTriad,
+) -> ToPixmapTaskSpec {
+    stack!(
+        paint_svg_task(fill, colors.color),
+        paint_svg_task(outline, colors.shadow),
+        paint_svg_task(accent, colors.highlight)
+    )
+}
+
+/// The frames of advancement icons, whose shape tells whether an advancement is a task, goal or
+/// challenge and whose color tells whether it's been obtained.
+pub struct AdvancementFrames;
+
+impl Material for AdvancementFrames {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let mut tasks = Vec::with_capacity(6);
+        for (kind, fill, outline, accent) in [
+            (
+                "task",
+                "borderSolidExtraThick",
+                "borderSolid",
+                "borderSolidTopLeft",
+            ),
+            (
+                "goal",
+                "bigRoundedSquare",
+                "cornersRound",
+                "borderRoundDots",
+            ),
+            ("challenge", "bigDiamondSolid", "cornersTri", "bigDiamond"),
+        ] {
+            for (state, colors) in [("obtained", GOLD.refined_colors), ("unobtained", INACTIVE)] {
+                tasks.push(out_task(
+                    format!("gui/sprites/advancements/{}_frame_{}", kind, state),
+                    widget_frame(fill, outline, accent, colors),
+                ));
+            }
+        }
+        tasks.into_boxed_slice()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Gui),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+const FILTER_ENABLED: ComparableColor = c(0x55ff55);
+const FILTER_DISABLED: ComparableColor = ComparableColor::DARKEST_GRAY;
+
+/// The recipe book's toggle button and its "show craftable only" filter, each plain and hovered.
+pub struct RecipeBookButtons;
+
+impl Material for RecipeBookButtons {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        let mut tasks = Vec::with_capacity(6);
+        for (suffix, colors) in [("", INACTIVE), ("_highlighted", HOVERED)] {
+            let button = widget_frame(
+                "borderSolidExtraThick",
+                "borderSolid",
+                "borderSolidTopLeft",
+                colors,
+            );
+            tasks.push(out_task(
+                format!("gui/sprites/recipe_book/button{}", suffix),
+                stack!(
+                    button.to_owned(),
+                    paint_svg_task("craftingGridSpaces", colors.shadow)
+                ),
+            ));
+            for (state, filter) in [("enabled", FILTER_ENABLED), ("disabled", FILTER_DISABLED)] {
+                tasks.push(out_task(
+                    format!("gui/sprites/recipe_book/filter_{}{}", state, suffix),
+                    stack!(
+                        button.to_owned(),
+                        paint_svg_task("craftingGridSquare", filter)
+                    ),
+                ));
+            }
+        }
+        tasks.into_boxed_slice()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Gui),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+group!(WIDGETS = AdvancementFrames, RecipeBookButtons);
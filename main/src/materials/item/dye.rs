@@ -0,0 +1,4 @@
+use crate::dyed_item;
+use crate::image_tasks::task_spec::paint_svg_task;
+
+dyed_item!(DYE = paint_svg_task("bonemealSmall", color!()));
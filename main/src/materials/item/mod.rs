@@ -1,12 +1,25 @@
 mod clock;
 mod compass;
+mod dye;
 mod music_disc;
 mod simple_items;
+mod trial_items;
 
 use crate::group;
+use crate::materials::block::axe::wood::WOOD_ITEM_ICONS;
 use crate::materials::item::clock::CLOCK;
 use crate::materials::item::compass::COMPASSES;
+use crate::materials::item::dye::DYE;
 use crate::materials::item::music_disc::MUSIC_DISCS;
 use crate::materials::item::simple_items::SIMPLE_ITEMS;
+use crate::materials::item::trial_items::TRIAL_ITEMS;
 
-group!(ALL_ITEMS = COMPASSES, CLOCK, MUSIC_DISCS, SIMPLE_ITEMS);
+group!(
+    ALL_ITEMS = COMPASSES,
+    CLOCK,
+    DYE,
+    MUSIC_DISCS,
+    SIMPLE_ITEMS,
+    TRIAL_ITEMS,
+    WOOD_ITEM_ICONS
+);
@@ -0,0 +1,56 @@
+use crate::image_tasks::color::c;
+use crate::image_tasks::task_spec::{paint_svg_task, ToPixmapTaskSpec};
+use crate::materials::block::pickaxe::ore::COPPER;
+use crate::materials::block::pickaxe::trial_chambers::{OMINOUS_FLAME, TRIAL_FLAME};
+use crate::texture_base::material::{introduced_in, ColorTriad};
+use crate::texture_base::version::TRICKY_TRIALS_VERSION;
+use crate::{group, paint_stack, single_texture_item, stack};
+
+const BREEZE: ColorTriad = ColorTriad {
+    color: c(0xa4b9e6),
+    shadow: c(0x6a7fc0),
+    highlight: c(0xdbe6ff),
+};
+
+/// A vault key, whose bit glows with the color of the vaults it opens.
+fn trial_key(bit: ColorTriad) -> ToPixmapTaskSpec {
+    stack!(
+        paint_svg_task("strokeBottomLeftTopRight2", COPPER.refined_colors.shadow),
+        paint_svg_task("tinyRing", COPPER.refined_colors.color),
+        paint_svg_task("bigDotsTopLeftBottomRight", bit.shadow),
+        paint_svg_task("bigDotsFillTopLeftBottomRight", bit.color)
+    )
+}
+
+single_texture_item!(
+    BREEZE_ROD = paint_svg_task("bambooThick", BREEZE.shadow),
+    paint_svg_task("bambooThin", BREEZE.highlight),
+    paint_svg_task("bambooThinMinusBorder", BREEZE.color)
+);
+
+single_texture_item!(
+    WIND_CHARGE = paint_svg_task("circle24", BREEZE.shadow),
+    paint_svg_task("ringsSpiral", BREEZE.highlight)
+);
+
+single_texture_item!(
+    OMINOUS_BOTTLE = paint_svg_task("circle24", c(0x1e2a2a)),
+    paint_stack!(
+        OMINOUS_FLAME.shadow,
+        "circle24BottomLeftTopRight",
+        "topStripeThick"
+    ),
+    paint_svg_task("witherSymbol", OMINOUS_FLAME.color)
+);
+
+single_texture_item!(TRIAL_KEY = trial_key(TRIAL_FLAME));
+
+single_texture_item!(OMINOUS_TRIAL_KEY = trial_key(OMINOUS_FLAME));
+
+group!(
+    TRIAL_ITEMS = introduced_in(&*BREEZE_ROD, TRICKY_TRIALS_VERSION),
+    introduced_in(&*WIND_CHARGE, TRICKY_TRIALS_VERSION),
+    introduced_in(&*OMINOUS_BOTTLE, TRICKY_TRIALS_VERSION),
+    introduced_in(&*TRIAL_KEY, TRICKY_TRIALS_VERSION),
+    introduced_in(&*OMINOUS_TRIAL_KEY, TRICKY_TRIALS_VERSION)
+);
@@ -1,11 +1,28 @@
 use crate::group;
+use crate::texture_base::material::MaterialGroup;
 
-mod block;
+pub(crate) mod block;
+mod entity;
+mod gui;
 mod item;
 mod particle;
 
 group!(
     ALL_MATERIALS = item::ALL_ITEMS,
     block::ALL_BLOCKS,
-    particle::ALL_PARTICLES
+    particle::ALL_PARTICLES,
+    gui::ALL_GUI,
+    entity::ALL_ENTITIES
 );
+
+/// The groups that make up [ALL_MATERIALS], with blocks split up by the tool that mines them.
+pub fn named_groups() -> Vec<(&'static str, &'static MaterialGroup)> {
+    let mut groups = vec![
+        ("item", &*item::ALL_ITEMS),
+        ("particle", &*particle::ALL_PARTICLES),
+        ("gui", &*gui::ALL_GUI),
+        ("entity", &*entity::ALL_ENTITIES),
+    ];
+    groups.extend(block::named_groups());
+    groups
+}
@@ -0,0 +1,67 @@
+use crate::image_tasks::color::ComparableColor;
+use crate::image_tasks::crop::TileRect;
+use crate::image_tasks::task_spec::{
+    crop_task, out_task, paint_svg_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+};
+use crate::texture_base::material::{Material, MaterialCategory, MaterialMetadata};
+use crate::{group, stack};
+
+/// Number of sprites a spark shrinks through as it fades.
+const SPARK_FRAMES: u8 = 8;
+
+/// A spark at full size. The game tints it with the firework's colors, so it's grayscale.
+fn spark() -> ToPixmapTaskSpec {
+    stack!(
+        paint_svg_task("ray", ComparableColor::STONE_HIGHLIGHT),
+        paint_svg_task("cross", ComparableColor::WHITE)
+    )
+}
+
+/// Sparks of an exploding firework. The game animates them by switching between one sprite per
+/// frame, from `spark_0` at full size to `spark_7` at its smallest, rather than from one vertical
+/// strip.
+struct FireworkSparks;
+
+impl Material for FireworkSparks {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        (0..SPARK_FRAMES)
+            .map(|index| {
+                out_task(
+                    format!("particle/spark_{}", index),
+                    crop_task(spark(), TileRect::FULL, TileRect::centered(16 - 2 * index)),
+                )
+            })
+            .collect()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Particle),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+/// The flash at the center of an explosion, also tinted by the game.
+struct FireworkFlash;
+
+impl Material for FireworkFlash {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        Box::new([out_task(
+            "particle/flash",
+            stack!(
+                paint_svg_task("glow", ComparableColor::STONE_HIGHLIGHT),
+                paint_svg_task("circle24", ComparableColor::WHITE)
+            ),
+        )])
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Particle),
+            ..MaterialMetadata::default()
+        }
+    }
+}
+
+group!(FIREWORKS = FireworkSparks, FireworkFlash);
@@ -1,6 +1,8 @@
+mod firework;
 mod simple_particle;
 
 use crate::group;
+use crate::materials::particle::firework::FIREWORKS;
 use crate::materials::particle::simple_particle::SIMPLE_PARTICLES;
 
-group!(ALL_PARTICLES = SIMPLE_PARTICLES);
+group!(ALL_PARTICLES = SIMPLE_PARTICLES, FIREWORKS);
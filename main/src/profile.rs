@@ -0,0 +1,88 @@
+use crate::cli::CLI;
+use crate::config::CONFIG;
+
+/// A named set of defaults for the command-line options, selected with `--profile <name>`, so that
+/// a common kind of build is one option instead of several. Options given on the command line or
+/// in the environment still take precedence.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Profile {
+    pub name: &'static str,
+    /// Used when the tile size is left off the command line.
+    pub tile_size: u32,
+    /// Values of other options, by name without the leading `--`.
+    pub options: &'static [(&'static str, &'static str)],
+}
+
+pub const PROFILES: &[Profile] = &[
+    // Close to vanilla's resolution, with SVGs supersampled so that curves are smoother
+    Profile {
+        name: "faithful",
+        tile_size: 32,
+        options: &[("render-svgs-at", "128")],
+    },
+    // Every texture limited to few enough colors that it could have been drawn by hand
+    Profile {
+        name: "low-color",
+        tile_size: 32,
+        options: &[("max-colors", "64"), ("max-color-error", "0.05")],
+    },
+    // Large textures, with a faster PNG optimization level so that the build finishes in
+    // reasonable time
+    Profile {
+        name: "hd",
+        tile_size: 512,
+        options: &[("oxipng-preset", "3")],
+    },
+];
+
+/// The profile named by `--profile` or the [CONFIG] file, or `None` if there isn't one. Panics if
+/// the name is unknown.
+pub fn selected_profile() -> Option<&'static Profile> {
+    let name = CLI
+        .options()
+        .value("profile")
+        .or_else(|| CONFIG.option_value("profile"))?;
+    Some(
+        PROFILES
+            .iter()
+            .find(|profile| profile.name == name)
+            .unwrap_or_else(|| {
+                panic!(
+                    "Unknown profile {}; the profiles are {}",
+                    name,
+                    PROFILES
+                        .iter()
+                        .map(|profile| profile.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            }),
+    )
+}
+
+impl Profile {
+    pub fn option_value(&self, name: &str) -> Option<&'static str> {
+        self.options
+            .iter()
+            .find(|(option, _)| *option == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+#[test]
+fn test_profiles() {
+    use crate::cli::BuildOptions;
+
+    for profile in PROFILES {
+        assert!(profile.tile_size.is_power_of_two(), "{}", profile.name);
+        for (option, value) in profile.options {
+            assert!(!option.starts_with('-'), "{}: {}", profile.name, option);
+            assert!(!value.is_empty(), "{}: {}", profile.name, option);
+            // Panics if the option isn't declared
+            BuildOptions::default().value(option);
+        }
+    }
+    let low_color = PROFILES.iter().find(|profile| profile.name == "low-color");
+    assert_eq!(low_color.unwrap().option_value("max-colors"), Some("64"));
+    assert_eq!(low_color.unwrap().option_value("theme"), None);
+}
@@ -1,7 +1,7 @@
 use crate::image_tasks::color::c;
 use crate::image_tasks::color::ComparableColor;
 use crate::image_tasks::task_spec::{out_task, FileOutputTaskSpec, ToPixmapTaskSpec};
-use crate::texture_base::material::Material;
+use crate::texture_base::material::{Material, MaterialCategory, MaterialMetadata};
 
 macro_rules! dyes {
     ($($name:tt = $color:expr),+) => {
@@ -34,15 +34,20 @@ dyes!(
     WHITE = ComparableColor::WHITE
 );
 
-pub struct DyedBlock<T = fn(ComparableColor) -> ToPixmapTaskSpec>
+/// A material that comes in all 16 dye colors, with outputs named `{directory}/{dye}_{name}`.
+pub struct DyedFamily<T = fn(ComparableColor) -> ToPixmapTaskSpec>
 where
     T: Fn(ComparableColor) -> ToPixmapTaskSpec,
 {
+    pub directory: &'static str,
     pub name: &'static str,
     pub create_dyed_texture: T,
 }
 
-impl<T> Material for DyedBlock<T>
+pub type DyedBlock<T = fn(ComparableColor) -> ToPixmapTaskSpec> = DyedFamily<T>;
+pub type DyedItem<T = fn(ComparableColor) -> ToPixmapTaskSpec> = DyedFamily<T>;
+
+impl<T> Material for DyedFamily<T>
 where
     T: Fn(ComparableColor) -> ToPixmapTaskSpec,
 {
@@ -50,19 +55,27 @@ where
         let mut out = Vec::with_capacity(DYES.len());
         for (dye_name, dye_color) in DYES {
             out.push(out_task(
-                format!("block/{}_{}", dye_name, self.name),
+                format!("{}/{}_{}", self.directory, dye_name, self.name),
                 (self.create_dyed_texture)(*dye_color),
             ));
         }
         out.into()
     }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: MaterialCategory::of_path(self.directory),
+            ..MaterialMetadata::default()
+        }
+    }
 }
 
 #[macro_export]
-macro_rules! dyed_block {
-    ($name:ident = $create_dyed_texture:expr) => {
-        pub const $name: $crate::texture_base::dyes::DyedBlock =
-            $crate::texture_base::dyes::DyedBlock {
+macro_rules! dyed_family {
+    ($name:ident: $family_type:ident = $directory:expr, $create_dyed_texture:expr) => {
+        pub const $name: $crate::texture_base::dyes::$family_type =
+            $crate::texture_base::dyes::DyedFamily {
+                directory: $directory,
                 name: const_format::map_ascii_case!(const_format::Case::Lower, &stringify!($name)),
                 create_dyed_texture: |color| {
                     macro_rules! color {
@@ -75,3 +88,17 @@ macro_rules! dyed_block {
             };
     };
 }
+
+#[macro_export]
+macro_rules! dyed_block {
+    ($name:ident = $create_dyed_texture:expr) => {
+        $crate::dyed_family!($name: DyedBlock = "block", $create_dyed_texture);
+    };
+}
+
+#[macro_export]
+macro_rules! dyed_item {
+    ($name:ident = $create_dyed_texture:expr) => {
+        $crate::dyed_family!($name: DyedItem = "item", $create_dyed_texture);
+    };
+}
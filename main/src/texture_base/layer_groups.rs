@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+
+use crate::image_tasks::task_spec::{paint_svg_task, ToPixmapTaskSpec};
+use crate::stack;
+use crate::texture_base::material::ColorTriad;
+
+/// Builds a sub-stack of layers in a material's colors.
+pub type LayerGroup = fn(ColorTriad) -> ToPixmapTaskSpec;
+
+/// Sub-stacks that many materials share, by name. Changing one here restyles every material that
+/// references it.
+static LAYER_GROUPS: Lazy<HashMap<&'static str, LayerGroup>> = Lazy::new(|| {
+    let mut groups: HashMap<&'static str, LayerGroup> = HashMap::new();
+    groups.insert("bevel", |colors| {
+        stack!(
+            paint_svg_task("borderSolid", colors.shadow),
+            paint_svg_task("borderSolidTopLeft", colors.highlight)
+        )
+    });
+    groups.insert("dashedBorder", |colors| {
+        stack!(
+            paint_svg_task("borderSolid", colors.shadow),
+            paint_svg_task("borderShortDashes", colors.highlight)
+        )
+    });
+    groups
+});
+
+/// Looks up a layer group by name and paints it in the given colors.
+pub fn layer_group(name: &str, colors: ColorTriad) -> ToPixmapTaskSpec {
+    match LAYER_GROUPS.get(name) {
+        Some(group) => group(colors),
+        None => panic!("No layer group named {}", name),
+    }
+}
+
+/// References a layer group from [LAYER_GROUPS]. With only a name, uses the `color!()`,
+/// `shadow!()` and `highlight!()` of the enclosing block macro.
+#[macro_export]
+macro_rules! layer_group {
+    ( $name:expr, $colors:expr $(,)? ) => {
+        $crate::texture_base::layer_groups::layer_group($name, $colors)
+    };
+    ( $name:expr ) => {
+        $crate::layer_group!(
+            $name,
+            $crate::texture_base::material::ColorTriad {
+                color: color!(),
+                shadow: shadow!(),
+                highlight: highlight!(),
+            }
+        )
+    };
+}
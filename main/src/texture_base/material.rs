@@ -1,13 +1,21 @@
+use std::collections::HashMap;
 use std::fmt::Debug;
 use std::hash::Hash;
+use std::str::FromStr;
 
-use crate::anyhoo;
-use crate::image_tasks::cloneable::CloneableError;
+use log::{info, warn};
+use once_cell::sync::Lazy;
+
+use crate::{anyhoo, flag_present, parsed_option};
+use crate::image_tasks::cloneable::{CloneableError, Name};
 
 use crate::image_tasks::color::{c, ComparableColor};
+use crate::image_tasks::output_path::texture_name;
 use crate::image_tasks::task_spec::{
-    from_svg_task, out_task, paint_svg_task, FileOutputTaskSpec, ToPixmapTaskSpec,
+    alias_task, from_svg_task, gray_out_task, out_task, paint_svg_task, FileOutputTaskSpec,
+    RenderLayer, ToPixmapTaskSpec,
 };
+use crate::texture_base::version::{MinecraftVersion, TARGET_VERSION};
 
 /// Specification in DSL form of how one or more texture images are to be generated.
 pub trait Material {
@@ -23,12 +31,163 @@ pub trait Material {
         }
         Err(anyhoo!("No output task found with name {}", name))
     }
+
+    /// Information about this material that doesn't affect how it's rendered.
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata::default()
+    }
+}
+
+/// Kind of game object a material's textures belong to.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum MaterialCategory {
+    Block,
+    Item,
+    Particle,
+    Gui,
+    Entity,
+}
+
+impl MaterialCategory {
+    /// Infers the category from the directory an output path begins with.
+    pub fn of_path(path: &str) -> Option<MaterialCategory> {
+        match path.split('/').next() {
+            Some("block") => Some(MaterialCategory::Block),
+            Some("item") => Some(MaterialCategory::Item),
+            Some("particle") => Some(MaterialCategory::Particle),
+            Some("gui") | Some("mob_effect") => Some(MaterialCategory::Gui),
+            Some("entity") => Some(MaterialCategory::Entity),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct MaterialMetadata {
+    pub category: Option<MaterialCategory>,
+    /// First Minecraft version that has this material, if it's newer than the oldest one supported.
+    pub introduced: Option<MinecraftVersion>,
+    /// First Minecraft version that no longer has this material, if any.
+    pub removed: Option<MinecraftVersion>,
+}
+
+impl MaterialMetadata {
+    pub fn exists_in(&self, version: MinecraftVersion) -> bool {
+        self.introduced
+            .is_none_or(|introduced| introduced <= version)
+            && self.removed.is_none_or(|removed| removed > version)
+    }
+
+    /// Whether this material should be emitted for the `--target-version`.
+    pub fn exists_in_target_version(&self) -> bool {
+        TARGET_VERSION.is_none_or(|version| self.exists_in(version))
+    }
+}
+
+/// Attaches [MaterialMetadata] to a material that doesn't supply its own.
+pub struct WithMetadata<T: Material + ?Sized + 'static> {
+    pub material: &'static T,
+    pub metadata: MaterialMetadata,
+}
+
+impl<T: Material + ?Sized + 'static> Material for WithMetadata<T> {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        self.material.get_output_tasks()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        self.metadata
+    }
+}
+
+/// Declares the [RenderLayer] of every block texture a material outputs, so that
+/// [crate::image_tasks::task_spec::audit_render_layers] can check their transparency. Items and
+/// particles aren't drawn in a render layer, so they're left alone.
+pub struct InRenderLayer<T: Material + ?Sized + 'static> {
+    pub material: &'static T,
+    pub layer: RenderLayer,
+}
+
+impl<T: Material + ?Sized + 'static> Material for InRenderLayer<T> {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        self.material
+            .get_output_tasks()
+            .into_vec()
+            .into_iter()
+            .map(|task| {
+                if texture_name(&task.get_path()).is_some_and(|name| {
+                    MaterialCategory::of_path(&name) == Some(MaterialCategory::Block)
+                }) {
+                    task.in_render_layer(self.layer)
+                } else {
+                    task
+                }
+            })
+            .collect()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        self.material.metadata()
+    }
+}
+
+pub fn in_render_layer<T: Material + ?Sized + 'static>(
+    material: &'static T,
+    layer: RenderLayer,
+) -> InRenderLayer<T> {
+    InRenderLayer { material, layer }
+}
+
+/// Marks a material as only existing from the given Minecraft version on.
+pub fn introduced_in<T: Material + ?Sized + 'static>(
+    material: &'static T,
+    version: MinecraftVersion,
+) -> WithMetadata<T> {
+    WithMetadata {
+        material,
+        metadata: MaterialMetadata {
+            introduced: Some(version),
+            ..material.metadata()
+        },
+    }
 }
 
 pub struct MaterialGroup {
     pub(crate) tasks: Box<[FileOutputTaskSpec]>,
 }
 
+impl MaterialGroup {
+    /// Combines the output tasks of several materials. A task whose output path was already used by
+    /// an earlier member is dropped, with a warning if the two tasks would produce different files.
+    pub fn new(members: Vec<Box<[FileOutputTaskSpec]>>) -> Self {
+        let mut tasks: Vec<FileOutputTaskSpec> =
+            Vec::with_capacity(members.iter().map(|member| member.len()).sum());
+        let mut index_by_path: HashMap<Box<str>, usize> = HashMap::with_capacity(tasks.capacity());
+        for task in members.into_iter().flat_map(|member| member.into_vec()) {
+            let path = task.get_path();
+            match index_by_path.get(&path) {
+                Some(index) => {
+                    if tasks[*index] != task {
+                        warn!(
+                            "Conflicting tasks for {}: keeping {:?} and dropping {:?}",
+                            path, tasks[*index], task
+                        );
+                    } else {
+                        info!("Dropping duplicate task for {}", path);
+                    }
+                }
+                None => {
+                    index_by_path.insert(path, tasks.len());
+                    tasks.push(task);
+                }
+            }
+        }
+        MaterialGroup {
+            tasks: tasks.into(),
+        }
+    }
+}
+
 impl Material for MaterialGroup {
     fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
         self.tasks.to_owned()
@@ -40,24 +199,41 @@ pub trait TricolorMaterial: Material {
     fn color(&self) -> ComparableColor;
     fn shadow(&self) -> ComparableColor;
     fn highlight(&self) -> ComparableColor;
-}
 
-pub const DEFAULT_GROUP_SIZE: usize = 1024;
+    fn colors(&self) -> ColorTriad {
+        ColorTriad {
+            color: self.color(),
+            shadow: self.shadow(),
+            highlight: self.highlight(),
+        }
+    }
+}
 
 #[macro_export]
 macro_rules! group {
     ($name:ident = $( $members:expr ),* ) => {
-        #[allow(unused_mut)]
         pub static $name: once_cell::sync::Lazy<$crate::texture_base::material::MaterialGroup>
         = once_cell::sync::Lazy::new(|| {
-            let mut tasks: Vec<$crate::image_tasks::task_spec::FileOutputTaskSpec>
-                = Vec::with_capacity($crate::texture_base::material::DEFAULT_GROUP_SIZE);
-            $({
-                #![allow(unused)]
-                use $crate::texture_base::material::Material;
-                tasks.extend($members.get_output_tasks().iter().cloned());
-            })*
-            $crate::texture_base::material::MaterialGroup { tasks: tasks.into() }
+            if !$crate::config::CONFIG.group_enabled(stringify!($name)) {
+                log::info!("Skipping {} because the config disables it", stringify!($name));
+                $crate::config::CONFIG.skip_members(&[$(stringify!($members)),*]);
+                return $crate::texture_base::material::MaterialGroup::new(vec![]);
+            }
+            $crate::texture_base::material::MaterialGroup::new(vec![
+                $({
+                    #![allow(unused)]
+                    use $crate::texture_base::material::Material;
+                    if $members.metadata().exists_in_target_version() {
+                        $members.get_output_tasks()
+                    } else {
+                        log::info!(
+                            "Skipping {} because it's not in the target version",
+                            stringify!($members)
+                        );
+                        Box::new([])
+                    }
+                }),*
+            ])
         });
     }
 }
@@ -66,6 +242,8 @@ macro_rules! group {
 pub struct SingleTextureMaterial {
     pub name: &'static str,
     texture: ToPixmapTaskSpec,
+    /// Other names the texture is also written under, without rendering or compressing it again.
+    aliases: &'static [&'static str],
 }
 
 impl SingleTextureMaterial {
@@ -73,7 +251,28 @@ impl SingleTextureMaterial {
         self.texture.to_owned()
     }
     pub const fn new(name: &'static str, texture: ToPixmapTaskSpec) -> Self {
-        SingleTextureMaterial { name, texture }
+        SingleTextureMaterial {
+            name,
+            texture,
+            aliases: &[],
+        }
+    }
+    /// Also writes the texture under each of `aliases`, with a [FileOutputTaskSpec::Copy], so that
+    /// it's only rendered and compressed once.
+    pub fn with_aliases(self, aliases: &'static [&'static str]) -> Self {
+        SingleTextureMaterial { aliases, ..self }
+    }
+
+    /// The output task for the texture, along with one for its aliases if it has any.
+    fn output_tasks_with_aliases(&self, task: FileOutputTaskSpec) -> Box<[FileOutputTaskSpec]> {
+        if self.aliases.is_empty() {
+            Box::new([task])
+        } else {
+            Box::new([
+                task.to_owned(),
+                alias_task(task, self.aliases.iter().copied()),
+            ])
+        }
     }
 }
 
@@ -87,6 +286,10 @@ impl Material for SingleTextureTricolorMaterial {
     fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
         self.material.get_output_tasks()
     }
+
+    fn metadata(&self) -> MaterialMetadata {
+        self.material.metadata()
+    }
 }
 
 impl TricolorMaterial for SingleTextureTricolorMaterial {
@@ -111,16 +314,30 @@ impl From<SingleTextureMaterial> for ToPixmapTaskSpec {
 
 impl Material for SingleTextureMaterial {
     fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
-        Box::new([out_task(self.name, self.texture())])
+        self.output_tasks_with_aliases(out_task(self.name, self.texture()))
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: MaterialCategory::of_path(self.name),
+            ..MaterialMetadata::default()
+        }
     }
 }
 
 #[macro_export]
 macro_rules! material {
-    ($name:ident = $directory:expr, $texture:expr) => {
+    ($name:ident $(, $aliases:ident)* = $directory:expr, $texture:expr) => {
         pub static $name: once_cell::sync::Lazy<
             $crate::texture_base::material::SingleTextureMaterial,
         > = once_cell::sync::Lazy::new(|| {
+            const ALIASES: &[&str] = &[$(
+                const_format::concatcp!(
+                    $directory,
+                    "/",
+                    const_format::map_ascii_case!(const_format::Case::Lower, &stringify!($aliases))
+                )
+            ),*];
             $crate::texture_base::material::SingleTextureMaterial::new(
                 const_format::concatcp!(
                     $directory,
@@ -129,15 +346,64 @@ macro_rules! material {
                 ),
                 $texture.into(),
             )
+            .with_aliases(ALIASES)
         });
     };
 }
 
+/// Grayscale overlay that the game tints with a biome color, such as the side of a grass block.
+#[derive(Clone, Debug, Ord, PartialOrd, Eq, PartialEq, Hash)]
+pub struct TintOverlay {
+    pub material: SingleTextureMaterial,
+}
+
+impl Material for TintOverlay {
+    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
+        self.material
+            .output_tasks_with_aliases(gray_out_task(self.material.name, self.material.texture()))
+    }
+}
+
+/// Defines a [TintOverlay] block texture; the build fails if any of its layers turns out not to be
+/// gray. Any names after the first are aliases; see [SingleTextureMaterial::with_aliases].
+#[macro_export]
+macro_rules! tint_overlay {
+    ($name:ident $(, $aliases:ident)* = $( $layers:expr ),* ) => {
+        pub static $name: once_cell::sync::Lazy<$crate::texture_base::material::TintOverlay> =
+            once_cell::sync::Lazy::new(|| {
+                const ALIASES: &[&str] = &[$(
+                    const_format::concatcp!(
+                        "block/",
+                        const_format::map_ascii_case!(
+                            const_format::Case::Lower,
+                            &stringify!($aliases)
+                        )
+                    )
+                ),*];
+                $crate::texture_base::material::TintOverlay {
+                    material: $crate::texture_base::material::SingleTextureMaterial::new(
+                        const_format::concatcp!(
+                            "block/",
+                            const_format::map_ascii_case!(
+                                const_format::Case::Lower,
+                                &stringify!($name)
+                            )
+                        ),
+                        $crate::stack!($($layers),*),
+                    )
+                    .with_aliases(ALIASES),
+                }
+            });
+    };
+}
+
 #[macro_export]
 macro_rules! single_texture_material {
-    ($name:ident = $directory:expr, $background:expr, $( $layers:expr ),* ) => {
+    (
+        $name:ident $(, $aliases:ident)* = $directory:expr, $background:expr, $( $layers:expr ),*
+    ) => {
         $crate::material!(
-            $name = $directory, $crate::stack_on!($background, $($layers),*));
+            $name $(, $aliases)* = $directory, $crate::stack_on!($background, $($layers),*));
     }
 }
 
@@ -187,8 +453,9 @@ macro_rules! single_layer_item {
 
 #[macro_export]
 macro_rules! single_texture_block {
-    ($name:ident = $background:expr, $( $layers:expr ),* ) => {
-        $crate::single_texture_material!($name = "block", $background, $($layers),*);
+    ($name:ident $(, $aliases:ident)* = $background:expr, $( $layers:expr ),* ) => {
+        $crate::single_texture_material!(
+            $name $(, $aliases)* = "block", $background, $($layers),*);
     }
 }
 
@@ -216,40 +483,23 @@ macro_rules! single_layer_particle {
     }
 }
 
-pub struct CopiedMaterial {
-    pub name: &'static str,
-    pub source: FileOutputTaskSpec,
-}
-
-impl Material for CopiedMaterial {
-    fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
-        Box::new([FileOutputTaskSpec::Copy {
-            original: Box::new(self.source.to_owned()),
-            link_name: self.name.into(),
-        }])
-    }
-}
-
-#[macro_export]
-macro_rules! copy_block {
-    ($name:ident = $base:expr, $base_name:expr) => {
-        pub static $name: once_cell::sync::Lazy<$crate::texture_base::material::CopiedMaterial> =
-            once_cell::sync::Lazy::new(|| $crate::texture_base::material::CopiedMaterial {
-                name: const_format::concatcp!(
-                    "block/",
-                    const_format::map_ascii_case!(const_format::Case::Lower, &stringify!($name))
-                ),
-                source: {
-                    use $crate::texture_base::material::Material;
-                    $base.get_output_task_by_name($base_name).unwrap()
-                },
-            });
-    };
-}
-
 #[macro_export]
 macro_rules! block_with_colors {
-    ($name:ident = $color:expr, $shadow:expr, $highlight:expr, $background:expr, $( $layers:expr ),* ) => {
+    (
+        $name:ident $(, $aliases:ident)* = base $color:expr, $background:expr, $( $layers:expr ),*
+    ) => {
+        $crate::block_with_colors!($name $(, $aliases)* =
+            $color,
+            $crate::texture_base::material::ColorTriad::from_base($color).shadow,
+            $crate::texture_base::material::ColorTriad::from_base($color).highlight,
+            $background,
+            $($layers),*
+        );
+    };
+    (
+        $name:ident $(, $aliases:ident)* = $color:expr, $shadow:expr, $highlight:expr,
+        $background:expr, $( $layers:expr ),*
+    ) => {
         macro_rules! color {
             () => { $color }
         }
@@ -260,7 +510,12 @@ macro_rules! block_with_colors {
             () => { $highlight }
         }
         pub static $name: once_cell::sync::Lazy<$crate::texture_base::material::SingleTextureTricolorMaterial>
-            = once_cell::sync::Lazy::new(||
+            = once_cell::sync::Lazy::new(|| {
+            const ALIASES: &[&str] = &[$(
+                const_format::concatcp!("block/",
+                    const_format::map_ascii_case!(const_format::Case::Lower, &stringify!($aliases))
+                )
+            ),*];
             $crate::texture_base::material::SingleTextureTricolorMaterial {
                 colors: $crate::texture_base::material::ColorTriad {
                     color: color!(),
@@ -273,8 +528,9 @@ macro_rules! block_with_colors {
                     ),
                     $crate::stack_on!($background, $($layers),*).into()
                 )
+                .with_aliases(ALIASES)
             }
-        );
+        });
     }
 }
 
@@ -324,23 +580,29 @@ pub struct GroundCoverBlock {
     pub base: ToPixmapTaskSpec,
     pub cover_side: ToPixmapTaskSpec,
     pub top: ToPixmapTaskSpec,
+    /// Other names the top texture is also written under.
+    pub top_aliases: &'static [&'static str],
 }
 
 impl Material for GroundCoverBlock {
     fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
-        Box::new([
-            out_task(
-                format!("block/{}{}", self.name, self.top_name_suffix),
-                self.top.to_owned(),
-            ),
-            out_task(
-                format!("block/{}_side", self.name),
-                ToPixmapTaskSpec::StackLayerOnLayer {
-                    background: Box::new(self.base.to_owned()),
-                    foreground: Box::new(self.cover_side.to_owned()),
-                },
-            ),
-        ])
+        let top = out_task(
+            format!("block/{}{}", self.name, self.top_name_suffix),
+            self.top.to_owned(),
+        );
+        let side = out_task(
+            format!("block/{}_side", self.name),
+            ToPixmapTaskSpec::StackLayerOnLayer {
+                background: self.base.to_owned().into(),
+                foreground: self.cover_side.to_owned().into(),
+            },
+        );
+        if self.top_aliases.is_empty() {
+            Box::new([top, side])
+        } else {
+            let top_alias = alias_task(top.to_owned(), self.top_aliases.iter().copied());
+            Box::new([top, top_alias, side])
+        }
     }
 }
 
@@ -380,6 +642,7 @@ pub fn ground_cover_block(
         },
         cover_side,
         top,
+        top_aliases: &[],
     }
 }
 
@@ -401,53 +664,129 @@ impl Material for SingleLayerMaterial {
             },
         )])
     }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: MaterialCategory::of_path(self.name),
+            ..MaterialMetadata::default()
+        }
+    }
 }
 
 pub const REDSTONE_ON: ComparableColor = c(0xff5e5e);
 
-pub struct RedstoneOffOnBlockPair {
-    pub name: &'static str,
-    pub create_texture: Box<dyn Fn(ComparableColor) -> ToPixmapTaskSpec + Send + Sync>,
+/// Suffix of an emissive texture's name, which OptiFine draws at full brightness over the texture
+/// it's named after; set as `suffix.emissive` in [EMISSIVE_PROPERTIES_PATH].
+pub const EMISSIVE_SUFFIX: &str = "_e";
+
+/// Whether to write the emissive textures of [MaterialState]s that have them, which only OptiFine
+/// and its imitators use. Set with `--emissive-textures`.
+pub static EMISSIVE_TEXTURES: Lazy<bool> = Lazy::new(|| flag_present("emissive-textures"));
+
+/// Where OptiFine reads [EMISSIVE_SUFFIX] from. Copied from the metadata folder only along with the
+/// emissive textures themselves.
+pub const EMISSIVE_PROPERTIES_PATH: &str = "assets/minecraft/optifine/emissive.properties";
+
+/// How a [StatefulMaterial] looks in one block state, such as powered or lit.
+pub struct MaterialState {
+    /// Appended to the material's name, such as `_on` or `_lit_powered`; empty for the state the
+    /// game uses by default.
+    pub suffix: &'static str,
+    pub texture: ToPixmapTaskSpec,
+    /// Just the parts of [Self::texture] that give off light, if any.
+    pub emissive: Option<ToPixmapTaskSpec>,
+}
+
+impl MaterialState {
+    pub fn new(suffix: &'static str, texture: ToPixmapTaskSpec) -> MaterialState {
+        MaterialState {
+            suffix,
+            texture,
+            emissive: None,
+        }
+    }
+
+    pub fn with_emissive(self, emissive: ToPixmapTaskSpec) -> MaterialState {
+        MaterialState {
+            emissive: Some(emissive),
+            ..self
+        }
+    }
+}
+
+/// A block with a texture for each of several block states, such as a repeater that's on or off,
+/// or a copper bulb that's lit, powered, both or neither.
+pub struct StatefulMaterial {
+    pub name: Name,
+    pub states: Box<[MaterialState]>,
+}
+
+impl StatefulMaterial {
+    /// The off and on states of a redstone component, whose `create_texture` paints its redstone
+    /// parts in the color it's given.
+    pub fn redstone_off_on<T: Fn(ComparableColor) -> ToPixmapTaskSpec>(
+        name: &'static str,
+        create_texture: T,
+    ) -> StatefulMaterial {
+        StatefulMaterial {
+            name: name.into(),
+            states: Box::new([
+                MaterialState::new("", create_texture(ComparableColor::BLACK)),
+                MaterialState::new("_on", create_texture(REDSTONE_ON)),
+            ]),
+        }
+    }
 }
 
-impl Material for RedstoneOffOnBlockPair {
+impl Material for StatefulMaterial {
     fn get_output_tasks(&self) -> Box<[FileOutputTaskSpec]> {
-        Box::new([
-            out_task(
-                format!("block/{}", self.name),
-                (self.create_texture)(ComparableColor::BLACK),
-            ),
-            out_task(
-                format!("block/{}_on", self.name),
-                (self.create_texture)(REDSTONE_ON),
-            ),
-        ])
+        let mut tasks = Vec::with_capacity(2 * self.states.len());
+        for state in self.states.iter() {
+            let name = format!("block/{}{}", self.name, state.suffix);
+            tasks.push(out_task(name.clone(), state.texture.to_owned()));
+            if *EMISSIVE_TEXTURES && let Some(emissive) = &state.emissive {
+                tasks.push(out_task(
+                    format!("{}{}", name, EMISSIVE_SUFFIX),
+                    emissive.to_owned(),
+                ));
+            }
+        }
+        tasks.into_boxed_slice()
+    }
+
+    fn metadata(&self) -> MaterialMetadata {
+        MaterialMetadata {
+            category: Some(MaterialCategory::Block),
+            ..MaterialMetadata::default()
+        }
     }
 }
 
+/// Defines a [StatefulMaterial] with the off and on states of a redstone component; see
+/// [StatefulMaterial::redstone_off_on]. In `$create_texture`, `state_color!()` is the color of
+/// the redstone parts.
 #[macro_export]
 macro_rules! redstone_off_on_block {
     ($name:ident = $create_texture:expr ) => {
-        pub static $name: once_cell::sync::Lazy<
-            $crate::texture_base::material::RedstoneOffOnBlockPair,
-        > = once_cell::sync::Lazy::new(|| $crate::texture_base::material::RedstoneOffOnBlockPair {
-            name: const_format::map_ascii_case!(const_format::Case::Lower, &stringify!($name)),
-            create_texture: Box::new(|state_color| {
-                macro_rules! state_color {
-                    () => {
-                        state_color
-                    };
-                }
-                $create_texture
-            }),
-        });
+        pub static $name: once_cell::sync::Lazy<$crate::texture_base::material::StatefulMaterial> =
+            once_cell::sync::Lazy::new(|| {
+                $crate::texture_base::material::StatefulMaterial::redstone_off_on(
+                    const_format::map_ascii_case!(const_format::Case::Lower, &stringify!($name)),
+                    |state_color| {
+                        macro_rules! state_color {
+                            () => {
+                                state_color
+                            };
+                        }
+                        $create_texture
+                    },
+                )
+            });
     };
 }
 
 pub type TextureSupplier<T> = Box<dyn Fn(&T) -> ToPixmapTaskSpec + Send + Sync>;
 pub type TextureUnaryFunc<T> = Box<dyn Fn(&T, ToPixmapTaskSpec) -> ToPixmapTaskSpec + Send + Sync>;
-pub type TextureBinaryFunc<T> =
-    Box<dyn Fn(&T, ToPixmapTaskSpec, ToPixmapTaskSpec) -> ToPixmapTaskSpec + Send + Sync>;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct ColorTriad {
@@ -455,3 +794,106 @@ pub struct ColorTriad {
     pub(crate) shadow: ComparableColor,
     pub(crate) highlight: ComparableColor,
 }
+
+impl ColorTriad {
+    /// Derives the shadow and highlight from the base color using [SHADE_OFFSETS].
+    pub fn from_base(color: ComparableColor) -> ColorTriad {
+        ColorTriad::from_base_with_offsets(color, *SHADE_OFFSETS)
+    }
+
+    pub fn from_base_with_offsets(color: ComparableColor, offsets: ShadeOffsets) -> ColorTriad {
+        ColorTriad {
+            color,
+            shadow: color.shift_oklab_lightness(offsets.shadow),
+            highlight: color.shift_oklab_lightness(offsets.highlight),
+        }
+    }
+}
+
+/// How far [ColorTriad::from_base] moves the shadow and highlight from the base color, in OKLab
+/// lightness (0.0 for black to 1.0 for white).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ShadeOffsets {
+    pub shadow: f32,
+    pub highlight: f32,
+}
+
+impl Default for ShadeOffsets {
+    /// About the average of the hand-tuned triads.
+    fn default() -> Self {
+        ShadeOffsets {
+            shadow: -0.1,
+            highlight: 0.12,
+        }
+    }
+}
+
+impl FromStr for ShadeOffsets {
+    type Err = CloneableError;
+
+    /// Parses `shadow,highlight`, e.g. `-0.1,0.12`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (shadow, highlight) = s
+            .split_once(',')
+            .ok_or_else(|| anyhoo!("Expected shadow,highlight but got {}", s))?;
+        match (shadow.trim().parse(), highlight.trim().parse()) {
+            (Ok(shadow), Ok(highlight)) => Ok(ShadeOffsets { shadow, highlight }),
+            _ => Err(anyhoo!("Invalid shade offsets: {}", s)),
+        }
+    }
+}
+
+/// The offsets set by `--shade-offsets`, which a theme can use to make every derived triad
+/// flatter or more contrasty at once.
+pub static SHADE_OFFSETS: Lazy<ShadeOffsets> =
+    Lazy::new(|| parsed_option("shade-offsets").unwrap_or_default());
+
+#[test]
+fn test_shade_offsets() {
+    assert_eq!(
+        "-0.2, 0.3".parse::<ShadeOffsets>().unwrap(),
+        ShadeOffsets {
+            shadow: -0.2,
+            highlight: 0.3
+        }
+    );
+    assert!("0.2".parse::<ShadeOffsets>().is_err());
+    assert!("a,b".parse::<ShadeOffsets>().is_err());
+    let triad = ColorTriad::from_base(c(0x76b297));
+    assert_eq!(triad.color, c(0x76b297));
+    assert!(triad.shadow.green() < triad.color.green());
+    assert!(triad.highlight.green() > triad.color.green());
+}
+
+#[test]
+fn test_stateful_material() {
+    let lantern = StatefulMaterial {
+        name: "lantern".into(),
+        states: Box::new([
+            MaterialState::new("", from_svg_task("borderSolid")),
+            MaterialState::new("_lit", paint_svg_task("borderSolid", REDSTONE_ON))
+                .with_emissive(paint_svg_task("glow", REDSTONE_ON)),
+        ]),
+    };
+    let paths: Vec<Box<str>> = lantern
+        .get_output_tasks()
+        .iter()
+        .map(FileOutputTaskSpec::get_path)
+        .collect();
+    // Emissive textures are only written with --emissive-textures
+    assert_eq!(
+        paths,
+        vec![
+            "assets/minecraft/textures/block/lantern.png".into(),
+            "assets/minecraft/textures/block/lantern_lit.png".into(),
+        ] as Vec<Box<str>>
+    );
+
+    let repeater =
+        StatefulMaterial::redstone_off_on("repeater", |color| paint_svg_task("repeater", color));
+    assert_eq!(repeater.states[0].suffix, "");
+    assert_eq!(
+        repeater.states[1].texture,
+        paint_svg_task("repeater", REDSTONE_ON)
+    );
+}
@@ -1,2 +1,5 @@
 pub mod dyes;
+pub mod layer_groups;
 pub mod material;
+pub mod theme;
+pub mod version;
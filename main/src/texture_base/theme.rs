@@ -0,0 +1,267 @@
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+
+use crate::anyhoo;
+use crate::config::parse_toml_subset;
+use crate::image_tasks::cloneable::CloneableError;
+use crate::image_tasks::color::ComparableColor;
+use crate::image_tasks::task_spec::FileOutputTaskSpec;
+use crate::materials::block::axe::wood::{
+    Wood, ACACIA, BIRCH, CRIMSON, DARK_OAK, JUNGLE, MANGROVE, OAK, PALE_OAK, SPRUCE, WARPED,
+};
+use crate::option_value;
+use crate::texture_base::dyes::DYES;
+
+/// A color that a theme can replace, such as `dye.red` or `stone_shadow`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ColorRole {
+    /// The name a theme file uses for it: the table it's under, a dot, and then its key.
+    pub name: String,
+    /// What the role is the palette of, such as a dye or a kind of wood, if it's not of the whole
+    /// pack. Its table is the kind of owner, such as `dye`.
+    pub owner: Option<(&'static str, &'static str)>,
+    pub color: ComparableColor,
+}
+
+/// Every color that a theme can replace. Materials don't need to refer to roles, since a theme
+/// replaces a role's default color wherever it appears in the textures the role is scoped to; see
+/// [Theme::apply].
+pub static COLOR_ROLES: Lazy<Vec<ColorRole>> = Lazy::new(|| {
+    let global = |name: &str, color| ColorRole {
+        name: name.to_owned(),
+        owner: None,
+        color,
+    };
+    let mut roles = vec![
+        global("stone", ComparableColor::STONE),
+        global("stone_shadow", ComparableColor::STONE_SHADOW),
+        global("stone_highlight", ComparableColor::STONE_HIGHLIGHT),
+        global(
+            "stone_extreme_shadow",
+            ComparableColor::STONE_EXTREME_SHADOW,
+        ),
+        global(
+            "stone_extreme_highlight",
+            ComparableColor::STONE_EXTREME_HIGHLIGHT,
+        ),
+        global("deepslate_shadow", ComparableColor::DEEPSLATE_SHADOW),
+    ];
+    roles.extend(DYES.iter().map(|(name, color)| ColorRole {
+        name: format!("dye.{}", name),
+        owner: Some(("dye", *name)),
+        color: *color,
+    }));
+    let woods: [(&'static str, &Wood); 10] = [
+        ("acacia", &ACACIA),
+        ("birch", &BIRCH),
+        ("dark_oak", &DARK_OAK),
+        ("jungle", &JUNGLE),
+        ("mangrove", &MANGROVE),
+        ("spruce", &SPRUCE),
+        ("oak", &OAK),
+        ("pale_oak", &PALE_OAK),
+        ("crimson", &CRIMSON),
+        ("warped", &WARPED),
+    ];
+    for (name, wood) in woods {
+        for (suffix, color) in [
+            ("", wood.color),
+            ("_highlight", wood.highlight),
+            ("_shadow", wood.shadow),
+        ] {
+            roles.push(ColorRole {
+                name: format!("wood.{}{}", name, suffix),
+                owner: Some(("wood", name)),
+                color,
+            });
+        }
+    }
+    roles
+});
+
+/// Replacement colors for some of the [COLOR_ROLES], which swap the palette of the whole pack.
+/// Each replacement is keyed by the role's default RGB, so that the alpha of a semitransparent
+/// use is kept.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Theme {
+    /// Replacements for the roles of the whole pack.
+    global: HashMap<(u8, u8, u8), ComparableColor>,
+    /// Replacements for the roles of an owner, such as `("dye", "red")`, which apply only to its
+    /// textures, and take precedence over [Self::global] there.
+    owned: HashMap<(&'static str, &'static str), HashMap<(u8, u8, u8), ComparableColor>>,
+}
+
+/// Loaded from the file named by `--theme`, if any.
+pub static THEME: Lazy<Option<Theme>> = Lazy::new(|| {
+    option_value("theme").map(|path| {
+        read_to_string(&path)
+            .map_err(CloneableError::from)
+            .and_then(|text| text.parse())
+            .unwrap_or_else(|e| panic!("Invalid value for --theme: {:?}", e))
+    })
+});
+
+const fn rgb_key(color: ComparableColor) -> (u8, u8, u8) {
+    (color.red(), color.green(), color.blue())
+}
+
+/// The owner of the given kind, such as `dye`, whose textures include the one named `name`: the
+/// one whose name is the longest run of whole `_`-separated words of the texture's file name, so
+/// that `block/dark_oak_planks` is `dark_oak`'s and not `oak`'s.
+fn owner_of(kind: &str, name: &str) -> Option<&'static str> {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    let words: Vec<&str> = file_name.split('_').collect();
+    COLOR_ROLES
+        .iter()
+        .filter_map(|role| role.owner)
+        .filter(|(owner_kind, _)| *owner_kind == kind)
+        .map(|(_, owner)| owner)
+        .filter(|owner| {
+            let owner_words: Vec<&str> = owner.split('_').collect();
+            words
+                .windows(owner_words.len())
+                .any(|window| window == owner_words)
+        })
+        .max_by_key(|owner| owner.len())
+}
+
+impl FromStr for Theme {
+    type Err = CloneableError;
+
+    /// Parses a theme file, in the same subset of TOML as a config file; see [parse_toml_subset].
+    /// Lines are like `stone = "#888888"`, where a `[dye]` or `[wood]` table header prefixes the
+    /// roles after it, so that `red` under `[dye]` is the role `dye.red`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut theme = Theme::default();
+        for entry in parse_toml_subset(s, &["dye", "wood"])? {
+            let role_name = if entry.table.is_empty() {
+                entry.key.to_owned()
+            } else {
+                format!("{}.{}", entry.table, entry.key)
+            };
+            let value: ComparableColor = entry.value.parse()?;
+            let role = COLOR_ROLES
+                .iter()
+                .find(|role| role.name == role_name)
+                .ok_or_else(|| anyhoo!("Unknown color role: {}", role_name))?;
+            let replacements = match role.owner {
+                Some(owner) => theme.owned.entry(owner).or_default(),
+                None => &mut theme.global,
+            };
+            if let Some(previous) = replacements.insert(rgb_key(role.color), value)
+                && previous != value
+            {
+                return Err(anyhoo!(
+                    "{} has the same default color as another role of the same textures, so it \
+                    can't be set to {} when the other is {}",
+                    role_name,
+                    value,
+                    previous
+                ));
+            }
+        }
+        Ok(theme)
+    }
+}
+
+impl Theme {
+    /// Applies the replacements that apply to the texture named `name`, which is a path such as
+    /// `block/stone` within the textures directory.
+    pub fn apply_to_color(&self, name: &str, color: ComparableColor) -> ComparableColor {
+        if color.alpha() == 0 {
+            return color;
+        }
+        let key = rgb_key(color);
+        let owned = self
+            .owned
+            .iter()
+            .filter(|((kind, owner), _)| owner_of(kind, name) == Some(*owner))
+            .find_map(|(_, replacements)| replacements.get(&key));
+        match owned.or_else(|| self.global.get(&key)) {
+            Some(replacement) => *replacement * (color.alpha() as f32 / u8::MAX as f32),
+            None => color,
+        }
+    }
+
+    /// Recolors the texture `task` writes, with the replacements for the roles of the whole pack
+    /// and those of the texture's owners. A copy is recolored as its original is.
+    pub fn apply(&self, task: &FileOutputTaskSpec) -> FileOutputTaskSpec {
+        match task {
+            FileOutputTaskSpec::PngOutput {
+                destination_name, ..
+            } => task.map_colors(&|color| self.apply_to_color(destination_name, color)),
+            FileOutputTaskSpec::Copy {
+                original,
+                link_names,
+            } => FileOutputTaskSpec::Copy {
+                original: Box::new(self.apply(original)),
+                link_names: link_names.to_owned(),
+            },
+        }
+    }
+}
+
+#[test]
+fn test_theme() {
+    use crate::image_tasks::color::c;
+    use crate::image_tasks::task_spec::{out_task, paint_svg_task};
+
+    let theme: Theme = "# Pastel\nstone = \"#99aacc\"\n\n[dye]\nred = \"#ffaaaa\"\n"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        theme.apply_to_color("block/stone", ComparableColor::STONE),
+        c(0x99aacc)
+    );
+    assert_eq!(
+        theme.apply_to_color("block/stone", ComparableColor::STONE * 0.5),
+        c(0x99aacc) * 0.5
+    );
+    assert_eq!(
+        theme.apply_to_color("block/red_wool", c(0xba0000)),
+        c(0xffaaaa)
+    );
+    // A dye's roles only recolor that dye's textures
+    assert_eq!(
+        theme.apply_to_color("block/netherrack", c(0xba0000)),
+        c(0xba0000)
+    );
+    assert_eq!(
+        theme.apply_to_color("block/redstone_block", c(0xba0000)),
+        c(0xba0000)
+    );
+    assert_eq!(
+        theme.apply_to_color("block/stone", c(0x123456)),
+        c(0x123456)
+    );
+    assert_eq!(
+        theme.apply(&out_task(
+            "block/stone",
+            paint_svg_task("bricks", ComparableColor::STONE)
+        )),
+        out_task("block/stone", paint_svg_task("bricks", c(0x99aacc)))
+    );
+    assert!("granite = \"#ffffff\"".parse::<Theme>().is_err());
+    assert!("stone = pink".parse::<Theme>().is_err());
+    assert!("[stone]\nshadow = \"#000000\"".parse::<Theme>().is_err());
+
+    // Light gray dye and stone highlight are the same color, but light gray textures are the dye's
+    let theme: Theme = "stone_highlight = \"#ffffff\"\n[dye]\nlight_gray = \"#000000\""
+        .parse()
+        .unwrap();
+    let light_gray = ComparableColor::STONE_HIGHLIGHT;
+    assert_eq!(
+        theme.apply_to_color("block/light_gray_wool", light_gray),
+        ComparableColor::BLACK
+    );
+    assert_eq!(
+        theme.apply_to_color("block/gray_wool", light_gray),
+        ComparableColor::WHITE
+    );
+    assert_eq!(owner_of("wood", "block/dark_oak_planks"), Some("dark_oak"));
+    assert_eq!(owner_of("wood", "block/stripped_oak_log"), Some("oak"));
+    assert_eq!(owner_of("dye", "block/stone"), None);
+}
@@ -0,0 +1,113 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+use once_cell::sync::Lazy;
+
+use crate::image_tasks::cloneable::CloneableError;
+use crate::{anyhoo, option_value};
+
+/// A Minecraft release version, such as 1.20 or 1.20.4.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct MinecraftVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl MinecraftVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        MinecraftVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl FromStr for MinecraftVersion {
+    type Err = CloneableError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('.').map(|part| part.parse::<u16>());
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(Ok(major)), Some(Ok(minor)), None, None) => {
+                Ok(MinecraftVersion::new(major, minor, 0))
+            }
+            (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => {
+                Ok(MinecraftVersion::new(major, minor, patch))
+            }
+            _ => Err(anyhoo!("Not a Minecraft release version: {}", s)),
+        }
+    }
+}
+
+impl Display for MinecraftVersion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.patch == 0 {
+            write!(f, "{}.{}", self.major, self.minor)
+        } else {
+            write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+        }
+    }
+}
+
+/// The version set by `--target-version`. `None` (the default, also selected with `latest`) means
+/// to emit every texture, including those in the latest snapshot.
+pub static TARGET_VERSION: Lazy<Option<MinecraftVersion>> = Lazy::new(|| {
+    option_value("target-version")
+        .filter(|version| version != "latest")
+        .map(|version| {
+            version
+                .parse()
+                .unwrap_or_else(|e| panic!("Invalid value for --target-version: {:?}", e))
+        })
+});
+
+/// The Armored Paws drop, which added wolf variants and wolf armor.
+pub const ARMORED_PAWS_VERSION: MinecraftVersion = MinecraftVersion::new(1, 20, 5);
+
+/// The Tricky Trials update, which added trial chambers, the breeze and the rest of the tuff
+/// family.
+pub const TRICKY_TRIALS_VERSION: MinecraftVersion = MinecraftVersion::new(1, 21, 0);
+
+/// The Garden Awakens drop, which added pale oak, pale moss and resin.
+pub const PALE_GARDEN_VERSION: MinecraftVersion = MinecraftVersion::new(1, 21, 4);
+
+/// The Spring to Life drop, which added leaf litter.
+pub const SPRING_TO_LIFE_VERSION: MinecraftVersion = MinecraftVersion::new(1, 21, 5);
+
+/// Textures that were renamed, as (name before, name after, first version with the new name).
+/// Materials may use either name.
+const RENAMED_TEXTURES: &[(&str, &str, MinecraftVersion)] = &[(
+    "block/grass",
+    "block/short_grass",
+    MinecraftVersion::new(1, 20, 3),
+)];
+
+/// Returns the name that the `--target-version` uses for a texture, or the latest name if there's
+/// no target version.
+pub fn name_for_target_version(name: &str) -> &str {
+    for (old_name, new_name, renamed_in) in RENAMED_TEXTURES {
+        if name == *old_name || name == *new_name {
+            return match *TARGET_VERSION {
+                Some(version) if version < *renamed_in => old_name,
+                _ => new_name,
+            };
+        }
+    }
+    name
+}
+
+/// Returns the older names that a texture must also be emitted under, so that a pack built without
+/// a `--target-version` works in every supported version. Empty when there is a target version.
+pub fn legacy_names(name: &str) -> Vec<&'static str> {
+    if TARGET_VERSION.is_some() {
+        return vec![];
+    }
+    let name = name_for_target_version(name);
+    RENAMED_TEXTURES
+        .iter()
+        .filter(|(_, new_name, _)| *new_name == name)
+        .map(|(old_name, _, _)| *old_name)
+        .collect()
+}
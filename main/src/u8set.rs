@@ -1,5 +1,6 @@
 use itertools::Itertools;
 use std::fmt::{Debug, Display, Formatter};
+use std::ops::RangeInclusive;
 
 #[derive(Copy, Clone, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct U8BitSet([u64; 4]);
@@ -19,6 +20,24 @@ impl U8BitSet {
         self.0[limb] |= bit;
     }
 
+    /// Inserts every value in the range, a whole limb at a time.
+    pub fn insert_range(&mut self, range: RangeInclusive<u8>) {
+        let (start, end) = (*range.start() as u32, *range.end() as u32);
+        if start > end {
+            return;
+        }
+        for (index, limb) in self.0.iter_mut().enumerate() {
+            let limb_start = index as u32 * 64;
+            let limb_end = limb_start + 63;
+            if start > limb_end || end < limb_start {
+                continue;
+            }
+            let low = start.max(limb_start) - limb_start;
+            let high = end.min(limb_end) - limb_start;
+            *limb |= (u64::MAX >> (63 - high)) & (u64::MAX << low);
+        }
+    }
+
     pub fn extend(&mut self, other: &U8BitSet) {
         self.0
             .iter_mut()
@@ -45,9 +64,44 @@ impl U8BitSet {
         intersection
     }
 
+    pub fn remove_all_in(&mut self, other: &U8BitSet) {
+        self.0
+            .iter_mut()
+            .zip(other.0)
+            .for_each(|(self_limb, other_limb)| *self_limb &= !other_limb)
+    }
+
+    pub fn difference(&self, other: &U8BitSet) -> U8BitSet {
+        let mut difference = *self;
+        difference.remove_all_in(other);
+        difference
+    }
+
+    /// The least value in the set, found from the lowest nonzero limb. Named like
+    /// [BTreeSet::first](std::collections::BTreeSet::first), since [Ord::min] would shadow `min`.
+    pub fn first(&self) -> Option<u8> {
+        self.0
+            .iter()
+            .enumerate()
+            .find(|(_, limb)| **limb != 0)
+            .map(|(index, limb)| (index as u32 * 64 + limb.trailing_zeros()) as u8)
+    }
+
+    /// The greatest value in the set, found from the highest nonzero limb.
+    pub fn last(&self) -> Option<u8> {
+        self.0
+            .iter()
+            .enumerate()
+            .rfind(|(_, limb)| **limb != 0)
+            .map(|(index, limb)| (index as u32 * 64 + 63 - limb.leading_zeros()) as u8)
+    }
+
     pub fn len(&self) -> usize {
         self.0.iter().copied().map(u64::count_ones).sum::<u32>() as usize
     }
+    pub fn is_empty(&self) -> bool {
+        self.0 == [0, 0, 0, 0]
+    }
     pub fn new() -> Self {
         Self([0, 0, 0, 0])
     }
@@ -59,6 +113,12 @@ impl U8BitSet {
     }
 }
 
+impl Default for U8BitSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Display for U8BitSet {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         f.write_str(&self.into_iter().map(|x| u8::to_string(&x)).join(","))
@@ -71,36 +131,45 @@ impl Debug for U8BitSet {
     }
 }
 
+/// Iterates in ascending order. Empty limbs are skipped whole, and within a limb each step jumps
+/// straight to the next set bit.
 pub struct U8BitIter {
-    set: U8BitSet,
-    index: usize,
+    remaining: U8BitSet,
+    limb: usize,
 }
 
 impl Iterator for U8BitIter {
     type Item = u8;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while self.index <= u8::MAX as usize {
-            if self.set.contains(self.index as u8) {
-                let current = self.index;
-                self.index += 1;
-                return Some(current as u8);
+        while let Some(bits) = self.remaining.0.get_mut(self.limb) {
+            if *bits == 0 {
+                self.limb += 1;
             } else {
-                self.index += 1;
+                let bit = bits.trailing_zeros();
+                *bits &= *bits - 1;
+                return Some((self.limb as u32 * 64 + bit) as u8);
             }
         }
         None
     }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.remaining.len();
+        (len, Some(len))
+    }
 }
 
+impl ExactSizeIterator for U8BitIter {}
+
 impl IntoIterator for U8BitSet {
     type Item = u8;
     type IntoIter = U8BitIter;
 
     fn into_iter(self) -> Self::IntoIter {
         U8BitIter {
-            set: self,
-            index: 0,
+            remaining: self,
+            limb: 0,
         }
     }
 }
@@ -112,3 +181,45 @@ impl FromIterator<u8> for U8BitSet {
         result
     }
 }
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_matches_btree_set(
+        first: Vec<u8>,
+        second: Vec<u8>,
+        range_start: u8,
+        range_end: u8
+    ) {
+        use std::collections::BTreeSet;
+
+        let first_model: BTreeSet<u8> = first.iter().copied().collect();
+        let second_model: BTreeSet<u8> = second.iter().copied().collect();
+        let first_set: U8BitSet = first.into_iter().collect();
+        let second_set: U8BitSet = second.into_iter().collect();
+        proptest::prop_assert_eq!(first_set.len(), first_model.len());
+        proptest::prop_assert_eq!(first_set.is_empty(), first_model.is_empty());
+        proptest::prop_assert_eq!(first_set.first(), first_model.first().copied());
+        proptest::prop_assert_eq!(first_set.last(), first_model.last().copied());
+        proptest::prop_assert_eq!(first_set.into_iter().len(), first_model.len());
+        proptest::prop_assert!(first_set.into_iter().eq(first_model.iter().copied()));
+        proptest::prop_assert!(first_set
+            .union(&second_set)
+            .into_iter()
+            .eq(first_model.union(&second_model).copied()));
+        proptest::prop_assert!(first_set
+            .intersect(&second_set)
+            .into_iter()
+            .eq(first_model.intersection(&second_model).copied()));
+        proptest::prop_assert!(first_set
+            .difference(&second_set)
+            .into_iter()
+            .eq(first_model.difference(&second_model).copied()));
+
+        let mut with_range = first_set;
+        with_range.insert_range(range_start..=range_end);
+        let mut model_with_range = first_model;
+        model_with_range.extend(range_start..=range_end);
+        proptest::prop_assert!(with_range.into_iter().eq(model_with_range.into_iter()));
+    }
+}